@@ -0,0 +1,1144 @@
+use crate::ast_parser::filter::parse_filter;
+use crate::ast_parser::ErrorType;
+use crate::rustifier::{dumps, loads_json5, JSONElement};
+use crate::schema::regex::Regex;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+// Deep enough for any sensible recursive function, but well short of overflowing the stack
+const MAX_CALL_DEPTH: usize = 100;
+
+// Functions that are simple enough to write in the filter language itself. Everything else is
+// built in to `call_builtin`.
+const PRELUDE: &str = r#"
+    def map(f): [.[] | f];
+    def select(f): if f then . else empty end;
+    def recurse(f): def r: ., (f | r); r;
+    def recurse: recurse(.[]?);
+    def values: select(. != null);
+    def nulls: select(. == null);
+    def booleans: select(type == "boolean");
+    def numbers: select(type == "number");
+    def strings: select(type == "string");
+    def arrays: select(type == "array");
+    def objects: select(type == "object");
+    def iterables: select(type == "array" or type == "object");
+    def scalars: select(type != "array" and type != "object");
+    def with_entries(f): to_entries | map(f) | from_entries;
+    def map_values(f): if type == "array" then map(f) else with_entries({key, value: (.value | f)}) end;
+    def add: reduce .[] as $x (null; . + $x);
+    def any: reduce .[] as $x (false; . or $x);
+    def all: reduce .[] as $x (true; . and $x);
+    def any(f): reduce (.[] | f) as $x (false; . or $x);
+    def all(f): reduce (.[] | f) as $x (true; . and $x);
+    def sort: sort_by(.);
+    def unique: unique_by(.);
+    def min: min_by(.);
+    def max: max_by(.);
+    def first: .[0];
+    def last: .[-1];
+    def isempty(g): first((g | false), true);
+    def in(xs): . as $x | xs | has($x);
+    def inside(xs): . as $x | xs | contains($x);
+    def until(cond; next): def u: if cond then . else (next | u) end; u;
+    def while(cond; update): def w: if cond then ., (update | w) else empty end; w;
+    def walk(f): def w: if type == "object" then map_values(w) elif type == "array" then map(w) else . end | f; w;
+    .
+"#;
+
+thread_local! {
+    // Parsing the prelude isn't free, so each thread does it once
+    static PRELUDE_SCOPE: Rc<Scope> = prelude_scope();
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// A parsed filter. Running one gives back a stream of values, which is why so many of these
+/// take every combination of their operands' outputs.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Filter {
+    // .
+    Identity,
+    // .., the input and every value inside it
+    Recurse,
+    Literal(JSONElement),
+    // A string literal with \(...) in it
+    Interpolate(Vec<StringPart>),
+    Variable(String),
+    // target[key], where the key is worked out from the same input as the target
+    Index(Box<Filter>, Box<Filter>),
+    // target[from:to]
+    Slice(Box<Filter>, Option<Box<Filter>>, Option<Box<Filter>>),
+    // target[]
+    Iterate(Box<Filter>),
+    Pipe(Box<Filter>, Box<Filter>),
+    Comma(Box<Filter>, Box<Filter>),
+    Negate(Box<Filter>),
+    Binary(Operator, Box<Filter>, Box<Filter>),
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    // a // b, the outputs of a that aren't false or null, or those of b if there are none
+    Alternative(Box<Filter>, Box<Filter>),
+    // [f], or [] when there's no filter
+    Array(Option<Box<Filter>>),
+    // {key: value, ...}, with an object for each combination of keys and values
+    Object(Vec<(Filter, Filter)>),
+    If(Box<Filter>, Box<Filter>, Box<Filter>),
+    // try body catch handler, where errors are dropped if there's no handler
+    Try(Box<Filter>, Option<Box<Filter>>),
+    // reduce source as $variable (init; update)
+    Reduce {
+        source: Box<Filter>,
+        variable: String,
+        init: Box<Filter>,
+        update: Box<Filter>,
+    },
+    // source as $variable | body
+    Bind {
+        source: Box<Filter>,
+        variable: String,
+        body: Box<Filter>,
+    },
+    // def name(parameters): body; rest
+    Define(Rc<Definition>, Box<Filter>),
+    Call(String, Vec<Filter>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart {
+    Text(String),
+    Filter(Filter),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub parameters: Vec<Parameter>,
+    pub body: Filter,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Parameter {
+    // f, which is passed a filter that runs wherever the function uses it
+    Filter(String),
+    // $f, which is bound to each output of the argument in turn
+    Value(String),
+}
+
+// What's visible from some point in a filter, innermost binding first
+enum Scope {
+    Root,
+    Variable(String, JSONElement, Rc<Scope>),
+    Function(Rc<Definition>, Rc<Scope>),
+    // A filter passed to a function, which runs in the scope of the call that passed it
+    Argument(String, Rc<Filter>, Rc<Scope>, Rc<Scope>),
+}
+
+enum Callable {
+    // A function, and the scope its body runs in, which includes the function itself so it can
+    // recurse
+    Function(Rc<Definition>, Rc<Scope>),
+    Argument(Rc<Filter>, Rc<Scope>),
+}
+
+impl Scope {
+    fn variable(&self, name: &str) -> Option<&JSONElement> {
+        match self {
+            Scope::Root => None,
+            Scope::Variable(variable, value, _) if variable == name => Some(value),
+            Scope::Variable(_, _, parent)
+            | Scope::Function(_, parent)
+            | Scope::Argument(_, _, _, parent) => parent.variable(name),
+        }
+    }
+
+    fn callable(scope: &Rc<Scope>, name: &str, arity: usize) -> Option<Callable> {
+        match &**scope {
+            Scope::Root => None,
+            Scope::Function(definition, _)
+                if definition.name == name && definition.parameters.len() == arity =>
+            {
+                Some(Callable::Function(definition.clone(), scope.clone()))
+            }
+            Scope::Argument(argument, filter, defined_in, _) if argument == name && arity == 0 => {
+                Some(Callable::Argument(filter.clone(), defined_in.clone()))
+            }
+            Scope::Variable(_, _, parent)
+            | Scope::Function(_, parent)
+            | Scope::Argument(_, _, _, parent) => Scope::callable(parent, name, arity),
+        }
+    }
+}
+
+fn prelude_scope() -> Rc<Scope> {
+    let mut scope = Rc::new(Scope::Root);
+    let mut filter = parse_filter(PRELUDE).expect("the prelude should parse");
+    while let Filter::Define(definition, rest) = filter {
+        scope = Rc::new(Scope::Function(definition, scope));
+        filter = *rest;
+    }
+    scope
+}
+
+/// Runs `filter` on `input`, giving back everything it outputs
+pub fn run_filter(filter: &Filter, input: &JSONElement) -> Result<Vec<JSONElement>, ErrorType> {
+    let context = Context {
+        scope: PRELUDE_SCOPE.with(Rc::clone),
+        depth: 0,
+    };
+    run(filter, input, &context)
+}
+
+struct Context {
+    scope: Rc<Scope>,
+    // How many function calls deep this is
+    depth: usize,
+}
+
+impl Context {
+    fn with(&self, scope: Scope) -> Context {
+        Context {
+            scope: Rc::new(scope),
+            depth: self.depth,
+        }
+    }
+
+    fn with_variable(&self, name: &str, value: JSONElement) -> Context {
+        self.with(Scope::Variable(name.to_string(), value, self.scope.clone()))
+    }
+}
+
+fn run(
+    filter: &Filter,
+    input: &JSONElement,
+    context: &Context,
+) -> Result<Vec<JSONElement>, ErrorType> {
+    match filter {
+        Filter::Identity => Ok(vec![input.clone()]),
+        Filter::Recurse => {
+            let mut output = vec![];
+            descendants(input, &mut output);
+            Ok(output)
+        }
+        Filter::Literal(value) => Ok(vec![value.clone()]),
+        Filter::Interpolate(parts) => interpolate(parts, input, context),
+        Filter::Variable(name) => match context.scope.variable(name) {
+            Some(value) => Ok(vec![value.clone()]),
+            None => Err(format!("${} is not defined", name)),
+        },
+        Filter::Index(target, key) => {
+            let keys = run(key, input, context)?;
+            let mut output = vec![];
+            for target in run(target, input, context)? {
+                for key in &keys {
+                    output.push(index(&target, key)?);
+                }
+            }
+            Ok(output)
+        }
+        Filter::Slice(target, from, to) => slice_all(target, from, to, input, context),
+        Filter::Iterate(target) => {
+            let mut output = vec![];
+            for target in run(target, input, context)? {
+                output.extend(iterate(&target)?);
+            }
+            Ok(output)
+        }
+        Filter::Pipe(first, second) => {
+            let mut output = vec![];
+            for value in run(first, input, context)? {
+                output.extend(run(second, &value, context)?);
+            }
+            Ok(output)
+        }
+        Filter::Comma(first, second) => {
+            let mut output = run(first, input, context)?;
+            output.extend(run(second, input, context)?);
+            Ok(output)
+        }
+        Filter::Negate(operand) => run(operand, input, context)?
+            .into_iter()
+            .map(|value| match as_number(&value) {
+                Some(n) => Ok(number(-n)),
+                None => Err(format!("{} cannot be negated", describe(&value))),
+            })
+            .collect(),
+        Filter::Binary(operator, left, right) => {
+            let right = run(right, input, context)?;
+            let mut output = vec![];
+            for left in run(left, input, context)? {
+                for right in &right {
+                    output.push(binary(*operator, &left, right)?);
+                }
+            }
+            Ok(output)
+        }
+        Filter::And(left, right) | Filter::Or(left, right) => {
+            // The right side is only run when the left doesn't already decide the answer
+            let is_and = matches!(filter, Filter::And(..));
+            let mut output = vec![];
+            for left in run(left, input, context)? {
+                if truthy(&left) != is_and {
+                    output.push(JSONElement::Boolean(!is_and));
+                    continue;
+                }
+                for right in run(right, input, context)? {
+                    output.push(JSONElement::Boolean(truthy(&right)));
+                }
+            }
+            Ok(output)
+        }
+        Filter::Alternative(first, second) => {
+            let output: Vec<JSONElement> = run(first, input, context)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(truthy)
+                .collect();
+            match output.is_empty() {
+                true => run(second, input, context),
+                false => Ok(output),
+            }
+        }
+        Filter::Array(None) => Ok(vec![JSONElement::Array(vec![])]),
+        Filter::Array(Some(elements)) => {
+            Ok(vec![JSONElement::Array(run(elements, input, context)?)])
+        }
+        Filter::Object(entries) => construct_objects(entries, input, context),
+        Filter::If(condition, then, otherwise) => {
+            let mut output = vec![];
+            for condition in run(condition, input, context)? {
+                let branch = if truthy(&condition) { then } else { otherwise };
+                output.extend(run(branch, input, context)?);
+            }
+            Ok(output)
+        }
+        Filter::Try(body, handler) => match (run(body, input, context), handler) {
+            (Ok(output), _) => Ok(output),
+            (Err(message), Some(handler)) => run(handler, &JSONElement::String(message), context),
+            (Err(_), None) => Ok(vec![]),
+        },
+        Filter::Reduce {
+            source,
+            variable,
+            init,
+            update,
+        } => {
+            let items = run(source, input, context)?;
+            let mut output = vec![];
+            for mut accumulator in run(init, input, context)? {
+                for item in &items {
+                    // Only the last output of the update carries on, like jq
+                    let inner = context.with_variable(variable, item.clone());
+                    accumulator = run(update, &accumulator, &inner)?
+                        .pop()
+                        .unwrap_or(JSONElement::Null);
+                }
+                output.push(accumulator);
+            }
+            Ok(output)
+        }
+        Filter::Bind {
+            source,
+            variable,
+            body,
+        } => {
+            let mut output = vec![];
+            for value in run(source, input, context)? {
+                output.extend(run(body, input, &context.with_variable(variable, value))?);
+            }
+            Ok(output)
+        }
+        Filter::Define(definition, rest) => run(
+            rest,
+            input,
+            &context.with(Scope::Function(definition.clone(), context.scope.clone())),
+        ),
+        Filter::Call(name, arguments) => call(name, arguments, input, context),
+    }
+}
+
+// The input and everything inside it, parents before their children
+fn descendants(value: &JSONElement, output: &mut Vec<JSONElement>) {
+    output.push(value.clone());
+    if let Ok(children) = iterate(value) {
+        for child in &children {
+            descendants(child, output);
+        }
+    }
+}
+
+fn interpolate(
+    parts: &[StringPart],
+    input: &JSONElement,
+    context: &Context,
+) -> Result<Vec<JSONElement>, ErrorType> {
+    let mut strings = vec![String::new()];
+    for part in parts {
+        match part {
+            StringPart::Text(text) => strings.iter_mut().for_each(|s| s.push_str(text)),
+            StringPart::Filter(filter) => {
+                let values = run(filter, input, context)?;
+                strings = strings
+                    .iter()
+                    .flat_map(|s| values.iter().map(move |value| s.clone() + &to_text(value)))
+                    .collect();
+            }
+        }
+    }
+    Ok(strings.into_iter().map(JSONElement::String).collect())
+}
+
+fn slice_all(
+    target: &Filter,
+    from: &Option<Box<Filter>>,
+    to: &Option<Box<Filter>>,
+    input: &JSONElement,
+    context: &Context,
+) -> Result<Vec<JSONElement>, ErrorType> {
+    let bound = |filter: &Option<Box<Filter>>| match filter {
+        Some(filter) => run(filter, input, context),
+        None => Ok(vec![JSONElement::Null]),
+    };
+    let (froms, tos) = (bound(from)?, bound(to)?);
+    let mut output = vec![];
+    for target in run(target, input, context)? {
+        for from in &froms {
+            for to in &tos {
+                output.push(slice(&target, from, to)?);
+            }
+        }
+    }
+    Ok(output)
+}
+
+fn construct_objects(
+    entries: &[(Filter, Filter)],
+    input: &JSONElement,
+    context: &Context,
+) -> Result<Vec<JSONElement>, ErrorType> {
+    let mut objects = vec![HashMap::new()];
+    for (key, value) in entries {
+        let keys = run(key, input, context)?
+            .into_iter()
+            .map(|key| match key {
+                JSONElement::String(key) => Ok(key),
+                other => Err(format!(
+                    "Object keys must be strings, not {}",
+                    describe(&other)
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let values = run(value, input, context)?;
+        let mut next = vec![];
+        for object in &objects {
+            for key in &keys {
+                for value in &values {
+                    let mut object: HashMap<String, JSONElement> = object.clone();
+                    object.insert(key.clone(), value.clone());
+                    next.push(object);
+                }
+            }
+        }
+        objects = next;
+    }
+    Ok(objects.into_iter().map(JSONElement::Object).collect())
+}
+
+fn call(
+    name: &str,
+    arguments: &[Filter],
+    input: &JSONElement,
+    context: &Context,
+) -> Result<Vec<JSONElement>, ErrorType> {
+    match Scope::callable(&context.scope, name, arguments.len()) {
+        Some(Callable::Argument(filter, scope)) => run(
+            &filter,
+            input,
+            &Context {
+                scope,
+                depth: context.depth,
+            },
+        ),
+        Some(Callable::Function(definition, scope)) => {
+            if context.depth >= MAX_CALL_DEPTH {
+                return Err(format!(
+                    "{} recursed more than {} times",
+                    definition.name, MAX_CALL_DEPTH
+                ));
+            }
+            // Each value parameter multiplies the scopes the body runs in, one per output
+            let mut scopes = vec![scope];
+            for (parameter, argument) in definition.parameters.iter().zip(arguments) {
+                scopes = match parameter {
+                    Parameter::Filter(name) => scopes
+                        .into_iter()
+                        .map(|scope| {
+                            Rc::new(Scope::Argument(
+                                name.clone(),
+                                Rc::new(argument.clone()),
+                                context.scope.clone(),
+                                scope,
+                            ))
+                        })
+                        .collect(),
+                    Parameter::Value(name) => {
+                        let values = run(argument, input, context)?;
+                        scopes
+                            .iter()
+                            .flat_map(|scope| {
+                                values.iter().map(|value| {
+                                    Rc::new(Scope::Variable(
+                                        name.clone(),
+                                        value.clone(),
+                                        scope.clone(),
+                                    ))
+                                })
+                            })
+                            .collect()
+                    }
+                };
+            }
+            let mut output = vec![];
+            for scope in scopes {
+                let inner = Context {
+                    scope,
+                    depth: context.depth + 1,
+                };
+                output.extend(run(&definition.body, input, &inner)?);
+            }
+            Ok(output)
+        }
+        None => call_builtin(name, arguments, input, context),
+    }
+}
+
+fn call_builtin(
+    name: &str,
+    arguments: &[Filter],
+    input: &JSONElement,
+    context: &Context,
+) -> Result<Vec<JSONElement>, ErrorType> {
+    let argument = |i: usize| run(&arguments[i], input, context);
+    // Most builtins with an argument are run once for each value it outputs
+    let each = |apply: &dyn Fn(&JSONElement) -> Result<JSONElement, ErrorType>| {
+        argument(0)?.iter().map(apply).collect()
+    };
+    let string = |value: &JSONElement| -> Result<String, ErrorType> {
+        match value {
+            JSONElement::String(s) => Ok(s.clone()),
+            other => Err(format!("{} is not a string", describe(other))),
+        }
+    };
+    let one = |value: JSONElement| Ok(vec![value]);
+    match (name, arguments.len()) {
+        ("empty", 0) => Ok(vec![]),
+        ("error", 0) => Err(to_text(input)),
+        ("error", 1) => Err(to_text(argument(0)?.first().unwrap_or(&JSONElement::Null))),
+        ("not", 0) => one(JSONElement::Boolean(!truthy(input))),
+        ("type", 0) => one(JSONElement::String(type_name(input).to_string())),
+        ("length", 0) => one(length(input)?),
+        ("keys" | "keys_unsorted", 0) => one(JSONElement::Array(keys(input)?)),
+        ("has", 1) => each(&|key| has(input, key).map(JSONElement::Boolean)),
+        ("contains", 1) => each(&|other| Ok(JSONElement::Boolean(contains(input, other)))),
+        ("to_entries", 0) => one(to_entries(input)?),
+        ("from_entries", 0) => one(from_entries(input)?),
+        ("tostring", 0) => one(JSONElement::String(to_text(input))),
+        ("tonumber", 0) => one(to_number(input)?),
+        ("tojson", 0) => one(JSONElement::String(dumps(input))),
+        ("fromjson", 0) => one(loads_json5(&string(input)?)?),
+        ("ascii_downcase", 0) => one(JSONElement::String(string(input)?.to_ascii_lowercase())),
+        ("ascii_upcase", 0) => one(JSONElement::String(string(input)?.to_ascii_uppercase())),
+        ("floor", 0) => match as_number(input) {
+            Some(n) => one(number(n.floor())),
+            None => Err(format!("{} has no floor", describe(input))),
+        },
+        ("reverse", 0) => one(match input {
+            JSONElement::Array(elements) => {
+                JSONElement::Array(elements.iter().rev().cloned().collect())
+            }
+            JSONElement::String(s) => JSONElement::String(s.chars().rev().collect()),
+            JSONElement::Null => JSONElement::Array(vec![]),
+            other => return Err(format!("{} cannot be reversed", describe(other))),
+        }),
+        ("flatten", 0) => one(JSONElement::Array(flatten(input, usize::MAX)?)),
+        ("flatten", 1) => each(&|depth| match as_number(depth) {
+            Some(depth) if depth >= 0.0 => Ok(JSONElement::Array(flatten(input, depth as usize)?)),
+            _ => Err("flatten depth must not be negative".to_string()),
+        }),
+        ("split", 1) => each(&|separator| Ok(split(&string(input)?, &string(separator)?))),
+        ("join", 1) => each(&|separator| {
+            let separator = string(separator)?;
+            let parts = elements(input)?
+                .iter()
+                .map(|element| match element {
+                    JSONElement::Null => Ok(String::new()),
+                    JSONElement::Array(_) | JSONElement::Object(_) => {
+                        Err(format!("Cannot join {}", describe(element)))
+                    }
+                    other => Ok(to_text(other)),
+                })
+                .collect::<Result<Vec<_>, ErrorType>>()?;
+            Ok(JSONElement::String(parts.join(&separator)))
+        }),
+        ("startswith" | "endswith", 1) => each(&|affix| {
+            let (text, affix) = (string(input)?, string(affix)?);
+            Ok(JSONElement::Boolean(match name {
+                "startswith" => text.starts_with(&affix),
+                _ => text.ends_with(&affix),
+            }))
+        }),
+        ("ltrimstr" | "rtrimstr", 1) => each(&|affix| {
+            let (JSONElement::String(text), JSONElement::String(affix)) = (input, affix) else {
+                return Ok(input.clone());
+            };
+            let trimmed = match name {
+                "ltrimstr" => text.strip_prefix(affix.as_str()),
+                _ => text.strip_suffix(affix.as_str()),
+            };
+            Ok(JSONElement::String(trimmed.unwrap_or(text).to_string()))
+        }),
+        ("test", 1) => each(&|pattern| {
+            let regex = Regex::new(&string(pattern)?)?;
+            Ok(JSONElement::Boolean(regex.is_match(&string(input)?)))
+        }),
+        ("range", 1 | 2) => {
+            let bounds = |i: usize| -> Result<Vec<f64>, ErrorType> {
+                argument(i)?
+                    .iter()
+                    .map(|bound| {
+                        as_number(bound)
+                            .ok_or_else(|| format!("range needs numbers, not {}", describe(bound)))
+                    })
+                    .collect()
+            };
+            let (starts, ends) = match arguments.len() {
+                1 => (vec![0.0], bounds(0)?),
+                _ => (bounds(0)?, bounds(1)?),
+            };
+            let mut output = vec![];
+            for start in &starts {
+                for end in &ends {
+                    let mut n = *start;
+                    while n < *end {
+                        output.push(number(n));
+                        n += 1.0;
+                    }
+                }
+            }
+            Ok(output)
+        }
+        ("sort_by" | "group_by" | "unique_by" | "min_by" | "max_by", 1) => {
+            // Elements are ordered by all the outputs of the filter, like jq
+            let mut keyed = elements(input)?
+                .iter()
+                .map(|element| {
+                    Ok((
+                        JSONElement::Array(run(&arguments[0], element, context)?),
+                        element.clone(),
+                    ))
+                })
+                .collect::<Result<Vec<_>, ErrorType>>()?;
+            keyed.sort_by(|(a, _), (b, _)| compare(a, b));
+            let mut groups: Vec<(JSONElement, Vec<JSONElement>)> = vec![];
+            for (key, element) in keyed.iter().cloned() {
+                match groups.last_mut() {
+                    Some((last, group)) if compare(last, &key) == Ordering::Equal => {
+                        group.push(element)
+                    }
+                    _ => groups.push((key, vec![element])),
+                }
+            }
+            one(match name {
+                "sort_by" => {
+                    JSONElement::Array(keyed.into_iter().map(|(_, element)| element).collect())
+                }
+                "group_by" => JSONElement::Array(
+                    groups
+                        .into_iter()
+                        .map(|(_, group)| JSONElement::Array(group))
+                        .collect(),
+                ),
+                "unique_by" => JSONElement::Array(
+                    groups
+                        .into_iter()
+                        .map(|(_, mut group)| group.swap_remove(0))
+                        .collect(),
+                ),
+                "min_by" => keyed
+                    .into_iter()
+                    .next()
+                    .map_or(JSONElement::Null, |(_, element)| element),
+                // The last of equal maximums wins, like jq
+                _ => keyed
+                    .into_iter()
+                    .last()
+                    .map_or(JSONElement::Null, |(_, element)| element),
+            })
+        }
+        ("first", 1) => Ok(argument(0)?.into_iter().take(1).collect()),
+        ("last", 1) => Ok(argument(0)?.pop().into_iter().collect()),
+        ("limit", 2) => {
+            let mut output = vec![];
+            for limit in argument(0)? {
+                let limit = as_number(&limit)
+                    .ok_or_else(|| format!("limit needs a number, not {}", describe(&limit)))?;
+                output.extend(argument(1)?.into_iter().take(limit.max(0.0) as usize));
+            }
+            Ok(output)
+        }
+        _ => Err(format!("{}/{} is not defined", name, arguments.len())),
+    }
+}
+
+fn type_name(value: &JSONElement) -> &'static str {
+    match value {
+        JSONElement::Object(_) => "object",
+        JSONElement::Array(_) => "array",
+        JSONElement::String(_) => "string",
+        JSONElement::Number(_) | JSONElement::Float(_) => "number",
+        JSONElement::Boolean(_) => "boolean",
+        JSONElement::Null => "null",
+    }
+}
+
+// The type and value, for error messages, like `string ("abc")`
+fn describe(value: &JSONElement) -> String {
+    let mut text = dumps(value);
+    if text.len() > 30 {
+        let mut end = 27;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.truncate(end);
+        text.push_str("...");
+    }
+    format!("{} ({})", type_name(value), text)
+}
+
+fn truthy(value: &JSONElement) -> bool {
+    !matches!(value, JSONElement::Null | JSONElement::Boolean(false))
+}
+
+// Strings as they are, and anything else as JSON
+fn to_text(value: &JSONElement) -> String {
+    match value {
+        JSONElement::String(s) => s.clone(),
+        other => dumps(other),
+    }
+}
+
+fn as_number(value: &JSONElement) -> Option<f64> {
+    match value {
+        JSONElement::Number(n) => Some(*n as f64),
+        JSONElement::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+// Whole numbers that fit are kept as integers so they print without a fraction
+fn number(n: f64) -> JSONElement {
+    if n.fract() == 0.0 && n >= i32::MIN as f64 && n <= i32::MAX as f64 {
+        JSONElement::Number(n as i32)
+    } else {
+        JSONElement::Float(n)
+    }
+}
+
+fn to_number(value: &JSONElement) -> Result<JSONElement, ErrorType> {
+    match value {
+        JSONElement::Number(_) | JSONElement::Float(_) => Ok(value.clone()),
+        JSONElement::String(s) => s
+            .trim()
+            .parse::<f64>()
+            .map(number)
+            .map_err(|_| format!("Cannot parse {} as a number", describe(value))),
+        other => Err(format!(
+            "{} cannot be turned into a number",
+            describe(other)
+        )),
+    }
+}
+
+fn elements(value: &JSONElement) -> Result<&Vec<JSONElement>, ErrorType> {
+    match value {
+        JSONElement::Array(elements) => Ok(elements),
+        other => Err(format!("{} is not an array", describe(other))),
+    }
+}
+
+fn index(target: &JSONElement, key: &JSONElement) -> Result<JSONElement, ErrorType> {
+    match (target, key) {
+        (JSONElement::Object(members), JSONElement::String(key)) => {
+            Ok(members.get(key).cloned().unwrap_or(JSONElement::Null))
+        }
+        (JSONElement::Array(elements), key) if as_number(key).is_some() => {
+            let i = as_number(key).unwrap().floor();
+            let i = if i < 0.0 {
+                i + elements.len() as f64
+            } else {
+                i
+            };
+            Ok(match i >= 0.0 {
+                true => elements
+                    .get(i as usize)
+                    .cloned()
+                    .unwrap_or(JSONElement::Null),
+                false => JSONElement::Null,
+            })
+        }
+        (
+            JSONElement::Null,
+            JSONElement::String(_) | JSONElement::Number(_) | JSONElement::Float(_),
+        ) => Ok(JSONElement::Null),
+        (target, key) => Err(format!(
+            "Cannot index {} with {}",
+            describe(target),
+            describe(key)
+        )),
+    }
+}
+
+fn slice(
+    target: &JSONElement,
+    from: &JSONElement,
+    to: &JSONElement,
+) -> Result<JSONElement, ErrorType> {
+    // Negative bounds count from the end, and both are clamped to the length
+    let range = |length: usize| -> Result<(usize, usize), ErrorType> {
+        let bound = |value: &JSONElement, default: usize| match value {
+            JSONElement::Null => Ok(default),
+            value => match as_number(value) {
+                Some(n) => {
+                    let n = if n < 0.0 { n + length as f64 } else { n };
+                    Ok(n.clamp(0.0, length as f64) as usize)
+                }
+                None => Err(format!(
+                    "Slice bounds must be numbers, not {}",
+                    describe(value)
+                )),
+            },
+        };
+        let (start, end) = (bound(from, 0)?, bound(to, length)?);
+        Ok((start, end.max(start)))
+    };
+    match target {
+        JSONElement::Array(elements) => {
+            let (start, end) = range(elements.len())?;
+            Ok(JSONElement::Array(elements[start..end].to_vec()))
+        }
+        JSONElement::String(s) => {
+            let characters: Vec<char> = s.chars().collect();
+            let (start, end) = range(characters.len())?;
+            Ok(JSONElement::String(characters[start..end].iter().collect()))
+        }
+        JSONElement::Null => Ok(JSONElement::Null),
+        other => Err(format!("Cannot slice {}", describe(other))),
+    }
+}
+
+// Object members come out in key order, so the output doesn't depend on hashing
+fn iterate(value: &JSONElement) -> Result<Vec<JSONElement>, ErrorType> {
+    match value {
+        JSONElement::Array(elements) => Ok(elements.clone()),
+        JSONElement::Object(members) => {
+            let mut entries: Vec<_> = members.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            Ok(entries
+                .into_iter()
+                .map(|(_, value)| value.clone())
+                .collect())
+        }
+        other => Err(format!("Cannot iterate over {}", describe(other))),
+    }
+}
+
+fn length(value: &JSONElement) -> Result<JSONElement, ErrorType> {
+    let count = |n: usize| JSONElement::Number(n as i32);
+    match value {
+        JSONElement::Object(members) => Ok(count(members.len())),
+        JSONElement::Array(elements) => Ok(count(elements.len())),
+        JSONElement::String(s) => Ok(count(s.chars().count())),
+        JSONElement::Number(_) | JSONElement::Float(_) => {
+            Ok(number(as_number(value).unwrap().abs()))
+        }
+        JSONElement::Null => Ok(count(0)),
+        JSONElement::Boolean(_) => Err(format!("{} has no length", describe(value))),
+    }
+}
+
+fn keys(value: &JSONElement) -> Result<Vec<JSONElement>, ErrorType> {
+    match value {
+        JSONElement::Object(members) => {
+            let mut keys: Vec<&String> = members.keys().collect();
+            keys.sort();
+            Ok(keys
+                .into_iter()
+                .map(|key| JSONElement::String(key.clone()))
+                .collect())
+        }
+        JSONElement::Array(elements) => Ok((0..elements.len())
+            .map(|i| JSONElement::Number(i as i32))
+            .collect()),
+        other => Err(format!("{} has no keys", describe(other))),
+    }
+}
+
+fn has(value: &JSONElement, key: &JSONElement) -> Result<bool, ErrorType> {
+    match (value, key) {
+        (JSONElement::Object(members), JSONElement::String(key)) => Ok(members.contains_key(key)),
+        (JSONElement::Array(elements), key) if as_number(key).is_some() => {
+            let i = as_number(key).unwrap();
+            Ok(i >= 0.0 && i < elements.len() as f64)
+        }
+        _ => Err(format!(
+            "Cannot check whether {} has a key {}",
+            describe(value),
+            describe(key)
+        )),
+    }
+}
+
+// Whether `b` is inside `a`: substrings, array elements contained in any element of the other
+// array, and object members contained in the other object's member with the same key
+fn contains(a: &JSONElement, b: &JSONElement) -> bool {
+    match (a, b) {
+        (JSONElement::String(a), JSONElement::String(b)) => a.contains(b.as_str()),
+        (JSONElement::Array(a), JSONElement::Array(b)) => {
+            b.iter().all(|b| a.iter().any(|a| contains(a, b)))
+        }
+        (JSONElement::Object(a), JSONElement::Object(b)) => b
+            .iter()
+            .all(|(key, b)| a.get(key).is_some_and(|a| contains(a, b))),
+        _ => compare(a, b) == Ordering::Equal,
+    }
+}
+
+fn to_entries(value: &JSONElement) -> Result<JSONElement, ErrorType> {
+    let JSONElement::Object(members) = value else {
+        return Err(format!("{} has no entries", describe(value)));
+    };
+    let mut entries: Vec<_> = members.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    Ok(JSONElement::Array(
+        entries
+            .into_iter()
+            .map(|(key, value)| {
+                JSONElement::Object(HashMap::from([
+                    ("key".to_string(), JSONElement::String(key.clone())),
+                    ("value".to_string(), value.clone()),
+                ]))
+            })
+            .collect(),
+    ))
+}
+
+// Accepts the same spellings of "key" and "value" as jq
+fn from_entries(value: &JSONElement) -> Result<JSONElement, ErrorType> {
+    let mut object = HashMap::new();
+    for entry in elements(value)? {
+        let JSONElement::Object(members) = entry else {
+            return Err(format!("{} is not an entry", describe(entry)));
+        };
+        let find = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                members
+                    .get(*name)
+                    .filter(|value| truthy(value) || **name == *"value")
+            })
+        };
+        let key = match find(&["key", "k", "name", "Name", "Key", "K"]) {
+            Some(JSONElement::String(key)) => key.clone(),
+            Some(
+                key @ (JSONElement::Number(_) | JSONElement::Float(_) | JSONElement::Boolean(_)),
+            ) => dumps(key),
+            _ => return Err(format!("{} has no usable key", describe(entry))),
+        };
+        let value = find(&["value", "v", "Value", "V"])
+            .cloned()
+            .unwrap_or(JSONElement::Null);
+        object.insert(key, value);
+    }
+    Ok(JSONElement::Object(object))
+}
+
+fn flatten(value: &JSONElement, depth: usize) -> Result<Vec<JSONElement>, ErrorType> {
+    let mut output = vec![];
+    for element in elements(value)? {
+        match element {
+            JSONElement::Array(_) if depth > 0 => output.extend(flatten(element, depth - 1)?),
+            other => output.push(other.clone()),
+        }
+    }
+    Ok(output)
+}
+
+// Where a type comes in jq's sort order
+fn rank(value: &JSONElement) -> u8 {
+    match value {
+        JSONElement::Null => 0,
+        JSONElement::Boolean(false) => 1,
+        JSONElement::Boolean(true) => 2,
+        JSONElement::Number(_) | JSONElement::Float(_) => 3,
+        JSONElement::String(_) => 4,
+        JSONElement::Array(_) => 5,
+        JSONElement::Object(_) => 6,
+    }
+}
+
+/// Orders any two values the way jq does: null, false, true, numbers, strings, arrays and then
+/// objects, with objects compared by their sorted keys before their values
+pub fn compare(a: &JSONElement, b: &JSONElement) -> Ordering {
+    match (a, b) {
+        (JSONElement::String(a), JSONElement::String(b)) => a.cmp(b),
+        (JSONElement::Array(a), JSONElement::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or(a.len().cmp(&b.len())),
+        (JSONElement::Object(a), JSONElement::Object(b)) => {
+            let mut a_keys: Vec<&String> = a.keys().collect();
+            let mut b_keys: Vec<&String> = b.keys().collect();
+            a_keys.sort();
+            b_keys.sort();
+            a_keys.cmp(&b_keys).then_with(|| {
+                a_keys
+                    .iter()
+                    .map(|key| compare(&a[*key], &b[*key]))
+                    .find(|ordering| ordering.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            })
+        }
+        _ => match (as_number(a), as_number(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            _ => rank(a).cmp(&rank(b)),
+        },
+    }
+}
+
+fn binary(
+    operator: Operator,
+    left: &JSONElement,
+    right: &JSONElement,
+) -> Result<JSONElement, ErrorType> {
+    let ordering = || compare(left, right);
+    let failure = |verb: &str| {
+        Err(format!(
+            "{} and {} cannot be {}",
+            describe(left),
+            describe(right),
+            verb
+        ))
+    };
+    let numbers = as_number(left).zip(as_number(right));
+    match operator {
+        Operator::Equal => Ok(JSONElement::Boolean(ordering().is_eq())),
+        Operator::NotEqual => Ok(JSONElement::Boolean(ordering().is_ne())),
+        Operator::Less => Ok(JSONElement::Boolean(ordering().is_lt())),
+        Operator::LessOrEqual => Ok(JSONElement::Boolean(ordering().is_le())),
+        Operator::Greater => Ok(JSONElement::Boolean(ordering().is_gt())),
+        Operator::GreaterOrEqual => Ok(JSONElement::Boolean(ordering().is_ge())),
+        Operator::Add => match (left, right) {
+            (JSONElement::Null, other) | (other, JSONElement::Null) => Ok(other.clone()),
+            (JSONElement::String(a), JSONElement::String(b)) => {
+                Ok(JSONElement::String(a.clone() + b))
+            }
+            (JSONElement::Array(a), JSONElement::Array(b)) => {
+                Ok(JSONElement::Array(a.iter().chain(b).cloned().collect()))
+            }
+            (JSONElement::Object(a), JSONElement::Object(b)) => {
+                let mut merged = a.clone();
+                merged.extend(b.iter().map(|(key, value)| (key.clone(), value.clone())));
+                Ok(JSONElement::Object(merged))
+            }
+            _ => match numbers {
+                Some((a, b)) => Ok(number(a + b)),
+                None => failure("added"),
+            },
+        },
+        Operator::Subtract => match (left, right) {
+            (JSONElement::Array(a), JSONElement::Array(b)) => Ok(JSONElement::Array(
+                a.iter()
+                    .filter(|a| !b.iter().any(|b| compare(a, b).is_eq()))
+                    .cloned()
+                    .collect(),
+            )),
+            _ => match numbers {
+                Some((a, b)) => Ok(number(a - b)),
+                None => failure("subtracted"),
+            },
+        },
+        Operator::Multiply => match (left, right) {
+            (JSONElement::Object(_), JSONElement::Object(_)) => Ok(deep_merge(left, right)),
+            (JSONElement::String(s), n) | (n, JSONElement::String(s)) if as_number(n).is_some() => {
+                let times = as_number(n).unwrap();
+                Ok(match times > 0.0 {
+                    true => JSONElement::String(s.repeat(times.ceil() as usize)),
+                    false => JSONElement::Null,
+                })
+            }
+            _ => match numbers {
+                Some((a, b)) => Ok(number(a * b)),
+                None => failure("multiplied"),
+            },
+        },
+        Operator::Divide => match (left, right) {
+            (JSONElement::String(text), JSONElement::String(separator)) => {
+                Ok(split(text, separator))
+            }
+            _ => match numbers {
+                Some((_, 0.0)) => failure("divided because the divisor is zero"),
+                Some((a, b)) => Ok(number(a / b)),
+                None => failure("divided"),
+            },
+        },
+        Operator::Remainder => match numbers {
+            Some((a, b)) => match (a as i64).checked_rem(b as i64) {
+                Some(remainder) => Ok(number(remainder as f64)),
+                None => failure("divided because the divisor is zero"),
+            },
+            None => failure("divided"),
+        },
+    }
+}
+
+// A string divided by another is split on it too
+fn split(text: &str, separator: &str) -> JSONElement {
+    let string = |part: &str| JSONElement::String(part.to_string());
+    JSONElement::Array(match separator {
+        "" => text.chars().map(|c| string(&c.to_string())).collect(),
+        _ => text.split(separator).map(string).collect(),
+    })
+}
+
+// Objects multiplied together are merged recursively, with the right side winning
+fn deep_merge(left: &JSONElement, right: &JSONElement) -> JSONElement {
+    match (left, right) {
+        (JSONElement::Object(a), JSONElement::Object(b)) => {
+            let mut merged = a.clone();
+            for (key, value) in b {
+                let value = match merged.get(key) {
+                    Some(existing) => deep_merge(existing, value),
+                    None => value.clone(),
+                };
+                merged.insert(key.clone(), value);
+            }
+            JSONElement::Object(merged)
+        }
+        _ => right.clone(),
+    }
+}
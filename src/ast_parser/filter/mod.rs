@@ -0,0 +1,696 @@
+use crate::ast_parser::filter::eval::{
+    run_filter, Definition, Filter, Operator, Parameter, StringPart,
+};
+use crate::ast_parser::math::IdentifierParser;
+use crate::ast_parser::*;
+use crate::rustifier::JSONElement;
+use std::rc::Rc;
+
+pub mod eval;
+mod tests;
+
+// Words that can't be used as function names
+const KEYWORDS: [&str; 12] = [
+    "and", "or", "if", "then", "elif", "else", "end", "as", "def", "reduce", "try", "catch",
+];
+
+/// Parses a jq-style filter like `.items[] | select(.status == "failed") | {id, reason}`
+pub fn parse_filter(text: &str) -> Result<Filter, ErrorType> {
+    let (rest, filter) = PipeFilterParser().parse(skip_whitespace(text))?;
+    let rest = skip_whitespace(rest);
+    if !rest.is_empty() {
+        return Err(format!("Unexpected \"{}\" in the filter", prefix(rest, 20)));
+    }
+    Ok(filter)
+}
+
+/// Parses `filter` and runs it on `input`, giving back everything it outputs
+pub fn query(filter: &str, input: &JSONElement) -> Result<Vec<JSONElement>, ErrorType> {
+    run_filter(&parse_filter(filter)?, input)
+}
+
+type FilterResult<'i> = Result<(&'i str, Filter), ErrorType>;
+
+// Like Parser, but for the parsers in this module, which give back the `Filter` they read rather
+// than an ASTNode. The names and keywords in a filter still use Parser.
+trait FilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i>;
+}
+
+fn skip_whitespace(input: &str) -> &str {
+    WhitespaceParser()
+        .parse(input)
+        .map_or(input, |(rest, _)| rest)
+}
+
+fn literal<'i>(input: &'i str, symbol: &'static str) -> Result<&'i str, ErrorType> {
+    Ok(LiteralParser(symbol).parse(input)?.0)
+}
+
+fn keyword<'i>(input: &'i str, word: &'static str) -> Result<&'i str, ErrorType> {
+    Ok(KeywordParser(word).parse(input)?.0)
+}
+
+// A filter if there is one, or the input as it was
+fn parse_optional<'i>(input: &'i str, parser: &dyn FilterParser) -> (&'i str, Option<Filter>) {
+    match parser.parse(input) {
+        Ok((rest, filter)) => (rest, Some(filter)),
+        Err(_) => (input, None),
+    }
+}
+
+// The first of `parsers` that can read the input, like ChoiceParser
+fn parse_choice<'i>(input: &'i str, parsers: &[&dyn FilterParser]) -> FilterResult<'i> {
+    parsers
+        .iter()
+        .find_map(|parser| parser.parse(input).ok())
+        .ok_or_else(|| format!("Unexpected {}", found(input)))
+}
+
+fn expect_name(node: Option<ASTNode>) -> String {
+    match node {
+        Some(ASTNode::String(name)) => name.to_string(),
+        _ => panic!("Name parser did not return a string"),
+    }
+}
+
+// A whole word, so "or" doesn't match the start of "order"
+struct KeywordParser(&'static str);
+impl Parser for KeywordParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        match IdentifierParser().parse(input) {
+            Ok((rest, Some(ASTNode::String(word)))) if word == self.0 => Ok((rest, None)),
+            _ => Err(format!(
+                "{} did not start with {}",
+                prefix(input, 10),
+                self.0
+            )),
+        }
+    }
+}
+
+struct NameParser();
+impl Parser for NameParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, node) = IdentifierParser().parse(input)?;
+        match node {
+            Some(ASTNode::String(name)) if KEYWORDS.contains(&name) => {
+                Err(format!("{} is a keyword, not a name", name))
+            }
+            node => Ok((rest, node)),
+        }
+    }
+}
+
+// $name, giving back the name without the $
+struct VariableParser();
+impl Parser for VariableParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, _) = LiteralParser("$").parse(input)?;
+        IdentifierParser().parse(rest)
+    }
+}
+
+// A function parameter, giving back its name with the $ if it has one
+struct ParameterParser();
+impl Parser for ParameterParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, _) = OptionParser(LiteralParser("$")).parse(input)?;
+        let (rest, _) = NameParser().parse(rest)?;
+        Ok((
+            rest,
+            Some(ASTNode::String(&input[..input.len() - rest.len()])),
+        ))
+    }
+}
+
+// The operator, and whitespace either side of it
+fn operator<'i>(input: &'i str, symbol: &'static str) -> Option<&'i str> {
+    let (rest, _) = WhitespaceParser().parse(input).ok()?;
+    let (rest, _) = match symbol.starts_with(char::is_alphabetic) {
+        true => KeywordParser(symbol).parse(rest),
+        false => LiteralParser(symbol).parse(rest),
+    }
+    .ok()?;
+    Some(WhitespaceParser().parse(rest).ok()?.0)
+}
+
+// An operator and how it joins its two operands
+type Combination = (&'static str, fn(Box<Filter>, Box<Filter>) -> Filter);
+
+fn parse_infix<'i>(
+    input: &'i str,
+    combinations: &[Combination],
+    operand: &dyn FilterParser,
+) -> FilterResult<'i> {
+    let (mut rest, mut filter) = operand.parse(input)?;
+    'operators: loop {
+        for (symbol, combine) in combinations {
+            let Some(after_operator) = operator(rest, symbol) else {
+                continue;
+            };
+            if let Ok((after, right)) = operand.parse(after_operator) {
+                // Everything here is left-associative, so the filter so far is the left operand
+                filter = combine(Box::new(filter), Box::new(right));
+                rest = after;
+                continue 'operators;
+            }
+        }
+        break;
+    }
+    Ok((rest, filter))
+}
+
+// `operand symbol itself`, where `itself` is the parser this is called from, so the operator is
+// right-associative
+fn parse_right_infix<'i>(
+    input: &'i str,
+    (symbol, combine): Combination,
+    operand: &dyn FilterParser,
+    itself: &dyn FilterParser,
+) -> FilterResult<'i> {
+    let (rest, left) = operand.parse(input)?;
+    if let Some(after_operator) = operator(rest, symbol) {
+        if let Ok((after, right)) = itself.parse(after_operator) {
+            return Ok((after, combine(Box::new(left), Box::new(right))));
+        }
+    }
+    Ok((rest, left))
+}
+
+struct NumberFilterParser();
+impl FilterParser for NumberFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        // Like LiteralExpressionParser, but a leading "-" is always negation here
+        let after_integer = skip_digits(input)
+            .ok_or_else(|| format!("{} did not start with a number", prefix(input, 10)))?;
+        let after_fraction = after_integer
+            .strip_prefix('.')
+            .and_then(skip_digits)
+            .unwrap_or(after_integer);
+        let after_exponent = after_fraction
+            .strip_prefix(['e', 'E'])
+            .map(|rest| rest.strip_prefix(['+', '-']).unwrap_or(rest))
+            .and_then(skip_digits)
+            .unwrap_or(after_fraction);
+        let text = &input[..input.len() - after_exponent.len()];
+        let number = match text.parse::<NumberType>() {
+            Ok(n) => JSONElement::Number(n),
+            Err(_) => JSONElement::Float(text.parse::<f64>().map_err(|err| format!("{}", err))?),
+        };
+        Ok((after_exponent, Filter::Literal(number)))
+    }
+}
+
+// A string literal, where \(...) splices in the outputs of a filter
+struct StringFilterParser();
+impl FilterParser for StringFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let mut rest = literal(input, "\"")?;
+        let mut parts = vec![];
+        let mut text = String::new();
+        loop {
+            let mut characters = rest.char_indices();
+            let end = loop {
+                match characters.next() {
+                    Some((i, '"')) => break i,
+                    Some((i, '\\')) if rest[i + 1..].starts_with('(') => break i,
+                    Some((_, '\\')) => {
+                        characters.next();
+                    }
+                    Some(_) => {}
+                    None => return Err(format!("string {} was never closed", prefix(input, 10))),
+                }
+            };
+            text.push_str(&unescape(&rest[..end])?);
+            if let Some(after) = rest[end..].strip_prefix('"') {
+                rest = after;
+                break;
+            }
+            let (after, filter) = PipeFilterParser().parse(skip_whitespace(&rest[end + 2..]))?;
+            if !text.is_empty() {
+                parts.push(StringPart::Text(std::mem::take(&mut text)));
+            }
+            parts.push(StringPart::Filter(filter));
+            rest = literal(skip_whitespace(after), ")")?;
+        }
+        let filter = if parts.is_empty() {
+            Filter::Literal(JSONElement::String(text))
+        } else {
+            if !text.is_empty() {
+                parts.push(StringPart::Text(text));
+            }
+            Filter::Interpolate(parts)
+        };
+        Ok((rest, filter))
+    }
+}
+
+// The key after a ".", either a name or a string
+fn field_key(input: &str) -> Option<(&str, Filter)> {
+    if let Ok((rest, node)) = IdentifierParser().parse(input) {
+        let name = expect_name(node);
+        return Some((rest, Filter::Literal(JSONElement::String(name))));
+    }
+    StringFilterParser().parse(input).ok()
+}
+
+// ., .., .name and ."name"
+struct DotFilterParser();
+impl FilterParser for DotFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let rest = literal(input, ".")?;
+        if let Some(rest) = rest.strip_prefix('.') {
+            return Ok((rest, Filter::Recurse));
+        }
+        Ok(match field_key(rest) {
+            Some((rest, key)) => (
+                rest,
+                Filter::Index(Box::new(Filter::Identity), Box::new(key)),
+            ),
+            None => (rest, Filter::Identity),
+        })
+    }
+}
+
+struct VariableFilterParser();
+impl FilterParser for VariableFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let (rest, node) = VariableParser().parse(input)?;
+        Ok((rest, Filter::Variable(expect_name(node))))
+    }
+}
+
+struct ArrayFilterParser();
+impl FilterParser for ArrayFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let rest = skip_whitespace(literal(input, "[")?);
+        let (rest, elements) = parse_optional(rest, &PipeFilterParser());
+        let rest = literal(skip_whitespace(rest), "]")?;
+        Ok((rest, Filter::Array(elements.map(Box::new))))
+    }
+}
+
+// A bare name as an object key, which is allowed to be a keyword
+struct KeyNameFilterParser();
+impl FilterParser for KeyNameFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let (rest, node) = IdentifierParser().parse(input)?;
+        Ok((
+            rest,
+            Filter::Literal(JSONElement::String(expect_name(node))),
+        ))
+    }
+}
+
+// An object member, where leaving out the value of "key" means "key: .key", and "$name" means
+// "name: $name"
+fn parse_object_entry(input: &str) -> Result<(&str, (Filter, Filter)), ErrorType> {
+    if let Ok((rest, node)) = VariableParser().parse(input) {
+        let name = expect_name(node);
+        let key = Filter::Literal(JSONElement::String(name.clone()));
+        return Ok((rest, (key, Filter::Variable(name))));
+    }
+    let (rest, key) = parse_choice(
+        input,
+        &[
+            &KeyNameFilterParser(),
+            &StringFilterParser(),
+            &GroupFilterParser(),
+        ],
+    )?;
+    let value = literal(skip_whitespace(rest), ":")
+        .and_then(|after| ObjectValueFilterParser().parse(skip_whitespace(after)));
+    match value {
+        Ok((rest, value)) => Ok((rest, (key, value))),
+        Err(_) => {
+            let value = Filter::Index(Box::new(Filter::Identity), Box::new(key.clone()));
+            Ok((rest, (key, value)))
+        }
+    }
+}
+
+// Object values can't have a "," outside of brackets, since that ends the member
+struct ObjectValueFilterParser();
+impl FilterParser for ObjectValueFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        parse_right_infix(
+            input,
+            ("|", Filter::Pipe),
+            &AlternativeFilterParser(),
+            &ObjectValueFilterParser(),
+        )
+    }
+}
+
+struct ObjectFilterParser();
+impl FilterParser for ObjectFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let (rest, entries) = parse_items(
+            input,
+            ("{", ",", "}"),
+            &parse_object_entry,
+            &WhitespaceParser(),
+        )?;
+        Ok((rest, Filter::Object(entries)))
+    }
+}
+
+struct GroupFilterParser();
+impl FilterParser for GroupFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let rest = skip_whitespace(literal(input, "(")?);
+        let (rest, filter) = PipeFilterParser().parse(rest)?;
+        Ok((literal(skip_whitespace(rest), ")")?, filter))
+    }
+}
+
+// if ... then ... (elif ... then ...)* (else ...)? end, where a missing else passes the input
+// through
+struct IfFilterParser();
+impl FilterParser for IfFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let branch = |word: &'static str, input: &'i str| {
+            let rest = skip_whitespace(keyword(skip_whitespace(input), word)?);
+            let (rest, condition) = PipeFilterParser().parse(rest)?;
+            let rest = skip_whitespace(keyword(skip_whitespace(rest), "then")?);
+            let (rest, then) = PipeFilterParser().parse(rest)?;
+            Ok::<_, ErrorType>((rest, (condition, then)))
+        };
+        let (mut rest, first) = branch("if", input)?;
+        let mut branches = vec![first];
+        while let Ok((after, branch)) = branch("elif", rest) {
+            branches.push(branch);
+            rest = after;
+        }
+        let mut otherwise = Filter::Identity;
+        if let Ok((after, filter)) = keyword(skip_whitespace(rest), "else")
+            .and_then(|after| PipeFilterParser().parse(skip_whitespace(after)))
+        {
+            otherwise = filter;
+            rest = after;
+        }
+        let rest = keyword(skip_whitespace(rest), "end")?;
+        let filter = branches
+            .into_iter()
+            .rev()
+            .fold(otherwise, |otherwise, (condition, then)| {
+                Filter::If(Box::new(condition), Box::new(then), Box::new(otherwise))
+            });
+        Ok((rest, filter))
+    }
+}
+
+struct ReduceFilterParser();
+impl FilterParser for ReduceFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let rest = skip_whitespace(keyword(input, "reduce")?);
+        let (rest, source) = PostfixFilterParser().parse(rest)?;
+        let rest = skip_whitespace(keyword(skip_whitespace(rest), "as")?);
+        let (rest, node) = VariableParser().parse(rest)?;
+        let rest = skip_whitespace(literal(skip_whitespace(rest), "(")?);
+        let (rest, init) = PipeFilterParser().parse(rest)?;
+        let rest = skip_whitespace(literal(skip_whitespace(rest), ";")?);
+        let (rest, update) = PipeFilterParser().parse(rest)?;
+        let rest = literal(skip_whitespace(rest), ")")?;
+        Ok((
+            rest,
+            Filter::Reduce {
+                source: Box::new(source),
+                variable: expect_name(node),
+                init: Box::new(init),
+                update: Box::new(update),
+            },
+        ))
+    }
+}
+
+struct TryFilterParser();
+impl FilterParser for TryFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let rest = skip_whitespace(keyword(input, "try")?);
+        let (rest, body) = PostfixFilterParser().parse(rest)?;
+        let rest = skip_whitespace(rest);
+        let (rest, handler) = match keyword(rest, "catch")
+            .and_then(|after| PostfixFilterParser().parse(skip_whitespace(after)))
+        {
+            Ok((after, handler)) => (after, Some(Box::new(handler))),
+            Err(_) => (rest, None),
+        };
+        Ok((rest, Filter::Try(Box::new(body), handler)))
+    }
+}
+
+// A function call, with its arguments separated by ";". This is also where true, false and null
+// get picked up.
+struct CallFilterParser();
+impl FilterParser for CallFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let (rest, node) = NameParser().parse(input)?;
+        let name = expect_name(node);
+        let literal = match name.as_str() {
+            "true" => Some(JSONElement::Boolean(true)),
+            "false" => Some(JSONElement::Boolean(false)),
+            "null" => Some(JSONElement::Null),
+            _ => None,
+        };
+        if let Some(literal) = literal {
+            return Ok((rest, Filter::Literal(literal)));
+        }
+        if !rest.starts_with('(') {
+            return Ok((rest, Filter::Call(name, vec![])));
+        }
+        let (rest, arguments) = parse_items(
+            rest,
+            ("(", ";", ")"),
+            &|input| PipeFilterParser().parse(input),
+            &WhitespaceParser(),
+        )?;
+        Ok((rest, Filter::Call(name, arguments)))
+    }
+}
+
+struct PrimaryFilterParser();
+impl FilterParser for PrimaryFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        parse_choice(
+            input,
+            &[
+                &NumberFilterParser(),
+                &StringFilterParser(),
+                &DotFilterParser(),
+                &VariableFilterParser(),
+                &ArrayFilterParser(),
+                &ObjectFilterParser(),
+                &GroupFilterParser(),
+                &IfFilterParser(),
+                &ReduceFilterParser(),
+                &TryFilterParser(),
+                &CallFilterParser(),
+            ],
+        )
+    }
+}
+
+// [...] after a filter, which is an iteration, an index or a slice
+fn parse_brackets(input: &str, target: Filter) -> FilterResult<'_> {
+    let rest = skip_whitespace(literal(input, "[")?);
+    if let Some(rest) = rest.strip_prefix(']') {
+        return Ok((rest, Filter::Iterate(Box::new(target))));
+    }
+    let (rest, from) = parse_optional(rest, &PipeFilterParser());
+    let from = from.map(Box::new);
+    let rest = skip_whitespace(rest);
+    if let Some(rest) = rest.strip_prefix(':') {
+        let (rest, to) = parse_optional(skip_whitespace(rest), &PipeFilterParser());
+        let rest = literal(skip_whitespace(rest), "]")?;
+        return Ok((
+            rest,
+            Filter::Slice(Box::new(target), from, to.map(Box::new)),
+        ));
+    }
+    let rest = literal(rest, "]")?;
+    let key =
+        from.ok_or_else(|| format!("{} has brackets with nothing in them", prefix(input, 10)))?;
+    Ok((rest, Filter::Index(Box::new(target), key)))
+}
+
+// A primary filter followed by any number of .name, [...] and ?, and maybe "as $name | ..."
+struct PostfixFilterParser();
+impl FilterParser for PostfixFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let (mut rest, mut filter) = PrimaryFilterParser().parse(input)?;
+        loop {
+            if let Some(after) = rest.strip_prefix('?') {
+                filter = Filter::Try(Box::new(filter), None);
+                rest = after;
+            } else if let Some((after, key)) = rest.strip_prefix('.').and_then(field_key) {
+                filter = Filter::Index(Box::new(filter), Box::new(key));
+                rest = after;
+            } else if rest.starts_with('[') || rest.starts_with(".[") {
+                (rest, filter) = parse_brackets(rest.trim_start_matches('.'), filter)?;
+            } else {
+                break;
+            }
+        }
+        // The binding is handled here rather than with the pipes so the filter before "as"
+        // doesn't have to be parsed twice
+        let binding = |input: &'i str| {
+            let rest = skip_whitespace(keyword(skip_whitespace(input), "as")?);
+            let (rest, node) = VariableParser().parse(rest)?;
+            let rest = skip_whitespace(literal(skip_whitespace(rest), "|")?);
+            let (rest, body) = PipeFilterParser().parse(rest)?;
+            Ok::<_, ErrorType>((rest, expect_name(node), body))
+        };
+        match binding(rest) {
+            Ok((rest, variable, body)) => Ok((
+                rest,
+                Filter::Bind {
+                    source: Box::new(filter),
+                    variable,
+                    body: Box::new(body),
+                },
+            )),
+            Err(_) => Ok((rest, filter)),
+        }
+    }
+}
+
+struct NegationFilterParser();
+impl FilterParser for NegationFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let Ok(rest) = literal(input, "-") else {
+            return PostfixFilterParser().parse(input);
+        };
+        let (rest, filter) = PostfixFilterParser().parse(skip_whitespace(rest))?;
+        let filter = match filter {
+            Filter::Literal(JSONElement::Number(n)) => Filter::Literal(JSONElement::Number(-n)),
+            Filter::Literal(JSONElement::Float(f)) => Filter::Literal(JSONElement::Float(-f)),
+            filter => Filter::Negate(Box::new(filter)),
+        };
+        Ok((rest, filter))
+    }
+}
+
+struct ProductFilterParser();
+impl FilterParser for ProductFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        parse_infix(
+            input,
+            &[
+                ("*", |l, r| Filter::Binary(Operator::Multiply, l, r)),
+                ("/", |l, r| Filter::Binary(Operator::Divide, l, r)),
+                ("%", |l, r| Filter::Binary(Operator::Remainder, l, r)),
+            ],
+            &NegationFilterParser(),
+        )
+    }
+}
+
+struct SumFilterParser();
+impl FilterParser for SumFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        parse_infix(
+            input,
+            &[
+                ("+", |l, r| Filter::Binary(Operator::Add, l, r)),
+                ("-", |l, r| Filter::Binary(Operator::Subtract, l, r)),
+            ],
+            &ProductFilterParser(),
+        )
+    }
+}
+
+struct ComparisonFilterParser();
+impl FilterParser for ComparisonFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        // The two-character operators have to come first or "<" would eat the start of "<="
+        parse_infix(
+            input,
+            &[
+                ("==", |l, r| Filter::Binary(Operator::Equal, l, r)),
+                ("!=", |l, r| Filter::Binary(Operator::NotEqual, l, r)),
+                ("<=", |l, r| Filter::Binary(Operator::LessOrEqual, l, r)),
+                (">=", |l, r| Filter::Binary(Operator::GreaterOrEqual, l, r)),
+                ("<", |l, r| Filter::Binary(Operator::Less, l, r)),
+                (">", |l, r| Filter::Binary(Operator::Greater, l, r)),
+            ],
+            &SumFilterParser(),
+        )
+    }
+}
+
+struct AndFilterParser();
+impl FilterParser for AndFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        parse_infix(input, &[("and", Filter::And)], &ComparisonFilterParser())
+    }
+}
+
+struct OrFilterParser();
+impl FilterParser for OrFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        parse_infix(input, &[("or", Filter::Or)], &AndFilterParser())
+    }
+}
+
+struct AlternativeFilterParser();
+impl FilterParser for AlternativeFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        parse_right_infix(
+            input,
+            ("//", Filter::Alternative),
+            &OrFilterParser(),
+            &AlternativeFilterParser(),
+        )
+    }
+}
+
+struct CommaFilterParser();
+impl FilterParser for CommaFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        parse_infix(input, &[(",", Filter::Comma)], &AlternativeFilterParser())
+    }
+}
+
+// The loosest level, where pipes and function definitions go
+struct PipeFilterParser();
+impl FilterParser for PipeFilterParser {
+    fn parse<'i>(&self, input: &'i str) -> FilterResult<'i> {
+        let Ok(after_def) = keyword(input, "def") else {
+            return parse_right_infix(
+                input,
+                ("|", Filter::Pipe),
+                &CommaFilterParser(),
+                &PipeFilterParser(),
+            );
+        };
+        let (rest, name) = NameParser().parse(skip_whitespace(after_def))?;
+        let (rest, parameters) =
+            OptionParser(DelimitedSequenceParser(";", ParameterParser(), "(", ")"))
+                .parse(skip_whitespace(rest))?;
+        let parameters = match parameters {
+            Some(ASTNode::Sequence(parameters)) => parameters
+                .into_iter()
+                .map(|parameter| {
+                    let name = expect_name(Some(parameter));
+                    match name.strip_prefix('$') {
+                        Some(name) => Parameter::Value(name.to_string()),
+                        None => Parameter::Filter(name),
+                    }
+                })
+                .collect(),
+            _ => vec![],
+        };
+        let rest = skip_whitespace(literal(skip_whitespace(rest), ":")?);
+        let (rest, body) = PipeFilterParser().parse(rest)?;
+        let rest = skip_whitespace(literal(skip_whitespace(rest), ";")?);
+        let (rest, after) = PipeFilterParser().parse(rest)?;
+        let definition = Definition {
+            name: expect_name(name),
+            parameters,
+            body,
+        };
+        Ok((rest, Filter::Define(Rc::new(definition), Box::new(after))))
+    }
+}
@@ -0,0 +1,280 @@
+#[cfg(test)]
+use crate::ast_parser::filter::eval::*;
+#[cfg(test)]
+use crate::ast_parser::filter::*;
+#[cfg(test)]
+use crate::rustifier::{dumps, loads_json5};
+
+// Each output of the filter, compactly serialized and separated by spaces
+#[cfg(test)]
+fn outputs(filter: &str, input: &str) -> String {
+    query(filter, &loads_json5(input).unwrap())
+        .unwrap_or_else(|err| panic!("{} failed: {}", filter, err))
+        .iter()
+        .map(dumps)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[test]
+fn selects_paths() {
+    let input = r#"{"a": {"b": [1, 2, 3]}, "c d": "x"}"#;
+    assert_eq!(outputs(".", "1"), "1");
+    assert_eq!(outputs(".a.b[1]", input), "2");
+    assert_eq!(outputs(r#".a["b"][-1]"#, input), "3");
+    assert_eq!(outputs(r#"."c d""#, input), "\"x\"");
+    assert_eq!(outputs(".a.b[]", input), "1 2 3");
+    assert_eq!(outputs(".a.b[1:]", input), "[2,3]");
+    assert_eq!(outputs(".a.b[:-1]", input), "[1,2]");
+    assert_eq!(outputs(".missing.deeper", input), "null");
+    assert_eq!(outputs("..|numbers", input), "1 2 3");
+    assert_eq!(outputs(".[]?", "1"), "");
+    assert_eq!(
+        query(".a.b.c", &loads_json5(input).unwrap()).unwrap_err(),
+        "Cannot index array ([1,2,3]) with string (\"c\")"
+    );
+}
+
+#[test]
+fn runs_the_example_from_the_request() {
+    let input = r#"{"items": [
+        {"id": 1, "status": "ok"},
+        {"id": 2, "status": "failed", "reason": "timeout", "extra": true},
+        {"id": 3, "status": "failed", "reason": "disk full"}
+    ]}"#;
+    assert_eq!(
+        outputs(
+            r#".items[] | select(.status == "failed") | {id, reason}"#,
+            input
+        ),
+        r#"{"id":2,"reason":"timeout"} {"id":3,"reason":"disk full"}"#
+    );
+    assert_eq!(
+        outputs(
+            r#"[.items[] | .status] | group_by(.) | map({(.[0]): length}) | add"#,
+            input
+        ),
+        r#"{"failed":2,"ok":1}"#
+    );
+}
+
+#[test]
+fn evaluates_operators() {
+    assert_eq!(
+        outputs("1 + 2 * 3, (1 + 2) * 3, 7 % 3, 1 / 4", "null"),
+        "7 9 1 0.25"
+    );
+    assert_eq!(outputs(".a - 1, -.a", "{a: 5}"), "4 -5");
+    assert_eq!(
+        outputs(r#""a" + "b", [1, 2] - [2], {a: 1} + {b: 2}"#, "null"),
+        r#""ab" [1] {"a":1,"b":2}"#
+    );
+    assert_eq!(
+        outputs(r#"{a: {b: 1}} * {a: {c: 2}}"#, "null"),
+        r#"{"a":{"b":1,"c":2}}"#
+    );
+    assert_eq!(
+        outputs("1 < 2, 1 == 1.0, \"a\" > 1, null < false", "null"),
+        "true true true true"
+    );
+    assert_eq!(
+        outputs("true and null, false or 1, (1, null) // 2", "null"),
+        "false true 1"
+    );
+    assert_eq!(outputs("null // 2, empty // 3", "null"), "2 3");
+    assert_eq!(outputs("(1, 2) + (10, 20)", "null"), "11 21 12 22");
+    assert_eq!(
+        query("{} - 1", &JSONElement::Null).unwrap_err(),
+        "object ({}) and number (1) cannot be subtracted"
+    );
+    assert!(query("1 / 0", &JSONElement::Null).is_err());
+}
+
+#[test]
+fn constructs_values() {
+    assert_eq!(outputs("[.[] | . * 2]", "[1, 2]"), "[2,4]");
+    assert_eq!(outputs("[]", "null"), "[]");
+    assert_eq!(
+        outputs(
+            r#"{a, "b": 2, (.k): 3, c: .a | . + 1}"#,
+            r#"{a: 1, k: "key"}"#
+        ),
+        r#"{"a":1,"b":2,"c":2,"key":3}"#
+    );
+    // Every combination of outputs makes its own object
+    assert_eq!(
+        outputs("{a: (1, 2), b: .}", "0"),
+        r#"{"a":1,"b":0} {"a":2,"b":0}"#
+    );
+    assert_eq!(
+        outputs(r#""\(.name) is \(.age + 1)""#, r#"{name: "ann", age: 41}"#),
+        "\"ann is 42\""
+    );
+    assert_eq!(outputs(r#""list: \(.)""#, "[1]"), "\"list: [1]\"");
+    assert_eq!(outputs(r#""tab\t""#, "null"), "\"tab\\t\"");
+}
+
+#[test]
+fn runs_builtins() {
+    assert_eq!(
+        outputs("keys, length", r#"{b: 1, a: [2]}"#),
+        r#"["a","b"] 2"#
+    );
+    assert_eq!(
+        outputs("to_entries", r#"{b: 1, a: 2}"#),
+        r#"[{"key":"a","value":2},{"key":"b","value":1}]"#
+    );
+    assert_eq!(
+        outputs(
+            "from_entries",
+            r#"[{key: "a", value: 1}, {name: "b", v: 2}]"#
+        ),
+        r#"{"a":1,"b":2}"#
+    );
+    assert_eq!(
+        outputs("with_entries({key: .value, value: .key})", r#"{a: "b"}"#),
+        r#"{"b":"a"}"#
+    );
+    assert_eq!(outputs("1 as $x | {$x}", "null"), r#"{"x":1}"#);
+    assert_eq!(
+        outputs("map(select(. > 1)), map(type)", "[1, 2, 3]"),
+        r#"[2,3] ["number","number","number"]"#
+    );
+    assert_eq!(
+        outputs("sort, unique, min, max, add, reverse", "[3, 1, 3]"),
+        "[1,3,3] [1,3] 1 3 7 [3,1,3]"
+    );
+    assert_eq!(
+        outputs("sort_by(.n) | map(.n)", "[{n: 2}, {n: 1}]"),
+        "[1,2]"
+    );
+    assert_eq!(
+        outputs(
+            r#"join(", "), (.[0] | split("")), has(1), any(. == "b"), all(length == 1)"#,
+            r#"["a", "b"]"#
+        ),
+        r#""a, b" ["a"] true true true"#
+    );
+    assert_eq!(
+        outputs(
+            r#"test("^[a-z]+$"), ascii_upcase, ltrimstr("ab"), startswith("ab"), tojson"#,
+            r#""abc""#
+        ),
+        r#"true "ABC" "c" true "\"abc\"""#
+    );
+    assert_eq!(
+        outputs(
+            "[range(3)], [limit(2; range(10))], first(range(5; 8)), isempty(empty)",
+            "null"
+        ),
+        "[0,1,2] [0,1] 5 true"
+    );
+    assert_eq!(
+        outputs("flatten, flatten(1), contains([[2]])", "[1, [2, [3]]]"),
+        "[1,2,3] [1,2,[3]] true"
+    );
+    assert_eq!(
+        outputs(
+            "tostring, (\"12\" | tonumber), (\"[1]\" | fromjson)",
+            "{a: 1}"
+        ),
+        r#""{\"a\":1}" 12 [1]"#
+    );
+    assert_eq!(
+        outputs(
+            "walk(if type == \"number\" then . + 1 else . end)",
+            "[1, {a: 2}]"
+        ),
+        r#"[2,{"a":3}]"#
+    );
+    assert_eq!(
+        outputs("[recurse(if . < 3 then . + 1 else empty end)]", "0"),
+        "[0,1,2,3]"
+    );
+}
+
+#[test]
+fn runs_control_flow() {
+    assert_eq!(
+        outputs(
+            "if . > 2 then \"big\" elif . > 1 then \"medium\" else \"small\" end",
+            "2"
+        ),
+        "\"medium\""
+    );
+    assert_eq!(outputs("if . then 1 end", "false"), "false");
+    assert_eq!(outputs("reduce .[] as $x (0; . + $x)", "[1, 2, 3]"), "6");
+    assert_eq!(outputs(".a as $x | .b | . + $x", "{a: 1, b: 2}"), "3");
+    assert_eq!(
+        outputs("try error(\"bad\") catch (\"caught: \" + .)", "null"),
+        "\"caught: bad\""
+    );
+    assert_eq!(
+        outputs("[.[] | try (if . == 2 then error else . end)]", "[1, 2, 3]"),
+        "[1,3]"
+    );
+    assert_eq!(outputs(".[] | (.a)?", "[1, {a: 2}]"), "2");
+}
+
+#[test]
+fn runs_user_defined_functions() {
+    assert_eq!(outputs("def double: . * 2; map(double)", "[1, 2]"), "[2,4]");
+    assert_eq!(
+        outputs("def apply(f): [.[] | f]; apply(. + 1)", "[1, 2]"),
+        "[2,3]"
+    );
+    assert_eq!(
+        outputs("def scale($by): map(. * $by); scale(3)", "[1, 2]"),
+        "[3,6]"
+    );
+    assert_eq!(
+        outputs(
+            "def fact: if . <= 1 then 1 else . * (. - 1 | fact) end; [.[] | fact]",
+            "[1, 5]"
+        ),
+        "[1,120]"
+    );
+    // Arguments run where they were passed, so they see the caller's variables
+    assert_eq!(
+        outputs("1 as $x | def f(g): 2 as $x | g; f($x)", "null"),
+        "1"
+    );
+    // Functions can be redefined for each number of arguments
+    assert_eq!(outputs("def f: 1; def f(a): 2; [f, f(.)]", "null"), "[1,2]");
+    assert!(query("def f: f; f", &JSONElement::Null)
+        .unwrap_err()
+        .contains("recursed more than"));
+    assert_eq!(
+        query("nope(1)", &JSONElement::Null).unwrap_err(),
+        "nope/1 is not defined"
+    );
+}
+
+#[test]
+fn parses_filters() {
+    assert_eq!(
+        parse_filter(".a | .b").unwrap(),
+        Filter::Pipe(
+            Box::new(Filter::Index(
+                Box::new(Filter::Identity),
+                Box::new(Filter::Literal(JSONElement::String("a".to_string())))
+            )),
+            Box::new(Filter::Index(
+                Box::new(Filter::Identity),
+                Box::new(Filter::Literal(JSONElement::String("b".to_string())))
+            ))
+        )
+    );
+    assert_eq!(
+        parse_filter(" -1 ").unwrap(),
+        Filter::Literal(JSONElement::Number(-1))
+    );
+    // "or" is a keyword, but ".order" and "order" aren't
+    assert!(parse_filter(".order or order").is_ok());
+    assert_eq!(
+        parse_filter(".a ]").unwrap_err(),
+        "Unexpected \"]\" in the filter"
+    );
+    assert!(parse_filter("if . then 1").is_err());
+    assert!(parse_filter("\"\\(.a\"").is_err());
+}
@@ -0,0 +1,374 @@
+use crate::ast_parser::*;
+use crate::{boxer, choice};
+use std::str::CharIndices;
+
+mod tests;
+
+// A single JSON5 whitespace character. On top of JSON's four that's vertical tab, form feed,
+// no-break space, the byte order mark, the line and paragraph separators and every other Unicode
+// space separator.
+struct WhitespaceCharacterParser();
+impl Parser for WhitespaceCharacterParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        match input.chars().next() {
+            // char::is_whitespace also counts U+0085, which ECMAScript doesn't
+            Some(c) if (c.is_whitespace() && c != '\u{85}') || c == '\u{feff}' => {
+                Ok((&input[c.len_utf8()..], None))
+            }
+            _ => Err(format!(
+                "{} did not start with whitespace",
+                prefix(input, 10)
+            )),
+        }
+    }
+}
+
+/// Any mix of whitespace and comments, which JSON5 allows between any two tokens
+pub struct TriviaParser();
+impl Parser for TriviaParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        OptionParser(RepeatParser(choice!(
+            WhitespaceCharacterParser(),
+            CommentParser()
+        )))
+        .parse(input)
+    }
+}
+
+fn skip_trivia(input: &str) -> Result<&str, ErrorType> {
+    Ok(TriviaParser().parse(input)?.0)
+}
+
+/// A single- or double-quoted string. Like the JSON StringParser, the node is the raw text between
+/// the quotes, and `unescape` from this module decodes it.
+pub struct StringParser();
+impl Parser for StringParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let quote = match input.chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(format!("{} did not start with a quote", prefix(input, 10))),
+        };
+        let string_start = &input[1..];
+        let mut characters = string_start.char_indices();
+        while let Some((i, c)) = characters.next() {
+            match c {
+                c if c == quote => {
+                    return Ok((
+                        &string_start[i + 1..],
+                        Some(ASTNode::String(&string_start[..i])),
+                    ))
+                }
+                '\\' if !skip_escape(&mut characters) => {
+                    return Err(format!(
+                        "string {} has an invalid escape",
+                        prefix(input, 10)
+                    ))
+                }
+                '\n' | '\r' => {
+                    return Err(format!(
+                        "string {} has a line break that wasn't escaped",
+                        prefix(input, 10)
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Err(format!("string {} was never closed", prefix(input, 10)))
+    }
+}
+
+// Moves `characters` past the escape that follows a backslash, returning whether it was valid
+fn skip_escape(characters: &mut CharIndices) -> bool {
+    let hex_digits = |characters: &mut CharIndices, n: usize| {
+        (0..n).all(|_| matches!(characters.next(), Some((_, h)) if h.is_ascii_hexdigit()))
+    };
+    let next_is = |characters: &CharIndices, accept: fn(char) -> bool| {
+        characters.clone().next().is_some_and(|(_, c)| accept(c))
+    };
+    match characters.next() {
+        Some((_, 'x')) => hex_digits(characters, 2),
+        Some((_, 'u')) => hex_digits(characters, 4),
+        // \0 is fine, but \01 would be a legacy octal escape
+        Some((_, '0')) => !next_is(characters, |c| c.is_ascii_digit()),
+        Some((_, '1'..='9')) | None => false,
+        // An escaped CRLF is a single line continuation
+        Some((_, '\r')) => {
+            if next_is(characters, |c| c == '\n') {
+                characters.next();
+            }
+            true
+        }
+        Some(_) => true,
+    }
+}
+
+/// Decodes the escapes in the raw contents of a string or identifier, as returned by StringParser
+/// or IdentifierParser
+pub fn unescape(raw: &str) -> Result<String, ErrorType> {
+    let mut output = String::with_capacity(raw.len());
+    let mut characters = raw.chars();
+    while let Some(c) = characters.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        let rest = characters.as_str();
+        match characters.next() {
+            Some('b') => output.push('\u{8}'),
+            Some('f') => output.push('\u{c}'),
+            Some('n') => output.push('\n'),
+            Some('r') => output.push('\r'),
+            Some('t') => output.push('\t'),
+            Some('v') => output.push('\u{b}'),
+            Some('0') => output.push('\0'),
+            Some('x') => {
+                let escaped = rest
+                    .get(1..3)
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("bad \\x escape in {}", raw))?;
+                output.push(escaped);
+                characters = rest[3..].chars();
+            }
+            // These work exactly like JSON's, surrogate pairs included
+            Some('u') => {
+                let high_surrogate = rest
+                    .get(1..5)
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .is_some_and(|unit| (0xD800..0xDC00).contains(&unit));
+                let length = if high_surrogate { 11 } else { 5 };
+                let escape = rest
+                    .get(..length)
+                    .ok_or_else(|| format!("bad \\u escape in {}", raw))?;
+                output.push_str(&crate::ast_parser::unescape(&format!("\\{}", escape))?);
+                characters = rest[length..].chars();
+            }
+            // A backslash before a line break continues the string onto the next line
+            Some('\r') => {
+                if characters.as_str().starts_with('\n') {
+                    characters.next();
+                }
+            }
+            Some('\n' | '\u{2028}' | '\u{2029}') => {}
+            Some(c @ '1'..='9') => return Err(format!("invalid escape \\{} in {}", c, raw)),
+            Some(c) => output.push(c),
+            None => return Err(format!("{} ends with a backslash", raw)),
+        }
+    }
+    Ok(output)
+}
+
+/// Writes `s` as a JSON5 string literal, quotes included. Single quotes are used unless the string
+/// has more of them than double quotes.
+pub fn escape(s: &str) -> String {
+    let quote = if s.matches('\'').count() > s.matches('"').count() {
+        '"'
+    } else {
+        '\''
+    };
+    let mut output = String::with_capacity(s.len() + 2);
+    output.push(quote);
+    let mut characters = s.chars().peekable();
+    while let Some(c) = characters.next() {
+        match c {
+            c if c == quote => {
+                output.push('\\');
+                output.push(c);
+            }
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '\u{8}' => output.push_str("\\b"),
+            '\u{b}' => output.push_str("\\v"),
+            '\u{c}' => output.push_str("\\f"),
+            '\0' if !characters.peek().is_some_and(char::is_ascii_digit) => output.push_str("\\0"),
+            // These are line breaks to JavaScript, even inside a string
+            '\u{2028}' | '\u{2029}' => {
+                let _ = write!(output, "\\u{:04x}", c as u32);
+            }
+            c if c < ' ' => {
+                let _ = write!(output, "\\x{:02x}", c as u32);
+            }
+            c => output.push(c),
+        }
+    }
+    output.push(quote);
+    output
+}
+
+fn is_identifier_character(c: char, first: bool) -> bool {
+    c == '$'
+        || c == '_'
+        || c.is_alphabetic()
+        || (!first && (c.is_alphanumeric() || c == '\u{200c}' || c == '\u{200d}'))
+}
+
+/// Whether `key` can be written as an object key without quotes
+pub fn is_identifier(key: &str) -> bool {
+    let mut characters = key.chars();
+    characters
+        .next()
+        .is_some_and(|c| is_identifier_character(c, true))
+        && characters.all(|c| is_identifier_character(c, false))
+}
+
+/// An ECMAScript IdentifierName, which JSON5 allows as an object key. The node is the raw text, so
+/// a key written with \u escapes needs `unescape` the same as a string.
+pub struct IdentifierParser();
+impl Parser for IdentifierParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let mut length = 0;
+        loop {
+            let rest = &input[length..];
+            let (c, size) = match rest.strip_prefix("\\u") {
+                Some(escaped) => (
+                    escaped
+                        .get(..4)
+                        .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                        .and_then(char::from_u32),
+                    6,
+                ),
+                None => (
+                    rest.chars().next(),
+                    rest.chars().next().map_or(0, char::len_utf8),
+                ),
+            };
+            match c {
+                Some(c) if is_identifier_character(c, length == 0) => length += size,
+                _ => break,
+            }
+        }
+        if length == 0 {
+            return Err(format!(
+                "{} did not start with an identifier",
+                prefix(input, 10)
+            ));
+        }
+        Ok((&input[length..], Some(ASTNode::String(&input[..length]))))
+    }
+}
+
+/// Everything JSON allows, plus hexadecimal, a leading "+", leading and trailing decimal points,
+/// Infinity and NaN. Integers that fit in NumberType are Numbers and everything else is a Float.
+pub struct NumberParser();
+impl Parser for NumberParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let unsigned = input.strip_prefix(['+', '-']).unwrap_or(input);
+        let sign = if input.starts_with('-') { -1.0 } else { 1.0 };
+        if let Some(rest) = unsigned.strip_prefix("Infinity") {
+            return Ok((rest, Some(ASTNode::Float(sign * f64::INFINITY))));
+        }
+        if let Some(rest) = unsigned.strip_prefix("NaN") {
+            return Ok((rest, Some(ASTNode::Float(f64::NAN))));
+        }
+        if let Some(digits) = unsigned
+            .strip_prefix("0x")
+            .or_else(|| unsigned.strip_prefix("0X"))
+        {
+            let rest = digits.trim_start_matches(|c: char| c.is_ascii_hexdigit());
+            let hex = &digits[..digits.len() - rest.len()];
+            if hex.is_empty() {
+                return Err(format!("{} has no hex digits", prefix(input, 10)));
+            }
+            let magnitude = hex
+                .chars()
+                .filter_map(|c| c.to_digit(16))
+                .fold(0.0, |total, digit| total * 16.0 + digit as f64);
+            return Ok((rest, Some(number_node(sign * magnitude))));
+        }
+
+        // Only a lone zero can start with 0, since anything else would look like octal
+        let after_integer = match unsigned.strip_prefix('0') {
+            Some(rest) if rest.starts_with(|c: char| c.is_ascii_digit()) => {
+                return Err(format!("{} has a leading zero", prefix(input, 10)))
+            }
+            Some(rest) => rest,
+            None => skip_digits(unsigned).unwrap_or(unsigned),
+        };
+        let after_fraction = match after_integer.strip_prefix('.') {
+            Some(fraction) => skip_digits(fraction).unwrap_or(fraction),
+            None => after_integer,
+        };
+        let mantissa = &unsigned[..unsigned.len() - after_fraction.len()];
+        if !mantissa.contains(|c: char| c.is_ascii_digit()) {
+            return Err(format!("{} did not start with a number", prefix(input, 10)));
+        }
+        let after_exponent = after_fraction
+            .strip_prefix(['e', 'E'])
+            .map(|rest| rest.strip_prefix(['+', '-']).unwrap_or(rest))
+            .and_then(skip_digits)
+            .unwrap_or(after_fraction);
+        let text = &input[..input.len() - after_exponent.len()];
+        let text = text.strip_prefix('+').unwrap_or(text);
+        let node = match text.parse::<NumberType>() {
+            Ok(n) => ASTNode::Number(n),
+            Err(_) => ASTNode::Float(text.parse::<f64>().map_err(|err| format!("{}", err))?),
+        };
+        Ok((after_exponent, Some(node)))
+    }
+}
+
+fn number_node<'i>(value: f64) -> ASTNode<'i> {
+    if value.fract() == 0.0 && (NumberType::MIN as f64..=NumberType::MAX as f64).contains(&value) {
+        ASTNode::Number(value as NumberType)
+    } else {
+        ASTNode::Float(value)
+    }
+}
+
+pub struct ValueParser();
+impl Parser for ValueParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        choice!(
+            ObjectParser(),
+            ArrayParser(),
+            StringParser(),
+            NumberParser(),
+            BooleanParser(),
+            NullParser()
+        )
+        .parse(input)
+    }
+}
+
+pub struct ArrayParser();
+impl Parser for ArrayParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        parse_delimited(input, ("[", ",", "]"), &ValueParser(), &TriviaParser())
+    }
+}
+
+struct MemberParser();
+impl Parser for MemberParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, key) = choice!(StringParser(), IdentifierParser()).parse(input)?;
+        let (rest, _) = LiteralParser(":").parse(skip_trivia(rest)?)?;
+        let (rest, value) = ValueParser().parse(skip_trivia(rest)?)?;
+        let (key, value) = key
+            .zip(value)
+            .expect("keys and values always produce a node");
+        Ok((rest, Some(ASTNode::Pair(Box::new(key), Box::new(value)))))
+    }
+}
+
+pub struct ObjectParser();
+impl Parser for ObjectParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (next_string, Some(ASTNode::Sequence(items))) =
+            parse_delimited(input, ("{", ",", "}"), &MemberParser(), &TriviaParser())?
+        else {
+            panic!("parse_delimited did not return a node");
+        };
+        Ok((next_string, Some(mapping_from_pairs(items))))
+    }
+}
+
+/// A whole JSON5 text: one value with any amount of whitespace and comments around it
+pub struct DocumentParser();
+impl Parser for DocumentParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, value) = ValueParser().parse(skip_trivia(input)?)?;
+        Ok((skip_trivia(rest)?, value))
+    }
+}
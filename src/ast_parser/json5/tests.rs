@@ -0,0 +1,273 @@
+#[cfg(test)]
+use crate::ast_parser::json5::*;
+#[cfg(test)]
+use crate::rustifier::{dumps_with, loads, loads_json5, Dialect, DumpOptions, JSONElement};
+#[cfg(test)]
+use std::path::Path;
+
+#[test]
+fn parses_numbers() {
+    let check = |input: &str, expected: ASTNode| {
+        assert_eq!(NumberParser().parse(input).unwrap(), ("", Some(expected)));
+    };
+    check("+15", ASTNode::Number(15));
+    check("-0xC8", ASTNode::Number(-200));
+    check("0xFFFFFFFF", ASTNode::Float(4294967295.0));
+    check(".5", ASTNode::Float(0.5));
+    check("5.", ASTNode::Float(5.0));
+    check("-.5e1", ASTNode::Float(-5.0));
+    check("-Infinity", ASTNode::Float(f64::NEG_INFINITY));
+    let (rest, nan) = NumberParser().parse("NaN").unwrap();
+    assert!(rest.is_empty() && matches!(nan, Some(ASTNode::Float(f)) if f.is_nan()));
+    assert!(NumberParser().parse("010").is_err());
+    assert!(NumberParser().parse(".").is_err());
+    assert!(NumberParser().parse("0x").is_err());
+}
+
+#[test]
+fn parses_strings() {
+    let actual = StringParser().parse(r#"'say "hi"' rest"#).unwrap();
+    assert_eq!(actual, (" rest", Some(ASTNode::String(r#"say "hi""#))));
+    assert!(StringParser().parse("'a\nb'").is_err());
+    assert!(StringParser().parse(r"'\01'").is_err());
+    assert!(StringParser().parse(r"'\x4'").is_err());
+}
+
+#[test]
+fn unescapes() {
+    let actual = unescape("\\'\\x41\\v\\0\\q \\u00e9\\ud83d\\ude00 a\\\r\nb").unwrap();
+    assert_eq!(actual, "'A\u{b}\0q \u{e9}\u{1f600} ab");
+    assert!(unescape(r"\1").is_err());
+}
+
+#[test]
+fn escapes() {
+    assert_eq!(escape("it's"), r#""it's""#);
+    assert_eq!(
+        escape("say \"hi\"\n\u{b}\0x\u{0}1"),
+        r#"'say "hi"\n\v\0x\x001'"#
+    );
+    for s in ["it's", "\u{2028}\\", "'\"'", "\u{1}\u{0}"] {
+        let escaped = escape(s);
+        assert_eq!(unescape(&escaped[1..escaped.len() - 1]).unwrap(), s);
+    }
+}
+
+#[test]
+fn parses_identifiers() {
+    let actual = IdentifierParser().parse("$_a1\\u0062é: 1").unwrap();
+    assert_eq!(actual, (": 1", Some(ASTNode::String("$_a1\\u0062é"))));
+    assert!(IdentifierParser().parse("1a").is_err());
+    assert!(is_identifier("while"));
+    assert!(!is_identifier("multi-word"));
+    assert!(!is_identifier(""));
+}
+
+#[test]
+fn loads_json5_values() {
+    let actual = loads_json5(
+        "// settings\n{unquoted: 'x', \"quoted\": [0x10, +1, .5,], /* c */ nested: {a: null,},}",
+    )
+    .unwrap();
+    let expected =
+        loads(r#"{"unquoted": "x", "quoted": [16, 1, 0], "nested": {"a": null}}"#).unwrap();
+    let JSONElement::Object(mut actual) = actual else {
+        panic!("expected an object");
+    };
+    let JSONElement::Object(mut expected) = expected else {
+        panic!("expected an object");
+    };
+    assert_eq!(
+        actual.remove("quoted"),
+        Some(JSONElement::Array(vec![
+            JSONElement::Number(16),
+            JSONElement::Number(1),
+            JSONElement::Float(0.5),
+        ]))
+    );
+    expected.remove("quoted");
+    assert_eq!(actual, expected);
+    assert!(loads_json5("{a: 1} x").is_err());
+}
+
+#[test]
+fn dumps_json5() {
+    let element =
+        loads_json5("{name: 'it\\'s', 'two words': [Infinity, NaN, -Infinity], nested: {}}")
+            .unwrap();
+    let compact = DumpOptions {
+        dialect: Dialect::Json5,
+        ..DumpOptions::default()
+    };
+    assert_eq!(
+        dumps_with(&element, compact),
+        r#"{name:"it's",nested:{},'two words':[Infinity,NaN,-Infinity]}"#
+    );
+    let pretty = DumpOptions {
+        indent: Some("  "),
+        ..compact
+    };
+    let expected = "{\n  name: \"it's\",\n  nested: {},\n  'two words': [\n    Infinity,\n    NaN,\n    -Infinity,\n  ],\n}";
+    assert_eq!(dumps_with(&element, pretty), expected);
+    let round_trip = loads_json5(&dumps_with(&element, pretty)).unwrap();
+    assert_eq!(
+        dumps_with(&round_trip, compact),
+        dumps_with(&element, compact)
+    );
+}
+
+// The fixtures in testdata/json5 follow the layout of the official JSON5 test suite
+// (https://github.com/json5/json5-tests): .json and .json5 files must parse, while .js (valid
+// JavaScript but not JSON5) and .txt files must not. They're a subset of it, with one or more
+// cases for each rule; the rest of the suite can be dropped in as it is. Cases this parser is
+// known to get wrong are listed here by their path under testdata/json5, and fail the test if
+// they start passing so the list stays accurate.
+#[cfg(test)]
+const EXPECTED_FAILURES: &[&str] = &[];
+
+// What each .json5 case has to parse to, written out compactly as JSON5 so the non-finite
+// numbers can be too. A .json case has to parse to the same as the strict JSON parser reads.
+#[cfg(test)]
+const EXPECTED_VALUES: &[(&str, &str)] = &[
+    ("arrays/trailing-comma-array.json5", "[null]"),
+    (
+        "comments/block-comment-following-array-element.json5",
+        "[false]",
+    ),
+    (
+        "comments/block-comment-following-top-level-value.json5",
+        "null",
+    ),
+    (
+        "comments/block-comment-preceding-top-level-value.json5",
+        "null",
+    ),
+    ("comments/block-comment-with-asterisks.json5", "true"),
+    (
+        "comments/inline-comment-following-array-element.json5",
+        "[false]",
+    ),
+    (
+        "comments/inline-comment-following-top-level-value.json5",
+        "null",
+    ),
+    (
+        "comments/inline-comment-preceding-top-level-value.json5",
+        "null",
+    ),
+    (
+        "misc/readme-example.json5",
+        concat!(
+            "{delta:10,finally:'a trailing comma',foo:'bar',half:0.5,here:'is another',",
+            "hex:3735928559.0,oh:[\"we shouldn't forget\",'arrays can have',",
+            "'trailing commas too'],this:'is a multi-line string',to:Infinity,while:true}"
+        ),
+    ),
+    ("misc/valid-whitespace.json5", "{a:true}"),
+    ("new-lines/comment-cr.json5", "{}"),
+    ("new-lines/comment-crlf.json5", "{}"),
+    ("new-lines/comment-lf.json5", "{}"),
+    ("new-lines/escaped-cr.json5", "{a:'line 1 line 2'}"),
+    ("new-lines/escaped-crlf.json5", "{a:'line 1 line 2'}"),
+    ("new-lines/escaped-lf.json5", "{a:'line 1 line 2'}"),
+    ("numbers/float-leading-decimal-point.json5", "0.5"),
+    (
+        "numbers/float-trailing-decimal-point-with-integer-exponent.json5",
+        "50000.0",
+    ),
+    ("numbers/float-trailing-decimal-point.json5", "5.0"),
+    ("numbers/hexadecimal-lowercase-letter.json5", "200"),
+    ("numbers/hexadecimal-uppercase-x.json5", "200"),
+    ("numbers/hexadecimal-with-integer-exponent.json5", "51428"),
+    ("numbers/hexadecimal.json5", "200"),
+    ("numbers/infinity.json5", "Infinity"),
+    ("numbers/nan.json5", "NaN"),
+    ("numbers/negative-float-leading-decimal-point.json5", "-0.5"),
+    ("numbers/negative-hexadecimal.json5", "-200"),
+    ("numbers/negative-infinity.json5", "-Infinity"),
+    ("numbers/positive-float-leading-decimal-point.json5", "0.5"),
+    ("numbers/positive-hexadecimal.json5", "200"),
+    ("numbers/positive-infinity.json5", "Infinity"),
+    ("numbers/positive-integer.json5", "15"),
+    ("numbers/positive-zero-integer.json5", "0"),
+    ("objects/reserved-unquoted-key.json5", "{while:true}"),
+    ("objects/single-quoted-key.json5", "{hello:'world'}"),
+    ("objects/trailing-comma-object.json5", "{foo:'bar'}"),
+    (
+        "objects/unquoted-keys.json5",
+        concat!(
+            "{$:'dollar sign',$_$hello123world_$_:'mixed',_:'underscore',",
+            "_$_:'multiple symbols',hello:'world',one1:'numerals'}"
+        ),
+    ),
+    (
+        "strings/escaped-single-quoted-string.json5",
+        "\"I can't wait\"",
+    ),
+    ("strings/multi-line-string.json5", "'hello world'"),
+    ("strings/single-quoted-string.json5", "'hello world'"),
+];
+
+// Why the case at `name` parsed to the wrong value, if it did
+#[cfg(test)]
+fn wrong_value(name: &str, text: &str, element: &JSONElement) -> Option<String> {
+    if name.ends_with(".json") {
+        let strict = loads(text);
+        return (strict.as_ref() != Ok(element))
+            .then(|| format!("the JSON parser gave {:?}", strict));
+    }
+    let compact = DumpOptions {
+        dialect: Dialect::Json5,
+        ..DumpOptions::default()
+    };
+    let found = dumps_with(element, compact);
+    match EXPECTED_VALUES.iter().find(|(case, _)| *case == name) {
+        Some((_, expected)) if *expected == found => None,
+        Some((_, expected)) => Some(format!("expected {}", expected)),
+        None => Some("it has no expected value".to_string()),
+    }
+    .map(|reason| format!("{}, but {}", found, reason))
+}
+
+#[test]
+fn passes_the_test_corpus() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/json5");
+    let mut checked = vec![];
+    let mut unexpected = vec![];
+    for category in std::fs::read_dir(&corpus).unwrap() {
+        for case in std::fs::read_dir(category.unwrap().path()).unwrap() {
+            let path = case.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            let valid = matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("json" | "json5")
+            );
+            let name = path.strip_prefix(&corpus).unwrap().to_string_lossy();
+            let name = name.replace('\\', "/");
+            let problem = match loads_json5(&text) {
+                Ok(element) if valid => wrong_value(&name, &text, &element),
+                Ok(element) => Some(format!("parsed to {:?}", element)),
+                Err(err) if valid => Some(format!("failed with {:?}", err)),
+                Err(_) => None,
+            };
+            if problem.is_some() != EXPECTED_FAILURES.contains(&name.as_str()) {
+                unexpected.push(format!(
+                    "{}: {}",
+                    name,
+                    problem.unwrap_or("passed".to_string())
+                ));
+            }
+            checked.push(name);
+        }
+    }
+    for (case, _) in EXPECTED_VALUES {
+        if !checked.iter().any(|name| name == case) {
+            unexpected.push(format!(
+                "{} has an expected value but isn't in the corpus",
+                case
+            ));
+        }
+    }
+    assert!(unexpected.is_empty(), "{:#?}", unexpected);
+    assert!(!checked.is_empty());
+}
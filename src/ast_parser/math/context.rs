@@ -0,0 +1,128 @@
+use crate::ast_parser::math::number::Number;
+use crate::ast_parser::math::value::Value;
+use crate::ast_parser::ErrorType;
+use std::collections::HashMap;
+
+pub type Function = Box<dyn Fn(&[Number]) -> Result<Number, ErrorType>>;
+
+/// Everything an expression can refer to by name. `Context::default()` comes with the built-in
+/// math functions and no variables, and callers can `register_function` their own functions on
+/// top of (or instead of) the built-ins.
+pub struct Context {
+    functions: HashMap<String, Function>,
+    variables: HashMap<String, Value>,
+}
+
+impl Context {
+    /// A context with no functions at all, not even the built-ins
+    pub fn empty() -> Context {
+        Context {
+            functions: HashMap::new(),
+            variables: HashMap::new(),
+        }
+    }
+
+    pub fn set_variable(&mut self, name: &str, value: Value) {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    pub fn variable(&self, name: &str) -> Result<&Value, ErrorType> {
+        self.variables
+            .get(name)
+            .ok_or_else(|| format!("{} is not a known variable", name))
+    }
+
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        function: impl Fn(&[Number]) -> Result<Number, ErrorType> + 'static,
+    ) {
+        self.functions.insert(name.to_string(), Box::new(function));
+    }
+
+    pub fn call(&self, name: &str, arguments: &[Number]) -> Result<Number, ErrorType> {
+        let function = self
+            .functions
+            .get(name)
+            .ok_or_else(|| format!("{} is not a known function", name))?;
+        function(arguments)
+    }
+}
+
+fn expect_arguments<const N: usize>(
+    name: &str,
+    arguments: &[Number],
+) -> Result<[Number; N], ErrorType> {
+    arguments.try_into().map_err(|_| {
+        format!(
+            "{} takes {} argument(s) but was given {}",
+            name,
+            N,
+            arguments.len()
+        )
+    })
+}
+
+fn fold_arguments(
+    name: &str,
+    arguments: &[Number],
+    pick: fn(f64, f64) -> bool,
+) -> Result<Number, ErrorType> {
+    let (first, rest) = arguments
+        .split_first()
+        .ok_or_else(|| format!("{} needs at least one argument", name))?;
+    Ok(rest.iter().fold(*first, |best, n| {
+        if pick(n.as_f64(), best.as_f64()) {
+            *n
+        } else {
+            best
+        }
+    }))
+}
+
+fn round_with(
+    name: &str,
+    arguments: &[Number],
+    rounding: fn(f64) -> f64,
+) -> Result<Number, ErrorType> {
+    let [n] = expect_arguments(name, arguments)?;
+    Ok(match n {
+        Number::Integer(_) => n,
+        Number::Float(f) => Number::from_whole_f64(rounding(f)),
+    })
+}
+
+impl Default for Context {
+    fn default() -> Context {
+        let mut context = Context::empty();
+        context.register_function("min", |arguments| {
+            fold_arguments("min", arguments, |a, b| a < b)
+        });
+        context.register_function("max", |arguments| {
+            fold_arguments("max", arguments, |a, b| a > b)
+        });
+        context.register_function("abs", |arguments| {
+            let [n] = expect_arguments("abs", arguments)?;
+            Ok(if n.as_f64() < 0.0 { -n } else { n })
+        });
+        context.register_function("round", |arguments| {
+            round_with("round", arguments, f64::round)
+        });
+        context.register_function("floor", |arguments| {
+            round_with("floor", arguments, f64::floor)
+        });
+        context.register_function("ceil", |arguments| round_with("ceil", arguments, f64::ceil));
+        context.register_function("sqrt", |arguments| {
+            let [n] = expect_arguments("sqrt", arguments)?;
+            if n.as_f64() < 0.0 {
+                return Err(format!("sqrt({}) is not a real number", n));
+            }
+            Ok(Number::Float(n.as_f64().sqrt()))
+        });
+        context.register_function("pow", |arguments| {
+            let [base, exponent] = expect_arguments("pow", arguments)?;
+            Ok(base.power(exponent))
+        });
+        context
+    }
+}
@@ -0,0 +1,288 @@
+use crate::ast_parser::math::context::Context;
+use crate::ast_parser::math::value::Value;
+use crate::ast_parser::ErrorType;
+use std::cmp::Ordering;
+use std::fmt;
+
+// Literals, variables, groups, calls and unary operations all bind tighter than any binary
+// operator, and the conditional operator binds looser than all of them
+const ATOM_PRECEDENCE: u8 = 8;
+const CONDITIONAL_PRECEDENCE: u8 = 0;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+impl UnaryOperator {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Self::Negate => "-",
+            Self::Not => "!",
+        }
+    }
+
+    fn apply(&self, operand: Value) -> Result<Value, ErrorType> {
+        match self {
+            Self::Negate => Ok(Value::Number(-operand.as_number()?)),
+            Self::Not => Ok(Value::Boolean(!operand.as_boolean()?)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Power,
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    And,
+    Or,
+}
+
+impl BinaryOperator {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Remainder => "%",
+            Self::Power => "^",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterOrEqual => ">=",
+            Self::And => "&&",
+            Self::Or => "||",
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Or => 1,
+            Self::And => 2,
+            Self::Equal | Self::NotEqual => 3,
+            Self::Less | Self::LessOrEqual | Self::Greater | Self::GreaterOrEqual => 4,
+            Self::Add | Self::Subtract => 5,
+            Self::Multiply | Self::Divide | Self::Remainder => 6,
+            Self::Power => 7,
+        }
+    }
+
+    fn apply(&self, lhs: Value, rhs: Value) -> Result<Value, ErrorType> {
+        let ordered = |accept: fn(Ordering) -> bool| -> Result<Value, ErrorType> {
+            Ok(Value::Boolean(accept(lhs.compare(&rhs)?)))
+        };
+        match self {
+            Self::Add => Ok(Value::Number(lhs.as_number()? + rhs.as_number()?)),
+            Self::Subtract => Ok(Value::Number(lhs.as_number()? - rhs.as_number()?)),
+            Self::Multiply => Ok(Value::Number(lhs.as_number()? * rhs.as_number()?)),
+            Self::Divide => Ok(Value::Number(lhs.as_number()?.divide(rhs.as_number()?)?)),
+            Self::Remainder => Ok(Value::Number(lhs.as_number()?.remainder(rhs.as_number()?)?)),
+            Self::Power => Ok(Value::Number(lhs.as_number()?.power(rhs.as_number()?))),
+            Self::Equal => Ok(Value::Boolean(lhs.equals(&rhs)?)),
+            Self::NotEqual => Ok(Value::Boolean(!lhs.equals(&rhs)?)),
+            Self::Less => ordered(Ordering::is_lt),
+            Self::LessOrEqual => ordered(Ordering::is_le),
+            Self::Greater => ordered(Ordering::is_gt),
+            Self::GreaterOrEqual => ordered(Ordering::is_ge),
+            Self::And => Ok(Value::Boolean(lhs.as_boolean()? && rhs.as_boolean()?)),
+            Self::Or => Ok(Value::Boolean(lhs.as_boolean()? || rhs.as_boolean()?)),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    Literal(Value),
+    Variable(String),
+    Unary(UnaryOperator, Box<Expr>),
+    Binary(BinaryOperator, Box<Expr>, Box<Expr>),
+    // condition ? then : otherwise
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    // Parentheses the user wrote. They don't change evaluation since the tree already encodes
+    // precedence, but keeping them lets tools show the expression as it was entered.
+    Group(Box<Expr>),
+    Call(String, Vec<Expr>),
+}
+
+impl Expr {
+    fn precedence(&self) -> u8 {
+        match self {
+            Self::Binary(op, _, _) => op.precedence(),
+            Self::Conditional(_, _, _) => CONDITIONAL_PRECEDENCE,
+            Self::Group(inner) => inner.precedence(),
+            Self::Literal(_) | Self::Variable(_) | Self::Unary(_, _) | Self::Call(_, _) => {
+                ATOM_PRECEDENCE
+            }
+        }
+    }
+}
+
+/// Evaluates with the built-in functions and no variables. Use `eval_with` to supply your own.
+pub fn eval(expr: &Expr) -> Result<Value, ErrorType> {
+    eval_with(expr, &Context::default())
+}
+
+pub fn eval_with(expr: &Expr, context: &Context) -> Result<Value, ErrorType> {
+    match expr {
+        Expr::Literal(value) => Ok(value.clone()),
+        Expr::Variable(name) => context.variable(name).cloned(),
+        Expr::Unary(op, operand) => op.apply(eval_with(operand, context)?),
+        // && and || short-circuit, so "x != null && x > 2" doesn't blow up when x is null
+        Expr::Binary(op @ (BinaryOperator::And | BinaryOperator::Or), lhs, rhs) => {
+            let lhs = eval_with(lhs, context)?.as_boolean()?;
+            if lhs == (*op == BinaryOperator::Or) {
+                Ok(Value::Boolean(lhs))
+            } else {
+                Ok(Value::Boolean(eval_with(rhs, context)?.as_boolean()?))
+            }
+        }
+        Expr::Binary(op, lhs, rhs) => op.apply(eval_with(lhs, context)?, eval_with(rhs, context)?),
+        Expr::Conditional(condition, then, otherwise) => {
+            if eval_with(condition, context)?.as_boolean()? {
+                eval_with(then, context)
+            } else {
+                eval_with(otherwise, context)
+            }
+        }
+        Expr::Group(inner) => eval_with(inner, context),
+        Expr::Call(name, arguments) => {
+            let arguments = arguments
+                .iter()
+                .map(|argument| eval_with(argument, context)?.as_number())
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Value::Number(context.call(name, &arguments)?))
+        }
+    }
+}
+
+/// Simplifies assuming only the built-in functions and no variables, so calls to anything else
+/// and every variable are kept as-is
+pub fn simplify(expr: Expr) -> Expr {
+    simplify_with(expr, &Context::default())
+}
+
+/// Folds every subexpression whose operands are all literals into a single literal and drops
+/// groups, which the printer re-adds only where precedence requires them. Every function in
+/// `context` is assumed to be pure, and variables are left alone even if `context` has them.
+pub fn simplify_with(expr: Expr, context: &Context) -> Expr {
+    let simplify_boxed = |operand: Box<Expr>| Box::new(simplify_with(*operand, context));
+    match expr {
+        Expr::Group(inner) => simplify_with(*inner, context),
+        Expr::Unary(op, operand) => fold(Expr::Unary(op, simplify_boxed(operand)), context),
+        Expr::Binary(op, lhs, rhs) => fold(
+            Expr::Binary(op, simplify_boxed(lhs), simplify_boxed(rhs)),
+            context,
+        ),
+        Expr::Conditional(condition, then, otherwise) => {
+            match simplify_with(*condition, context) {
+                // A constant condition means only one branch can ever be taken
+                Expr::Literal(Value::Boolean(true)) => simplify_with(*then, context),
+                Expr::Literal(Value::Boolean(false)) => simplify_with(*otherwise, context),
+                condition => Expr::Conditional(
+                    Box::new(condition),
+                    simplify_boxed(then),
+                    simplify_boxed(otherwise),
+                ),
+            }
+        }
+        Expr::Call(name, arguments) => fold(
+            Expr::Call(
+                name,
+                arguments
+                    .into_iter()
+                    .map(|argument| simplify_with(argument, context))
+                    .collect(),
+            ),
+            context,
+        ),
+        leaf @ (Expr::Literal(_) | Expr::Variable(_)) => leaf,
+    }
+}
+
+fn fold(expr: Expr, context: &Context) -> Expr {
+    let is_literal = |operand: &Expr| matches!(operand, Expr::Literal(_));
+    let is_constant = match &expr {
+        Expr::Unary(_, operand) => is_literal(operand),
+        Expr::Binary(_, lhs, rhs) => is_literal(lhs) && is_literal(rhs),
+        Expr::Call(_, arguments) => arguments.iter().all(is_literal),
+        _ => false,
+    };
+    if !is_constant {
+        return expr;
+    }
+    // Something like 1 / 0 is left alone so that evaluating the simplified expression still
+    // reports the error
+    match eval_with(&expr, context) {
+        Ok(value) => Expr::Literal(value),
+        Err(_) => expr,
+    }
+}
+
+fn write_operand(f: &mut fmt::Formatter<'_>, operand: &Expr, parenthesize: bool) -> fmt::Result {
+    if parenthesize {
+        write!(f, "({})", operand)
+    } else {
+        write!(f, "{}", operand)
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Literal(value) => write!(f, "{}", value),
+            Self::Variable(name) => write!(f, "{}", name),
+            Self::Group(inner) => write!(f, "{}", inner),
+            Self::Unary(op, operand) => {
+                write!(f, "{}", op.symbol())?;
+                // "--5" wouldn't parse, so a negative operand gets parenthesized too
+                let starts_with_minus = operand.to_string().starts_with('-');
+                write_operand(
+                    f,
+                    operand,
+                    operand.precedence() < ATOM_PRECEDENCE || starts_with_minus,
+                )
+            }
+            Self::Binary(op, lhs, rhs) => {
+                // Every binary operator in the grammar is left-associative, so the right operand
+                // needs parentheses even when its precedence is equal
+                write_operand(f, lhs, lhs.precedence() < op.precedence())?;
+                write!(f, " {} ", op.symbol())?;
+                write_operand(f, rhs, rhs.precedence() <= op.precedence())
+            }
+            Self::Conditional(condition, then, otherwise) => {
+                // The conditional operator is right-associative, so only a conditional used as
+                // the condition needs parentheses
+                write_operand(
+                    f,
+                    condition,
+                    condition.precedence() == CONDITIONAL_PRECEDENCE,
+                )?;
+                write!(f, " ? {} : {}", then, otherwise)
+            }
+            Self::Call(name, arguments) => {
+                write!(f, "{}(", name)?;
+                for (i, argument) in arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
@@ -1,137 +1,148 @@
-use crate::ast_parser::math::ExponentParser;
+use crate::ast_parser::math::expr::{BinaryOperator, Expr};
+use crate::ast_parser::math::{
+    expect_expression, expression_node, EvaluatingParser, PowerExpressionParser,
+};
 use crate::ast_parser::*;
-use crate::NumberType;
 use crate::{boxer, sequence};
 
-pub struct MultiplyDivideParser();
-
-// The delimeter and a function returning the appropriate Operation enum for that delimeter
-type OperationMapping<O> = (&'static str, fn(NumberType) -> O);
+// The delimeter and the operator it stands for
+type OperationMapping = (&'static str, BinaryOperator);
 
-impl Parser for MultiplyDivideParser {
+pub struct ProductExpressionParser();
+impl Parser for ProductExpressionParser {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
-        let operation_mapping: Vec<OperationMapping<MultiplyDivideOperation>> = vec![
-            (
-                "*",
-                MultiplyDivideOperation::Multiply as fn(i32) -> MultiplyDivideOperation,
-            ),
-            (
-                "/",
-                MultiplyDivideOperation::Divide as fn(i32) -> MultiplyDivideOperation,
-            ),
+        let operation_mapping: Vec<OperationMapping> = vec![
+            ("*", BinaryOperator::Multiply),
+            ("/", BinaryOperator::Divide),
+            ("%", BinaryOperator::Remainder),
         ];
-        parse_infix_expression::<MultiplyDivideOperation, ExponentParser>(
+        parse_infix_expression::<PowerExpressionParser>(
             operation_mapping,
             input,
-            ExponentParser,
+            PowerExpressionParser,
         )
     }
 }
 
-pub struct AddSubtractParser();
-impl Parser for AddSubtractParser {
+pub struct SumExpressionParser();
+impl Parser for SumExpressionParser {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
-        let operation_mapping: Vec<OperationMapping<AddSubtractOperation>> = vec![
-            (
-                "+",
-                AddSubtractOperation::Add as fn(NumberType) -> AddSubtractOperation,
-            ),
-            (
-                "-",
-                AddSubtractOperation::Subtract as fn(NumberType) -> AddSubtractOperation,
-            ),
-        ];
-        parse_infix_expression::<AddSubtractOperation, MultiplyDivideParser>(
+        let operation_mapping: Vec<OperationMapping> =
+            vec![("+", BinaryOperator::Add), ("-", BinaryOperator::Subtract)];
+        parse_infix_expression::<ProductExpressionParser>(
             operation_mapping,
             input,
-            MultiplyDivideParser,
+            ProductExpressionParser,
         )
     }
 }
 
-fn try_with_delimeter<'i, P: Parser + 'static>(
-    input: &'i str,
-    delimeter: Option<&'static str>,
-    get_component_parser: fn() -> P,
-) -> Option<(&'i str, NumberType)> {
-    let (next_string, Some(ASTNode::Sequence(mut exprs))) = match delimeter {
-        Some(delimeter) => sequence!(LiteralParser(delimeter), get_component_parser()),
-        None => sequence!(get_component_parser()),
+pub struct ComparisonExpressionParser();
+impl Parser for ComparisonExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        // The two-character operators have to come first or "<" would eat the start of "<="
+        let operation_mapping: Vec<OperationMapping> = vec![
+            ("<=", BinaryOperator::LessOrEqual),
+            (">=", BinaryOperator::GreaterOrEqual),
+            ("<", BinaryOperator::Less),
+            (">", BinaryOperator::Greater),
+        ];
+        parse_infix_expression::<SumExpressionParser>(operation_mapping, input, SumExpressionParser)
     }
-    .parse(input)
-    .ok()?
-    else {
-        panic!("Sequence did not return sequence");
-    };
-    let Some(ASTNode::Number(number)) = exprs.pop() else {
-        panic!("MultiplyDivideParser did not return a number")
-    };
-    Some((next_string, number))
 }
 
-trait Operation {
-    fn execute(&self, accumulator: NumberType) -> NumberType;
+pub struct EqualityExpressionParser();
+impl Parser for EqualityExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let operation_mapping: Vec<OperationMapping> = vec![
+            ("==", BinaryOperator::Equal),
+            ("!=", BinaryOperator::NotEqual),
+        ];
+        parse_infix_expression::<ComparisonExpressionParser>(
+            operation_mapping,
+            input,
+            ComparisonExpressionParser,
+        )
+    }
 }
 
-enum MultiplyDivideOperation {
-    Multiply(NumberType),
-    Divide(NumberType),
+pub struct AndExpressionParser();
+impl Parser for AndExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let operation_mapping: Vec<OperationMapping> = vec![("&&", BinaryOperator::And)];
+        parse_infix_expression::<EqualityExpressionParser>(
+            operation_mapping,
+            input,
+            EqualityExpressionParser,
+        )
+    }
 }
 
-impl Operation for MultiplyDivideOperation {
-    fn execute(&self, accumulator: NumberType) -> NumberType {
-        match self {
-            Self::Multiply(n) => accumulator * n,
-            Self::Divide(n) => accumulator / n,
-        }
+pub struct OrExpressionParser();
+impl Parser for OrExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let operation_mapping: Vec<OperationMapping> = vec![("||", BinaryOperator::Or)];
+        parse_infix_expression::<AndExpressionParser>(operation_mapping, input, AndExpressionParser)
     }
 }
 
-enum AddSubtractOperation {
-    Add(NumberType),
-    Subtract(NumberType),
+pub struct MultiplyDivideParser();
+impl Parser for MultiplyDivideParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        EvaluatingParser(ProductExpressionParser()).parse(input)
+    }
 }
 
-impl Operation for AddSubtractOperation {
-    fn execute(&self, accumulator: NumberType) -> NumberType {
-        match self {
-            Self::Add(n) => accumulator + n,
-            Self::Subtract(n) => accumulator - n,
-        }
+pub struct AddSubtractParser();
+impl Parser for AddSubtractParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        EvaluatingParser(SumExpressionParser()).parse(input)
     }
 }
 
-fn parse_infix_expression<O: Operation, P: Parser + 'static>(
-    operation_mapping: Vec<OperationMapping<O>>,
+fn try_with_delimeter<'i, P: Parser + 'static>(
+    input: &'i str,
+    delimeter: Option<&'static str>,
+    get_component_parser: fn() -> P,
+) -> Option<(&'i str, Expr)> {
+    let (next_string, node) = match delimeter {
+        // The leading whitespace is for when the previous operand didn't consume its own trailing
+        // whitespace, like the "3" in "2^3 * 4"
+        Some(delimeter) => sequence!(
+            WhitespaceParser(),
+            LiteralParser(delimeter),
+            get_component_parser()
+        ),
+        None => sequence!(get_component_parser()),
+    }
+    .parse(input)
+    .ok()?;
+    Some((next_string, expect_expression(node)))
+}
+
+fn parse_infix_expression<P: Parser + 'static>(
+    operation_mapping: Vec<OperationMapping>,
     input: &str,
     get_component_parser: fn() -> P,
 ) -> ParseResult<'_> {
-    let mut operations = vec![];
-
-    let (mut next_token, start_number) = try_with_delimeter(input, None, get_component_parser)
+    let (mut next_token, mut expression) = try_with_delimeter(input, None, get_component_parser)
         .ok_or(format!(
-            "string {} did not start with a number",
+            "string {} did not start with an expression",
             prefix(input, 10)
         ))?;
     'token_walker: loop {
-        for (delimeter, operation) in operation_mapping.iter() {
-            if let Some((next_string, number)) =
+        for (delimeter, operator) in operation_mapping.iter() {
+            if let Some((next_string, operand)) =
                 try_with_delimeter(next_token, Some(delimeter), get_component_parser)
             {
                 next_token = next_string;
-                operations.push(operation(number));
+                // Everything is left-associative, so the tree so far becomes the left operand
+                expression = Expr::Binary(*operator, Box::new(expression), Box::new(operand));
                 continue 'token_walker;
             }
         }
         // If we get here, none of the options matched
         break;
     }
-    let output_number = if !operations.is_empty() {
-        operations
-            .into_iter()
-            .fold(start_number, |acc, op| op.execute(acc))
-    } else {
-        start_number
-    };
-    Ok((next_token, Some(ASTNode::Number(output_number))))
+    Ok((next_token, expression_node(expression)))
 }
@@ -1,10 +1,24 @@
-use crate::ast_parser::math::infix::AddSubtractParser;
+use crate::ast_parser::math::expr::{eval, BinaryOperator, Expr, UnaryOperator};
+use crate::ast_parser::math::infix::OrExpressionParser;
+use crate::ast_parser::math::number::Number;
+use crate::ast_parser::math::value::Value;
 use crate::ast_parser::*;
 use crate::NumberType;
 use crate::{boxer, choice, sequence};
 
+pub mod context;
+pub mod expr;
 pub mod infix;
+pub mod number;
 mod test;
+pub mod value;
+
+lazy_static! {
+    static ref IDENTIFIER_CHARACTERS: HashSet<char> =
+        "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz1234567890_"
+            .chars()
+            .collect();
+}
 
 struct IntLiteralParser();
 
@@ -40,35 +54,199 @@ impl Parser for IntLiteralParser {
     }
 }
 
-struct ParenthesizedExpressionParser();
-impl Parser for ParenthesizedExpressionParser {
+// The parsers in this module that end in "ExpressionParser" build an `Expr` tree and return it
+// wrapped in an ASTNode::Expression. The other ones evaluate that tree immediately.
+
+fn expect_expression(node: Option<ASTNode>) -> Expr {
+    match node {
+        Some(ASTNode::Expression(expr)) => *expr,
+        Some(ASTNode::Sequence(mut list)) => expect_expression(list.pop()),
+        _ => panic!("Expression parser did not return an expression"),
+    }
+}
+
+fn expression_node<'i>(expr: Expr) -> Option<ASTNode<'i>> {
+    Some(ASTNode::Expression(Box::new(expr)))
+}
+
+fn value_node<'i>(value: Value) -> Result<ASTNode<'i>, ErrorType> {
+    match value {
+        Value::Number(Number::Integer(n)) => Ok(ASTNode::Number(n)),
+        Value::Number(Number::Float(f)) => Ok(ASTNode::Float(f)),
+        Value::Boolean(b) => Ok(ASTNode::Boolean(b)),
+        Value::Null => Ok(ASTNode::Null),
+        // ASTNode::String has to point into the input, which a computed string doesn't
+        Value::String(s) => Err(format!(
+            "expression evaluated to the string \"{}\", use ExpressionParser and eval instead",
+            s
+        )),
+    }
+}
+
+struct EvaluatingParser<T: Parser>(T);
+impl<T: Parser> Parser for EvaluatingParser<T> {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
-        let (output_string, node) =
-            sequence!(LiteralParser("("), AddSubtractParser(), LiteralParser(")")).parse(input)?;
-
-        let output_node = match node {
-            number @ Some(ASTNode::Number(_)) => number,
-            Some(ASTNode::Sequence(mut list)) => {
-                if let out_node @ Some(ASTNode::Number(_)) = list.pop() {
-                    out_node
-                } else {
-                    panic!("IntParser did not return a number");
-                }
+        let (output_string, node) = self.0.parse(input)?;
+        Ok((
+            output_string,
+            Some(value_node(eval(&expect_expression(node))?)?),
+        ))
+    }
+}
+
+struct LiteralExpressionParser();
+impl Parser for LiteralExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let after_integer = skip_digits(input.strip_prefix('-').unwrap_or(input))
+            .ok_or_else(|| format!("{} did not start with a number", prefix(input, 10)))?;
+        let after_fraction = after_integer
+            .strip_prefix('.')
+            .and_then(skip_digits)
+            .unwrap_or(after_integer);
+        let after_exponent = after_fraction
+            .strip_prefix(['e', 'E'])
+            .map(|rest| rest.strip_prefix(['+', '-']).unwrap_or(rest))
+            .and_then(skip_digits)
+            .unwrap_or(after_fraction);
+        let text = &input[..input.len() - after_exponent.len()];
+        // Anything with a fraction or exponent, or that's too big for NumberType, is a float
+        let number = match text.parse::<NumberType>() {
+            Ok(n) => Number::Integer(n),
+            Err(_) => Number::Float(text.parse::<f64>().map_err(|err| format!("{}", err))?),
+        };
+        Ok((
+            after_exponent,
+            expression_node(Expr::Literal(Value::Number(number))),
+        ))
+    }
+}
+
+struct StringExpressionParser();
+impl Parser for StringExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (output_string, Some(ASTNode::String(s))) = StringParser().parse(input)? else {
+            panic!("StringParser did not return a string");
+        };
+        Ok((
+            output_string,
+            expression_node(Expr::Literal(Value::String(unescape(s)?))),
+        ))
+    }
+}
+
+pub(crate) struct IdentifierParser();
+impl Parser for IdentifierParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        match input.chars().next() {
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                Ok(parse_character_string(input, &IDENTIFIER_CHARACTERS))
             }
-            _ => node,
+            _ => Err(format!(
+                "{} did not start with an identifier",
+                prefix(input, 10)
+            )),
+        }
+    }
+}
+
+struct CallExpressionParser();
+impl Parser for CallExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (after_name, Some(ASTNode::String(name))) = IdentifierParser().parse(input)? else {
+            panic!("IdentifierParser did not return a string");
+        };
+        let (output_string, Some(ASTNode::Sequence(arguments))) =
+            DelimitedSequenceParser(",", ConditionalExpressionParser(), "(", ")")
+                .parse(after_name)?
+        else {
+            panic!("DelimitedSequenceParser did not return a sequence");
+        };
+        Ok((
+            output_string,
+            expression_node(Expr::Call(
+                name.to_string(),
+                arguments
+                    .into_iter()
+                    .map(|argument| expect_expression(Some(argument)))
+                    .collect(),
+            )),
+        ))
+    }
+}
+
+// Keywords are names too, so this is where true, false and null get picked up
+struct NameExpressionParser();
+impl Parser for NameExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (output_string, Some(ASTNode::String(name))) = IdentifierParser().parse(input)? else {
+            panic!("IdentifierParser did not return a string");
+        };
+        let expr = match name {
+            "true" => Expr::Literal(Value::Boolean(true)),
+            "false" => Expr::Literal(Value::Boolean(false)),
+            "null" => Expr::Literal(Value::Null),
+            _ => Expr::Variable(name.to_string()),
         };
-        Ok((output_string, output_node))
+        Ok((output_string, expression_node(expr)))
     }
 }
 
-struct ExponentParser();
-impl Parser for ExponentParser {
+struct GroupExpressionParser();
+impl Parser for GroupExpressionParser {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
         let (output_string, node) = sequence!(
-            choice!(IntParser(), ParenthesizedExpressionParser()),
+            LiteralParser("("),
+            ConditionalExpressionParser(),
+            LiteralParser(")")
+        )
+        .parse(input)?;
+        Ok((
+            output_string,
+            expression_node(Expr::Group(Box::new(expect_expression(node)))),
+        ))
+    }
+}
+
+struct UnaryExpressionParser(&'static str, UnaryOperator);
+impl Parser for UnaryExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let UnaryExpressionParser(symbol, operator) = self;
+        let (output_string, node) =
+            sequence!(LiteralParser(symbol), AtomExpressionParser()).parse(input)?;
+        Ok((
+            output_string,
+            expression_node(Expr::Unary(*operator, Box::new(expect_expression(node)))),
+        ))
+    }
+}
+
+struct AtomExpressionParser();
+impl Parser for AtomExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        // Negative literals like "-5" are taken by LiteralExpressionParser before we get to
+        // negation, which only kicks in for things like "-(5)" or "- 5"
+        choice!(
+            LiteralExpressionParser(),
+            StringExpressionParser(),
+            GroupExpressionParser(),
+            CallExpressionParser(),
+            NameExpressionParser(),
+            UnaryExpressionParser("-", UnaryOperator::Negate),
+            UnaryExpressionParser("!", UnaryOperator::Not)
+        )
+        .parse(input)
+    }
+}
+
+struct PowerExpressionParser();
+impl Parser for PowerExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (output_string, node) = sequence!(
+            AtomExpressionParser(),
             RepeatParser(sequence!(
+                WhitespaceParser(),
                 LiteralParser("^"),
-                choice!(IntParser(), ParenthesizedExpressionParser())
+                AtomExpressionParser()
             ))
         )
         .parse(input)?;
@@ -76,38 +254,84 @@ impl Parser for ExponentParser {
             panic!("Sequence did not return a sequence");
         };
         let output_node = match list.len() {
-            1 => list.pop().unwrap(),
+            1 => expect_expression(list.pop()),
             2 => {
                 let Some(ASTNode::Sequence(stacked_exponents)) = list.pop() else {
-                    panic!("Parenthesized expression was not a number");
+                    panic!("RepeatParser did not return a sequence");
                 };
-                let stacked_exponents: Vec<u32> = stacked_exponents
-                    .into_iter()
-                    .map(|node| {
-                        if let ASTNode::Sequence(mut exprs) = node {
-                            if let Some(ASTNode::Number(n)) = exprs.pop() {
-                                n.try_into()
-                            } else {
-                                panic!("ParenthesizedExpressionParser() did not reutnr a number")
-                            }
-                        } else {
-                            panic!("Sequence did not return a list")
-                        }
-                    })
-                    .collect::<Result<Vec<_>, _>>()
-                    .map_err(|e| format!("{:?}", e))?;
-                let Some(ASTNode::Number(base)) = list.pop() else {
-                    panic!("Parenthesized expression was not a number");
-                };
-                ASTNode::Number(
-                    stacked_exponents
-                        .into_iter()
-                        .fold(base, |acc, exponent| acc.pow(exponent)),
-                )
+                let base = expect_expression(list.pop());
+                stacked_exponents.into_iter().fold(base, |acc, exponent| {
+                    Expr::Binary(
+                        BinaryOperator::Power,
+                        Box::new(acc),
+                        Box::new(expect_expression(Some(exponent))),
+                    )
+                })
             }
             _ => panic!("List should be 1 or 2 long!"),
         };
-        Ok((output_string, Some(output_node)))
+        Ok((output_string, expression_node(output_node)))
+    }
+}
+
+struct ConditionalExpressionParser();
+impl Parser for ConditionalExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (output_string, node) = sequence!(
+            OrExpressionParser(),
+            OptionParser(sequence!(
+                WhitespaceParser(),
+                LiteralParser("?"),
+                ConditionalExpressionParser(),
+                LiteralParser(":"),
+                ConditionalExpressionParser()
+            ))
+        )
+        .parse(input)?;
+        let Some(ASTNode::Sequence(mut list)) = node else {
+            panic!("Sequence did not return a sequence");
+        };
+        let output_node = match list.pop() {
+            Some(ASTNode::Sequence(mut branches)) => {
+                let otherwise = expect_expression(branches.pop());
+                let then = expect_expression(branches.pop());
+                Expr::Conditional(
+                    Box::new(expect_expression(list.pop())),
+                    Box::new(then),
+                    Box::new(otherwise),
+                )
+            }
+            condition => expect_expression(condition),
+        };
+        Ok((output_string, expression_node(output_node)))
+    }
+}
+
+pub struct ExpressionParser();
+impl Parser for ExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        ConditionalExpressionParser().parse(input)
+    }
+}
+
+pub struct ConditionalParser();
+impl Parser for ConditionalParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        EvaluatingParser(ConditionalExpressionParser()).parse(input)
+    }
+}
+
+pub struct ParenthesizedExpressionParser();
+impl Parser for ParenthesizedExpressionParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        EvaluatingParser(GroupExpressionParser()).parse(input)
+    }
+}
+
+pub struct ExponentParser();
+impl Parser for ExponentParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        EvaluatingParser(PowerExpressionParser()).parse(input)
     }
 }
 
@@ -0,0 +1,120 @@
+use crate::ast_parser::ErrorType;
+use crate::NumberType;
+use std::fmt;
+use std::ops::{Add, Mul, Neg, Sub};
+
+/// A value in the expression language's numeric tower. Arithmetic stays in integers for as long
+/// as the result is exact and fits, and falls back to floats otherwise.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Number {
+    Integer(NumberType),
+    Float(f64),
+}
+
+impl Number {
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Self::Integer(n) => *n as f64,
+            Self::Float(f) => *f,
+        }
+    }
+
+    /// Turns a float with no fractional part back into an integer when it fits
+    pub fn from_whole_f64(f: f64) -> Number {
+        if f.fract() == 0.0 && f >= NumberType::MIN as f64 && f <= NumberType::MAX as f64 {
+            Self::Integer(f as NumberType)
+        } else {
+            Self::Float(f)
+        }
+    }
+
+    pub fn divide(self, other: Number) -> Result<Number, ErrorType> {
+        if other.as_f64() == 0.0 {
+            return Err(format!("{} / {} is a division by zero", self, other));
+        }
+        Ok(match (self, other) {
+            // 7 / 2 is 3.5, not 3. MIN / -1 overflows, so it's left to floats too.
+            (Self::Integer(a), Self::Integer(b)) if a.checked_rem(b) == Some(0) => {
+                self.combine(other, NumberType::checked_div, |a, b| a / b)
+            }
+            _ => Self::Float(self.as_f64() / other.as_f64()),
+        })
+    }
+
+    pub fn remainder(self, other: Number) -> Result<Number, ErrorType> {
+        if other.as_f64() == 0.0 {
+            return Err(format!("{} % {} is a division by zero", self, other));
+        }
+        // Only MIN % -1 overflows, and -1 divides everything evenly
+        let remainder = |a: NumberType, b: NumberType| a.checked_rem(b).or(Some(0));
+        Ok(self.combine(other, remainder, |a, b| a % b))
+    }
+
+    pub fn power(self, exponent: Number) -> Number {
+        match (self, exponent) {
+            (Self::Integer(base), Self::Integer(e)) if e >= 0 => base
+                .checked_pow(e as u32)
+                .map_or_else(|| Self::Float((base as f64).powf(e as f64)), Self::Integer),
+            _ => Self::Float(self.as_f64().powf(exponent.as_f64())),
+        }
+    }
+
+    fn combine(
+        self,
+        other: Number,
+        integer_operation: fn(NumberType, NumberType) -> Option<NumberType>,
+        float_operation: fn(f64, f64) -> f64,
+    ) -> Number {
+        let float_result = || Self::Float(float_operation(self.as_f64(), other.as_f64()));
+        match (self, other) {
+            (Self::Integer(a), Self::Integer(b)) => {
+                integer_operation(a, b).map_or_else(float_result, Self::Integer)
+            }
+            _ => float_result(),
+        }
+    }
+}
+
+impl Neg for Number {
+    type Output = Number;
+    fn neg(self) -> Number {
+        match self {
+            Self::Integer(n) => n
+                .checked_neg()
+                .map_or_else(|| Self::Float(-(n as f64)), Self::Integer),
+            Self::Float(f) => Self::Float(-f),
+        }
+    }
+}
+
+impl Add for Number {
+    type Output = Number;
+    fn add(self, other: Number) -> Number {
+        self.combine(other, NumberType::checked_add, |a, b| a + b)
+    }
+}
+
+impl Sub for Number {
+    type Output = Number;
+    fn sub(self, other: Number) -> Number {
+        self.combine(other, NumberType::checked_sub, |a, b| a - b)
+    }
+}
+
+impl Mul for Number {
+    type Output = Number;
+    fn mul(self, other: Number) -> Number {
+        self.combine(other, NumberType::checked_mul, |a, b| a * b)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(n) => write!(f, "{}", n),
+            // Debug formatting always includes a decimal point or exponent, so a float never
+            // reads back as an integer
+            Self::Float(n) => write!(f, "{:?}", n),
+        }
+    }
+}
@@ -1,5 +1,14 @@
+#[cfg(test)]
+use crate::ast_parser::math::context::*;
+#[cfg(test)]
+use crate::ast_parser::math::expr::*;
+#[cfg(test)]
 use crate::ast_parser::math::infix::{AddSubtractParser, MultiplyDivideParser};
 #[cfg(test)]
+use crate::ast_parser::math::number::*;
+#[cfg(test)]
+use crate::ast_parser::math::value::*;
+#[cfg(test)]
 use crate::ast_parser::math::*;
 
 #[test]
@@ -93,3 +102,273 @@ fn adds_and_subtracts_a_lot() {
         assert_eq!(actual, expected);
     })
 }
+
+#[cfg(test)]
+fn parse_expression(s: &str) -> Expr {
+    let (rest, Some(ASTNode::Expression(expr))) = ExpressionParser().parse(s).unwrap() else {
+        panic!("ExpressionParser did not return an expression");
+    };
+    assert_eq!(rest, "");
+    *expr
+}
+
+#[test]
+fn builds_left_associative_tree() {
+    let actual = parse_expression("8 - 2 - 1");
+    let expected = Expr::Binary(
+        BinaryOperator::Subtract,
+        Box::new(Expr::Binary(
+            BinaryOperator::Subtract,
+            Box::new(Expr::Literal(Value::Number(Number::Integer(8)))),
+            Box::new(Expr::Literal(Value::Number(Number::Integer(2)))),
+        )),
+        Box::new(Expr::Literal(Value::Number(Number::Integer(1)))),
+    );
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn keeps_groups_and_negation() {
+    let actual = parse_expression("-(2)");
+    let expected = Expr::Unary(
+        UnaryOperator::Negate,
+        Box::new(Expr::Group(Box::new(Expr::Literal(Value::Number(
+            Number::Integer(2),
+        ))))),
+    );
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn evaluates_deferred_expression() {
+    let cases: &[(&'static str, NumberType)] = &[
+        ("(200 * (55+11) - 4^2)^2", 173817856),
+        ("-(3 + 4) * 2", -14),
+        ("- 5 - -5", 0),
+        ("2^3 * 4", 32),
+    ];
+    for (s, expected) in cases {
+        assert_eq!(
+            eval(&parse_expression(s)).unwrap(),
+            Value::Number(Number::Integer(*expected))
+        );
+    }
+}
+
+#[test]
+fn reports_evaluation_errors() {
+    assert!(eval(&parse_expression("5 / (3 - 3)")).is_err());
+    assert!(eval(&parse_expression("5 % 0")).is_err());
+    assert!(eval(&parse_expression("sqrt(-1)")).is_err());
+    assert!(eval(&parse_expression("abs(1, 2)")).is_err());
+    assert!(eval(&parse_expression("frobnicate(1)")).is_err());
+}
+
+#[test]
+fn simplifies_constants() {
+    assert_eq!(
+        simplify(parse_expression("((1 + 2)) * 3")),
+        Expr::Literal(Value::Number(Number::Integer(9)))
+    );
+    // Division by zero can't be folded, but everything around it still is
+    let actual = simplify(parse_expression("(2 * 3) / (1 - 1)"));
+    let expected = Expr::Binary(
+        BinaryOperator::Divide,
+        Box::new(Expr::Literal(Value::Number(Number::Integer(6)))),
+        Box::new(Expr::Literal(Value::Number(Number::Integer(0)))),
+    );
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn prints_minimal_parentheses() {
+    let cases: &[(&'static str, &'static str)] = &[
+        ("((1 + 2)) * 3", "(1 + 2) * 3"),
+        ("1 + (2 * 3)", "1 + 2 * 3"),
+        ("(1 + 2) + 3", "1 + 2 + 3"),
+        ("1 - (2 - 3)", "1 - (2 - 3)"),
+        ("(2^3)^2", "2 ^ 3 ^ 2"),
+        ("2^(3^2)", "2 ^ (3 ^ 2)"),
+        ("-(-5)", "-(-5)"),
+        ("-(1 + 2)", "-(1 + 2)"),
+    ];
+    for (s, expected) in cases {
+        let printed = parse_expression(s).to_string();
+        assert_eq!(printed, *expected);
+        // Printing must never change the value
+        assert_eq!(
+            eval(&parse_expression(&printed)),
+            eval(&parse_expression(s))
+        );
+    }
+}
+
+#[test]
+fn promotes_to_floats_when_needed() {
+    let cases: &[(&'static str, Number)] = &[
+        ("7 / 2", Number::Float(3.5)),
+        ("6 / 2", Number::Integer(3)),
+        ("7 % 2", Number::Integer(1)),
+        ("7.5 % 2", Number::Float(1.5)),
+        ("2 ^ -1", Number::Float(0.5)),
+        ("2 ^ 40", Number::Float(1099511627776.0)),
+        ("1.5e2 + 1", Number::Float(151.0)),
+        ("3000000000 - 1", Number::Float(2999999999.0)),
+        ("-2147483648 / -1", Number::Float(2147483648.0)),
+        ("-2147483648 % -1", Number::Integer(0)),
+        ("-2147483648 / 2", Number::Integer(-1073741824)),
+    ];
+    for (s, expected) in cases {
+        assert_eq!(
+            eval(&parse_expression(s)).unwrap(),
+            Value::Number(*expected)
+        );
+    }
+}
+
+#[test]
+fn calls_built_in_functions() {
+    let cases: &[(&'static str, Number)] = &[
+        ("min(4, 2, 8)", Number::Integer(2)),
+        ("max(4, 2.5)", Number::Integer(4)),
+        ("abs(-3)", Number::Integer(3)),
+        ("round(2.5)", Number::Integer(3)),
+        ("floor(-2.5)", Number::Integer(-3)),
+        ("ceil(2.1) * 2", Number::Integer(6)),
+        ("sqrt(16)", Number::Float(4.0)),
+        ("pow(2, max(1, 3)) + 1", Number::Integer(9)),
+    ];
+    for (s, expected) in cases {
+        assert_eq!(
+            eval(&parse_expression(s)).unwrap(),
+            Value::Number(*expected)
+        );
+    }
+}
+
+#[test]
+fn calls_custom_functions() {
+    let mut context = Context::default();
+    context.register_function("double", |arguments| Ok(arguments[0] * Number::Integer(2)));
+    let expr = parse_expression("double(min(5, 7)) + 1");
+    assert_eq!(
+        eval_with(&expr, &context).unwrap(),
+        Value::Number(Number::Integer(11))
+    );
+    assert!(eval(&expr).is_err());
+    // The default simplifier doesn't know about double, so it can only fold the min
+    assert_eq!(simplify(expr.clone()).to_string(), "double(5) + 1");
+    assert_eq!(
+        simplify_with(expr, &context),
+        Expr::Literal(Value::Number(Number::Integer(11)))
+    );
+}
+
+#[test]
+fn evaluating_parsers_return_floats() {
+    let actual = AddSubtractParser().parse("7 / 2").unwrap();
+    let expected = ("", Some(ASTNode::Float(3.5)));
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn evaluates_feature_flag_expression() {
+    let expr = parse_expression(r#"env == "prod" && replicas > 2 ? true : false"#);
+    let cases: &[(&'static str, NumberType, bool)] =
+        &[("prod", 3, true), ("prod", 2, false), ("dev", 5, false)];
+    for (env, replicas, expected) in cases {
+        let mut context = Context::default();
+        context.set_variable("env", Value::String(env.to_string()));
+        context.set_variable("replicas", Value::Number(Number::Integer(*replicas)));
+        assert_eq!(
+            eval_with(&expr, &context).unwrap(),
+            Value::Boolean(*expected)
+        );
+    }
+}
+
+#[test]
+fn compares_values() {
+    let cases: &[(&'static str, bool)] = &[
+        ("1 < 2", true),
+        ("2 <= 2", true),
+        ("3 >= 4", false),
+        ("1 == 1.0", true),
+        (r#""apple" < "banana""#, true),
+        (r#""a" != "b""#, true),
+        ("null == null", true),
+        (r#""a" == null"#, false),
+        ("!(1 > 2) || false", true),
+        ("1 + 1 == 2 && 2 * 3 != 5", true),
+    ];
+    for (s, expected) in cases {
+        assert_eq!(
+            eval(&parse_expression(s)).unwrap(),
+            Value::Boolean(*expected)
+        );
+    }
+}
+
+#[test]
+fn reports_type_errors() {
+    let cases = [
+        r#""a" == 1"#,
+        r#""a" < 1"#,
+        "true < false",
+        r#""a" + 1"#,
+        "!1",
+        "1 && true",
+        "1 ? 2 : 3",
+        "-null",
+        r#"abs("a")"#,
+        "missing + 1",
+    ];
+    for s in cases {
+        assert!(eval(&parse_expression(s)).is_err(), "{} should fail", s);
+    }
+}
+
+#[test]
+fn short_circuits_logic() {
+    // The right-hand sides would fail if they were evaluated
+    assert_eq!(
+        eval(&parse_expression("false && missing")).unwrap(),
+        Value::Boolean(false)
+    );
+    assert_eq!(
+        eval(&parse_expression("true || 1 / 0 == 1")).unwrap(),
+        Value::Boolean(true)
+    );
+}
+
+#[test]
+fn nests_conditionals() {
+    let expr = parse_expression(r#"n < 0 ? "negative" : n == 0 ? "zero" : "positive""#);
+    assert_eq!(
+        expr.to_string(),
+        r#"n < 0 ? "negative" : n == 0 ? "zero" : "positive""#
+    );
+    let mut context = Context::default();
+    context.set_variable("n", Value::Number(Number::Integer(0)));
+    assert_eq!(
+        eval_with(&expr, &context).unwrap(),
+        Value::String("zero".to_string())
+    );
+    assert_eq!(
+        parse_expression("(a ? b : c) ? d : e").to_string(),
+        "(a ? b : c) ? d : e"
+    );
+}
+
+#[test]
+fn simplifies_constant_conditions() {
+    let actual = simplify(parse_expression("1 < 2 ? x + (1 + 1) : y"));
+    assert_eq!(actual.to_string(), "x + 2");
+}
+
+#[test]
+fn conditional_parser_returns_booleans() {
+    let actual = ConditionalParser().parse("2 > 1 && !false").unwrap();
+    let expected = ("", Some(ASTNode::Boolean(true)));
+    assert_eq!(actual, expected);
+}
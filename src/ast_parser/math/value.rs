@@ -0,0 +1,90 @@
+use crate::ast_parser::math::number::Number;
+use crate::ast_parser::{escape, ErrorType};
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Anything an expression can evaluate to
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Number(Number),
+    String(String),
+    Boolean(bool),
+    Null,
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Number(_) => "number",
+            Self::String(_) => "string",
+            Self::Boolean(_) => "boolean",
+            Self::Null => "null",
+        }
+    }
+
+    pub fn as_number(&self) -> Result<Number, ErrorType> {
+        match self {
+            Self::Number(n) => Ok(*n),
+            other => Err(format!(
+                "expected a number but got {} ({})",
+                other,
+                other.type_name()
+            )),
+        }
+    }
+
+    pub fn as_boolean(&self) -> Result<bool, ErrorType> {
+        match self {
+            Self::Boolean(b) => Ok(*b),
+            other => Err(format!(
+                "expected a boolean but got {} ({})",
+                other,
+                other.type_name()
+            )),
+        }
+    }
+
+    /// Anything can be compared to null, but otherwise both sides need to be the same type
+    pub fn equals(&self, other: &Value) -> Result<bool, ErrorType> {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => Ok(a.as_f64() == b.as_f64()),
+            (Self::Null, _) | (_, Self::Null) => Ok(self == other),
+            (a, b) if a.type_name() == b.type_name() => Ok(a == b),
+            (a, b) => Err(mismatch("compare", a, b)),
+        }
+    }
+
+    /// Numbers are ordered numerically and strings lexicographically. Nothing else is ordered.
+    pub fn compare(&self, other: &Value) -> Result<Ordering, ErrorType> {
+        match (self, other) {
+            (Self::Number(a), Self::Number(b)) => a
+                .as_f64()
+                .partial_cmp(&b.as_f64())
+                .ok_or_else(|| format!("{} and {} can't be ordered", a, b)),
+            (Self::String(a), Self::String(b)) => Ok(a.cmp(b)),
+            (a, b) => Err(mismatch("order", a, b)),
+        }
+    }
+}
+
+fn mismatch(verb: &str, a: &Value, b: &Value) -> ErrorType {
+    format!(
+        "can't {} {} ({}) with {} ({})",
+        verb,
+        a,
+        a.type_name(),
+        b,
+        b.type_name()
+    )
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(n) => write!(f, "{}", n),
+            Self::String(s) => write!(f, "{}", escape(s)),
+            Self::Boolean(b) => write!(f, "{}", b),
+            Self::Null => write!(f, "null"),
+        }
+    }
+}
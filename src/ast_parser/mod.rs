@@ -1,17 +1,20 @@
+use crate::ast_parser::math::expr::Expr;
+use crate::rustifier::JSONElement;
 use crate::NumberType;
 use itertools::Itertools;
 use std::cmp::min;
 use std::collections::HashSet;
+use std::fmt::Write;
 
-mod math;
+pub mod filter;
+pub mod json5;
+pub mod math;
 mod tests;
-pub use crate::ast_parser::math::IntParser;
+pub mod toml;
+pub mod yaml;
+pub use crate::ast_parser::math::{ConditionalParser, ExpressionParser, IntParser};
 
 lazy_static! {
-    static ref STRING_CHARACTERS: HashSet<char> =
-        "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz1234567890-@."
-            .chars()
-            .collect();
     static ref NUMBER_CHARACTERS: HashSet<char> = "1234567890".chars().collect();
 }
 
@@ -40,23 +43,41 @@ macro_rules! sequence {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum ASTNode<'i> {
     Number(NumberType),
+    Float(f64),
     String(&'i str),
     Sequence(Vec<ASTNode<'i>>),
     Mapping(Vec<(ASTNode<'i>, ASTNode<'i>)>),
     Pair(Box<ASTNode<'i>>, Box<ASTNode<'i>>),
     Boolean(bool),
     Null,
+    Expression(Box<Expr>),
+    // Text decoded while parsing, for syntaxes like TOML with more than one kind of string
+    OwnedString(String),
 }
 
+#[cfg(test)]
 fn make_pair<'a>(key: &'a str, value: ASTNode<'a>) -> ASTNode<'a> {
     ASTNode::Pair(Box::new(ASTNode::String(key)), Box::new(value))
 }
 
+// Up to `n` bytes of `s`, cut short if needed so it doesn't split a character
 fn prefix(s: &str, n: usize) -> &str {
-    &s[..min(s.len(), n)]
+    let mut end = min(s.len(), n);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+// How an error describes the input it stopped at
+pub(crate) fn found(input: &str) -> String {
+    match input {
+        "" => "the end of the input".to_string(),
+        _ => format!("{:?}", prefix(input, 10)),
+    }
 }
 
 type ErrorType = String;
@@ -67,18 +88,14 @@ pub trait Parser {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i>;
 }
 
-struct LiteralParser(&'static str);
+pub(crate) struct LiteralParser(pub(crate) &'static str);
 
 impl Parser for LiteralParser {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
         Ok((
-            input.strip_prefix(self.0).ok_or_else(|| {
-                format!(
-                    "string \"{}\" did not start with \"{}\"",
-                    prefix(input, 10),
-                    self.0
-                )
-            })?,
+            input
+                .strip_prefix(self.0)
+                .ok_or_else(|| format!("Expected {:?} but found {}", self.0, found(input)))?,
             None,
         ))
     }
@@ -100,12 +117,32 @@ impl Parser for WhitespaceParser {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
         OptionParser(RepeatParser(choice!(
             LiteralParser(" "),
-            LiteralParser("\n")
+            LiteralParser("\n"),
+            LiteralParser("\t"),
+            LiteralParser("\r")
         )))
         .parse(input)
     }
 }
 
+pub struct CommentParser();
+impl Parser for CommentParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        if let Ok((body, _)) = LiteralParser("//").parse(input) {
+            // The line break isn't part of the comment, so whitespace handling still sees it
+            let end = body.find(['\n', '\r']).unwrap_or(body.len());
+            Ok((&body[end..], None))
+        } else if let Ok((body, _)) = LiteralParser("/*").parse(input) {
+            let end = body
+                .find("*/")
+                .ok_or_else(|| format!("comment at {} was never closed", prefix(input, 10)))?;
+            Ok((&body[end + 2..], None))
+        } else {
+            Err(format!("{} did not start a comment", prefix(input, 10)))
+        }
+    }
+}
+
 pub struct SequenceParser(pub Vec<Box<dyn Parser>>);
 
 impl Parser for SequenceParser {
@@ -152,14 +189,122 @@ impl<T: Parser> Parser for RepeatParser<T> {
 
 pub struct StringParser();
 
+// The node is the raw text between the quotes, escapes and all. Use `unescape` to get the actual
+// string out of it.
 impl Parser for StringParser {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
-        let delimeter_parser = LiteralParser("\"");
-        let (string_start, _) = delimeter_parser.parse(input)?;
-        let (string_end, node) = parse_character_string(string_start, &STRING_CHARACTERS);
-        let (after_delimeter, _) = delimeter_parser.parse(string_end)?;
-        Ok((after_delimeter, node))
+        let (string_start, _) = LiteralParser("\"").parse(input)?;
+        let mut characters = string_start.char_indices();
+        while let Some((i, c)) = characters.next() {
+            match c {
+                '"' => {
+                    return Ok((
+                        &string_start[i + 1..],
+                        Some(ASTNode::String(&string_start[..i])),
+                    ))
+                }
+                '\\' => match characters.next() {
+                    Some((_, '"' | '\\' | '/' | 'b' | 'f' | 'n' | 'r' | 't')) => {}
+                    Some((_, 'u')) => {
+                        let four_hex = (0..4).all(
+                            |_| matches!(characters.next(), Some((_, h)) if h.is_ascii_hexdigit()),
+                        );
+                        if !four_hex {
+                            return Err(format!(
+                                "string {} has a \\u escape without four hex digits",
+                                prefix(input, 10)
+                            ));
+                        }
+                    }
+                    _ => {
+                        return Err(format!(
+                            "string {} has an invalid escape",
+                            prefix(input, 10)
+                        ))
+                    }
+                },
+                c if c < ' ' => {
+                    return Err(format!(
+                        "string {} has an unescaped control character",
+                        prefix(input, 10)
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Err(format!("string {} was never closed", prefix(input, 10)))
+    }
+}
+
+/// Decodes the escapes in the raw contents of a string literal, as returned by StringParser
+pub fn unescape(raw: &str) -> Result<String, ErrorType> {
+    let mut output = String::with_capacity(raw.len());
+    let mut characters = raw.chars();
+    let read_code_unit = |characters: &mut std::str::Chars| -> Result<u32, ErrorType> {
+        let hex: String = characters.take(4).collect();
+        u32::from_str_radix(&hex, 16).map_err(|err| format!("bad \\u escape {}: {}", hex, err))
+    };
+    while let Some(c) = characters.next() {
+        if c != '\\' {
+            output.push(c);
+            continue;
+        }
+        let escaped = match characters.next() {
+            Some('b') => '\u{8}',
+            Some('f') => '\u{c}',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                let high = read_code_unit(&mut characters)?;
+                let code_point = if (0xD800..0xDC00).contains(&high) {
+                    // Characters outside the BMP are written as a UTF-16 surrogate pair
+                    let low = characters
+                        .as_str()
+                        .strip_prefix("\\u")
+                        .map(|_| {
+                            characters.nth(1);
+                            read_code_unit(&mut characters)
+                        })
+                        .transpose()?
+                        .filter(|low| (0xDC00..0xE000).contains(low))
+                        .ok_or_else(|| format!("unpaired surrogate in {}", raw))?;
+                    0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+                } else {
+                    high
+                };
+                char::from_u32(code_point)
+                    .ok_or_else(|| format!("{:x} is not a valid character", code_point))?
+            }
+            Some(c @ ('"' | '\\' | '/')) => c,
+            other => return Err(format!("invalid escape \\{:?} in {}", other, raw)),
+        };
+        output.push(escaped);
     }
+    Ok(output)
+}
+
+/// Writes `s` as a string literal, quotes included
+pub fn escape(s: &str) -> String {
+    let mut output = String::with_capacity(s.len() + 2);
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            '\u{8}' => output.push_str("\\b"),
+            '\u{c}' => output.push_str("\\f"),
+            c if c < ' ' => {
+                let _ = write!(output, "\\u{:04x}", c as u32);
+            }
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
 }
 
 fn parse_character_string<'a>(
@@ -173,6 +318,12 @@ fn parse_character_string<'a>(
     (&input[n_chars..], Some(ASTNode::String(&input[..n_chars])))
 }
 
+// The input after at least one digit, or None if it didn't start with one
+fn skip_digits(input: &str) -> Option<&str> {
+    let (rest, _) = parse_character_string(input, &NUMBER_CHARACTERS);
+    (rest.len() < input.len()).then_some(rest)
+}
+
 pub struct ChoiceParser(pub Vec<Box<dyn Parser>>);
 
 impl Parser for ChoiceParser {
@@ -181,12 +332,7 @@ impl Parser for ChoiceParser {
             .iter()
             .filter_map(|parser| parser.parse(input).ok())
             .next()
-            .ok_or_else(|| {
-                format!(
-                    "None of the options were satisfied at {}",
-                    prefix(input, 10)
-                )
-            })
+            .ok_or_else(|| format!("Unexpected {}", found(input)))
     }
 }
 
@@ -195,43 +341,57 @@ struct DelimitedSequenceParser<T: Parser>(&'static str, T, &'static str, &'stati
 impl<T: Parser> Parser for DelimitedSequenceParser<T> {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
         let DelimitedSequenceParser(delimeter, element_parser, start_literal, end_literal) = self;
-        let separator_parser = sequence!(
-            WhitespaceParser(),
-            LiteralParser(delimeter),
-            WhitespaceParser()
-        );
-        // Turns out the empty array is a whole separate production in the real grammar, so I can
-        // feel OK doing this
-        // I used a sequence instead of just a single literal to allow for whitespace
-        if let Ok((next, _)) =
-            sequence!(LiteralParser(start_literal), LiteralParser(end_literal)).parse(input)
-        {
-            return Ok((next, Some(ASTNode::Sequence(vec![]))));
-        }
-        let (mut current_location, _) =
-            sequence!(LiteralParser(start_literal), WhitespaceParser()).parse(input)?;
+        parse_delimited(
+            input,
+            (start_literal, delimeter, end_literal),
+            element_parser,
+            &WhitespaceParser(),
+        )
+    }
+}
 
-        let mut next_element = |include_comma: bool| -> Result<Option<ASTNode>, ErrorType> {
-            if include_comma {
-                current_location = &mut separator_parser.parse(current_location)?.0;
-            }
-            let (next_pointer, el) = element_parser.parse(current_location)?;
-            current_location = next_pointer;
-            Ok(el)
-        };
+// The guts of DelimitedSequenceParser, with whatever is allowed between tokens passed in so other
+// dialects can allow comments there. A trailing delimeter after the last element is fine.
+fn parse_delimited<'i>(
+    input: &'i str,
+    literals: (&'static str, &'static str, &'static str),
+    element_parser: &dyn Parser,
+    trivia_parser: &dyn Parser,
+) -> ParseResult<'i> {
+    let element = |input: &'i str| element_parser.parse(input);
+    let (rest, elements) = parse_items(input, literals, &element, trivia_parser)?;
+    let elements = elements.into_iter().flatten().collect();
+    Ok((rest, Some(ASTNode::Sequence(elements))))
+}
 
-        let mut elements = vec![];
-        if let Some(el) = next_element(false)? {
-            elements.push(el);
+// parse_delimited for parsers that give back something other than an ASTNode, like the ones for
+// filters
+fn parse_items<'i, T>(
+    input: &'i str,
+    (start_literal, delimeter, end_literal): (&'static str, &'static str, &'static str),
+    element_parser: &dyn Fn(&'i str) -> Result<(&'i str, T), ErrorType>,
+    trivia_parser: &dyn Parser,
+) -> Result<(&'i str, Vec<T>), ErrorType> {
+    let skip_trivia =
+        |input: &'i str| -> Result<&'i str, ErrorType> { Ok(trivia_parser.parse(input)?.0) };
+    let finish = |elements: Vec<T>, input: &'i str| {
+        let (after, _) = LiteralParser(end_literal).parse(input)?;
+        Ok((after, elements))
+    };
+    let (mut current_location, _) = LiteralParser(start_literal).parse(input)?;
+    current_location = skip_trivia(current_location)?;
+    let mut elements = vec![];
+    loop {
+        if current_location.starts_with(end_literal) {
+            return finish(elements, current_location);
         }
-        while let Ok(el) = next_element(true) {
-            if let Some(el) = el {
-                elements.push(el);
-            }
+        let (next, element) = element_parser(current_location)?;
+        elements.push(element);
+        current_location = skip_trivia(next)?;
+        match LiteralParser(delimeter).parse(current_location) {
+            Ok((next, _)) => current_location = skip_trivia(next)?,
+            Err(_) => return finish(elements, current_location),
         }
-        let (after, _) =
-            sequence!(WhitespaceParser(), LiteralParser(end_literal)).parse(current_location)?;
-        Ok((after, Some(ASTNode::Sequence(elements))))
     }
 }
 
@@ -243,7 +403,7 @@ impl Parser for ArrayParser {
             ",",
             choice!(
                 BooleanParser(),
-                IntParser(),
+                JsonNumberParser(),
                 StringParser(),
                 ArrayParser(),
                 ObjectParser(),
@@ -265,7 +425,7 @@ impl Parser for KeyValueParser {
             choice!(
                 StringParser(),
                 ArrayParser(),
-                IntParser(),
+                JsonNumberParser(),
                 ObjectParser(),
                 BooleanParser(),
                 NullParser()
@@ -294,24 +454,26 @@ impl Parser for ObjectParser {
         else {
             panic!("DelimitedSequenceParser did not return a node");
         };
-        Ok((
-            next_string,
-            Some(ASTNode::Mapping(
-                items
-                    .into_iter()
-                    .map(|node| {
-                        if let ASTNode::Pair(key, value) = node {
-                            (*key, *value)
-                        } else {
-                            panic!("KeyValueParser did not return a vec of Pairs")
-                        }
-                    })
-                    .collect(),
-            )),
-        ))
+        Ok((next_string, Some(mapping_from_pairs(items))))
     }
 }
 
+// Objects are parsed as a sequence of Pairs, which this turns into the Mapping they stand for
+fn mapping_from_pairs(items: Vec<ASTNode>) -> ASTNode {
+    ASTNode::Mapping(
+        items
+            .into_iter()
+            .map(|node| {
+                if let ASTNode::Pair(key, value) = node {
+                    (*key, *value)
+                } else {
+                    panic!("Object members were not all Pairs")
+                }
+            })
+            .collect(),
+    )
+}
+
 pub struct BooleanParser();
 impl Parser for BooleanParser {
     fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
@@ -338,3 +500,77 @@ impl Parser for NullParser {
         }
     }
 }
+
+/// A number as RFC 8259 writes it: an optional minus, an integer part without leading zeros, and
+/// an optional fraction and exponent. Integers that fit in a NumberType are ASTNode::Number and
+/// everything else is an ASTNode::Float.
+pub struct JsonNumberParser();
+impl Parser for JsonNumberParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let not_a_number = || format!("{} did not start with a number", prefix(input, 10));
+        let unsigned = input.strip_prefix('-').unwrap_or(input);
+        let after_integer = match unsigned.strip_prefix('0') {
+            Some(rest) => rest,
+            None => skip_digits(unsigned).ok_or_else(not_a_number)?,
+        };
+        let after_fraction = match after_integer.strip_prefix('.') {
+            Some(fraction) => skip_digits(fraction).ok_or_else(|| {
+                format!("number {} needs digits after its '.'", prefix(input, 10))
+            })?,
+            None => after_integer,
+        };
+        let after_exponent = match after_fraction.strip_prefix(['e', 'E']) {
+            Some(exponent) => skip_digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent))
+                .ok_or_else(|| {
+                    format!("number {} needs digits in its exponent", prefix(input, 10))
+                })?,
+            None => after_fraction,
+        };
+        let text = &input[..input.len() - after_exponent.len()];
+        let node = match text.parse::<NumberType>() {
+            Ok(n) => ASTNode::Number(n),
+            Err(_) => ASTNode::Float(text.parse().map_err(|err| format!("{}", err))?),
+        };
+        Ok((after_exponent, Some(node)))
+    }
+}
+
+// Reads text that doesn't say what type it is, like a YAML plain scalar or an unquoted CSV field,
+// the way YAML's JSON schema does: null, a boolean, a JSON number, or otherwise a string. A
+// number too big for a float, like 1e400, stays a string rather than becoming infinity, which
+// JSON can't hold.
+pub(crate) fn infer_scalar(text: String) -> JSONElement {
+    match text.as_str() {
+        "null" => JSONElement::Null,
+        "true" => JSONElement::Boolean(true),
+        "false" => JSONElement::Boolean(false),
+        _ if is_json_number(&text) => match (text.parse(), text.parse::<f64>()) {
+            (Ok(n), _) => JSONElement::Number(n),
+            (_, Ok(f)) if f.is_finite() => JSONElement::Float(f),
+            _ => JSONElement::String(text),
+        },
+        _ => JSONElement::String(text),
+    }
+}
+
+// Unlike JsonNumberParser, this allows a fraction with no digits, like `1.`
+fn is_json_number(text: &str) -> bool {
+    let unsigned = text.strip_prefix('-').unwrap_or(text);
+    let Some(after_integer) = unsigned.strip_prefix('0').or_else(|| skip_digits(unsigned)) else {
+        return false;
+    };
+    let after_fraction = match after_integer.strip_prefix('.') {
+        Some(fraction) => skip_digits(fraction).unwrap_or(fraction),
+        None => after_integer,
+    };
+    let after_exponent = match after_fraction.strip_prefix(['e', 'E']) {
+        Some(exponent) => {
+            match skip_digits(exponent.strip_prefix(['+', '-']).unwrap_or(exponent)) {
+                Some(rest) => rest,
+                None => return false,
+            }
+        }
+        None => after_fraction,
+    };
+    after_exponent.is_empty()
+}
@@ -31,6 +31,29 @@ mod numbers {
         };
     }
 
+    #[test]
+    fn parses_json_numbers() {
+        for (input, expected) in [
+            ("0,", ASTNode::Number(0)),
+            ("-12]", ASTNode::Number(-12)),
+            ("1.5}", ASTNode::Float(1.5)),
+            ("-0.25e2 ", ASTNode::Float(-25.0)),
+            ("1E+3,", ASTNode::Float(1000.0)),
+            ("3000000000,", ASTNode::Float(3e9)),
+        ] {
+            let (rest, node) = JsonNumberParser().parse(input).unwrap();
+            assert_eq!((rest.len(), node), (1, Some(expected)), "{}", input);
+        }
+        // A leading zero ends the number, so what follows is left over
+        assert_eq!(
+            JsonNumberParser().parse("01").unwrap(),
+            ("1", Some(ASTNode::Number(0)))
+        );
+        for invalid in ["-", "", ".5", "1.", "1.e3", "1e", "+1", "-a"] {
+            assert!(JsonNumberParser().parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
     #[test]
     fn parses_math() {
         let actual = AddSubtractParser()
@@ -66,6 +89,11 @@ mod literals {
             panic!("Should have failed");
         };
     }
+
+    #[test]
+    fn fails_without_splitting_characters() {
+        assert!(LiteralParser("ab").parse("é").is_err());
+    }
 }
 
 #[cfg(test)]
@@ -126,6 +154,11 @@ mod whitespace {
         check("\n  \n  a", "a");
     }
 
+    #[test]
+    fn takes_tabs_and_carriage_returns() {
+        check("\r\n\t a", "a");
+    }
+
     #[test]
     fn passes_on_nothing() {
         check("a", "a");
@@ -333,3 +366,40 @@ mod null {
         assert_eq!(actual, expected);
     }
 }
+
+#[cfg(test)]
+mod string {
+    use crate::ast_parser::*;
+
+    #[test]
+    fn keeps_escapes_raw() {
+        let actual = StringParser().parse(r#""a \"b\" é" rest"#).unwrap();
+        let expected = (" rest", Some(ASTNode::String(r#"a \"b\" é"#)));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn fails_for_bad_escapes_and_control_characters() {
+        assert!(StringParser().parse(r#""\x""#).is_err());
+        assert!(StringParser().parse(r#""\u12""#).is_err());
+        assert!(StringParser().parse("\"a\nb\"").is_err());
+        assert!(StringParser().parse("\"open").is_err());
+    }
+
+    #[test]
+    fn unescapes() {
+        let actual = unescape(r#"tab\t \"q\" \/ é 😀"#).unwrap();
+        assert_eq!(actual, "tab\t \"q\" / \u{e9} \u{1f600}");
+        assert!(unescape(r#"\ud83d"#).is_err());
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(escape("a\"b\\c\n\u{1}"), r#""a\"b\\c\n\u0001""#);
+        let escaped = escape("\u{1f600} \t");
+        assert_eq!(
+            unescape(&escaped[1..escaped.len() - 1]).unwrap(),
+            "\u{1f600} \t"
+        );
+    }
+}
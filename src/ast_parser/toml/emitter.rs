@@ -0,0 +1,203 @@
+// Writes JSONElements as TOML documents
+
+use crate::ast_parser::math::number::Number;
+use crate::ast_parser::toml::{is_control, parse_datetime, DateTimePolicy, TomlOptions};
+use crate::pointer::format_pointer;
+use crate::rustifier::JSONElement;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// Writes an object as a TOML document with sorted keys. Each table's plain values come first,
+/// then its subtables and arrays of tables under headers of their own. Anything TOML can't
+/// express is an error: documents that aren't objects, and nulls anywhere.
+pub fn emit(element: &JSONElement, options: &TomlOptions) -> Result<String, String> {
+    let JSONElement::Object(members) = element else {
+        return Err("Only objects can be TOML documents".to_string());
+    };
+    let mut output = String::new();
+    write_table(&mut output, members, &mut vec![], &mut vec![], options)?;
+    Ok(output)
+}
+
+// The text of a date or time, if `members` is one tagged by DateTimePolicy::Tagged and the
+// policy is in use
+fn tagged_datetime(
+    members: &HashMap<String, JSONElement>,
+    options: &TomlOptions,
+) -> Option<String> {
+    if options.datetimes != DateTimePolicy::Tagged || members.len() != 2 {
+        return None;
+    }
+    let (Some(JSONElement::String(kind)), Some(JSONElement::String(value))) =
+        (members.get("type"), members.get("value"))
+    else {
+        return None;
+    };
+    match parse_datetime(value) {
+        Ok(("", parsed_kind, text)) if parsed_kind == kind => Some(text),
+        _ => None,
+    }
+}
+
+fn is_table(element: &JSONElement, options: &TomlOptions) -> bool {
+    matches!(element, JSONElement::Object(members) if tagged_datetime(members, options).is_none())
+}
+
+fn is_array_of_tables(element: &JSONElement, options: &TomlOptions) -> bool {
+    matches!(element, JSONElement::Array(elements)
+        if !elements.is_empty() && elements.iter().all(|element| is_table(element, options)))
+}
+
+// Writes the header for the table at `keys`, with a blank line before it
+fn write_header(output: &mut String, keys: &[String], brackets: (&str, &str)) {
+    if !output.is_empty() {
+        output.push('\n');
+    }
+    let keys: Vec<_> = keys.iter().map(|key| format_key(key)).collect();
+    let _ = writeln!(output, "{}{}{}", brackets.0, keys.join("."), brackets.1);
+}
+
+// Writes a table's members. `keys` is the table's keys, whose header is already written, and
+// `path` is where it is for errors, which also counts positions in arrays of tables.
+fn write_table(
+    output: &mut String,
+    members: &HashMap<String, JSONElement>,
+    keys: &mut Vec<String>,
+    path: &mut Vec<String>,
+    options: &TomlOptions,
+) -> Result<(), String> {
+    let mut members: Vec<_> = members.iter().collect();
+    members.sort_by_key(|(key, _)| *key);
+    let (nested, values): (Vec<_>, Vec<_>) = members
+        .into_iter()
+        .partition(|(_, value)| is_table(value, options) || is_array_of_tables(value, options));
+    for (key, value) in values {
+        path.push(key.clone());
+        let _ = write!(output, "{} = ", format_key(key));
+        write_value(output, value, path, options)?;
+        output.push('\n');
+        path.pop();
+    }
+    for (key, value) in nested {
+        keys.push(key.clone());
+        path.push(key.clone());
+        match value {
+            JSONElement::Object(table) => {
+                // A table that only holds other tables doesn't need a header of its own
+                let has_values = table
+                    .values()
+                    .any(|value| !is_table(value, options) && !is_array_of_tables(value, options));
+                if table.is_empty() || has_values {
+                    write_header(output, keys, ("[", "]"));
+                }
+                write_table(output, table, keys, path, options)?;
+            }
+            JSONElement::Array(tables) => {
+                for (i, table) in tables.iter().enumerate() {
+                    write_header(output, keys, ("[[", "]]"));
+                    let JSONElement::Object(table) = table else {
+                        panic!("arrays of tables only hold objects");
+                    };
+                    path.push(i.to_string());
+                    write_table(output, table, keys, path, options)?;
+                    path.pop();
+                }
+            }
+            _ => panic!("nested members are tables or arrays of tables"),
+        }
+        keys.pop();
+        path.pop();
+    }
+    Ok(())
+}
+
+// Writes a value inline. `path` is where it is, for errors.
+fn write_value(
+    output: &mut String,
+    value: &JSONElement,
+    path: &mut Vec<String>,
+    options: &TomlOptions,
+) -> Result<(), String> {
+    match value {
+        JSONElement::Null => {
+            return Err(format!(
+                "TOML has no null, so {} can't be written",
+                format_pointer(path)
+            ))
+        }
+        JSONElement::Boolean(b) => output.push_str(&b.to_string()),
+        JSONElement::Number(n) => output.push_str(&n.to_string()),
+        JSONElement::Float(f) if f.is_nan() => output.push_str("nan"),
+        JSONElement::Float(f) if f.is_infinite() => {
+            output.push_str(if *f > 0.0 { "inf" } else { "-inf" })
+        }
+        JSONElement::Float(f) => output.push_str(&Number::Float(*f).to_string()),
+        JSONElement::String(s) => output.push_str(&basic_string(s)),
+        JSONElement::Array(elements) => {
+            output.push('[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(", ");
+                }
+                path.push(i.to_string());
+                write_value(output, element, path, options)?;
+                path.pop();
+            }
+            output.push(']');
+        }
+        JSONElement::Object(members) => match tagged_datetime(members, options) {
+            Some(text) => output.push_str(&text),
+            None if members.is_empty() => output.push_str("{}"),
+            None => {
+                let mut members: Vec<_> = members.iter().collect();
+                members.sort_by_key(|(key, _)| *key);
+                output.push_str("{ ");
+                for (i, (key, value)) in members.into_iter().enumerate() {
+                    if i > 0 {
+                        output.push_str(", ");
+                    }
+                    let _ = write!(output, "{} = ", format_key(key));
+                    path.push(key.clone());
+                    write_value(output, value, path, options)?;
+                    path.pop();
+                }
+                output.push_str(" }");
+            }
+        },
+    }
+    Ok(())
+}
+
+// Keys are bare when they can be, and basic strings otherwise
+fn format_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    match bare {
+        true => key.to_string(),
+        false => basic_string(key),
+    }
+}
+
+fn basic_string(s: &str) -> String {
+    let mut output = String::with_capacity(s.len() + 2);
+    output.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\u{8}' => output.push_str("\\b"),
+            '\t' => output.push_str("\\t"),
+            '\n' => output.push_str("\\n"),
+            '\u{c}' => output.push_str("\\f"),
+            '\r' => output.push_str("\\r"),
+            c if is_control(c) => {
+                let _ = write!(output, "\\u{:04X}", c as u32);
+            }
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
@@ -0,0 +1,849 @@
+use crate::ast_parser::*;
+use crate::rustifier::{convert, JSONElement};
+use std::collections::HashMap;
+
+pub mod emitter;
+mod tests;
+
+// Nesting deeper than this is rejected rather than risking the stack
+const MAX_DEPTH: usize = 256;
+
+/// How TOML's dates and times, which JSON has no type for, are read
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DateTimePolicy {
+    // RFC 3339 text like "1979-05-27T07:32:00Z", always with a `T` between the date and time
+    #[default]
+    String,
+    // An object like {"type": "datetime", "value": "1979-05-27T07:32:00Z"}, with toml-test's type
+    // names: datetime, datetime-local, date-local and time-local. The emitter writes these back as
+    // dates and times.
+    Tagged,
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TomlOptions {
+    pub datetimes: DateTimePolicy,
+}
+
+/// Parses a TOML v1.0 document, with dates and times read as RFC 3339 strings
+pub fn loads(s: &str) -> Result<JSONElement, String> {
+    loads_with(s, &TomlOptions::default())
+}
+
+/// Parses a TOML v1.0 document, with `options` deciding how dates and times are read
+pub fn loads_with(s: &str, options: &TomlOptions) -> Result<JSONElement, String> {
+    let (rest_of_string, node) = DocumentParser(*options).parse(s)?;
+    if !rest_of_string.is_empty() {
+        Err(format!("Trailing data: {}", rest_of_string))
+    } else if let Some(node) = node {
+        convert(&node)
+    } else {
+        Ok(JSONElement::Null)
+    }
+}
+
+/// Serializes an object as a TOML document with sorted keys. Nulls, and anything but an object
+/// at the top, are errors since TOML can't express them.
+pub fn dumps(element: &JSONElement) -> Result<String, String> {
+    dumps_with(element, &TomlOptions::default())
+}
+
+/// Serializes as TOML, writing objects tagged by DateTimePolicy::Tagged as dates and times when
+/// `options` uses that policy
+pub fn dumps_with(element: &JSONElement, options: &TomlOptions) -> Result<String, String> {
+    emitter::emit(element, options)
+}
+
+// TOML doesn't allow control characters other than tab in strings and comments
+fn is_control(c: char) -> bool {
+    (c < ' ' && c != '\t') || c == '\u{7f}'
+}
+
+fn skip_blanks(input: &str) -> &str {
+    input.trim_start_matches([' ', '\t'])
+}
+
+fn line_break(input: &str) -> Option<&str> {
+    input
+        .strip_prefix('\n')
+        .or_else(|| input.strip_prefix("\r\n"))
+}
+
+// The input after a comment, if it starts with one, up to its line break
+fn skip_comment(input: &str) -> Result<&str, ErrorType> {
+    let Some(comment) = input.strip_prefix('#') else {
+        return Ok(input);
+    };
+    let end = comment.find('\n').unwrap_or(comment.len());
+    let text = comment[..end].strip_suffix('\r').unwrap_or(&comment[..end]);
+    match text.chars().find(|c| is_control(*c)) {
+        Some(c) => Err(format!("Comments can't contain the character {:?}", c)),
+        None => Ok(&comment[text.len()..]),
+    }
+}
+
+// The input after the rest of a line, which can only hold a comment
+fn end_line(input: &str) -> Result<&str, ErrorType> {
+    let rest = skip_comment(skip_blanks(input))?;
+    match line_break(rest) {
+        Some(next) => Ok(next),
+        None if rest.is_empty() => Ok(rest),
+        None => Err(format!(
+            "{} was left at the end of a line",
+            prefix(rest, 10)
+        )),
+    }
+}
+
+// Blanks, line breaks and comments, which can go between statements and between array values
+struct TriviaParser();
+impl Parser for TriviaParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let mut rest = input;
+        loop {
+            let next = skip_comment(skip_blanks(rest))?;
+            match line_break(next) {
+                Some(after) => rest = after,
+                None => return Ok((next, None)),
+            }
+        }
+    }
+}
+
+// A key's parts, which are strings by the time they reach a Table
+fn key_parts(keys: ASTNode) -> Vec<String> {
+    let ASTNode::Sequence(parts) = keys else {
+        panic!("KeyParser did not return a sequence");
+    };
+    parts
+        .into_iter()
+        .map(|part| match part {
+            ASTNode::OwnedString(part) => part,
+            _ => panic!("Key parts were not all strings"),
+        })
+        .collect()
+}
+
+// Letters, digits, `_` and `-`
+struct BareKeyParser();
+impl Parser for BareKeyParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let length = input
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(input.len());
+        if length == 0 {
+            return Err(format!("{} didn't start with a key", prefix(input, 10)));
+        }
+        Ok((
+            &input[length..],
+            Some(ASTNode::OwnedString(input[..length].to_string())),
+        ))
+    }
+}
+
+/// A key, which is bare or quoted parts joined by dots, as the sequence of its parts. Blanks after
+/// it are skipped.
+pub struct KeyParser();
+impl Parser for KeyParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let mut parts = vec![];
+        let mut rest = input;
+        loop {
+            let (after, part) = match rest.chars().next() {
+                Some('"') => BasicStringParser().parse(rest)?,
+                Some('\'') => LiteralStringParser().parse(rest)?,
+                _ => BareKeyParser().parse(rest)?,
+            };
+            parts.extend(part);
+            let after = skip_blanks(after);
+            match after.strip_prefix('.') {
+                Some(next) => rest = skip_blanks(next),
+                None => return Ok((after, Some(ASTNode::Sequence(parts)))),
+            }
+        }
+    }
+}
+
+// The character an escape stands for, given the input after its backslash
+fn parse_escape(input: &str) -> Result<(char, &str), ErrorType> {
+    let invalid = || format!("\\{} isn't a valid escape", prefix(input, 8));
+    let c = input.chars().next().ok_or_else(invalid)?;
+    let after = &input[c.len_utf8()..];
+    let hex = |digits: usize| {
+        let code = after
+            .get(..digits)
+            .filter(|code| code.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(invalid)?;
+        let c = char::from_u32(u32::from_str_radix(code, 16).map_err(|_| invalid())?)
+            .ok_or_else(invalid)?;
+        Ok((c, &after[digits..]))
+    };
+    let escaped = match c {
+        'b' => '\u{8}',
+        't' => '\t',
+        'n' => '\n',
+        'f' => '\u{c}',
+        'r' => '\r',
+        '"' | '\\' => c,
+        'u' => return hex(4),
+        'U' => return hex(8),
+        _ => return Err(invalid()),
+    };
+    Ok((escaped, after))
+}
+
+// A string in double quotes, which can have escapes but not line breaks
+struct BasicStringParser();
+impl Parser for BasicStringParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (mut rest, _) = LiteralParser("\"").parse(input)?;
+        let mut text = String::new();
+        while let Some(c) = rest.chars().next() {
+            let after = &rest[c.len_utf8()..];
+            rest = match c {
+                '"' => return Ok((after, Some(ASTNode::OwnedString(text)))),
+                '\\' => {
+                    let (c, after) = parse_escape(after)?;
+                    text.push(c);
+                    after
+                }
+                c if is_control(c) => {
+                    return Err(format!(
+                        "string {} can't contain the character {:?}",
+                        prefix(input, 10),
+                        c
+                    ))
+                }
+                c => {
+                    text.push(c);
+                    after
+                }
+            };
+        }
+        Err(format!("string {} was never closed", prefix(input, 10)))
+    }
+}
+
+// A string in single quotes, taken as it's written
+struct LiteralStringParser();
+impl Parser for LiteralStringParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, _) = LiteralParser("'").parse(input)?;
+        let end = rest
+            .find('\'')
+            .ok_or_else(|| format!("string {} was never closed", prefix(input, 10)))?;
+        if let Some(c) = rest[..end].chars().find(|c| is_control(*c)) {
+            return Err(format!(
+                "string {} can't contain the character {:?}",
+                prefix(input, 10),
+                c
+            ));
+        }
+        Ok((
+            &rest[end + 1..],
+            Some(ASTNode::OwnedString(rest[..end].to_string())),
+        ))
+    }
+}
+
+// The end of a multi-line string, if `input` is at its closing quotes. Up to two quotes right
+// before them belong to the string.
+fn close_multiline<'i>(input: &'i str, quote: char, text: &mut String) -> Option<&'i str> {
+    let quotes = input.len() - input.trim_start_matches(quote).len();
+    if !(3..=5).contains(&quotes) {
+        return None;
+    }
+    text.extend(std::iter::repeat_n(quote, quotes - 3));
+    Some(&input[quotes..])
+}
+
+// A string in triple double quotes, which can have escapes and line breaks. A line break right
+// after the opening quotes is left out, and so is a backslash at the end of a line along with
+// all the whitespace after it.
+struct MultilineBasicStringParser();
+impl Parser for MultilineBasicStringParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, _) = LiteralParser("\"\"\"").parse(input)?;
+        let mut rest = line_break(rest).unwrap_or(rest);
+        let mut text = String::new();
+        while let Some(c) = rest.chars().next() {
+            let after = &rest[c.len_utf8()..];
+            rest = match c {
+                '"' if rest.starts_with("\"\"\"") => {
+                    let after = close_multiline(rest, '"', &mut text).ok_or_else(|| {
+                        format!("string {} has too many quotes", prefix(input, 10))
+                    })?;
+                    return Ok((after, Some(ASTNode::OwnedString(text))));
+                }
+                '\\' if line_break(skip_blanks(after)).is_some() => {
+                    after.trim_start_matches([' ', '\t', '\r', '\n'])
+                }
+                '\\' => {
+                    let (c, after) = parse_escape(after)?;
+                    text.push(c);
+                    after
+                }
+                '\n' => {
+                    text.push('\n');
+                    after
+                }
+                '\r' if after.starts_with('\n') => {
+                    text.push('\n');
+                    &after[1..]
+                }
+                c if is_control(c) => {
+                    return Err(format!(
+                        "string {} can't contain the character {:?}",
+                        prefix(input, 10),
+                        c
+                    ))
+                }
+                c => {
+                    text.push(c);
+                    after
+                }
+            };
+        }
+        Err(format!("string {} was never closed", prefix(input, 10)))
+    }
+}
+
+// A string in triple single quotes, taken as it's written apart from a line break right after
+// the opening quotes
+struct MultilineLiteralStringParser();
+impl Parser for MultilineLiteralStringParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, _) = LiteralParser("'''").parse(input)?;
+        let mut rest = line_break(rest).unwrap_or(rest);
+        let mut text = String::new();
+        while let Some(c) = rest.chars().next() {
+            let after = &rest[c.len_utf8()..];
+            rest = match c {
+                '\'' if rest.starts_with("'''") => {
+                    let after = close_multiline(rest, '\'', &mut text).ok_or_else(|| {
+                        format!("string {} has too many quotes", prefix(input, 10))
+                    })?;
+                    return Ok((after, Some(ASTNode::OwnedString(text))));
+                }
+                '\n' => {
+                    text.push('\n');
+                    after
+                }
+                '\r' if after.starts_with('\n') => {
+                    text.push('\n');
+                    &after[1..]
+                }
+                c if is_control(c) => {
+                    return Err(format!(
+                        "string {} can't contain the character {:?}",
+                        prefix(input, 10),
+                        c
+                    ))
+                }
+                c => {
+                    text.push(c);
+                    after
+                }
+            };
+        }
+        Err(format!("string {} was never closed", prefix(input, 10)))
+    }
+}
+
+// The digits of `s` without the underscores that can go between them, or None if it has
+// anything else
+fn without_underscores(s: &str, radix: u32) -> Option<String> {
+    let valid = !s.is_empty()
+        && !s.starts_with('_')
+        && !s.ends_with('_')
+        && !s.contains("__")
+        && s.chars().all(|c| c == '_' || c.is_digit(radix));
+    valid.then(|| s.replace('_', ""))
+}
+
+// Integers that don't fit in a NumberType become floats
+fn integer_node<'i>(n: i64) -> ASTNode<'i> {
+    match n.try_into() {
+        Ok(n) => ASTNode::Number(n),
+        Err(_) => ASTNode::Float(n as f64),
+    }
+}
+
+// Reads an integer or float token, or None if it isn't a valid one
+fn number_node<'i>(token: &str) -> Option<ASTNode<'i>> {
+    let (sign, unsigned) = match token.strip_prefix(['+', '-']) {
+        Some(unsigned) => (&token[..1], unsigned),
+        None => ("", token),
+    };
+    match unsigned {
+        "inf" if sign == "-" => return Some(ASTNode::Float(f64::NEG_INFINITY)),
+        "inf" => return Some(ASTNode::Float(f64::INFINITY)),
+        "nan" => return Some(ASTNode::Float(f64::NAN)),
+        _ => {}
+    }
+    for (radix_prefix, radix) in [("0x", 16), ("0o", 8), ("0b", 2)] {
+        if let Some(digits) = unsigned.strip_prefix(radix_prefix) {
+            if !sign.is_empty() {
+                return None;
+            }
+            let digits = without_underscores(digits, radix)?;
+            return i64::from_str_radix(&digits, radix).ok().map(integer_node);
+        }
+    }
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (unsigned, None),
+    };
+    let (whole, fraction) = match mantissa.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (mantissa, None),
+    };
+    let whole = without_underscores(whole, 10)?;
+    if whole.len() > 1 && whole.starts_with('0') {
+        return None;
+    }
+    let mut text = format!("{}{}", sign, whole);
+    if let Some(fraction) = fraction {
+        text.push('.');
+        text.push_str(&without_underscores(fraction, 10)?);
+    }
+    if let Some(exponent) = exponent {
+        let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        text.push('e');
+        text.push_str(&exponent[..exponent.len() - digits.len()]);
+        text.push_str(&without_underscores(digits, 10)?);
+    }
+    match fraction.is_none() && exponent.is_none() {
+        true => text.parse().ok().map(integer_node),
+        false => text.parse().ok().map(ASTNode::Float),
+    }
+}
+
+// A decimal, hexadecimal, octal or binary integer, or a float, any of which can have underscores
+// between digits
+struct NumberParser();
+impl Parser for NumberParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let length = input
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "_+-.".contains(c)))
+            .unwrap_or(input.len());
+        let token = &input[..length];
+        let node = number_node(token)
+            .ok_or_else(|| format!("{} isn't a valid value", prefix(input, 20)))?;
+        Ok((&input[length..], Some(node)))
+    }
+}
+
+// A fixed number of digits at the start of `input`, and the input after them
+fn fixed_digits(input: &str, count: usize) -> Option<(u32, &str)> {
+    let digits = input
+        .get(..count)
+        .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))?;
+    Some((digits.parse().ok()?, &input[count..]))
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// A full date like 1979-05-27
+fn parse_date(input: &str) -> Option<(&str, &str)> {
+    let (year, rest) = fixed_digits(input, 4)?;
+    let (month, rest) = fixed_digits(rest.strip_prefix('-')?, 2)?;
+    let (day, rest) = fixed_digits(rest.strip_prefix('-')?, 2)?;
+    let valid = (1..=12).contains(&month) && (1..=days_in_month(year, month)).contains(&day);
+    valid
+        .then(|| input.split_at(10))
+        .map(|(date, _)| (date, rest))
+}
+
+// A time like 07:32:00 or 07:32:00.999, where the seconds can be 60 for a leap second
+fn parse_time(input: &str) -> Option<(&str, &str)> {
+    let (hour, rest) = fixed_digits(input, 2)?;
+    let (minute, rest) = fixed_digits(rest.strip_prefix(':')?, 2)?;
+    let (second, mut rest) = fixed_digits(rest.strip_prefix(':')?, 2)?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    if let Some(fraction) = rest.strip_prefix('.') {
+        rest = skip_digits(fraction)?;
+    }
+    Some((&input[..input.len() - rest.len()], rest))
+}
+
+// A UTC offset like Z or -07:00
+fn parse_offset(input: &str) -> Option<(String, &str)> {
+    if let Some(rest) = input.strip_prefix(['Z', 'z']) {
+        return Some(("Z".to_string(), rest));
+    }
+    let (hour, rest) = fixed_digits(input.strip_prefix(['+', '-'])?, 2)?;
+    let (minute, rest) = fixed_digits(rest.strip_prefix(':')?, 2)?;
+    (hour <= 23 && minute <= 59).then(|| (input[..6].to_string(), rest))
+}
+
+/// Reads a TOML date, time or both, giving back the input after it, its toml-test type name and
+/// its RFC 3339 text
+pub fn parse_datetime(input: &str) -> Result<(&str, &'static str, String), ErrorType> {
+    let invalid = || format!("{} isn't a valid date or time", prefix(input, 30));
+    let (date, mut rest) = match parse_date(input) {
+        Some((date, rest)) => (Some(date), rest),
+        None => (None, input),
+    };
+    // A space can separate the date and time, but then it's only part of the value if a time
+    // follows it
+    let time_start = match date {
+        Some(_) => rest.strip_prefix(['T', 't']).or_else(|| {
+            rest.strip_prefix(' ')
+                .filter(|time| parse_time(time).is_some())
+        }),
+        None => Some(rest),
+    };
+    let time = match time_start {
+        Some(start) => {
+            let (time, after) = parse_time(start).ok_or_else(invalid)?;
+            rest = after;
+            Some(time)
+        }
+        None => None,
+    };
+    let offset = match (date, time) {
+        (Some(_), Some(_)) => parse_offset(rest).map(|(offset, after)| {
+            rest = after;
+            offset
+        }),
+        _ => None,
+    };
+    Ok(match (date, time, offset) {
+        (Some(date), Some(time), Some(offset)) => {
+            (rest, "datetime", format!("{}T{}{}", date, time, offset))
+        }
+        (Some(date), Some(time), None) => (rest, "datetime-local", format!("{}T{}", date, time)),
+        (Some(date), None, _) => (rest, "date-local", date.to_string()),
+        (None, Some(time), _) => (rest, "time-local", time.to_string()),
+        (None, None, _) => return Err(invalid()),
+    })
+}
+
+// An offset or local date and time, a local date, or a local time, read by the policy
+struct DateTimeParser(DateTimePolicy);
+impl Parser for DateTimeParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, kind, text) = parse_datetime(input)?;
+        let node = match self.0 {
+            DateTimePolicy::String => ASTNode::OwnedString(text),
+            DateTimePolicy::Tagged => ASTNode::Mapping(vec![
+                (
+                    ASTNode::OwnedString("type".to_string()),
+                    ASTNode::OwnedString(kind.to_string()),
+                ),
+                (
+                    ASTNode::OwnedString("value".to_string()),
+                    ASTNode::OwnedString(text),
+                ),
+            ]),
+            DateTimePolicy::Reject => {
+                return Err(format!("{} is a date or time, which isn't allowed", text))
+            }
+        };
+        Ok((rest, Some(node)))
+    }
+}
+
+// Whether `input` starts like a date (1979-) or a time (07:)
+fn looks_like_datetime(input: &str) -> bool {
+    let bytes = input.as_bytes();
+    let digits =
+        |count: usize| bytes.len() > count && bytes[..count].iter().all(u8::is_ascii_digit);
+    (digits(4) && bytes[4] == b'-') || (digits(2) && bytes[2] == b':')
+}
+
+/// Any value: a string, number, boolean, date or time, array or inline table
+pub struct ValueParser {
+    pub datetimes: DateTimePolicy,
+    pub depth: usize,
+}
+
+impl Parser for ValueParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        if self.depth > MAX_DEPTH {
+            return Err(format!("TOML is nested more than {} deep", MAX_DEPTH));
+        }
+        let nested = ValueParser {
+            datetimes: self.datetimes,
+            depth: self.depth + 1,
+        };
+        match input.chars().next() {
+            Some('"') if input.starts_with("\"\"\"") => MultilineBasicStringParser().parse(input),
+            Some('"') => BasicStringParser().parse(input),
+            Some('\'') if input.starts_with("'''") => MultilineLiteralStringParser().parse(input),
+            Some('\'') => LiteralStringParser().parse(input),
+            Some('t' | 'f') => BooleanParser().parse(input),
+            Some('[') => parse_delimited(input, ("[", ",", "]"), &nested, &TriviaParser()),
+            Some('{') => InlineTableParser(nested).parse(input),
+            _ if looks_like_datetime(input) => DateTimeParser(self.datetimes).parse(input),
+            _ => NumberParser().parse(input),
+        }
+    }
+}
+
+// A `key = value` line, or member of an inline table, as a Pair of the key's parts and the value
+struct KeyValueParser<'p>(&'p ValueParser);
+impl Parser for KeyValueParser<'_> {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, keys) = KeyParser().parse(input)?;
+        let (rest, _) = LiteralParser("=").parse(rest)?;
+        let (rest, value) = self.0.parse(skip_blanks(rest))?;
+        Ok((
+            rest,
+            Some(ASTNode::Pair(
+                Box::new(keys.expect("KeyParser always gives a node")),
+                Box::new(value.expect("ValueParser always gives a node")),
+            )),
+        ))
+    }
+}
+
+// A table on one line like `{a = 1, b.c = 2}`, which can't have a trailing comma and can't be
+// added to once it's closed
+struct InlineTableParser(ValueParser);
+impl Parser for InlineTableParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, _) = LiteralParser("{").parse(input)?;
+        let mut table = Table::new(Defined::ByHeader);
+        let mut rest = skip_blanks(rest);
+        if let Some(after) = rest.strip_prefix('}') {
+            return Ok((after, Some(table.into_node())));
+        }
+        loop {
+            let (after, pair) = KeyValueParser(&self.0).parse(rest)?;
+            table.insert(pair.expect("KeyValueParser always gives a node"))?;
+            let after = skip_blanks(after);
+            if let Some(after) = after.strip_prefix(',') {
+                rest = skip_blanks(after);
+            } else if let Some(after) = after.strip_prefix('}') {
+                return Ok((after, Some(table.into_node())));
+            } else {
+                return Err(format!(
+                    "inline table {} was never closed",
+                    prefix(input, 10)
+                ));
+            }
+        }
+    }
+}
+
+// A `[table]` header, as the sequence of its key's parts
+struct TableHeaderParser();
+impl Parser for TableHeaderParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, _) = LiteralParser("[").parse(input)?;
+        let (rest, keys) = KeyParser().parse(skip_blanks(rest))?;
+        Ok((LiteralParser("]").parse(rest)?.0, keys))
+    }
+}
+
+// A `[[array of tables]]` header, as the sequence of its key's parts
+struct ArrayHeaderParser();
+impl Parser for ArrayHeaderParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (rest, _) = LiteralParser("[[").parse(input)?;
+        let (rest, keys) = KeyParser().parse(skip_blanks(rest))?;
+        Ok((LiteralParser("]]").parse(rest)?.0, keys))
+    }
+}
+
+/// A whole TOML document, as a Mapping whose keys are OwnedStrings
+pub struct DocumentParser(pub TomlOptions);
+impl Parser for DocumentParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let value_parser = ValueParser {
+            datetimes: self.0.datetimes,
+            depth: 0,
+        };
+        let mut root = Table::new(Defined::ByHeader);
+        // The keys of the table that key/value lines go in
+        let mut current = vec![];
+        let (mut rest, _) =
+            TriviaParser().parse(input.strip_prefix('\u{feff}').unwrap_or(input))?;
+        while !rest.is_empty() {
+            let after = if rest.starts_with("[[") {
+                let (after, keys) = ArrayHeaderParser().parse(rest)?;
+                current = key_parts(keys.expect("KeyParser always gives a node"));
+                root.append_table(&current)?;
+                after
+            } else if rest.starts_with('[') {
+                let (after, keys) = TableHeaderParser().parse(rest)?;
+                current = key_parts(keys.expect("KeyParser always gives a node"));
+                root.define_table(&current)?;
+                after
+            } else {
+                let (after, pair) = KeyValueParser(&value_parser).parse(rest)?;
+                root.table_at(&current)?
+                    .insert(pair.expect("KeyValueParser always gives a node"))?;
+                after
+            };
+            rest = TriviaParser().parse(end_line(after)?)?.0;
+        }
+        Ok((rest, Some(root.into_node())))
+    }
+}
+
+// How a table came to be, which decides what can add to it later
+#[derive(Clone, Copy, PartialEq)]
+enum Defined {
+    // As part of a longer header's key, so a header of its own can still define it
+    Implicitly,
+    ByHeader,
+    ByDottedKeys,
+}
+
+enum Entry<'i> {
+    // Including inline tables and arrays, which can't be added to
+    Value(ASTNode<'i>),
+    Table(Table<'i>),
+    ArrayOfTables(Vec<Table<'i>>),
+}
+
+// A table as it's built up
+struct Table<'i> {
+    entries: HashMap<String, Entry<'i>>,
+    defined: Defined,
+}
+
+impl<'i> Table<'i> {
+    fn new(defined: Defined) -> Self {
+        Table {
+            entries: HashMap::new(),
+            defined,
+        }
+    }
+
+    // The table `key` names in this one, made if it's missing. Dotted keys can only go into
+    // tables other dotted keys made, while headers can go into any table, and into the last
+    // table of an array of tables.
+    fn child(&mut self, key: &str, defined: Defined) -> Result<&mut Table<'i>, ErrorType> {
+        let entry = self
+            .entries
+            .entry(key.to_string())
+            .or_insert_with(|| Entry::Table(Table::new(defined)));
+        match entry {
+            Entry::Table(table)
+                if defined != Defined::ByDottedKeys || table.defined == Defined::ByDottedKeys =>
+            {
+                Ok(table)
+            }
+            Entry::ArrayOfTables(tables) if defined != Defined::ByDottedKeys => {
+                Ok(tables.last_mut().expect("arrays of tables are never empty"))
+            }
+            _ => Err(format!(
+                "{} was already defined, so it can't be added to",
+                key
+            )),
+        }
+    }
+
+    // The table a header's key names, once everything before its last part is made
+    fn parent(&mut self, path: &[String]) -> Result<&mut Table<'i>, ErrorType> {
+        if path.len() > MAX_DEPTH {
+            return Err(format!("TOML is nested more than {} deep", MAX_DEPTH));
+        }
+        let mut table = self;
+        for key in &path[..path.len() - 1] {
+            table = table.child(key, Defined::Implicitly)?;
+        }
+        Ok(table)
+    }
+
+    fn table_at(&mut self, path: &[String]) -> Result<&mut Table<'i>, ErrorType> {
+        let mut table = self;
+        for key in path {
+            table = table.child(key, Defined::Implicitly)?;
+        }
+        Ok(table)
+    }
+
+    fn define_table(&mut self, path: &[String]) -> Result<(), ErrorType> {
+        let key = path.last().expect("keys have at least one part");
+        let parent = self.parent(path)?;
+        match parent.entries.get_mut(key) {
+            None => {
+                parent
+                    .entries
+                    .insert(key.clone(), Entry::Table(Table::new(Defined::ByHeader)));
+            }
+            Some(Entry::Table(table)) if table.defined == Defined::Implicitly => {
+                table.defined = Defined::ByHeader
+            }
+            Some(_) => return Err(format!("[{}] is defined more than once", path.join("."))),
+        }
+        Ok(())
+    }
+
+    fn append_table(&mut self, path: &[String]) -> Result<(), ErrorType> {
+        let key = path.last().expect("keys have at least one part");
+        let parent = self.parent(path)?;
+        match parent.entries.get_mut(key) {
+            None => {
+                let tables = vec![Table::new(Defined::ByHeader)];
+                parent
+                    .entries
+                    .insert(key.clone(), Entry::ArrayOfTables(tables));
+            }
+            Some(Entry::ArrayOfTables(tables)) => tables.push(Table::new(Defined::ByHeader)),
+            Some(_) => {
+                return Err(format!(
+                    "[[{}]] can't add to something that isn't an array of tables",
+                    path.join(".")
+                ))
+            }
+        }
+        Ok(())
+    }
+
+    // Adds a Pair from KeyValueParser, making the tables its dotted key goes through
+    fn insert(&mut self, pair: ASTNode<'i>) -> Result<(), ErrorType> {
+        let ASTNode::Pair(keys, value) = pair else {
+            panic!("KeyValueParser did not return a pair");
+        };
+        let path = key_parts(*keys);
+        let key = path.last().expect("keys have at least one part");
+        if path.len() > MAX_DEPTH {
+            return Err(format!("TOML is nested more than {} deep", MAX_DEPTH));
+        }
+        let mut table = self;
+        for part in &path[..path.len() - 1] {
+            table = table.child(part, Defined::ByDottedKeys)?;
+        }
+        if table.entries.contains_key(key) {
+            return Err(format!("{} is defined more than once", path.join(".")));
+        }
+        table.entries.insert(key.clone(), Entry::Value(*value));
+        Ok(())
+    }
+
+    fn into_node(self) -> ASTNode<'i> {
+        ASTNode::Mapping(
+            self.entries
+                .into_iter()
+                .map(|(key, entry)| {
+                    let value = match entry {
+                        Entry::Value(value) => value,
+                        Entry::Table(table) => table.into_node(),
+                        Entry::ArrayOfTables(tables) => {
+                            ASTNode::Sequence(tables.into_iter().map(Table::into_node).collect())
+                        }
+                    };
+                    (ASTNode::OwnedString(key), value)
+                })
+                .collect(),
+        )
+    }
+}
@@ -0,0 +1,255 @@
+#[cfg(test)]
+use crate::ast_parser::toml::*;
+#[cfg(test)]
+use crate::rustifier::{loads_json5, JSONElement};
+
+#[cfg(test)]
+fn assert_loads(toml: &str, json: &str) {
+    assert_eq!(loads(toml).unwrap(), loads_json5(json).unwrap(), "{}", toml);
+}
+
+#[test]
+fn loads_tables() {
+    assert_loads(
+        r#"
+# A comment
+title = "TOML" # after a value
+
+[owner]
+name = "Tom"
+site."google.com" = true
+
+[database.connection]
+ports = [ 8000, 8001,
+  8002, # trailing comma
+]
+inline = { a = 1, b.c = 2 }
+
+[[products]]
+name = "Hammer"
+
+[[products]]
+
+[[products]]
+name = "Nail"
+[products.size]
+mm = 3
+
+[fruit]
+apple.color = "red"
+apple.taste.sweet = true
+[fruit.apple.texture]
+smooth = true
+"#,
+        r#"{
+            "title": "TOML",
+            "owner": {"name": "Tom", "site": {"google.com": true}},
+            "database": {"connection": {"ports": [8000, 8001, 8002], "inline": {"a": 1, "b": {"c": 2}}}},
+            "products": [{"name": "Hammer"}, {}, {"name": "Nail", "size": {"mm": 3}}],
+            "fruit": {"apple": {"color": "red", "taste": {"sweet": true}, "texture": {"smooth": true}}}
+        }"#,
+    );
+    assert_loads("", "{}");
+    assert_loads("[a.b.c]\n[a]\nd = 1", r#"{"a": {"b": {"c": {}}, "d": 1}}"#);
+    assert_loads("\u{feff}a = 1\r\nb = 2\r\n", r#"{"a": 1, "b": 2}"#);
+    assert_loads(
+        "\"quoted key\" = 1\n'literal key' = 2\n\"\" = 3\n1234 = 4",
+        r#"{"quoted key": 1, "literal key": 2, "": 3, "1234": 4}"#,
+    );
+}
+
+#[test]
+fn loads_strings() {
+    assert_loads(
+        r#"basic = "tab\there \"quoted\" \u00e9 \U0001F600"
+literal = 'C:\Users\nodejs'
+multi = """
+Roses are red
+Violets are blue"""
+continued = """\
+    The quick \
+    brown fox.\
+    """
+quotes = """Here are two quotes: "". Here is one more: """"
+raw = '''
+The first newline is
+trimmed in raw strings.
+   All other whitespace
+   is preserved.
+'''
+apostrophes = ''''That,' she said.''''"#,
+        r#"{
+            "basic": "tab\there \"quoted\" \u00e9 \uD83D\uDE00",
+            "literal": "C:\\Users\\nodejs",
+            "multi": "Roses are red\nViolets are blue",
+            "continued": "The quick brown fox.",
+            "quotes": "Here are two quotes: \"\". Here is one more: \"",
+            "raw": "The first newline is\ntrimmed in raw strings.\n   All other whitespace\n   is preserved.\n",
+            "apostrophes": "'That,' she said.'"
+        }"#,
+    );
+}
+
+#[test]
+fn loads_numbers() {
+    assert_loads(
+        "a = [+99, -17, 0, 1_000, 0xDEAD_beef, 0o755, 0b1101, 9_223_372_036_854_775_807]\n\
+         b = [1.0, -3.1415, 5e+22, 1E6, -2E-2, 6.626e-34, 224_617.445_991]",
+        r#"{"a": [99, -17, 0, 1000, 3735928559.0, 493, 13, 9223372036854775807.0],
+            "b": [1.0, -3.1415, 5e22, 1000000.0, -0.02, 6.626e-34, 224617.445991]}"#,
+    );
+    let special = loads("a = [inf, +inf, -inf, nan, -nan]").unwrap();
+    let JSONElement::Object(members) = special else {
+        panic!("not an object");
+    };
+    let JSONElement::Array(values) = &members["a"] else {
+        panic!("not an array");
+    };
+    assert_eq!(values[0], JSONElement::Float(f64::INFINITY));
+    assert_eq!(values[2], JSONElement::Float(f64::NEG_INFINITY));
+    assert!(matches!(values[4], JSONElement::Float(f) if f.is_nan()));
+}
+
+#[test]
+fn reads_datetimes_by_policy() {
+    let toml = "odt = 1979-05-27 07:32:00.999z\noffset = 1979-05-27T00:32:00-07:00\n\
+                ldt = 1979-05-27T07:32:00\nld = 2000-02-29\nlt = 00:32:00.5";
+    assert_loads(
+        toml,
+        r#"{"odt": "1979-05-27T07:32:00.999Z", "offset": "1979-05-27T00:32:00-07:00",
+            "ldt": "1979-05-27T07:32:00", "ld": "2000-02-29", "lt": "00:32:00.5"}"#,
+    );
+    let tagged = TomlOptions {
+        datetimes: DateTimePolicy::Tagged,
+    };
+    assert_eq!(
+        loads_with("ld = 2000-02-29\nlt = 00:32:00", &tagged).unwrap(),
+        loads_json5(
+            r#"{"ld": {"type": "date-local", "value": "2000-02-29"},
+                "lt": {"type": "time-local", "value": "00:32:00"}}"#
+        )
+        .unwrap()
+    );
+    let reject = TomlOptions {
+        datetimes: DateTimePolicy::Reject,
+    };
+    assert!(loads_with(toml, &reject).is_err());
+    for invalid in [
+        "a = 2001-02-29",
+        "a = 1979-13-01",
+        "a = 24:00:00",
+        "a = 07:32",
+        "a = 1979-05-27T07:32:00+25:00",
+        "a = 1979-05-27T",
+    ] {
+        assert!(loads(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn rejects_invalid_documents() {
+    for invalid in [
+        "a = 1\na = 2",
+        "[a]\n[a]",
+        "[a]\nb = 1\n[a.b]",
+        "a.b = 1\n[a]",
+        "[a.b.c]\n[a]\nb.d = 1",
+        "a = {b = 1}\n[a]",
+        "a = {b = 1}\na.c = 2",
+        "a = [1]\n[[a]]",
+        "[[a]]\n[a]",
+        "a = {b = 1,}",
+        "a = {b = 1\n}",
+        "a = 1 b = 2",
+        "a =",
+        "= 1",
+        "a = 01",
+        "a = 1__0",
+        "a = _1",
+        "a = 1.",
+        "a = .5",
+        "a = +0x1",
+        "a = 0x",
+        "a = trueish",
+        "a = \"unclosed",
+        "a = \"\\x41\"",
+        "a = \"tab\u{1}\"",
+        "a = '''''''''",
+        "a = 1 # bad \u{7f} comment",
+        "[a",
+        "[[a]",
+        "a = 1\r",
+    ] {
+        assert!(loads(invalid).is_err(), "{:?}", invalid);
+    }
+    let deep = format!("a = {}{}", "[".repeat(1000), "]".repeat(1000));
+    assert!(loads(&deep).is_err());
+}
+
+#[test]
+fn emits_documents() {
+    let element = loads_json5(
+        r#"{
+            "title": "TOML", "list": [1, "two", [3.5], {"x": true}], "empty": {},
+            "owner": {"name": "Tom", "bio": "line\nbreak \"quoted\"\u0001"},
+            "nested": {"deeper": {"n": -1}},
+            "products": [{"name": "Hammer"}, {"name": "Nail", "size": {"mm": 3}}],
+            "odd key": 1.0, "specials": [Infinity, -Infinity]
+        }"#,
+    )
+    .unwrap();
+    let expected = r#"list = [1, "two", [3.5], { x = true }]
+"odd key" = 1.0
+specials = [inf, -inf]
+title = "TOML"
+
+[empty]
+
+[nested.deeper]
+n = -1
+
+[owner]
+bio = "line\nbreak \"quoted\"\u0001"
+name = "Tom"
+
+[[products]]
+name = "Hammer"
+
+[[products]]
+name = "Nail"
+
+[products.size]
+mm = 3
+"#;
+    let toml = dumps(&element).unwrap();
+    assert_eq!(toml, expected);
+    assert_eq!(loads(&toml).unwrap(), element);
+}
+
+#[test]
+fn rejects_what_toml_cannot_express() {
+    for (json, error) in [
+        ("[1]", "Only objects"),
+        ("1", "Only objects"),
+        (r#"{"a": null}"#, "/a"),
+        (r#"{"a": {"b": [1, null]}}"#, "/a/b/1"),
+        (r#"{"a": [{"b": null}]}"#, "/a/0/b"),
+    ] {
+        let err = dumps(&loads_json5(json).unwrap()).unwrap_err();
+        assert!(err.contains(error), "{}: {}", json, err);
+    }
+}
+
+#[test]
+fn round_trips_tagged_datetimes() {
+    let tagged = TomlOptions {
+        datetimes: DateTimePolicy::Tagged,
+    };
+    let toml = "a = 1979-05-27T07:32:00Z\nb = 07:32:00\nc = [1979-05-27]\n";
+    let element = loads_with(toml, &tagged).unwrap();
+    assert_eq!(dumps_with(&element, &tagged).unwrap(), toml);
+    // Without the policy they're ordinary tables
+    assert!(dumps(&element).unwrap().contains("[a]"));
+    let mismatched = loads_json5(r#"{"a": {"type": "date-local", "value": "07:32:00"}}"#).unwrap();
+    assert!(dumps_with(&mismatched, &tagged).unwrap().contains("[a]"));
+}
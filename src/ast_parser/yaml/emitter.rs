@@ -0,0 +1,172 @@
+// Writes JSONElements as block-style YAML
+
+use crate::ast_parser::escape;
+use crate::ast_parser::math::number::Number;
+use crate::ast_parser::yaml::INDICATORS;
+use crate::rustifier::JSONElement;
+use std::fmt::Write;
+
+// Words YAML 1.1 readers take for booleans or null, which have to be quoted to stay strings
+const RESERVED: [&str; 11] = [
+    "null", "~", "true", "false", "yes", "no", "on", "off", "y", "n", "",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Position {
+    Document,
+    // After a `key:`
+    Value,
+    // After a `- `
+    Entry,
+}
+
+/// Writes block-style YAML with two spaces of indentation and sorted keys, ending in a line
+/// break. Only empty collections are written in flow style, and strings with line breaks are
+/// written as literal block scalars.
+pub fn emit(element: &JSONElement) -> String {
+    let mut output = String::new();
+    write_node(&mut output, element, 0, Position::Document);
+    output
+}
+
+// Writes `element` at `position`, finishing its last line. The lines of a block collection in it
+// are indented by `indent`, except a first one that can go on the current line.
+fn write_node(output: &mut String, element: &JSONElement, indent: usize, position: Position) {
+    let indentation = " ".repeat(indent);
+    match element {
+        JSONElement::Object(members) if !members.is_empty() => {
+            let mut members: Vec<_> = members.iter().collect();
+            members.sort_by_key(|(key, _)| *key);
+            for (i, (key, value)) in members.into_iter().enumerate() {
+                if i > 0 || position == Position::Value {
+                    output.push('\n');
+                    output.push_str(&indentation);
+                }
+                output.push_str(&string_scalar(key));
+                output.push(':');
+                write_node(output, value, indent + 2, Position::Value);
+            }
+        }
+        JSONElement::Array(elements) if !elements.is_empty() => {
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 || position == Position::Value {
+                    output.push('\n');
+                    output.push_str(&indentation);
+                }
+                output.push_str("- ");
+                write_node(output, element, indent + 2, Position::Entry);
+            }
+        }
+        _ => {
+            if position == Position::Value {
+                output.push(' ');
+            }
+            match element {
+                JSONElement::String(s) if is_literal_block(s) => {
+                    write_literal_block(output, s, indent.max(2))
+                }
+                _ => output.push_str(&scalar(element)),
+            }
+        }
+    }
+    if position == Position::Document && !output.ends_with('\n') {
+        output.push('\n');
+    }
+}
+
+fn scalar(element: &JSONElement) -> String {
+    match element {
+        JSONElement::String(s) => string_scalar(s),
+        JSONElement::Number(n) => n.to_string(),
+        // The JSON schema has no plain form for these, so they need a tag
+        JSONElement::Float(f) if f.is_nan() => "!!float .nan".to_string(),
+        JSONElement::Float(f) if f.is_infinite() => match *f > 0.0 {
+            true => "!!float .inf".to_string(),
+            false => "!!float -.inf".to_string(),
+        },
+        JSONElement::Float(f) => Number::Float(*f).to_string(),
+        JSONElement::Boolean(b) => b.to_string(),
+        JSONElement::Null => "null".to_string(),
+        JSONElement::Array(_) => "[]".to_string(),
+        JSONElement::Object(_) => "{}".to_string(),
+    }
+}
+
+fn string_scalar(s: &str) -> String {
+    match is_plain(s) {
+        true => s.to_string(),
+        false => double_quoted(s),
+    }
+}
+
+// YAML can hold any character but these C0 and C1 controls and noncharacters, unescaped
+fn is_printable(c: char) -> bool {
+    matches!(c,
+        '\t' | '\n' | '\r' | ' '..='~' | '\u{85}' | '\u{a0}'..='\u{d7ff}'
+        | '\u{e000}'..='\u{fffd}' | '\u{10000}'..)
+}
+
+// Whether `s` reads back as the same string without quotes, in YAML 1.2 and in YAML 1.1, which
+// reads things like `yes`, `0x1F` and `2001-12-14` as other types. Anything starting like a
+// number is quoted rather than trying to match all of 1.1's formats.
+fn is_plain(s: &str) -> bool {
+    let mut characters = s.chars();
+    let Some(first) = characters.next() else {
+        return false;
+    };
+    let second = characters.next();
+    let numeric = first.is_ascii_digit()
+        || (matches!(first, '+' | '.') && second.is_some_and(|c| c.is_ascii_digit() || c == '.'));
+    let lowercase = s.to_lowercase();
+    !numeric
+        && !INDICATORS.contains(first)
+        && !RESERVED.contains(&lowercase.as_str())
+        && ![".inf", ".nan", "+.inf"].contains(&lowercase.as_str())
+        && !first.is_whitespace()
+        && !s.ends_with([' ', ':'])
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && s.chars().all(|c| c >= ' ' && is_printable(c))
+}
+
+// JSON's escapes all work in YAML, which also needs a few more characters escaped
+fn double_quoted(s: &str) -> String {
+    escape(s).chars().fold(String::new(), |mut output, c| {
+        match is_printable(c) {
+            true => output.push(c),
+            false => {
+                let _ = write!(output, "\\u{:04x}", c as u32);
+            }
+        }
+        output
+    })
+}
+
+// Whether `s` can be written as a literal block scalar, which needs more than one line, and a
+// first line with text that doesn't start with a space so its indentation can be found
+fn is_literal_block(s: &str) -> bool {
+    s.contains('\n')
+        && s.chars()
+            .all(|c| c == '\n' || (c != '\r' && is_printable(c)))
+        && s.split('\n')
+            .find(|line| !line.trim_start_matches(' ').is_empty())
+            .is_some_and(|line| !line.starts_with([' ', '\t']))
+}
+
+// Writes a `|` block scalar, with the chomping indicator that keeps the line breaks at the end
+fn write_literal_block(output: &mut String, s: &str, indent: usize) {
+    let (body, chomping) = match s.strip_suffix('\n') {
+        Some(body) if body.ends_with('\n') => (body, "+"),
+        Some(body) => (body, ""),
+        None => (s, "-"),
+    };
+    output.push('|');
+    output.push_str(chomping);
+    for line in body.split('\n') {
+        output.push('\n');
+        if !line.is_empty() {
+            output.push_str(&" ".repeat(indent));
+            output.push_str(line);
+        }
+    }
+}
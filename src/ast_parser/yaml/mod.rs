@@ -0,0 +1,969 @@
+use crate::ast_parser::*;
+use crate::rustifier::{self, JSONElement};
+use crate::{boxer, choice};
+use std::collections::HashMap;
+
+pub mod emitter;
+mod tests;
+
+// Nesting deeper than this is rejected rather than risking the stack
+const MAX_DEPTH: usize = 256;
+
+// Aliases can't expand the document into more nodes than this, so a few lines of anchors that
+// each refer to the one before can't blow up into billions of nodes
+const MAX_NODES: usize = 1_000_000;
+
+// Characters that can't start a plain scalar
+const INDICATORS: &str = "-?:,[]{}#&*!|>'\"%@`";
+
+// Characters that end a plain scalar or an anchor inside a flow collection
+const FLOW_INDICATORS: &str = ",[]{}";
+
+/// Parses a YAML 1.2 document: block and flow collections, every scalar style, comments, anchors
+/// and aliases. Plain scalars are read with the JSON schema, so only `null`, `true`, `false` and
+/// JSON numbers are anything but strings.
+pub fn loads(s: &str) -> Result<JSONElement, String> {
+    // The parsers only look for \n as a line break
+    let s = s.replace("\r\n", "\n");
+    let (rest_of_string, node) = DocumentParser().parse(&s)?;
+    if !rest_of_string.is_empty() {
+        Err(format!("Trailing data: {}", rest_of_string))
+    } else {
+        to_element(&node)
+    }
+}
+
+/// Serializes as block-style YAML with sorted keys, quoting strings only where a YAML 1.1 or 1.2
+/// reader would otherwise take them for something else
+pub fn dumps(element: &JSONElement) -> String {
+    emitter::emit(element)
+}
+
+// A node as the parsers here read it. Anchors and aliases only mean something inside the one
+// document, so they're kept in this type until to_element resolves them.
+#[derive(Debug)]
+enum Node<'i> {
+    Scalar(JSONElement),
+    Sequence(Vec<Node<'i>>),
+    Mapping(Vec<(Node<'i>, Node<'i>)>),
+    Anchor(&'i str, Box<Node<'i>>),
+    Alias(&'i str),
+}
+
+type NodeResult<'i> = Result<(&'i str, Node<'i>), ErrorType>;
+
+// Like Parser, but for the parsers here, which give back a Node rather than an ASTNode
+trait NodeParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i>;
+}
+
+fn skip_blanks(input: &str) -> &str {
+    input.trim_start_matches([' ', '\t', '\r'])
+}
+
+// Whether nothing but a comment is left on the line
+fn ends_line(input: &str) -> bool {
+    let rest = skip_blanks(input);
+    rest.is_empty() || rest.starts_with(['\n', '#'])
+}
+
+// The input after the rest of the current line, which can only hold a comment
+fn end_line(input: &str) -> Result<&str, ErrorType> {
+    let rest = skip_blanks(input);
+    let rest = match rest.strip_prefix('#') {
+        Some(comment) => &comment[comment.find('\n').unwrap_or(comment.len())..],
+        None => rest,
+    };
+    match rest.strip_prefix('\n') {
+        Some(next) => Ok(next),
+        None if rest.is_empty() => Ok(rest),
+        None => Err(format!(
+            "{} was left at the end of a line",
+            prefix(rest, 10)
+        )),
+    }
+}
+
+// Moves from the start of a line past any that are blank or only hold a comment
+fn skip_blank_lines(mut input: &str) -> &str {
+    loop {
+        match end_line(input) {
+            Ok(next) if next.len() < input.len() => input = next,
+            _ => return input,
+        }
+    }
+}
+
+fn skip_to_next_line(input: &str) -> Result<&str, ErrorType> {
+    Ok(skip_blank_lines(end_line(input)?))
+}
+
+fn indentation(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_sequence_entry(input: &str) -> bool {
+    input
+        .strip_prefix('-')
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with([' ', '\t', '\r', '\n']))
+}
+
+// Whether the line is `---` or `...`, which start and end documents
+fn is_document_marker(line: &str) -> bool {
+    (line.starts_with("---") || line.starts_with("..."))
+        && matches!(
+            line[3..].chars().next(),
+            None | Some(' ' | '\t' | '\r' | '\n')
+        )
+}
+
+// The length of an anchor or alias name
+fn name_length(input: &str) -> usize {
+    input
+        .find(|c: char| c.is_whitespace() || FLOW_INDICATORS.contains(c))
+        .unwrap_or(input.len())
+}
+
+#[derive(Default)]
+struct Properties<'i> {
+    anchor: Option<&'i str>,
+    tag: Option<&'i str>,
+}
+
+// The anchor and tag before a node, in either order. The input returned is before any blanks
+// after them.
+fn parse_properties(input: &str) -> Result<(&str, Properties<'_>), ErrorType> {
+    let mut properties = Properties::default();
+    let mut rest = input;
+    loop {
+        let start = skip_blanks(rest);
+        let (name, slot) = match start.chars().next() {
+            Some('&') => (&start[1..], &mut properties.anchor),
+            Some('!') => (start, &mut properties.tag),
+            _ => return Ok((rest, properties)),
+        };
+        // A verbatim tag like `!<tag:yaml.org,2002:str>` can have commas in it
+        let length = match name.strip_prefix("!<") {
+            Some(verbatim) => verbatim.find('>').map_or(0, |end| end + 3),
+            None => name_length(name),
+        };
+        if length == 0 || slot.is_some() {
+            return Err(format!(
+                "{} has an invalid anchor or tag",
+                prefix(start, 10)
+            ));
+        }
+        *slot = Some(&name[..length]);
+        rest = &name[length..];
+    }
+}
+
+fn apply_properties<'i>(node: Node<'i>, properties: Properties<'i>) -> Result<Node<'i>, ErrorType> {
+    let node = match properties.tag {
+        Some(tag) => apply_tag(node, tag)?,
+        None => node,
+    };
+    Ok(match properties.anchor {
+        Some(anchor) => Node::Anchor(anchor, Box::new(node)),
+        None => node,
+    })
+}
+
+// Reads a node the way a tag says to. Only the non-specific `!` and the JSON schema's tags are
+// supported, written like `!!int` or `!<tag:yaml.org,2002:int>`.
+fn apply_tag<'i>(node: Node<'i>, tag: &str) -> Result<Node<'i>, ErrorType> {
+    let name = match tag {
+        "!" => "!",
+        _ => tag
+            .strip_prefix("!!")
+            .or_else(|| {
+                tag.strip_prefix("!<tag:yaml.org,2002:")
+                    .and_then(|name| name.strip_suffix('>'))
+            })
+            .ok_or_else(|| format!("The tag {} isn't supported", tag))?,
+    };
+    if matches!(
+        (name, &node),
+        ("!", Node::Sequence(_) | Node::Mapping(_))
+            | ("seq", Node::Sequence(_))
+            | ("map", Node::Mapping(_))
+    ) {
+        return Ok(node);
+    }
+    // An empty node counts as an empty scalar
+    let text = match &node {
+        Node::Scalar(JSONElement::String(text)) => Some(text.as_str()),
+        Node::Scalar(JSONElement::Null) => Some(""),
+        _ => None,
+    };
+    let resolved = match (name, text) {
+        ("!" | "str", Some(text)) => Some(JSONElement::String(text.to_string())),
+        ("null", Some("" | "~" | "null" | "Null" | "NULL")) => Some(JSONElement::Null),
+        ("bool", Some("true")) => Some(JSONElement::Boolean(true)),
+        ("bool", Some("false")) => Some(JSONElement::Boolean(false)),
+        ("int", Some(text)) => text.parse::<i64>().ok().map(|n| match n.try_into() {
+            Ok(n) => JSONElement::Number(n),
+            Err(_) => JSONElement::Float(n as f64),
+        }),
+        ("float", Some(text)) => parse_float(text).map(JSONElement::Float),
+        _ => None,
+    };
+    resolved.map(Node::Scalar).ok_or_else(|| {
+        format!(
+            "{:?} can't be read as {}",
+            text.unwrap_or("a collection"),
+            tag
+        )
+    })
+}
+
+fn parse_float(text: &str) -> Option<f64> {
+    match text {
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => Some(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => Some(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => Some(f64::NAN),
+        _ if text.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c)) => {
+            text.parse().ok()
+        }
+        _ => None,
+    }
+}
+
+// Where a block node is, which decides what can start on the same line and how far the lines
+// under it have to be indented
+#[derive(Clone, Copy, PartialEq)]
+enum Context {
+    Document,
+    MappingValue,
+    SequenceEntry,
+}
+
+// A whole YAML document: any directives, an optional `---` line, a single node and an optional
+// `...` line, with comments allowed around them. Streams of several documents aren't supported.
+struct DocumentParser();
+impl NodeParser for DocumentParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        let mut rest = skip_blank_lines(input.strip_prefix('\u{feff}').unwrap_or(input));
+        let mut directives = false;
+        while rest.starts_with('%') {
+            directives = true;
+            rest = skip_blank_lines(&rest[rest.find('\n').unwrap_or(rest.len())..]);
+        }
+        let column = match rest.strip_prefix("---") {
+            Some(after) if is_document_marker(rest) => {
+                rest = after;
+                3
+            }
+            _ if directives => return Err("Directives have to be followed by ---".to_string()),
+            _ => 0,
+        };
+        let node_parser = ValueParser {
+            indent: -1,
+            column,
+            context: Context::Document,
+            depth: 0,
+        };
+        let (rest, node) = node_parser.parse(rest)?;
+        let mut rest = skip_blank_lines(rest);
+        if let Some(after) = rest
+            .strip_prefix("...")
+            .filter(|_| is_document_marker(rest))
+        {
+            rest = skip_to_next_line(after)?;
+        }
+        Ok((rest, node))
+    }
+}
+
+// A node in a block collection, or a document's node. `indent` is the indentation of the
+// collection it's in, -1 for the document, and `column` is where the input starts, which a block
+// collection on the same line takes as its indentation, like the mapping in `- a: 1`. The input
+// left is at the start of the next line that isn't blank.
+struct ValueParser {
+    indent: isize,
+    column: usize,
+    context: Context,
+    depth: usize,
+}
+
+impl NodeParser for ValueParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        if self.depth > MAX_DEPTH {
+            return Err(format!("YAML is nested more than {} deep", MAX_DEPTH));
+        }
+        let (rest, properties) = parse_properties(input)?;
+        let content = skip_blanks(rest);
+        if !ends_line(content) {
+            let column = self.column + input.len() - content.len();
+            // Only a mapping value can't have a block collection start on its own line
+            let compact = self.context != Context::MappingValue;
+            let tagged = properties.tag.is_some();
+            let (rest, node) = self.parse_content(content, column, compact, tagged)?;
+            return Ok((rest, apply_properties(node, properties)?));
+        }
+        let next = skip_to_next_line(content)?;
+        let indentation = indentation(next);
+        let line = &next[indentation..];
+        // A sequence can be a mapping value without being indented past the keys
+        let nested = !line.is_empty()
+            && !is_document_marker(next)
+            && (indentation as isize > self.indent
+                || (self.context == Context::MappingValue
+                    && indentation as isize == self.indent
+                    && is_sequence_entry(line)));
+        if !nested {
+            let empty = Node::Scalar(JSONElement::Null);
+            return Ok((next, apply_properties(empty, properties)?));
+        }
+        if line.starts_with('\t') {
+            return Err(format!(
+                "{} is indented with a tab, which YAML doesn't allow",
+                prefix(line.trim_start(), 10)
+            ));
+        }
+        let tagged = properties.tag.is_some();
+        let (rest, node) = self.parse_content(line, indentation, true, tagged)?;
+        Ok((rest, apply_properties(node, properties)?))
+    }
+}
+
+impl ValueParser {
+    // The node starting at `input`, which is at `column`. `compact` is whether that can be a
+    // block collection, and `tagged` whether a tag before it is left to read a scalar.
+    fn parse_content<'i>(
+        &self,
+        input: &'i str,
+        column: usize,
+        compact: bool,
+        tagged: bool,
+    ) -> NodeResult<'i> {
+        let depth = self.depth + 1;
+        if input.starts_with(['|', '>']) {
+            BlockScalarParser {
+                indent: self.indent,
+            }
+            .parse(input)
+        } else if compact && is_sequence_entry(input) {
+            BlockSequenceParser { column, depth }.parse(input)
+        } else if compact && (KeyParser { depth }).parse(input).is_ok() {
+            BlockMappingParser { column, depth }.parse(input)
+        } else {
+            let node_parser = FlowNodeParser {
+                indent: self.indent,
+                flow: false,
+                depth,
+                tagged,
+            };
+            let (rest, node) = node_parser.parse(input)?;
+            Ok((skip_to_next_line(rest)?, node))
+        }
+    }
+}
+
+// The next line of a block collection whose entries are at `column`, or None if the collection
+// ended before it
+fn next_entry(input: &str, column: usize) -> Result<Option<&str>, ErrorType> {
+    let indentation = indentation(input);
+    if input.is_empty() || is_document_marker(input) || indentation < column {
+        return Ok(None);
+    }
+    if indentation > column {
+        return Err(format!(
+            "{} is indented more than the entries before it",
+            prefix(&input[indentation..], 10)
+        ));
+    }
+    Ok(Some(&input[indentation..]))
+}
+
+// A block sequence, starting at the `-` of its first entry, which is at `column`
+struct BlockSequenceParser {
+    column: usize,
+    depth: usize,
+}
+
+impl NodeParser for BlockSequenceParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        let entry_parser = ValueParser {
+            indent: self.column as isize,
+            column: self.column + 1,
+            context: Context::SequenceEntry,
+            depth: self.depth,
+        };
+        let mut elements = vec![];
+        let mut rest = input;
+        loop {
+            let (next, node) = entry_parser.parse(&rest[1..])?;
+            elements.push(node);
+            match next_entry(next, self.column)? {
+                Some(line) if is_sequence_entry(line) => rest = line,
+                _ => return Ok((next, Node::Sequence(elements))),
+            }
+        }
+    }
+}
+
+// A block mapping, starting at its first key, which is at `column`
+struct BlockMappingParser {
+    column: usize,
+    depth: usize,
+}
+
+impl NodeParser for BlockMappingParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        let mut pairs = vec![];
+        let mut rest = input;
+        loop {
+            let (after_key, key) = KeyParser { depth: self.depth }.parse(rest)?;
+            let value_parser = ValueParser {
+                indent: self.column as isize,
+                column: self.column + rest.len() - after_key.len(),
+                context: Context::MappingValue,
+                depth: self.depth,
+            };
+            let (next, value) = value_parser.parse(after_key)?;
+            pairs.push((key, value));
+            match next_entry(next, self.column)? {
+                Some(line) => rest = line,
+                None => return Ok((next, Node::Mapping(pairs))),
+            }
+        }
+    }
+}
+
+// An implicit key in a block mapping and the `:` after it, all on one line
+struct KeyParser {
+    depth: usize,
+}
+
+impl NodeParser for KeyParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        let key_parser = FlowNodeParser {
+            // So a plain key can't go on to the next line
+            indent: isize::MAX,
+            flow: false,
+            depth: self.depth,
+            tagged: false,
+        };
+        let not_a_key = || format!("{} isn't a mapping key", prefix(input, 20));
+        let (rest, key) = key_parser.parse(input).map_err(|_| not_a_key())?;
+        let rest = skip_blanks(rest);
+        // A quoted key can be right next to its `:`, like in JSON
+        match rest.strip_prefix(':') {
+            Some(after)
+                if after.is_empty()
+                    || after.starts_with([' ', '\t', '\r', '\n'])
+                    || input.starts_with(['"', '\'']) =>
+            {
+                Ok((after, key))
+            }
+            _ => Err(not_a_key()),
+        }
+    }
+}
+
+// An alias, a flow collection or a quoted or plain scalar, after any anchor and tag. `flow` is
+// whether it's inside a flow collection, where plain scalars also end at `,[]{}`, and a plain
+// scalar can only go on to lines indented past `indent`. `tagged` is whether the node has a tag
+// that comes before where it starts, like in a block mapping value.
+struct FlowNodeParser {
+    indent: isize,
+    flow: bool,
+    depth: usize,
+    tagged: bool,
+}
+
+impl NodeParser for FlowNodeParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        if self.depth > MAX_DEPTH {
+            return Err(format!("YAML is nested more than {} deep", MAX_DEPTH));
+        }
+        let (rest, properties) = parse_properties(input)?;
+        let rest = skip_blanks(rest);
+        let has_properties = properties.anchor.is_some() || properties.tag.is_some();
+        let element_parser = FlowNodeParser {
+            indent: self.indent,
+            flow: true,
+            depth: self.depth + 1,
+            tagged: false,
+        };
+        let (rest, node) = match rest.chars().next() {
+            Some('*') if !has_properties => {
+                let length = name_length(&rest[1..]);
+                if length == 0 {
+                    return Err(format!("{} is an alias without a name", prefix(rest, 10)));
+                }
+                let (name, rest) = rest[1..].split_at(length);
+                return Ok((rest, Node::Alias(name)));
+            }
+            Some('[') => {
+                let element = |input: &'i str| element_parser.parse(input);
+                let (rest, elements) =
+                    parse_items(rest, ("[", ",", "]"), &element, &FlowTriviaParser())?;
+                (rest, Node::Sequence(elements))
+            }
+            Some('{') => {
+                let pair = |input: &'i str| parse_flow_pair(input, &element_parser);
+                let (rest, pairs) = parse_items(rest, ("{", ",", "}"), &pair, &FlowTriviaParser())?;
+                (rest, Node::Mapping(pairs))
+            }
+            Some('"') => DoubleQuotedParser().parse(rest)?,
+            Some('\'') => SingleQuotedParser().parse(rest)?,
+            // Properties can stand on their own for an empty node
+            None | Some(',' | ']' | '}' | '\n' | '#') if has_properties => {
+                (rest, Node::Scalar(JSONElement::Null))
+            }
+            _ => PlainScalarParser {
+                indent: self.indent,
+                flow: self.flow,
+                resolve: !self.tagged && properties.tag.is_none(),
+            }
+            .parse(rest)?,
+        };
+        Ok((rest, apply_properties(node, properties)?))
+    }
+}
+
+// A `#` comment, up to the end of its line
+struct HashCommentParser();
+impl Parser for HashCommentParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        let (comment, _) = LiteralParser("#").parse(input)?;
+        Ok((
+            &comment[comment.find('\n').unwrap_or(comment.len())..],
+            None,
+        ))
+    }
+}
+
+// Whitespace, line breaks and comments, which can go between the tokens of a flow collection
+struct FlowTriviaParser();
+impl Parser for FlowTriviaParser {
+    fn parse<'i>(&self, input: &'i str) -> ParseResult<'i> {
+        OptionParser(RepeatParser(choice!(
+            LiteralParser(" "),
+            LiteralParser("\t"),
+            LiteralParser("\n"),
+            LiteralParser("\r"),
+            HashCommentParser()
+        )))
+        .parse(input)
+    }
+}
+
+// A member of a flow mapping, read with `node_parser`. The value can be left out, like in
+// `{a, b: 1}`, and is then null.
+fn parse_flow_pair<'i>(
+    input: &'i str,
+    node_parser: &FlowNodeParser,
+) -> Result<(&'i str, (Node<'i>, Node<'i>)), ErrorType> {
+    let (rest, key) = node_parser.parse(input)?;
+    let (after_trivia, _) = FlowTriviaParser().parse(rest)?;
+    let (rest, value) = match after_trivia.strip_prefix(':') {
+        Some(after) => {
+            let (after, _) = FlowTriviaParser().parse(after)?;
+            match after.starts_with([',', '}']) {
+                true => (after, Node::Scalar(JSONElement::Null)),
+                false => node_parser.parse(after)?,
+            }
+        }
+        None => (rest, Node::Scalar(JSONElement::Null)),
+    };
+    Ok((rest, (key, value)))
+}
+
+// An unquoted scalar, which can go on over several lines. A single line break between them
+// becomes a space, and each blank line a line feed. `resolve` is whether to read it with the
+// JSON schema rather than keep it as a string for a tag to read.
+struct PlainScalarParser {
+    indent: isize,
+    flow: bool,
+    resolve: bool,
+}
+
+impl NodeParser for PlainScalarParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        let mut characters = input.chars();
+        let can_start = match characters.next() {
+            None => false,
+            Some('-' | '?' | ':') => characters
+                .next()
+                .is_some_and(|c| !(c.is_whitespace() || self.flow && FLOW_INDICATORS.contains(c))),
+            Some(c) => !c.is_whitespace() && !INDICATORS.contains(c),
+        };
+        if !can_start {
+            return Err(format!("{} didn't start a scalar", prefix(input, 10)));
+        }
+        let mut text = String::new();
+        let mut line = input;
+        loop {
+            let content = line[..self.line_length(line)].trim_end_matches([' ', '\t', '\r']);
+            text.push_str(content);
+            let end = &line[content.len()..];
+            let Some(mut next) = skip_blanks(end).strip_prefix('\n') else {
+                return Ok((end, self.node(text)));
+            };
+            let mut breaks = 0;
+            while let Some(after) = skip_blanks(next).strip_prefix('\n') {
+                breaks += 1;
+                next = after;
+            }
+            let continuation = skip_blanks(next);
+            let ends = continuation.is_empty()
+                || continuation.starts_with('#')
+                || is_document_marker(next)
+                || match self.flow {
+                    true => continuation.starts_with([',', '[', ']', '{', '}', ':']),
+                    false => indentation(next) as isize <= self.indent,
+                };
+            if ends {
+                return Ok((end, self.node(text)));
+            }
+            match breaks {
+                0 => text.push(' '),
+                _ => text.push_str(&"\n".repeat(breaks)),
+            }
+            line = continuation;
+        }
+    }
+}
+
+impl PlainScalarParser {
+    // How much of `line` is part of the scalar, which ends at a line break, a `: `, a ` #` and,
+    // in a flow collection, a flow indicator
+    fn line_length(&self, line: &str) -> usize {
+        for (i, c) in line.char_indices() {
+            let next = line[i + c.len_utf8()..].chars().next();
+            let ends = match c {
+                '\n' => true,
+                ':' => next.is_none_or(|next| {
+                    next.is_whitespace() || (self.flow && FLOW_INDICATORS.contains(next))
+                }),
+                '#' => line[..i].ends_with([' ', '\t']),
+                _ => self.flow && FLOW_INDICATORS.contains(c),
+            };
+            if ends {
+                return i;
+            }
+        }
+        line.len()
+    }
+
+    fn node<'i>(&self, text: String) -> Node<'i> {
+        Node::Scalar(match self.resolve {
+            true => infer_scalar(text),
+            false => JSONElement::String(text),
+        })
+    }
+}
+
+// Folds the line break at the start of `input` along with any blank lines after it, and gives
+// back the input at the next line's content. An escaped line break is left out rather than
+// becoming a space.
+fn fold_break<'i>(input: &'i str, text: &mut String, escaped: bool) -> &'i str {
+    let mut breaks = 0;
+    let mut rest = input;
+    while let Some(after) = rest.strip_prefix('\n') {
+        breaks += 1;
+        rest = skip_blanks(after);
+    }
+    match breaks {
+        1 if !escaped => text.push(' '),
+        _ => text.push_str(&"\n".repeat(breaks - 1)),
+    }
+    rest
+}
+
+// A double-quoted scalar, which can use escapes and go on over several lines
+struct DoubleQuotedParser();
+impl NodeParser for DoubleQuotedParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        let (mut rest, _) = LiteralParser("\"").parse(input)?;
+        let mut text = String::new();
+        // Spaces and tabs only count if the line goes on after them
+        let mut blanks = String::new();
+        while let Some(c) = rest.chars().next() {
+            let after = &rest[c.len_utf8()..];
+            if c != '\n' && c != ' ' && c != '\t' {
+                text.push_str(&blanks);
+                blanks.clear();
+            }
+            rest = match c {
+                '"' => return Ok((after, Node::Scalar(JSONElement::String(text)))),
+                ' ' | '\t' => {
+                    blanks.push(c);
+                    after
+                }
+                '\n' => {
+                    blanks.clear();
+                    fold_break(rest, &mut text, false)
+                }
+                '\\' if skip_blanks(after).starts_with('\n') => {
+                    fold_break(skip_blanks(after), &mut text, true)
+                }
+                '\\' => {
+                    let (c, after) = parse_escape(after).ok_or_else(|| {
+                        format!("string {} has an invalid escape", prefix(input, 10))
+                    })?;
+                    text.push(c);
+                    after
+                }
+                _ => {
+                    text.push(c);
+                    after
+                }
+            };
+        }
+        Err(format!("string {} was never closed", prefix(input, 10)))
+    }
+}
+
+// The character an escape stands for, given the input after its backslash
+fn parse_escape(input: &str) -> Option<(char, &str)> {
+    let c = input.chars().next()?;
+    let after = &input[c.len_utf8()..];
+    let hex = |digits: usize| {
+        let code = after
+            .get(..digits)
+            .filter(|code| code.bytes().all(|b| b.is_ascii_hexdigit()))?;
+        let c = char::from_u32(u32::from_str_radix(code, 16).ok()?)?;
+        Some((c, &after[digits..]))
+    };
+    let escaped = match c {
+        '0' => '\0',
+        'a' => '\u{7}',
+        'b' => '\u{8}',
+        't' | '\t' => '\t',
+        'n' => '\n',
+        'v' => '\u{b}',
+        'f' => '\u{c}',
+        'r' => '\r',
+        'e' => '\u{1b}',
+        ' ' | '"' | '/' | '\\' => c,
+        'N' => '\u{85}',
+        '_' => '\u{a0}',
+        'L' => '\u{2028}',
+        'P' => '\u{2029}',
+        'x' => return hex(2),
+        'u' => return hex(4),
+        'U' => return hex(8),
+        _ => return None,
+    };
+    Some((escaped, after))
+}
+
+// A single-quoted scalar, where `''` is the only escape, which can go on over several lines
+struct SingleQuotedParser();
+impl NodeParser for SingleQuotedParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        let (mut rest, _) = LiteralParser("'").parse(input)?;
+        let mut text = String::new();
+        let mut blanks = String::new();
+        while let Some(c) = rest.chars().next() {
+            let after = &rest[c.len_utf8()..];
+            if c != '\n' && c != ' ' && c != '\t' {
+                text.push_str(&blanks);
+                blanks.clear();
+            }
+            rest = match c {
+                '\'' => match after.strip_prefix('\'') {
+                    Some(after) => {
+                        text.push('\'');
+                        after
+                    }
+                    None => return Ok((after, Node::Scalar(JSONElement::String(text)))),
+                },
+                ' ' | '\t' => {
+                    blanks.push(c);
+                    after
+                }
+                '\n' => {
+                    blanks.clear();
+                    fold_break(rest, &mut text, false)
+                }
+                _ => {
+                    text.push(c);
+                    after
+                }
+            };
+        }
+        Err(format!("string {} was never closed", prefix(input, 10)))
+    }
+}
+
+// A literal (`|`) or folded (`>`) block scalar, whose lines have to be indented past `indent`.
+// The header can give the indentation, which is otherwise that of the first line with text, and
+// whether to strip (`-`) or keep (`+`) the line breaks at the end rather than keep just one.
+struct BlockScalarParser {
+    indent: isize,
+}
+
+impl NodeParser for BlockScalarParser {
+    fn parse<'i>(&self, input: &'i str) -> NodeResult<'i> {
+        let literal = input.starts_with('|');
+        let mut header = &input[1..];
+        let (mut chomping, mut explicit) = (None, None);
+        for _ in 0..2 {
+            match header.chars().next() {
+                Some(c @ ('-' | '+')) if chomping.is_none() => chomping = Some(c),
+                Some(c @ '1'..='9') if explicit.is_none() => explicit = c.to_digit(10),
+                _ => break,
+            }
+            header = &header[1..];
+        }
+        if !header.is_empty() && !header.starts_with([' ', '\t', '\r', '\n']) {
+            return Err(format!("{} isn't a block scalar header", prefix(input, 10)));
+        }
+        let mut rest = end_line(header)?;
+        let minimum = (self.indent + 1).max(0) as usize;
+        let width = match explicit {
+            Some(n) => (self.indent + n as isize).max(0) as usize,
+            None => rest
+                .split('\n')
+                .find(|line| !line.trim_start_matches([' ', '\r']).is_empty())
+                .map_or(minimum, |line| indentation(line).max(minimum)),
+        };
+        let mut lines = vec![];
+        while !rest.is_empty() {
+            let end = rest.find('\n').map_or(rest.len(), |i| i + 1);
+            let line = rest[..end].trim_end_matches(['\n', '\r']);
+            let spaces = indentation(line);
+            if spaces == line.len() {
+                lines.push(line.get(width..).unwrap_or(""));
+            } else if spaces >= width && !(width == 0 && is_document_marker(line)) {
+                lines.push(&line[width..]);
+            } else {
+                break;
+            }
+            rest = &rest[end..];
+        }
+        let content = lines
+            .iter()
+            .rposition(|line| !line.is_empty())
+            .map_or(0, |i| i + 1);
+        let trailing = lines.len() - content;
+        let mut text = match literal {
+            true => lines[..content].join("\n"),
+            false => fold_block(&lines[..content]),
+        };
+        match chomping {
+            Some('-') => {}
+            Some(_) => text.push_str(&"\n".repeat(trailing + (content > 0) as usize)),
+            None if content > 0 => text.push('\n'),
+            None => {}
+        }
+        Ok((
+            skip_blank_lines(rest),
+            Node::Scalar(JSONElement::String(text)),
+        ))
+    }
+}
+
+// Joins the lines of a folded scalar. A single line break between two lines of text becomes a
+// space, but breaks are kept as they are around blank lines and lines indented more than the rest.
+fn fold_block(lines: &[&str]) -> String {
+    let indented = |line: &str| line.starts_with([' ', '\t']);
+    let mut text = String::new();
+    let mut previous: Option<&str> = None;
+    let mut blanks = 0;
+    for line in lines {
+        if line.is_empty() {
+            blanks += 1;
+            continue;
+        }
+        match previous {
+            Some(previous) if indented(previous) || indented(line) => {
+                text.push_str(&"\n".repeat(blanks + 1))
+            }
+            Some(_) if blanks == 0 => text.push(' '),
+            _ => text.push_str(&"\n".repeat(blanks)),
+        }
+        text.push_str(line);
+        previous = Some(line);
+        blanks = 0;
+    }
+    text
+}
+
+// Turns the tree DocumentParser gives into a JSONElement, replacing each alias with a copy of
+// what its anchor is on. Scalar keys that aren't strings become their JSON text, like `1` or
+// `true`, and keys that are collections or repeated are errors.
+fn to_element(node: &Node) -> Result<JSONElement, String> {
+    Resolver {
+        anchors: HashMap::new(),
+        nodes: 0,
+    }
+    .element(node)
+}
+
+// The anchors seen so far, with how many nodes each one's element holds, and how many nodes
+// have been made, so aliases can't expand the document past MAX_NODES
+struct Resolver<'i> {
+    anchors: HashMap<&'i str, (JSONElement, usize)>,
+    nodes: usize,
+}
+
+impl<'i> Resolver<'i> {
+    fn count(&mut self, nodes: usize) -> Result<(), String> {
+        self.nodes += nodes;
+        match self.nodes > MAX_NODES {
+            true => Err(format!(
+                "The YAML's aliases expand it to more than {} nodes",
+                MAX_NODES
+            )),
+            false => Ok(()),
+        }
+    }
+
+    fn element(&mut self, node: &Node<'i>) -> Result<JSONElement, String> {
+        self.count(1)?;
+        Ok(match node {
+            Node::Scalar(scalar) => scalar.clone(),
+            Node::Sequence(items) => JSONElement::Array(
+                items
+                    .iter()
+                    .map(|item| self.element(item))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Node::Mapping(pairs) => {
+                let mut members = HashMap::new();
+                for (key, value) in pairs {
+                    let key = match self.element(key)? {
+                        JSONElement::String(key) => key,
+                        JSONElement::Array(_) | JSONElement::Object(_) => {
+                            return Err("YAML mapping keys have to be scalars".to_string())
+                        }
+                        key => rustifier::dumps(&key),
+                    };
+                    let value = self.element(value)?;
+                    if members.insert(key.clone(), value).is_some() {
+                        return Err(format!("YAML mapping has the key {:?} more than once", key));
+                    }
+                }
+                JSONElement::Object(members)
+            }
+            Node::Anchor(name, node) => {
+                let element = self.element(node)?;
+                self.anchors.insert(name, (element.clone(), size(&element)));
+                element
+            }
+            Node::Alias(name) => {
+                let size = match self.anchors.get(name) {
+                    Some((_, size)) => *size,
+                    None => return Err(format!("Alias *{} has no anchor before it", name)),
+                };
+                self.count(size)?;
+                self.anchors[name].0.clone()
+            }
+        })
+    }
+}
+
+// How many elements `element` is made of, counting itself
+fn size(element: &JSONElement) -> usize {
+    1 + match element {
+        JSONElement::Array(elements) => elements.iter().map(size).sum(),
+        JSONElement::Object(members) => members.values().map(size).sum(),
+        _ => 0,
+    }
+}
@@ -0,0 +1,229 @@
+#[cfg(test)]
+use crate::ast_parser::yaml::{dumps, emitter::emit, loads};
+#[cfg(test)]
+use crate::rustifier::{loads_json5, JSONElement};
+
+#[cfg(test)]
+fn assert_loads(yaml: &str, json: &str) {
+    assert_eq!(loads(yaml).unwrap(), loads_json5(json).unwrap(), "{}", yaml);
+}
+
+#[test]
+fn loads_block_collections() {
+    assert_loads(
+        "# a comment\na: 1\nb:\n  c: true # another\n  d:\n  - x\n  - - y\n    - z\n  -\n  - e: f\n    g: null\nh: end\n",
+        r#"{"a": 1, "b": {"c": true, "d": ["x", ["y", "z"], null, {"e": "f", "g": null}]}, "h": "end"}"#,
+    );
+    assert_loads(
+        "- a\n-   - b\n    -\n      c: d\n",
+        r#"["a", ["b", {"c": "d"}]]"#,
+    );
+    assert_loads("key:\n- 1\n- 2\nother: 3", r#"{"key": [1, 2], "other": 3}"#);
+    assert_loads(
+        "\"quoted key\": 1\n'single': 2\n\"json\":3",
+        r#"{"quoted key": 1, "single": 2, "json": 3}"#,
+    );
+    assert_loads("", "null");
+    assert_loads("# only a comment\n", "null");
+    assert_loads("empty:\nafter: 1", r#"{"empty": null, "after": 1}"#);
+}
+
+#[test]
+fn loads_flow_collections() {
+    assert_loads(
+        "[1, -2.5e3, 'a', \"b\", [], {}, plain text, ]",
+        r#"[1, -2500.0, "a", "b", [], {}, "plain text"]"#,
+    );
+    assert_loads(
+        "{a: 1, b, \"c\":2, d: [x, y] # comment\n , e: {f: g}}",
+        r#"{"a": 1, "b": null, "c": 2, "d": ["x", "y"], "e": {"f": "g"}}"#,
+    );
+    assert_loads(
+        "key: [a,\n  b]\nurl: http://x.y/z",
+        r#"{"key": ["a", "b"], "url": "http://x.y/z"}"#,
+    );
+}
+
+#[test]
+fn resolves_plain_scalars_with_the_json_schema() {
+    assert_loads(
+        "[null, true, false, 0, -12, 1.5, 1e3, 2147483648, Null, TRUE, yes, ~, 012, 0x1F, .5, +1, 1_000, .inf]",
+        r#"[null, true, false, 0, -12, 1.5, 1000.0, 2147483648.0, "Null", "TRUE", "yes", "~", "012",
+            "0x1F", ".5", "+1", "1_000", ".inf"]"#,
+    );
+    assert_loads(
+        "a: multi\n  line\n\n  plain\nb: c#not a comment",
+        r#"{"a": "multi line\nplain", "b": "c#not a comment"}"#,
+    ); // Infinity would be written out as null
+    assert_loads("[1e400, -1e400]", r#"["1e400", "-1e400"]"#);
+}
+
+#[test]
+fn loads_quoted_scalars() {
+    assert_loads(
+        r#"["\x41\u00e9\U0001F600\t\N\_\/", 'it''s', "a\
+            b", "folded
+        line
+
+        break  "]"#,
+        "[\"A\u{e9}\u{1f600}\t\u{85}\u{a0}/\", \"it's\", \"ab\", \"folded line\\nbreak  \"]",
+    );
+    assert!(loads(r#""\q""#).is_err());
+    assert!(loads(r#""\uD800""#).is_err());
+    assert!(loads("'never closed").is_err());
+}
+
+#[test]
+fn loads_block_scalars() {
+    assert_loads(
+        "literal: |\n  a\n   b\n\n  c\n\n\nfolded: >\n  a\n  b\n\n  c\n    d\n  e\nafter: 1",
+        r#"{"literal": "a\n b\n\nc\n", "folded": "a b\nc\n  d\ne\n", "after": 1}"#,
+    );
+    assert_loads(
+        "- |-\n  strip\n\n- |+\n  keep\n\n- |2\n    indented\n- >\n\n  leading\n- |\n",
+        r#"["strip", "keep\n\n", "  indented\n", "\nleading\n", ""]"#,
+    );
+    assert_loads("--- |\n  text\n", r#""text\n""#);
+    assert!(loads("a: |x\n  b").is_err());
+}
+
+#[test]
+fn applies_tags() {
+    assert_loads(
+        "[!!str 1, !!int '2', !!float 3, !!bool 'true', !!null '', ! 4, !!str, !<tag:yaml.org,2002:int> 5, !!float -.inf]",
+        r#"["1", 2, 3.0, true, null, "4", "", 5, -Infinity]"#,
+    );
+    assert_loads("!!map {a: !!seq [b]}", r#"{"a": ["b"]}"#);
+    // Block values and whole documents are read by their tag rather than resolved first
+    assert_loads(
+        "a: !!str 123\nb: !!str true\nc: !!float 1\nd: !!int \"7\"\ne: !!str null\nf: !!str\n  8\n",
+        r#"{"a": "123", "b": "true", "c": 1.0, "d": 7, "e": "null", "f": "8"}"#,
+    );
+    assert_loads("- !!str 123\n- !!null null", r#"["123", null]"#);
+    assert_loads("--- !!str true", r#""true""#);
+    for invalid in [
+        "!!int x",
+        "!!bool yes",
+        "!custom a",
+        "!!seq {}",
+        "!!str [a]",
+    ] {
+        assert!(loads(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn resolves_anchors_and_aliases() {
+    assert_loads(
+        "base: &b {x: 1}\ncopy: *b\nlist: &l\n  - &s one\n  - *s\nagain: *l\n",
+        r#"{"base": {"x": 1}, "copy": {"x": 1}, "list": ["one", "one"], "again": ["one", "one"]}"#,
+    );
+    assert!(loads("a: *missing").is_err());
+    assert!(loads("a: &a [*a]").is_err());
+}
+
+#[test]
+fn rejects_alias_bombs() {
+    let mut yaml = "a: &a [lol, lol, lol, lol, lol, lol, lol, lol, lol]\n".to_string();
+    let names: Vec<char> = ('a'..='i').collect();
+    for pair in names.windows(2) {
+        let aliases = vec![format!("*{}", pair[0]); 9].join(", ");
+        yaml.push_str(&format!("{}: &{} [{}]\n", pair[1], pair[1], aliases));
+    }
+    let err = loads(&yaml).unwrap_err();
+    assert!(err.contains("more than"), "{}", err);
+}
+
+#[test]
+fn handles_documents() {
+    assert_loads(
+        "%YAML 1.2\n---\na: 1\n...\n# trailing comment\n",
+        r#"{"a": 1}"#,
+    );
+    assert_loads("--- [1]\n", "[1]");
+    assert_loads("\u{feff}a: 1\r\nb: 2\r\n", r#"{"a": 1, "b": 2}"#);
+    assert!(loads("a: 1\n---\nb: 2").is_err());
+    assert!(loads("%YAML 1.2\na: 1").is_err());
+}
+
+#[test]
+fn rejects_invalid_yaml() {
+    for invalid in [
+        "a: 1\n  b: 2",
+        "a: 1\n- b",
+        "a: b: c",
+        "[1, 2",
+        "{a: 1",
+        "a: 1\na: 2",
+        "[a, b]: c",
+        "a:\n\t- b",
+        "- a\n  - b: c\n - d",
+    ] {
+        assert!(loads(invalid).is_err(), "{:?}", invalid);
+    }
+    let deep = "[".repeat(1000) + &"]".repeat(1000);
+    assert!(loads(&deep).is_err());
+}
+
+#[test]
+fn emits_block_style() {
+    let element = loads_json5(
+        r#"{"b": [1, {"c": "d", "e": [true, null]}, []], "a": {"x": 1.5, "y": {}}, "s": "multi\nline\n"}"#,
+    )
+    .unwrap();
+    // `y` is a boolean in YAML 1.1
+    let expected = "a:\n  x: 1.5\n  \"y\": {}\nb:\n  - 1\n  - c: d\n    e:\n      - true\n      - null\n  - []\ns: |\n  multi\n  line\n";
+    assert_eq!(emit(&element), expected);
+    assert_eq!(dumps(&JSONElement::Number(1)), "1\n");
+    assert_eq!(dumps(&JSONElement::Array(vec![])), "[]\n");
+}
+
+#[test]
+fn quotes_strings_only_when_needed() {
+    for (s, expected) in [
+        ("plain text", "plain text"),
+        ("a:b", "a:b"),
+        ("", "\"\""),
+        ("true", "\"true\""),
+        ("Yes", "\"Yes\""),
+        ("~", "\"~\""),
+        ("12", "\"12\""),
+        ("2001-12-14", "\"2001-12-14\""),
+        (".5", "\".5\""),
+        ("- x", "\"- x\""),
+        ("a: b", "\"a: b\""),
+        ("a #b", "\"a #b\""),
+        (" lead", "\" lead\""),
+        ("trail ", "\"trail \""),
+        ("tab\there", "\"tab\\there\""),
+        ("\u{7f}", "\"\\u007f\""),
+    ] {
+        assert_eq!(
+            dumps(&JSONElement::String(s.to_string())),
+            format!("{}\n", expected)
+        );
+    }
+}
+
+#[test]
+fn round_trips() {
+    for json in [
+        r#"{"nested": {"list": [[1, 2], [], {}, {"k": [null]}], "s": "a: b"}, "n": -0.25}"#,
+        r#"[" lead", "keep\n\n", "strip\nend", "\n\nleading blank", "ends with space\n ", "  \n"]"#,
+        r##"{"1": "one", "": "empty", "#": "hash", "key with: colon": "é\u2028"}"##,
+        r#"[1e300, 2147483648.0, "\u0085", "\t\n\t"]"#,
+    ] {
+        let element = loads_json5(json).unwrap();
+        let yaml = dumps(&element);
+        assert_eq!(loads(&yaml).unwrap(), element, "{}", yaml);
+    }
+    let special = JSONElement::Array(vec![
+        JSONElement::Float(f64::INFINITY),
+        JSONElement::Float(f64::NAN),
+    ]);
+    let JSONElement::Array(elements) = loads(&dumps(&special)).unwrap() else {
+        panic!("not an array");
+    };
+    assert_eq!(elements[0], JSONElement::Float(f64::INFINITY));
+    assert!(matches!(elements[1], JSONElement::Float(f) if f.is_nan()));
+}
@@ -0,0 +1,8 @@
+use std::io::{stdin, stdout};
+
+fn main() {
+    if let Err(err) = json_parser::lsp::run(stdin().lock(), stdout().lock()) {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
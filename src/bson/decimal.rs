@@ -0,0 +1,113 @@
+// IEEE 754-2008 decimal128 in the binary integer decimal encoding BSON uses: a sign bit, a
+// 14-bit exponent and a 113-bit coefficient
+
+const EXPONENT_BIAS: i32 = 6176;
+const MIN_EXPONENT: i32 = -6176;
+const MAX_EXPONENT: i32 = 6111;
+const MAX_DIGITS: usize = 34;
+
+/// Writes a decimal128, given as its 16 little-endian bytes, the way the BSON spec does: in
+/// plain notation when the exponent isn't positive and the number isn't much smaller than
+/// 1e-6, and with an exponent otherwise
+pub fn decimal_to_string(bytes: [u8; 16]) -> String {
+    let bits = u128::from_le_bytes(bytes);
+    let sign = if bits >> 127 == 1 { "-" } else { "" };
+    match (bits >> 122) & 0x1f {
+        0x1f => return "NaN".to_string(),
+        0x1e => return format!("{}Infinity", sign),
+        _ => (),
+    }
+    let (exponent, coefficient) = if (bits >> 125) & 3 == 3 {
+        // This form can only hold coefficients above 10^34, which count as 0
+        ((bits >> 111) & 0x3fff, 0)
+    } else {
+        ((bits >> 113) & 0x3fff, bits & ((1 << 113) - 1))
+    };
+    let exponent = exponent as i32 - EXPONENT_BIAS;
+    let coefficient = if coefficient >= 10u128.pow(MAX_DIGITS as u32) {
+        0
+    } else {
+        coefficient
+    };
+    let digits = coefficient.to_string();
+    let adjusted = exponent + digits.len() as i32 - 1;
+    let body = if exponent == 0 {
+        digits
+    } else if exponent < 0 && adjusted >= -6 {
+        let point = digits.len() as i32 + exponent;
+        match point > 0 {
+            true => format!(
+                "{}.{}",
+                &digits[..point as usize],
+                &digits[point as usize..]
+            ),
+            false => format!("0.{}{}", "0".repeat(-point as usize), digits),
+        }
+    } else {
+        let (first, rest) = digits.split_at(1);
+        let fraction = if rest.is_empty() {
+            String::new()
+        } else {
+            format!(".{}", rest)
+        };
+        let exponent_sign = if adjusted < 0 { '-' } else { '+' };
+        format!("{}{}E{}{}", first, fraction, exponent_sign, adjusted.abs())
+    };
+    format!("{}{}", sign, body)
+}
+
+/// Reads a decimal number, "Infinity" or "NaN" into the 16 little-endian bytes of a
+/// decimal128. Numbers that would need rounding are errors.
+pub fn parse_decimal(text: &str) -> Result<[u8; 16], String> {
+    let invalid = || format!("{:?} isn't a decimal number", text);
+    let (negative, unsigned) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let sign = (negative as u128) << 127;
+    match unsigned.to_ascii_lowercase().as_str() {
+        "nan" => return Ok((0x7cu128 << 120).to_le_bytes()),
+        "inf" | "infinity" => return Ok((sign | 0x78 << 120).to_le_bytes()),
+        _ => (),
+    }
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse().map_err(|_| invalid())?),
+        None => (unsigned, 0i32),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    if whole.len() + fraction.len() == 0
+        || !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    let mut exponent = exponent
+        .checked_sub(fraction.len() as i32)
+        .ok_or_else(invalid)?;
+    let mut digits = format!("{}{}", whole, fraction)
+        .trim_start_matches('0')
+        .to_string();
+    // Trailing zeros can move into the exponent, and the exponent can move into zeros, when
+    // either is out of range
+    while (digits.len() > MAX_DIGITS || exponent < MIN_EXPONENT) && digits.ends_with('0') {
+        digits.pop();
+        exponent += 1;
+    }
+    while exponent > MAX_EXPONENT && !digits.is_empty() && digits.len() < MAX_DIGITS {
+        digits.push('0');
+        exponent -= 1;
+    }
+    if digits.is_empty() {
+        exponent = exponent.clamp(MIN_EXPONENT, MAX_EXPONENT);
+    }
+    if digits.len() > MAX_DIGITS || !(MIN_EXPONENT..=MAX_EXPONENT).contains(&exponent) {
+        return Err(format!(
+            "{:?} can't be stored exactly as a decimal128",
+            text
+        ));
+    }
+    let coefficient: u128 = digits.parse().unwrap_or(0);
+    Ok((sign | ((exponent + EXPONENT_BIAS) as u128) << 113 | coefficient).to_le_bytes())
+}
@@ -0,0 +1,451 @@
+// MongoDB Extended JSON v2: the objects that stand in for the BSON types JSON doesn't have
+
+use crate::bson::decimal::parse_decimal;
+use crate::bson::{
+    sorted, write_cstring, write_document, write_length, write_string, ExtendedJsonMode,
+};
+use crate::cbor::{base64, BASE64_ALPHABET};
+use crate::rustifier::{dumps, JSONElement};
+use std::collections::HashMap;
+
+const DAY: i64 = 86_400_000;
+
+// 9999-12-31T23:59:59.999Z, the last date relaxed mode writes as a string
+const LAST_ISO_DATE: i64 = 253_402_300_799_999;
+
+// The keys that make an object a type wrapper
+const WRAPPERS: [&str; 15] = [
+    "$numberInt",
+    "$numberLong",
+    "$numberDouble",
+    "$numberDecimal",
+    "$oid",
+    "$date",
+    "$binary",
+    "$regularExpression",
+    "$timestamp",
+    "$dbPointer",
+    "$code",
+    "$symbol",
+    "$minKey",
+    "$maxKey",
+    "$undefined",
+];
+
+pub fn object<const N: usize>(members: [(&str, JSONElement); N]) -> JSONElement {
+    JSONElement::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn string(s: impl Into<String>) -> JSONElement {
+    JSONElement::String(s.into())
+}
+
+pub fn int32(n: i32, mode: ExtendedJsonMode) -> JSONElement {
+    match mode {
+        ExtendedJsonMode::Relaxed => JSONElement::Number(n),
+        ExtendedJsonMode::Canonical => object([("$numberInt", string(n.to_string()))]),
+    }
+}
+
+pub fn int64(n: i64, mode: ExtendedJsonMode) -> JSONElement {
+    match (mode, i32::try_from(n)) {
+        (ExtendedJsonMode::Relaxed, Ok(n)) => JSONElement::Number(n),
+        // Floats hold every integer up to 2^53
+        (ExtendedJsonMode::Relaxed, Err(_)) if n.unsigned_abs() <= 1 << 53 => {
+            JSONElement::Float(n as f64)
+        }
+        _ => object([("$numberLong", string(n.to_string()))]),
+    }
+}
+
+pub fn double(f: f64, mode: ExtendedJsonMode) -> JSONElement {
+    match mode {
+        ExtendedJsonMode::Relaxed if f.is_finite() => JSONElement::Float(f),
+        _ => object([("$numberDouble", string(format_double(f)))]),
+    }
+}
+
+// Like "1.0", "-0.0" or "1.5E+300"
+fn format_double(f: f64) -> String {
+    match f {
+        _ if f.is_nan() => "NaN".to_string(),
+        f64::INFINITY => "Infinity".to_string(),
+        f64::NEG_INFINITY => "-Infinity".to_string(),
+        _ => format!("{:?}", f).replace("e-", "E-").replace('e', "E+"),
+    }
+}
+
+pub fn date(milliseconds: i64, mode: ExtendedJsonMode) -> JSONElement {
+    match mode {
+        ExtendedJsonMode::Relaxed if (0..=LAST_ISO_DATE).contains(&milliseconds) => {
+            object([("$date", string(format_date(milliseconds)))])
+        }
+        _ => object([(
+            "$date",
+            object([("$numberLong", string(milliseconds.to_string()))]),
+        )]),
+    }
+}
+
+pub fn binary(subtype: u8, data: &[u8]) -> JSONElement {
+    object([(
+        "$binary",
+        object([
+            ("base64", string(base64(data, BASE64_ALPHABET, true))),
+            ("subType", string(format!("{:02x}", subtype))),
+        ]),
+    )])
+}
+
+pub fn object_id(bytes: &[u8; 12]) -> JSONElement {
+    object([("$oid", string(hex(bytes)))])
+}
+
+pub fn regular_expression(pattern: &str, options: &str) -> JSONElement {
+    object([(
+        "$regularExpression",
+        object([
+            ("pattern", string(pattern)),
+            ("options", string(sort_options(options))),
+        ]),
+    )])
+}
+
+pub fn db_pointer(namespace: String, id: &[u8; 12]) -> JSONElement {
+    object([(
+        "$dbPointer",
+        object([("$ref", string(namespace)), ("$id", object_id(id))]),
+    )])
+}
+
+pub fn timestamp(time: u32, increment: u32) -> JSONElement {
+    object([(
+        "$timestamp",
+        object([
+            ("t", integer(time as i64)),
+            ("i", integer(increment as i64)),
+        ]),
+    )])
+}
+
+fn integer(n: i64) -> JSONElement {
+    match i32::try_from(n) {
+        Ok(n) => JSONElement::Number(n),
+        Err(_) => JSONElement::Float(n as f64),
+    }
+}
+
+// Regular expression options are written in alphabetical order
+fn sort_options(options: &str) -> String {
+    let mut options: Vec<char> = options.chars().collect();
+    options.sort();
+    options.into_iter().collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Writes `members` as the BSON type they stand for if they're a type wrapper, and returns the
+/// type. Objects without any wrapper key are left to be written as documents.
+pub fn write_wrapper(
+    output: &mut Vec<u8>,
+    members: &HashMap<String, JSONElement>,
+    depth: usize,
+) -> Result<Option<u8>, String> {
+    let Some(wrapper) = WRAPPERS.into_iter().find(|key| members.contains_key(*key)) else {
+        return Ok(None);
+    };
+    let value = &members[wrapper];
+    let expected = match wrapper {
+        "$code" if members.contains_key("$scope") => 2,
+        _ => 1,
+    };
+    if members.len() != expected {
+        return Err(format!(
+            "{} can't be in an object with other keys: {}",
+            wrapper,
+            dumps(&JSONElement::Object(members.clone()))
+        ));
+    }
+    let invalid = || format!("Invalid {}: {}", wrapper, dumps(value));
+    let kind = match wrapper {
+        "$numberInt" => {
+            let n: i32 = text(value)
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(invalid)?;
+            output.extend(n.to_le_bytes());
+            0x10
+        }
+        "$numberLong" => {
+            let n: i64 = text(value)
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(invalid)?;
+            output.extend(n.to_le_bytes());
+            0x12
+        }
+        "$numberDouble" => {
+            let f = match text(value).ok_or_else(invalid)? {
+                "Infinity" => f64::INFINITY,
+                "-Infinity" => f64::NEG_INFINITY,
+                "NaN" => f64::NAN,
+                s => s.parse().map_err(|_| invalid())?,
+            };
+            output.extend(f.to_le_bytes());
+            0x01
+        }
+        "$numberDecimal" => {
+            output.extend(parse_decimal(text(value).ok_or_else(invalid)?)?);
+            0x13
+        }
+        "$oid" => {
+            output.extend(parse_object_id(value).ok_or_else(invalid)?);
+            0x07
+        }
+        "$date" => {
+            let milliseconds = match value {
+                JSONElement::String(s) => parse_date(s),
+                JSONElement::Object(_) => fields(value, ["$numberLong"])
+                    .and_then(|[n]| text(n))
+                    .and_then(|s| s.parse().ok()),
+                _ => whole_number(value),
+            };
+            output.extend(milliseconds.ok_or_else(invalid)?.to_le_bytes());
+            0x09
+        }
+        "$binary" => {
+            let [data, subtype] = fields(value, ["base64", "subType"]).ok_or_else(invalid)?;
+            let data = text(data).and_then(decode_base64).ok_or_else(invalid)?;
+            let subtype = text(subtype)
+                .filter(|s| (1..=2).contains(&s.len()))
+                .and_then(|s| u8::from_str_radix(s, 16).ok())
+                .ok_or_else(invalid)?;
+            // The old binary subtype repeats the length inside the data
+            let inner = if subtype == 0x02 { 4 } else { 0 };
+            let length = i32::try_from(data.len() + inner).map_err(|_| invalid())?;
+            output.extend(length.to_le_bytes());
+            output.push(subtype);
+            if subtype == 0x02 {
+                output.extend((data.len() as i32).to_le_bytes());
+            }
+            output.extend(data);
+            0x05
+        }
+        "$regularExpression" => {
+            let [pattern, options] = fields(value, ["pattern", "options"]).ok_or_else(invalid)?;
+            write_cstring(output, text(pattern).ok_or_else(invalid)?)?;
+            write_cstring(output, &sort_options(text(options).ok_or_else(invalid)?))?;
+            0x0b
+        }
+        "$timestamp" => {
+            let [time, increment] = fields(value, ["t", "i"]).ok_or_else(invalid)?;
+            for part in [increment, time] {
+                let part = whole_number(part)
+                    .and_then(|n| u32::try_from(n).ok())
+                    .ok_or_else(invalid)?;
+                output.extend(part.to_le_bytes());
+            }
+            0x11
+        }
+        "$dbPointer" => {
+            let [namespace, id] = fields(value, ["$ref", "$id"]).ok_or_else(invalid)?;
+            let id = fields(id, ["$oid"])
+                .and_then(|[id]| parse_object_id(id))
+                .ok_or_else(invalid)?;
+            write_string(output, text(namespace).ok_or_else(invalid)?);
+            output.extend(id);
+            0x0c
+        }
+        "$code" => {
+            let code = text(value).ok_or_else(invalid)?;
+            match members.get("$scope") {
+                Some(JSONElement::Object(scope)) => {
+                    let start = output.len();
+                    output.extend([0; 4]);
+                    write_string(output, code);
+                    write_document(output, sorted(scope), depth + 1)?;
+                    write_length(output, start)?;
+                    0x0f
+                }
+                Some(scope) => return Err(format!("Invalid $scope: {}", dumps(scope))),
+                None => {
+                    write_string(output, code);
+                    0x0d
+                }
+            }
+        }
+        "$symbol" => {
+            write_string(output, text(value).ok_or_else(invalid)?);
+            0x0e
+        }
+        "$minKey" | "$maxKey" => match value {
+            JSONElement::Number(1) if wrapper == "$minKey" => 0xff,
+            JSONElement::Number(1) => 0x7f,
+            _ => return Err(invalid()),
+        },
+        _ => match value {
+            JSONElement::Boolean(true) => 0x06,
+            _ => return Err(invalid()),
+        },
+    };
+    Ok(Some(kind))
+}
+
+fn text(value: &JSONElement) -> Option<&str> {
+    match value {
+        JSONElement::String(s) => Some(s),
+        _ => None,
+    }
+}
+
+// The values of an object that has exactly these keys
+fn fields<'a, const N: usize>(
+    value: &'a JSONElement,
+    keys: [&str; N],
+) -> Option<[&'a JSONElement; N]> {
+    match value {
+        JSONElement::Object(members) if members.len() == N => {
+            let values: Vec<_> = keys.iter().filter_map(|key| members.get(*key)).collect();
+            values.try_into().ok()
+        }
+        _ => None,
+    }
+}
+
+fn whole_number(value: &JSONElement) -> Option<i64> {
+    match value {
+        JSONElement::Number(n) => Some(*n as i64),
+        JSONElement::Float(f) if f.fract() == 0.0 && f.abs() < 2f64.powi(63) => Some(*f as i64),
+        _ => None,
+    }
+}
+
+fn parse_object_id(value: &JSONElement) -> Option<[u8; 12]> {
+    let s = text(value).filter(|s| s.len() == 24)?;
+    let mut bytes = [0; 12];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(s.get(2 * i..2 * i + 2)?, 16).ok()?;
+    }
+    Some(bytes)
+}
+
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut output = vec![];
+    let (mut group, mut bits) = (0u32, 0);
+    for c in text.trim_end_matches('=').bytes() {
+        let value = BASE64_ALPHABET.iter().position(|a| *a == c)? as u32;
+        group = (group << 6 | value) & 0xffff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            output.push((group >> bits) as u8);
+        }
+    }
+    Some(output)
+}
+
+// Days since 1970-01-01 to a date and back, with Howard Hinnant's algorithms for the
+// proleptic Gregorian calendar
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let (era, day_of_era) = (z.div_euclid(146_097), z.rem_euclid(146_097));
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let (era, year_of_era) = (year.div_euclid(400), year.rem_euclid(400));
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Like "2012-12-24T12:15:30.501Z", with the milliseconds left out when they're 0
+fn format_date(milliseconds: i64) -> String {
+    let (days, time) = (milliseconds.div_euclid(DAY), milliseconds.rem_euclid(DAY));
+    let (year, month, day) = civil_from_days(days);
+    let fraction = match time % 1000 {
+        0 => String::new(),
+        n => format!(".{:03}", n),
+    };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}Z",
+        year,
+        month,
+        day,
+        time / 3_600_000,
+        time / 60_000 % 60,
+        time / 1000 % 60,
+        fraction
+    )
+}
+
+// Milliseconds since the epoch from an ISO-8601 date and time with an offset, like
+// "2012-12-24T12:15:30.501+01:00". Anything past milliseconds is dropped.
+fn parse_date(text: &str) -> Option<i64> {
+    let number = |start: usize, length: usize| -> Option<i64> {
+        let digits = text.get(start..start + length)?;
+        match digits.bytes().all(|c| c.is_ascii_digit()) {
+            true => digits.parse().ok(),
+            false => None,
+        }
+    };
+    let separators = [(4, b'-'), (7, b'-'), (13, b':'), (16, b':')];
+    if !separators
+        .iter()
+        .all(|(i, c)| text.as_bytes().get(*i) == Some(c))
+        || !matches!(text.as_bytes().get(10), Some(b'T' | b't'))
+    {
+        return None;
+    }
+    let (year, month, day) = (number(0, 4)?, number(5, 2)?, number(8, 2)?);
+    let (hour, minute, second) = (number(11, 2)?, number(14, 2)?, number(17, 2)?);
+    let days = days_from_civil(year, month, day);
+    if civil_from_days(days) != (year, month, day) || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let mut rest = text.get(19..)?;
+    let mut milliseconds = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return None;
+        }
+        milliseconds = format!("{:0<3}", &fraction[..digits.min(3)]).parse().ok()?;
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let sign = match rest.bytes().next()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let digits = rest[1..].replacen(':', "", 1);
+            if digits.len() != 4 || !digits.bytes().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let (hours, minutes): (i64, i64) =
+                (digits[..2].parse().ok()?, digits[2..].parse().ok()?);
+            sign * (hours * 60 + minutes) * 60_000
+        }
+    };
+    Some(days * DAY + hour * 3_600_000 + minute * 60_000 + second * 1000 + milliseconds - offset)
+}
@@ -0,0 +1,337 @@
+use crate::bson::decimal::decimal_to_string;
+use crate::bson::extended::*;
+use crate::rustifier::JSONElement;
+use std::collections::HashMap;
+
+pub mod decimal;
+mod extended;
+mod tests;
+
+// Nesting deeper than this is rejected rather than risking the stack
+const MAX_DEPTH: usize = 256;
+
+/// Which form of MongoDB Extended JSON v2 decoded documents use for the BSON types JSON lacks
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExtendedJsonMode {
+    // Plain numbers, and ISO-8601 strings for dates, wherever that loses nothing
+    #[default]
+    Relaxed,
+    // Every number and date in a wrapper like {"$numberInt": "1"}, so it keeps its BSON type
+    Canonical,
+}
+
+/// Encodes an object as a BSON document. Numbers become int32, floats double, and arrays
+/// documents keyed by index. Objects that are Extended JSON type wrappers, in either mode,
+/// become the type they describe, like {"$numberLong": "1"} an int64 or {"$oid": "..."} an
+/// ObjectId.
+pub fn to_bson(element: &JSONElement) -> Result<Vec<u8>, String> {
+    match element {
+        JSONElement::Object(members) => {
+            let mut output = vec![];
+            write_document(&mut output, sorted(members), 0)?;
+            Ok(output)
+        }
+        _ => Err("Only objects can be BSON documents".to_string()),
+    }
+}
+
+/// Decodes a BSON document into Extended JSON. In relaxed mode int32 values, and int64 values
+/// a float holds exactly, become plain numbers.
+pub fn from_bson(bytes: &[u8], mode: ExtendedJsonMode) -> Result<JSONElement, String> {
+    let mut decoder = Decoder {
+        bytes,
+        position: 0,
+        mode,
+    };
+    let document = decoder.document(0)?;
+    if decoder.position < bytes.len() {
+        return Err(format!(
+            "{} bytes were left over after the BSON document",
+            bytes.len() - decoder.position
+        ));
+    }
+    Ok(document)
+}
+
+/// Rewrites an Extended JSON document in the given mode, like turning relaxed into canonical
+pub fn to_extended_json(
+    element: &JSONElement,
+    mode: ExtendedJsonMode,
+) -> Result<JSONElement, String> {
+    from_bson(&to_bson(element)?, mode)
+}
+
+// Object members in a deterministic order
+fn sorted(members: &HashMap<String, JSONElement>) -> Vec<(String, &JSONElement)> {
+    let mut members: Vec<_> = members
+        .iter()
+        .map(|(key, value)| (key.clone(), value))
+        .collect();
+    members.sort_by(|(a, _), (b, _)| a.cmp(b));
+    members
+}
+
+fn write_document(
+    output: &mut Vec<u8>,
+    members: Vec<(String, &JSONElement)>,
+    depth: usize,
+) -> Result<(), String> {
+    if depth > MAX_DEPTH {
+        return Err(format!(
+            "BSON documents can't be nested more than {} deep",
+            MAX_DEPTH
+        ));
+    }
+    let start = output.len();
+    output.extend([0; 4]);
+    for (key, value) in members {
+        let kind = output.len();
+        output.push(0);
+        write_cstring(output, &key)?;
+        output[kind] = write_value(output, value, depth)?;
+    }
+    output.push(0);
+    write_length(output, start)
+}
+
+// Fills in the int32 length at `start` to cover everything written since
+fn write_length(output: &mut [u8], start: usize) -> Result<(), String> {
+    let length = i32::try_from(output.len() - start)
+        .map_err(|_| "BSON documents can't be bigger than 2GiB".to_string())?;
+    output[start..start + 4].copy_from_slice(&length.to_le_bytes());
+    Ok(())
+}
+
+// Keys and regular expressions end with a 0 byte, so they can't contain one
+fn write_cstring(output: &mut Vec<u8>, s: &str) -> Result<(), String> {
+    if s.contains('\0') {
+        return Err(format!("{:?} can't contain a null character in BSON", s));
+    }
+    output.extend(s.as_bytes());
+    output.push(0);
+    Ok(())
+}
+
+fn write_string(output: &mut Vec<u8>, s: &str) {
+    output.extend((s.len() as i32 + 1).to_le_bytes());
+    output.extend(s.as_bytes());
+    output.push(0);
+}
+
+// Writes the value of an element and returns its BSON type
+fn write_value(output: &mut Vec<u8>, value: &JSONElement, depth: usize) -> Result<u8, String> {
+    Ok(match value {
+        JSONElement::Float(f) => {
+            output.extend(f.to_le_bytes());
+            0x01
+        }
+        JSONElement::String(s) => {
+            write_string(output, s);
+            0x02
+        }
+        JSONElement::Object(members) => match write_wrapper(output, members, depth)? {
+            Some(kind) => kind,
+            None => {
+                write_document(output, sorted(members), depth + 1)?;
+                0x03
+            }
+        },
+        JSONElement::Array(elements) => {
+            let members = elements
+                .iter()
+                .enumerate()
+                .map(|(i, element)| (i.to_string(), element))
+                .collect();
+            write_document(output, members, depth + 1)?;
+            0x04
+        }
+        JSONElement::Boolean(b) => {
+            output.push(*b as u8);
+            0x08
+        }
+        JSONElement::Null => 0x0a,
+        JSONElement::Number(n) => {
+            output.extend(n.to_le_bytes());
+            0x10
+        }
+    })
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    mode: ExtendedJsonMode,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if count > self.bytes.len() - self.position {
+            return Err(format!(
+                "BSON ended at byte {}, in the middle of a value",
+                self.bytes.len()
+            ));
+        }
+        let taken = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], String> {
+        Ok(self.take(N)?.try_into().expect("took N bytes"))
+    }
+
+    fn int32(&mut self) -> Result<i32, String> {
+        Ok(i32::from_le_bytes(self.array()?))
+    }
+
+    // A length, which can't be negative
+    fn length(&mut self) -> Result<usize, String> {
+        let start = self.position;
+        usize::try_from(self.int32()?)
+            .map_err(|_| format!("BSON length at byte {} is negative", start))
+    }
+
+    fn cstring(&mut self) -> Result<String, String> {
+        let start = self.position;
+        let length = self.bytes[start..]
+            .iter()
+            .position(|byte| *byte == 0)
+            .ok_or_else(|| format!("BSON string at byte {} doesn't end", start))?;
+        let bytes = self.take(length + 1)?;
+        String::from_utf8(bytes[..length].to_vec())
+            .map_err(|_| format!("BSON string at byte {} isn't UTF-8", start))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let start = self.position;
+        let length = self.length()?;
+        let bytes = self.take(length)?;
+        match bytes.split_last() {
+            Some((0, contents)) => String::from_utf8(contents.to_vec())
+                .map_err(|_| format!("BSON string at byte {} isn't UTF-8", start)),
+            _ => Err(format!("BSON string at byte {} doesn't end with 0", start)),
+        }
+    }
+
+    // A document's elements in order
+    fn elements(&mut self, depth: usize) -> Result<Vec<(String, JSONElement)>, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("BSON is nested more than {} deep", MAX_DEPTH));
+        }
+        let start = self.position;
+        let length = self.length()?;
+        let mut elements = vec![];
+        loop {
+            let kind = self.take(1)?[0];
+            if kind == 0 {
+                break;
+            }
+            let key = self.cstring()?;
+            elements.push((key, self.value(kind, depth)?));
+        }
+        if self.position - start != length {
+            return Err(format!(
+                "BSON document at byte {} says it's {} bytes long but is {}",
+                start,
+                length,
+                self.position - start
+            ));
+        }
+        Ok(elements)
+    }
+
+    fn document(&mut self, depth: usize) -> Result<JSONElement, String> {
+        let mut members = HashMap::new();
+        for (key, value) in self.elements(depth)? {
+            if members.contains_key(&key) {
+                return Err(format!(
+                    "BSON document has the key {:?} more than once",
+                    key
+                ));
+            }
+            members.insert(key, value);
+        }
+        Ok(JSONElement::Object(members))
+    }
+
+    fn value(&mut self, kind: u8, depth: usize) -> Result<JSONElement, String> {
+        let start = self.position;
+        Ok(match kind {
+            0x01 => double(f64::from_le_bytes(self.array()?), self.mode),
+            0x02 => JSONElement::String(self.string()?),
+            0x03 => self.document(depth + 1)?,
+            // Arrays are documents keyed by index, but the keys don't matter
+            0x04 => JSONElement::Array(
+                self.elements(depth + 1)?
+                    .into_iter()
+                    .map(|(_, element)| element)
+                    .collect(),
+            ),
+            0x05 => {
+                let length = self.length()?;
+                let subtype = self.take(1)?[0];
+                let mut data = self.take(length)?;
+                // The old binary subtype repeats the length inside the data
+                if subtype == 0x02 {
+                    match data.split_first_chunk::<4>() {
+                        Some((inner, rest))
+                            if i32::from_le_bytes(*inner) as usize == rest.len() =>
+                        {
+                            data = rest
+                        }
+                        _ => {
+                            return Err(format!(
+                                "BSON binary at byte {} has the wrong length",
+                                start
+                            ))
+                        }
+                    }
+                }
+                binary(subtype, data)
+            }
+            0x06 => object([("$undefined", JSONElement::Boolean(true))]),
+            0x07 => object_id(&self.array::<12>()?),
+            0x08 => match self.take(1)?[0] {
+                0 => JSONElement::Boolean(false),
+                1 => JSONElement::Boolean(true),
+                b => return Err(format!("BSON boolean at byte {} is {}", start, b)),
+            },
+            0x09 => date(i64::from_le_bytes(self.array()?), self.mode),
+            0x0a => JSONElement::Null,
+            0x0b => regular_expression(&self.cstring()?, &self.cstring()?),
+            0x0c => {
+                let namespace = self.string()?;
+                db_pointer(namespace, &self.array::<12>()?)
+            }
+            0x0d => object([("$code", JSONElement::String(self.string()?))]),
+            0x0e => object([("$symbol", JSONElement::String(self.string()?))]),
+            0x0f => {
+                let length = self.length()?;
+                let code = self.string()?;
+                let scope = self.document(depth + 1)?;
+                if self.position - start != length {
+                    return Err(format!("BSON code at byte {} has the wrong length", start));
+                }
+                object([("$code", JSONElement::String(code)), ("$scope", scope)])
+            }
+            0x10 => int32(self.int32()?, self.mode),
+            0x11 => {
+                let increment = u32::from_le_bytes(self.array()?);
+                timestamp(u32::from_le_bytes(self.array()?), increment)
+            }
+            0x12 => int64(i64::from_le_bytes(self.array()?), self.mode),
+            0x13 => object([(
+                "$numberDecimal",
+                JSONElement::String(decimal_to_string(self.array()?)),
+            )]),
+            0xff => object([("$minKey", JSONElement::Number(1))]),
+            0x7f => object([("$maxKey", JSONElement::Number(1))]),
+            _ => {
+                return Err(format!(
+                    "BSON value at byte {} has the unknown type 0x{:02x}",
+                    start, kind
+                ))
+            }
+        })
+    }
+}
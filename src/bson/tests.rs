@@ -0,0 +1,250 @@
+#[cfg(test)]
+use crate::bson::decimal::*;
+#[cfg(test)]
+use crate::bson::*;
+#[cfg(test)]
+use crate::rustifier::loads_json5;
+
+#[cfg(test)]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+fn unhex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+// Checks the encoding of canonical Extended JSON, and what decoding it gives in each mode
+#[cfg(test)]
+fn assert_codec(canonical: &str, relaxed: &str, bytes: &str) {
+    let canonical = loads_json5(canonical).unwrap();
+    let relaxed = loads_json5(relaxed).unwrap();
+    assert_eq!(hex(&to_bson(&canonical).unwrap()), bytes);
+    let decoded = |mode| from_bson(&unhex(bytes), mode).unwrap();
+    assert_eq!(decoded(ExtendedJsonMode::Canonical), canonical);
+    assert_eq!(decoded(ExtendedJsonMode::Relaxed), relaxed);
+}
+
+// Examples from the BSON corpus
+#[test]
+fn encodes_and_decodes_each_type() {
+    assert_codec(
+        r#"{"a": {"$numberInt": "1"}}"#,
+        r#"{"a": 1}"#,
+        "0c0000001061000100000000",
+    );
+    assert_codec(
+        r#"{"a": {"$numberInt": "-2147483648"}}"#,
+        r#"{"a": -2147483648}"#,
+        "0c0000001061000000008000",
+    );
+    assert_codec(
+        r#"{"a": {"$numberLong": "1"}}"#,
+        r#"{"a": 1}"#,
+        "10000000126100010000000000000000",
+    );
+    assert_codec(
+        r#"{"d": {"$numberDouble": "1.0"}}"#,
+        r#"{"d": 1.0}"#,
+        "10000000016400000000000000f03f00",
+    );
+    assert_codec(
+        r#"{"d": {"$numberDouble": "-1.0001"}}"#,
+        r#"{"d": -1.0001}"#,
+        "1000000001640071ac8bdb6800f0bf00",
+    );
+    assert_codec(
+        r#"{"d": {"$numberDouble": "-Infinity"}}"#,
+        r#"{"d": {"$numberDouble": "-Infinity"}}"#,
+        "10000000016400000000000000f0ff00",
+    );
+    assert_codec(
+        r#"{"a": "b", "c": true, "d": null}"#,
+        r#"{"a": "b", "c": true, "d": null}"#,
+        "15000000026100020000006200086300010a640000",
+    );
+    assert_codec(
+        r#"{"a": [{"$numberInt": "1"}, {"b": []}]}"#,
+        r#"{"a": [1, {"b": []}]}"#,
+        "240000000461001c000000103000010000000331000d0000000462000500000000000000",
+    );
+    assert_codec(
+        r#"{"a": {"$date": {"$numberLong": "1356351330501"}}}"#,
+        r#"{"a": {"$date": "2012-12-24T12:15:30.501Z"}}"#,
+        "10000000096100c5d8d6cc3b01000000",
+    );
+    assert_codec(
+        r#"{"a": {"$date": {"$numberLong": "-284643869501"}}}"#,
+        r#"{"a": {"$date": {"$numberLong": "-284643869501"}}}"#,
+        "10000000096100c33ce7b9bdffffff00",
+    );
+    assert_codec(
+        r#"{"a": {"$oid": "56e1fc72e0c917e9c4714161"}}"#,
+        r#"{"a": {"$oid": "56e1fc72e0c917e9c4714161"}}"#,
+        "1400000007610056e1fc72e0c917e9c471416100",
+    );
+    assert_codec(
+        r#"{"x": {"$binary": {"base64": "//8=", "subType": "00"}}}"#,
+        r#"{"x": {"$binary": {"base64": "//8=", "subType": "00"}}}"#,
+        "0f0000000578000200000000ffff00",
+    );
+    assert_codec(
+        r#"{"x": {"$binary": {"base64": "//8=", "subType": "02"}}}"#,
+        r#"{"x": {"$binary": {"base64": "//8=", "subType": "02"}}}"#,
+        "13000000057800060000000202000000ffff00",
+    );
+    assert_codec(
+        r#"{"a": {"$regularExpression": {"pattern": "abc", "options": "im"}}}"#,
+        r#"{"a": {"$regularExpression": {"pattern": "abc", "options": "im"}}}"#,
+        "0f0000000b610061626300696d0000",
+    );
+    assert_codec(
+        r#"{"a": {"$timestamp": {"t": 123456789, "i": 42}}}"#,
+        r#"{"a": {"$timestamp": {"t": 123456789, "i": 42}}}"#,
+        "100000001161002a00000015cd5b0700",
+    );
+    assert_codec(
+        r#"{"d": {"$numberDecimal": "1"}}"#,
+        r#"{"d": {"$numberDecimal": "1"}}"#,
+        "180000001364000100000000000000000000000000403000",
+    );
+    assert_codec(
+        r#"{"a": {"$code": "abcd", "$scope": {"x": {"$numberInt": "1"}}}}"#,
+        r#"{"a": {"$code": "abcd", "$scope": {"x": 1}}}"#,
+        "210000000f6100190000000500000061626364000c000000107800010000000000",
+    );
+    assert_codec(
+        r#"{"a": {"$minKey": 1}, "b": {"$maxKey": 1}, "c": {"$undefined": true}}"#,
+        r#"{"a": {"$minKey": 1}, "b": {"$maxKey": 1}, "c": {"$undefined": true}}"#,
+        "0e000000ff61007f620006630000",
+    );
+}
+
+#[test]
+fn converts_between_modes() {
+    let relaxed = loads_json5(
+        r#"{"n": 1, "big": 9007199254740993, "when": {"$date": "2012-12-24T13:15:30.501+01:00"}}"#,
+    );
+    // Whole floats that need an int64 stay floats, so only the wrapped form keeps them exact
+    let relaxed = relaxed.unwrap();
+    let canonical = to_extended_json(&relaxed, ExtendedJsonMode::Canonical).unwrap();
+    assert_eq!(
+        canonical,
+        loads_json5(
+            r#"{"n": {"$numberInt": "1"}, "big": {"$numberDouble": "9007199254740992.0"},
+                "when": {"$date": {"$numberLong": "1356351330501"}}}"#
+        )
+        .unwrap()
+    );
+    let long =
+        loads_json5(r#"{"a": {"$numberLong": "9007199254740993"}, "b": {"$numberLong": "-5"}}"#)
+            .unwrap();
+    assert_eq!(
+        to_extended_json(&long, ExtendedJsonMode::Relaxed).unwrap(),
+        loads_json5(r#"{"a": {"$numberLong": "9007199254740993"}, "b": -5}"#).unwrap()
+    );
+    let date = |text: &str| {
+        let document = loads_json5(&format!(r#"{{"d": {{"$date": "{}"}}}}"#, text)).unwrap();
+        to_extended_json(&document, ExtendedJsonMode::Relaxed)
+    };
+    assert_eq!(
+        date("1970-01-01T00:00:00.000Z").unwrap(),
+        loads_json5(r#"{"d": {"$date": "1970-01-01T00:00:00Z"}}"#).unwrap()
+    );
+    assert_eq!(
+        date("2000-02-29T23:59:59.9999-0130").unwrap(),
+        loads_json5(r#"{"d": {"$date": "2000-03-01T01:29:59.999Z"}}"#).unwrap()
+    );
+    for invalid in [
+        "2001-02-29T00:00:00Z",
+        "2001-01-01 00:00:00Z",
+        "2001-01-01T00:00:00",
+    ] {
+        assert!(date(invalid).is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn rejects_invalid_documents() {
+    for document in [
+        "[1]",
+        r#"{"a": {"$numberInt": "2147483648"}}"#,
+        r#"{"a": {"$numberLong": 1}}"#,
+        r#"{"a": {"$oid": "56e1fc72e0c917e9c47141"}}"#,
+        r#"{"a": {"$oid": "56e1fc72e0c917e9c4714161", "b": 1}}"#,
+        r#"{"a": {"$binary": {"base64": "!", "subType": "00"}}}"#,
+        r#"{"a": {"$timestamp": {"t": -1, "i": 0}}}"#,
+        r#"{"a": {"$minKey": 0}}"#,
+        r#"{"a\u0000": 1}"#,
+    ] {
+        assert!(
+            to_bson(&loads_json5(document).unwrap()).is_err(),
+            "{}",
+            document
+        );
+    }
+    for bytes in [
+        "",
+        "0500000000ff",
+        "0600000000",
+        "0c0000001061000100000000 00",
+        "0c000000106100010000",
+        "0c0000001461000100000000",
+        "0d000000086100020000000000",
+        "0e00000002610002000000620100",
+        "0e00000002610002000000ff0000",
+        "0d0000000861000108610000 00",
+    ] {
+        let bytes = bytes.replace(' ', "");
+        assert!(
+            from_bson(&unhex(&bytes), ExtendedJsonMode::Relaxed).is_err(),
+            "{}",
+            bytes
+        );
+    }
+}
+
+#[test]
+fn formats_decimals_like_the_spec() {
+    for (input, expected) in [
+        ("0", "0"),
+        ("-0", "-0"),
+        ("0.000001234", "0.000001234"),
+        ("0.0000001234", "1.234E-7"),
+        ("-1.23E-10", "-1.23E-10"),
+        ("1E+3", "1E+3"),
+        ("1000", "1000"),
+        ("12.50", "12.50"),
+        ("0E+6112", "0E+6111"),
+        ("1E+6112", "1.0E+6112"),
+        (
+            "9.999999999999999999999999999999999E+6144",
+            "9.999999999999999999999999999999999E+6144",
+        ),
+        ("1E-6176", "1E-6176"),
+        ("NaN", "NaN"),
+        ("-Infinity", "-Infinity"),
+    ] {
+        assert_eq!(
+            decimal_to_string(parse_decimal(input).unwrap()),
+            expected,
+            "{}",
+            input
+        );
+    }
+    for input in [
+        "",
+        ".",
+        "1.2.3",
+        "1E",
+        "1E-6177",
+        "12345678901234567890123456789012345",
+        "1E+6145",
+    ] {
+        assert!(parse_decimal(input).is_err(), "{}", input);
+    }
+}
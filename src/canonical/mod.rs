@@ -0,0 +1,115 @@
+use crate::ast_parser::escape;
+use crate::canonical::sha256::sha256;
+use crate::rustifier::JSONElement;
+use std::fmt::Write;
+
+pub mod sha256;
+mod tests;
+
+/// Serializes in the JSON Canonicalization Scheme (RFC 8785), so documents that are equal get
+/// exactly the same bytes: no whitespace, object keys sorted by their UTF-16 code units, numbers
+/// written the way ECMAScript writes them, and only the escapes a string needs. Fails on NaN and
+/// infinities, which JSON can't represent.
+pub fn canonicalize(element: &JSONElement) -> Result<String, String> {
+    let mut output = String::new();
+    write_canonical(&mut output, element)?;
+    Ok(output)
+}
+
+/// The SHA-256 of the canonical form, as lowercase hex. It stays the same however the document
+/// is formatted, whatever order its keys are in and however its numbers are written.
+pub fn content_hash(element: &JSONElement) -> Result<String, String> {
+    let digest = sha256(canonicalize(element)?.as_bytes());
+    Ok(digest.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    }))
+}
+
+fn write_canonical(output: &mut String, element: &JSONElement) -> Result<(), String> {
+    match element {
+        JSONElement::Object(members) => {
+            let mut members: Vec<_> = members.iter().collect();
+            // Not the same as sorting the strings, which compares code points
+            members.sort_by(|(a, _), (b, _)| a.encode_utf16().cmp(b.encode_utf16()));
+            output.push('{');
+            for (i, (key, value)) in members.into_iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                output.push_str(&escape(key));
+                output.push(':');
+                write_canonical(output, value)?;
+            }
+            output.push('}');
+        }
+        JSONElement::Array(elements) => {
+            output.push('[');
+            for (i, element) in elements.iter().enumerate() {
+                if i > 0 {
+                    output.push(',');
+                }
+                write_canonical(output, element)?;
+            }
+            output.push(']');
+        }
+        // `escape` already only escapes quotes, backslashes and control characters
+        JSONElement::String(s) => output.push_str(&escape(s)),
+        JSONElement::Number(n) => output.push_str(&n.to_string()),
+        JSONElement::Float(f) => output.push_str(&format_number(*f)?),
+        JSONElement::Boolean(b) => output.push_str(if *b { "true" } else { "false" }),
+        JSONElement::Null => output.push_str("null"),
+    }
+    Ok(())
+}
+
+/// Writes a number like ECMAScript's Number.prototype.toString: the shortest digits that read
+/// back as the same number, in plain notation from 1e-6 up to 1e21 and with an exponent
+/// outside that
+pub fn format_number(n: f64) -> Result<String, String> {
+    if !n.is_finite() {
+        return Err(format!("{} can't be written as JSON", n));
+    }
+    // Covers -0 too
+    if n == 0.0 {
+        return Ok("0".to_string());
+    }
+    // Rust's exponent format has the fewest digits that round-trip, like "1.25e-7", but when
+    // the number is exactly halfway between two choices of last digit it doesn't pick the even
+    // one like ECMAScript does. Formatting with that many digits rounds exactly, ties to even.
+    let shortest = format!("{:e}", n.abs());
+    let precision = shortest.split_once('e').map_or(0, |(mantissa, _)| {
+        mantissa.chars().filter(char::is_ascii_digit).count() - 1
+    });
+    let scientific = format!("{:.*e}", precision, n.abs());
+    let (mantissa, exponent) = scientific
+        .split_once('e')
+        .expect("exponent format has an e");
+    let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+    let exponent: i32 = exponent.parse().expect("exponent is a number");
+    let length = digits.len() as i32;
+    // Where the decimal point goes, counting from the start of the digits
+    let point = exponent + 1;
+    let zeros = |count: i32| "0".repeat(count as usize);
+    let body = if length <= point && point <= 21 {
+        digits + &zeros(point - length)
+    } else if 0 < point && point <= 21 {
+        let (whole, fraction) = digits.split_at(point as usize);
+        format!("{}.{}", whole, fraction)
+    } else if -6 < point && point <= 0 {
+        format!("0.{}{}", zeros(-point), digits)
+    } else {
+        let (first, rest) = digits.split_at(1);
+        let fraction = if rest.is_empty() {
+            String::new()
+        } else {
+            format!(".{}", rest)
+        };
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}{}e{}{}", first, fraction, sign, exponent.abs())
+    };
+    Ok(match n < 0.0 {
+        true => format!("-{}", body),
+        false => body,
+    })
+}
@@ -0,0 +1,121 @@
+#[cfg(test)]
+use crate::canonical::sha256::sha256;
+#[cfg(test)]
+use crate::canonical::*;
+#[cfg(test)]
+use crate::rustifier::{loads, loads_json5};
+
+// The example from RFC 8785 section 3.2.2
+#[test]
+fn canonicalizes_the_rfc_example() {
+    let input = r#"{
+        "numbers": [333333333.33333329, 1E30, 4.50, 2e-3, 0.000000000000000000000000001],
+        "string": "\u20ac$\u000F\u000aA'\u0042\u0022\u005c\\\"\/",
+        "literals": [null, true, false]
+    }"#;
+    assert_eq!(
+        canonicalize(&loads(input).unwrap()).unwrap(),
+        r#"{"literals":[null,true,false],"numbers":[333333333.3333333,1e+30,4.5,0.002,1e-27],"string":"€$\u000f\nA'B\"\\\\\"/"}"#
+    );
+}
+
+// The example from RFC 8785 section 3.2.3, where UTF-16 order puts the emoji's surrogates
+// before U+FB33
+#[test]
+fn sorts_keys_by_utf16_code_units() {
+    let input = r#"{
+        "\u20ac": "Euro Sign",
+        "\r": "Carriage Return",
+        "\ufb33": "Hebrew Letter Dalet With Dagesh",
+        "1": "One",
+        "\ud83d\ude00": "Emoji: Grinning Face",
+        "\u0080": "Control",
+        "\u00f6": "Latin Small Letter O With Diaeresis"
+    }"#;
+    let output = canonicalize(&loads(input).unwrap()).unwrap();
+    let keys: Vec<&str> = output
+        .split(['{', ','])
+        .filter_map(|member| member.split_once(':'))
+        .map(|(key, _)| key)
+        .collect();
+    assert_eq!(
+        keys,
+        [
+            "\"\\r\"",
+            "\"1\"",
+            "\"\u{80}\"",
+            "\"ö\"",
+            "\"€\"",
+            "\"😀\"",
+            "\"\u{fb33}\""
+        ]
+    );
+}
+
+// The number examples from RFC 8785 appendix B
+#[test]
+fn formats_numbers_like_ecmascript() {
+    for (bits, expected) in [
+        (0x0000000000000000, "0"),
+        (0x8000000000000000, "0"),
+        (0x0000000000000001, "5e-324"),
+        (0x8000000000000001, "-5e-324"),
+        (0x7fefffffffffffff, "1.7976931348623157e+308"),
+        (0xffefffffffffffff, "-1.7976931348623157e+308"),
+        (0x4340000000000000, "9007199254740992"),
+        (0xc340000000000000, "-9007199254740992"),
+        (0x4430000000000000, "295147905179352830000"),
+        (0x44b52d02c7e14af5, "9.999999999999997e+22"),
+        (0x44b52d02c7e14af6, "1e+23"),
+        (0x44b52d02c7e14af7, "1.0000000000000001e+23"),
+        (0x444b1ae4d6e2ef4e, "999999999999999700000"),
+        (0x444b1ae4d6e2ef4f, "999999999999999900000"),
+        (0x444b1ae4d6e2ef50, "1e+21"),
+        (0x3eb0c6f7a0b5ed8c, "9.999999999999997e-7"),
+        (0x3eb0c6f7a0b5ed8d, "0.000001"),
+        (0x41b3de4355555553, "333333333.3333332"),
+        (0x41b3de4355555554, "333333333.33333325"),
+        (0x41b3de4355555555, "333333333.3333333"),
+        (0x41b3de4355555556, "333333333.3333334"),
+        (0x41b3de4355555557, "333333333.33333343"),
+        (0xbecbf647612f3696, "-0.0000033333333333333333"),
+        (0x43143ff3c1cb0959, "1424953923781206.2"),
+    ] {
+        assert_eq!(
+            format_number(f64::from_bits(bits)).unwrap(),
+            expected,
+            "{:x}",
+            bits
+        );
+    }
+    assert!(format_number(f64::NAN).is_err());
+    assert!(format_number(f64::INFINITY).is_err());
+}
+
+#[test]
+fn hashes_content_not_formatting() {
+    let hex =
+        |digest: [u8; 32]| -> String { digest.iter().map(|b| format!("{:02x}", b)).collect() };
+    assert_eq!(
+        hex(sha256(b"")),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert_eq!(
+        hex(sha256(b"abc")),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    // Long enough to need a second block for the padding
+    assert_eq!(
+        hex(sha256(
+            b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+        )),
+        "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+    );
+    let a = loads(r#"{"b": [1, 2.50], "a": "x"}"#).unwrap();
+    let b = loads_json5("{a:'x',b:[1.0,2.5]}").unwrap();
+    assert_eq!(content_hash(&a).unwrap(), content_hash(&b).unwrap());
+    assert_eq!(
+        content_hash(&a).unwrap(),
+        hex(sha256(br#"{"a":"x","b":[1,2.5]}"#))
+    );
+}
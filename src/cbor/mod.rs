@@ -0,0 +1,457 @@
+use crate::rustifier::{dumps, JSONElement};
+use std::collections::HashMap;
+
+mod tests;
+
+// Nesting deeper than this is rejected rather than risking the stack
+const MAX_DEPTH: usize = 256;
+
+pub(crate) const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64URL_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+/// How byte strings, which JSON doesn't have, become JSON strings. The default is base64url
+/// without padding, as RFC 8949 section 6.1 suggests.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BytesPolicy {
+    #[default]
+    Base64Url,
+    Base64,
+    Hex,
+    Reject,
+}
+
+/// What happens to map keys that aren't text strings
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum KeyPolicy {
+    // Integers are written in decimal, byte strings follow the bytes policy, and anything else
+    // becomes its JSON text
+    #[default]
+    Stringify,
+    Reject,
+}
+
+/// What happens to tags this module doesn't know. Known tags are always handled: bignums become
+/// numbers, the expected-encoding tags 21 to 23 pick how the byte strings inside them are
+/// written, and the ones that only describe their content, like dates, pass it through.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TagPolicy {
+    // Drop the tag and keep its content
+    #[default]
+    PassThrough,
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CborOptions {
+    pub bytes: BytesPolicy,
+    pub keys: KeyPolicy,
+    pub unknown_tags: TagPolicy,
+}
+
+/// Encodes with the preferred serialization of RFC 8949: the shortest form of each integer and
+/// length, and floats in the smallest size that keeps their value. Whole numbers are written as
+/// integers, and object keys are sorted by their encoded bytes, so the output is deterministic.
+pub fn to_cbor(element: &JSONElement) -> Vec<u8> {
+    let mut output = vec![];
+    encode(&mut output, element);
+    output
+}
+
+/// Decodes a single CBOR data item. Integers that don't fit in a NumberType become floats, and
+/// undefined, NaN and the infinities become null, like RFC 8949 section 6.1 says.
+pub fn from_cbor(bytes: &[u8], options: &CborOptions) -> Result<JSONElement, String> {
+    let mut decoder = Decoder {
+        bytes,
+        position: 0,
+        options,
+    };
+    let element = match decoder.item(0, options.bytes)? {
+        Item::Element(element) => element,
+        Item::Break => return Err("CBOR started with a break".to_string()),
+    };
+    if decoder.position < bytes.len() {
+        return Err(format!(
+            "{} bytes were left over after the CBOR item",
+            bytes.len() - decoder.position
+        ));
+    }
+    Ok(element)
+}
+
+fn write_head(output: &mut Vec<u8>, major: u8, argument: u64) {
+    let major = major << 5;
+    match argument {
+        0..=23 => output.push(major | argument as u8),
+        24..=0xff => output.extend([major | 24, argument as u8]),
+        0x100..=0xffff => {
+            output.push(major | 25);
+            output.extend((argument as u16).to_be_bytes());
+        }
+        0x10000..=0xffff_ffff => {
+            output.push(major | 26);
+            output.extend((argument as u32).to_be_bytes());
+        }
+        _ => {
+            output.push(major | 27);
+            output.extend(argument.to_be_bytes());
+        }
+    }
+}
+
+fn encode_integer(output: &mut Vec<u8>, n: i128) {
+    match n >= 0 {
+        true => write_head(output, 0, n as u64),
+        false => write_head(output, 1, (-1 - n) as u64),
+    }
+}
+
+fn encode(output: &mut Vec<u8>, element: &JSONElement) {
+    match element {
+        JSONElement::Null => output.push(0xf6),
+        JSONElement::Boolean(b) => output.push(if *b { 0xf5 } else { 0xf4 }),
+        JSONElement::Number(n) => encode_integer(output, *n as i128),
+        JSONElement::Float(f) if f.fract() == 0.0 && f.abs() < 2f64.powi(64) && *f != 0.0 => {
+            encode_integer(output, *f as i128)
+        }
+        JSONElement::Float(f) => encode_float(output, *f),
+        JSONElement::String(s) => {
+            write_head(output, 3, s.len() as u64);
+            output.extend(s.as_bytes());
+        }
+        JSONElement::Array(elements) => {
+            write_head(output, 4, elements.len() as u64);
+            for element in elements {
+                encode(output, element);
+            }
+        }
+        JSONElement::Object(members) => {
+            let mut members: Vec<(Vec<u8>, &JSONElement)> = members
+                .iter()
+                .map(|(key, value)| (to_cbor(&JSONElement::String(key.clone())), value))
+                .collect();
+            members.sort_by(|(a, _), (b, _)| a.cmp(b));
+            write_head(output, 5, members.len() as u64);
+            for (key, value) in members {
+                output.extend(key);
+                encode(output, value);
+            }
+        }
+    }
+}
+
+fn encode_float(output: &mut Vec<u8>, f: f64) {
+    if let Some(half) = to_half(f) {
+        output.push(0xf9);
+        output.extend(half.to_be_bytes());
+    } else if (f as f32) as f64 == f {
+        output.push(0xfa);
+        output.extend((f as f32).to_be_bytes());
+    } else {
+        output.push(0xfb);
+        output.extend(f.to_be_bytes());
+    }
+}
+
+// The bits of a float16 with exactly the same value, if there is one
+fn to_half(f: f64) -> Option<u16> {
+    let sign = if f.is_sign_negative() { 0x8000 } else { 0 };
+    if f.is_nan() {
+        return Some(0x7e00);
+    }
+    if f.is_infinite() {
+        return Some(sign | 0x7c00);
+    }
+    if f == 0.0 {
+        return Some(sign);
+    }
+    let magnitude = f.abs();
+    let exponent = ((magnitude.to_bits() >> 52) & 0x7ff) as i32 - 1023;
+    let bits = if (-14..=15).contains(&exponent) {
+        let fraction = (magnitude / 2f64.powi(exponent) - 1.0) * 1024.0;
+        (((exponent + 15) as u16) << 10) | fraction as u16
+    } else if exponent < -14 {
+        // Subnormal, in units of 2^-24
+        let fraction = magnitude / 2f64.powi(-24);
+        if fraction >= 1024.0 {
+            return None;
+        }
+        fraction as u16
+    } else {
+        return None;
+    };
+    (from_half(sign | bits) == f).then_some(sign | bits)
+}
+
+fn from_half(bits: u16) -> f64 {
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let fraction = (bits & 0x3ff) as f64;
+    let magnitude = match exponent {
+        0 => fraction * 2f64.powi(-24),
+        31 if fraction == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (1024.0 + fraction) * 2f64.powi(exponent - 25),
+    };
+    if bits & 0x8000 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+pub(crate) fn base64(bytes: &[u8], alphabet: &[u8; 64], padding: bool) -> String {
+    let mut output = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, byte)| {
+            group | (*byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..=chunk.len() {
+            output.push(alphabet[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+        }
+        if padding {
+            output.push_str(&"=".repeat(3 - chunk.len()));
+        }
+    }
+    output
+}
+
+pub(crate) fn bytes_to_string(bytes: &[u8], policy: BytesPolicy) -> Result<String, String> {
+    match policy {
+        BytesPolicy::Base64Url => Ok(base64(bytes, BASE64URL_ALPHABET, false)),
+        BytesPolicy::Base64 => Ok(base64(bytes, BASE64_ALPHABET, true)),
+        BytesPolicy::Hex => Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect()),
+        BytesPolicy::Reject => Err("Byte strings can't be converted to JSON".to_string()),
+    }
+}
+
+// Whole numbers that fit in a NumberType stay integers
+pub(crate) fn integer_element(n: i128) -> JSONElement {
+    match i32::try_from(n) {
+        Ok(n) => JSONElement::Number(n),
+        Err(_) => JSONElement::Float(n as f64),
+    }
+}
+
+// JSON has no NaN or infinities
+pub(crate) fn float_element(f: f64) -> JSONElement {
+    match f.is_finite() {
+        true => JSONElement::Float(f),
+        false => JSONElement::Null,
+    }
+}
+
+enum Item {
+    Element(JSONElement),
+    // The end of an indefinite-length item
+    Break,
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    options: &'a CborOptions,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: u64) -> Result<&'a [u8], String> {
+        let remaining = (self.bytes.len() - self.position) as u64;
+        if count > remaining {
+            return Err(format!(
+                "CBOR ended at byte {}, in the middle of an item",
+                self.bytes.len()
+            ));
+        }
+        let taken = &self.bytes[self.position..self.position + count as usize];
+        self.position += count as usize;
+        Ok(taken)
+    }
+
+    // The major type, the additional information and the argument it gives, which is None for
+    // indefinite lengths
+    fn head(&mut self) -> Result<(u8, u8, Option<u64>), String> {
+        let start = self.position;
+        let initial = self.take(1)?[0];
+        let (major, info) = (initial >> 5, initial & 0x1f);
+        let argument = match info {
+            0..=23 => Some(info as u64),
+            24..=27 => {
+                let bytes = self.take(1 << (info - 24))?;
+                Some(bytes.iter().fold(0, |n, byte| n << 8 | *byte as u64))
+            }
+            31 if matches!(major, 2..=5 | 7) => None,
+            _ => {
+                return Err(format!(
+                    "CBOR byte {} has invalid additional information {}",
+                    start, info
+                ))
+            }
+        };
+        Ok((major, info, argument))
+    }
+
+    fn element(&mut self, depth: usize, bytes: BytesPolicy) -> Result<JSONElement, String> {
+        match self.item(depth, bytes)? {
+            Item::Element(element) => Ok(element),
+            Item::Break => Err(format!(
+                "CBOR has a break at byte {} that doesn't end anything",
+                self.position - 1
+            )),
+        }
+    }
+
+    // `bytes` is how byte strings are written, which the tags 21 to 23 change for what's inside
+    // them
+    fn item(&mut self, depth: usize, bytes: BytesPolicy) -> Result<Item, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!("CBOR is nested more than {} deep", MAX_DEPTH));
+        }
+        let start = self.position;
+        let (major, info, argument) = self.head()?;
+        let element = match (major, argument) {
+            (0, Some(n)) => integer_element(n as i128),
+            (1, Some(n)) => integer_element(-1 - n as i128),
+            (2, _) => JSONElement::String(bytes_to_string(&self.string(2, argument)?, bytes)?),
+            (3, _) => JSONElement::String(
+                String::from_utf8(self.string(3, argument)?)
+                    .map_err(|_| format!("CBOR text string at byte {} isn't UTF-8", start))?,
+            ),
+            (4, length) => {
+                let mut elements = vec![];
+                while length.is_none_or(|length| (elements.len() as u64) < length) {
+                    match self.item(depth + 1, bytes)? {
+                        Item::Element(element) => elements.push(element),
+                        Item::Break if length.is_none() => break,
+                        Item::Break => {
+                            return Err(format!("Unexpected break in the array at byte {}", start))
+                        }
+                    }
+                }
+                JSONElement::Array(elements)
+            }
+            (5, length) => self.map(length, depth, bytes)?,
+            (6, Some(tag)) => self.tagged(tag, depth, bytes)?,
+            (7, None) => return Ok(Item::Break),
+            (7, Some(_)) => match info {
+                20 => JSONElement::Boolean(false),
+                21 => JSONElement::Boolean(true),
+                22 | 23 => JSONElement::Null,
+                25 => float_element(from_half(argument.unwrap() as u16)),
+                26 => float_element(f32::from_bits(argument.unwrap() as u32) as f64),
+                27 => float_element(f64::from_bits(argument.unwrap())),
+                _ => {
+                    return Err(format!(
+                        "CBOR simple value {} at byte {} has no JSON equivalent",
+                        argument.unwrap(),
+                        start
+                    ))
+                }
+            },
+            _ => unreachable!("indefinite lengths are only read for majors 2 to 5 and 7"),
+        };
+        Ok(Item::Element(element))
+    }
+
+    // The contents of a byte or text string, joining up the chunks of an indefinite one
+    fn string(&mut self, major: u8, length: Option<u64>) -> Result<Vec<u8>, String> {
+        if let Some(length) = length {
+            return Ok(self.take(length)?.to_vec());
+        }
+        let mut contents = vec![];
+        loop {
+            let start = self.position;
+            match self.head()? {
+                (7, _, None) => return Ok(contents),
+                (chunk_major, _, Some(length)) if chunk_major == major => {
+                    // Each chunk of a text string has to be valid UTF-8 by itself
+                    let chunk = self.take(length)?;
+                    if major == 3 && std::str::from_utf8(chunk).is_err() {
+                        return Err(format!("CBOR text string at byte {} isn't UTF-8", start));
+                    }
+                    contents.extend(chunk);
+                }
+                _ => {
+                    return Err(format!(
+                        "CBOR string chunk at byte {} isn't a definite string of the same type",
+                        start
+                    ))
+                }
+            }
+        }
+    }
+
+    fn map(
+        &mut self,
+        length: Option<u64>,
+        depth: usize,
+        bytes: BytesPolicy,
+    ) -> Result<JSONElement, String> {
+        let mut members = HashMap::new();
+        while length.is_none_or(|length| (members.len() as u64) < length) {
+            let start = self.position;
+            let is_text = self
+                .bytes
+                .get(start)
+                .is_some_and(|initial| initial >> 5 == 3);
+            let key = match self.item(depth + 1, bytes)? {
+                Item::Break if length.is_none() => break,
+                Item::Break => {
+                    return Err(format!("Unexpected break in the map at byte {}", start))
+                }
+                Item::Element(JSONElement::String(key)) if is_text => key,
+                Item::Element(_) if self.options.keys == KeyPolicy::Reject => {
+                    return Err(format!(
+                        "CBOR map key at byte {} isn't a text string",
+                        start
+                    ))
+                }
+                Item::Element(JSONElement::String(key)) => key,
+                Item::Element(key) => dumps(&key),
+            };
+            let value = self.element(depth + 1, bytes)?;
+            if members.insert(key.clone(), value).is_some() {
+                return Err(format!("CBOR map has the key {:?} more than once", key));
+            }
+        }
+        Ok(JSONElement::Object(members))
+    }
+
+    fn tagged(
+        &mut self,
+        tag: u64,
+        depth: usize,
+        bytes: BytesPolicy,
+    ) -> Result<JSONElement, String> {
+        let start = self.position;
+        match tag {
+            // Unsigned and negative bignums
+            2 | 3 => {
+                let (major, _, length) = self.head()?;
+                if major != 2 {
+                    return Err(format!("CBOR bignum at byte {} isn't a byte string", start));
+                }
+                let magnitude = self
+                    .string(2, length)?
+                    .iter()
+                    .fold(0.0, |n, byte| n * 256.0 + *byte as f64);
+                Ok(JSONElement::Float(if tag == 2 {
+                    magnitude
+                } else {
+                    -1.0 - magnitude
+                }))
+            }
+            21 => self.element(depth + 1, BytesPolicy::Base64Url),
+            22 => self.element(depth + 1, BytesPolicy::Base64),
+            23 => self.element(depth + 1, BytesPolicy::Hex),
+            // Date/time strings, epoch times, URIs, base64 text, regular expressions, MIME
+            // messages and the self-described CBOR marker
+            0 | 1 | 32..=36 | 55799 => self.element(depth + 1, bytes),
+            _ if self.options.unknown_tags == TagPolicy::Reject => Err(format!(
+                "CBOR tag {} at byte {} isn't supported",
+                tag,
+                start - 1
+            )),
+            _ => self.element(depth + 1, bytes),
+        }
+    }
+}
@@ -0,0 +1,193 @@
+#[cfg(test)]
+use crate::cbor::*;
+#[cfg(test)]
+use crate::rustifier::{loads, loads_json5};
+
+#[cfg(test)]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+fn unhex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+fn decode(text: &str) -> Result<JSONElement, String> {
+    from_cbor(&unhex(text), &CborOptions::default())
+}
+
+// Examples from RFC 8949 appendix A
+#[test]
+fn encodes_the_rfc_examples() {
+    for (json, expected) in [
+        ("0", "00"),
+        ("23", "17"),
+        ("24", "1818"),
+        ("100", "1864"),
+        ("1000", "1903e8"),
+        ("1000000", "1a000f4240"),
+        ("-1", "20"),
+        ("-1000", "3903e7"),
+        ("1000000000000", "1b000000e8d4a51000"),
+        ("1.1", "fb3ff199999999999a"),
+        ("1.5", "f93e00"),
+        ("65504.0", "19ffe0"),
+        ("100000.5", "fa47c35040"),
+        ("5.960464477539063e-8", "f90001"),
+        ("0.00006103515625", "f90400"),
+        ("-4.1", "fbc010666666666666"),
+        ("1.0e+300", "fb7e37e43c8800759c"),
+        ("false", "f4"),
+        ("true", "f5"),
+        ("null", "f6"),
+        ("\"\"", "60"),
+        ("\"IETF\"", "6449455446"),
+        ("\"\\u00fc\"", "62c3bc"),
+        ("\"\\u6c34\"", "63e6b0b4"),
+        ("[]", "80"),
+        ("[1, [2, 3], [4, 5]]", "8301820203820405"),
+        ("{}", "a0"),
+        ("{\"a\": 1, \"b\": [2, 3]}", "a26161016162820203"),
+        ("[\"a\", {\"b\": \"c\"}]", "826161a161626163"),
+    ] {
+        assert_eq!(
+            hex(&to_cbor(&loads_json5(json).unwrap())),
+            expected,
+            "{}",
+            json
+        );
+    }
+    assert_eq!(hex(&to_cbor(&JSONElement::Float(-0.0))), "f98000");
+    assert_eq!(hex(&to_cbor(&JSONElement::Float(f64::INFINITY))), "f97c00");
+    assert_eq!(hex(&to_cbor(&JSONElement::Float(f64::NAN))), "f97e00");
+}
+
+#[test]
+fn decodes_the_rfc_examples() {
+    for (input, expected) in [
+        ("1a000f4240", "1000000"),
+        ("3903e7", "-1000"),
+        ("f93c00", "1.0"),
+        ("fa47c35000", "100000.0"),
+        ("f90001", "5.960464477539063e-8"),
+        ("fb3ff199999999999a", "1.1"),
+        ("f7", "null"),
+        ("826161a161626163", "[\"a\",{\"b\":\"c\"}]"),
+        ("a201020304", "{\"1\":2,\"3\":4}"),
+        // Indefinite lengths
+        ("9f018202039f0405ffff", "[1,[2,3],[4,5]]"),
+        ("83018202039f0405ff", "[1,[2,3],[4,5]]"),
+        ("7f657374726561646d696e67ff", "\"streaming\""),
+        ("bf6346756ef563416d7421ff", "{\"Amt\":-2,\"Fun\":true}"),
+        // Tags
+        (
+            "c074323031332d30332d32315432303a30343a30305a",
+            "\"2013-03-21T20:04:00Z\"",
+        ),
+        ("c11a514b67b0", "1363896240"),
+        ("c249010000000000000000", "18446744073709552000"),
+        ("3bffffffffffffffff", "-18446744073709552000"),
+        ("d74401020304", "\"01020304\""),
+        ("d818456449455446", "\"ZElFVEY\""),
+    ] {
+        let element = decode(input).unwrap();
+        assert_eq!(element, loads_json5(expected).unwrap(), "{}", input);
+    }
+    // Infinities and NaN have no JSON form
+    for input in ["f97c00", "f97e00", "fa7f800000", "fbfff0000000000000", "f7"] {
+        assert_eq!(decode(input), Ok(JSONElement::Null), "{}", input);
+    }
+}
+
+#[test]
+fn applies_the_conversion_policies() {
+    let bytes = "5f42010243030405ff";
+    assert_eq!(
+        decode(bytes),
+        Ok(JSONElement::String("AQIDBAU".to_string()))
+    );
+    let options = |bytes, keys, unknown_tags| CborOptions {
+        bytes,
+        keys,
+        unknown_tags,
+    };
+    let hex_bytes = options(
+        BytesPolicy::Hex,
+        KeyPolicy::Stringify,
+        TagPolicy::PassThrough,
+    );
+    assert_eq!(
+        from_cbor(&unhex(bytes), &hex_bytes),
+        Ok(JSONElement::String("0102030405".to_string()))
+    );
+    let padded = options(
+        BytesPolicy::Base64,
+        KeyPolicy::Stringify,
+        TagPolicy::PassThrough,
+    );
+    assert_eq!(
+        from_cbor(&unhex("43fbff00"), &padded),
+        Ok(JSONElement::String("+/8A".to_string()))
+    );
+    assert_eq!(
+        from_cbor(&unhex("42fbff"), &padded),
+        Ok(JSONElement::String("+/8=".to_string()))
+    );
+    let strict = options(BytesPolicy::Reject, KeyPolicy::Reject, TagPolicy::Reject);
+    assert!(from_cbor(&unhex(bytes), &strict).is_err());
+    assert!(from_cbor(&unhex("a10102"), &strict).is_err());
+    assert!(from_cbor(&unhex("d9d9f7a0"), &strict).is_ok());
+    assert!(from_cbor(&unhex("d82000"), &strict).is_ok());
+    assert!(from_cbor(&unhex("d8ff00"), &strict).is_err());
+    assert_eq!(decode("d8ff00"), Ok(JSONElement::Number(0)));
+    // Keys that aren't strings are written as JSON
+    assert_eq!(
+        decode("a3f5008201020141616143"),
+        Ok(loads(r#"{"true": 0, "[1,2]": 1, "YQ": "C"}"#).unwrap())
+    );
+}
+
+#[test]
+fn rejects_malformed_input() {
+    for input in [
+        "",
+        "18",
+        "1a0001",
+        "62c3",
+        "62c328",
+        "1c",
+        "5f6161ff",
+        "7f4161ff",
+        "7f6161",
+        "9f01",
+        "ff",
+        "8201ff",
+        "a2616100616100",
+        "a1ff",
+        "f8ff",
+        "0000",
+    ] {
+        assert!(decode(input).is_err(), "{}", input);
+    }
+    // Claimed lengths far beyond the input don't allocate
+    assert!(decode("5b7fffffffffffffff").is_err());
+    assert!(decode("9b7fffffffffffffff").is_err());
+    assert!(decode(&"81".repeat(10000)).is_err());
+    assert!(decode(&format!("{}00", "81".repeat(200))).is_ok());
+}
+
+#[test]
+fn round_trips_documents() {
+    let document = loads_json5(
+        r#"{"name": "caf\u00e9", "list": [1, -2, 3.25, 1e100, null, true, [], {}],
+            "big": 4294967296, "nested": {"b": {"c": ["d"]}}}"#,
+    )
+    .unwrap();
+    let encoded = to_cbor(&document);
+    assert_eq!(from_cbor(&encoded, &CborOptions::default()), Ok(document));
+}
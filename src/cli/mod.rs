@@ -0,0 +1,485 @@
+use crate::ast_parser::filter::eval::run_filter;
+use crate::ast_parser::filter::parse_filter;
+use crate::codegen::{rust_from_samples, rust_from_schema, CodegenOptions};
+use crate::cst::{parse_cst_recovering, CstNode, CstOptions};
+use crate::pointer::resolve;
+use crate::rustifier::{dumps, dumps_pretty, loads_json5, JSONElement};
+use crate::spanned::LineIndex;
+use crate::NumberType;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+
+pub const USAGE: &str = "\
+Usage: json <command> [options] [FILE...]
+
+Reads each FILE, or standard input when there are none (or the FILE is -).
+
+Commands:
+  validate          Check that every input is valid, printing each error with its line and column
+  fmt               Pretty-print, keeping key order and comments
+  minify            Print without any whitespace or comments
+  get POINTER       Print the value at a JSON pointer like /servers/0/host
+  query FILTER      Run a jq-style filter like '.items[] | select(.ok) | {id}' on each input,
+                    printing every value it outputs
+  keys              Print the keys of an object, or the indexes of an array, one per line
+  stats             Print counts of each kind of value, the nesting depth and the size
+  codegen           Print Rust types for the documents like the inputs, or for the documents
+                    a JSON Schema describes with --schema
+
+Options:
+  --comments        Allow // and /* */ comments
+  --json5           Read JSON5 instead, which also allows unquoted keys, single quotes,
+                    hexadecimal numbers and trailing commas
+  --indent N        Indent by N spaces (default 2)
+  --tab             Indent with tabs
+  -w, --write       fmt: rewrite the files in place instead of printing them
+  --check           fmt: print the files that aren't formatted, and fail if there are any
+  -r, --raw         get, query: print strings without quotes
+  -c, --compact     query: print each value on one line
+  -p, --pointer P   keys: list the keys of the value at P instead of the whole document
+  --                Take everything after this as arguments, like a filter that starts with -
+  --schema FILE     codegen: generate types from the JSON Schema in FILE instead of inputs
+  --name NAME       codegen: name the type for the whole document NAME (default Root)
+  --serde           codegen: derive serde's Serialize and Deserialize instead of FromElement
+";
+
+enum Failure {
+    // Bad arguments, which exit with 2 rather than 1
+    Usage(String),
+    Failed(String),
+}
+
+impl From<String> for Failure {
+    fn from(message: String) -> Failure {
+        Failure::Failed(message)
+    }
+}
+
+#[derive(Default)]
+struct Options {
+    cst: CstOptions,
+    json5: bool,
+    indent: Option<String>,
+    write: bool,
+    check: bool,
+    raw: bool,
+    compact: bool,
+    pointer: Option<String>,
+    schema: Option<String>,
+    name: Option<String>,
+    serde: bool,
+    arguments: Vec<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, Failure> {
+        let mut options = Options::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = |flag: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| Failure::Usage(format!("{} needs a value", flag)))
+            };
+            match arg.as_str() {
+                "--comments" => options.cst.comments = true,
+                "--json5" => options.json5 = true,
+                "--indent" => {
+                    let width = value(arg)?;
+                    let width: usize = width.parse().map_err(|_| {
+                        Failure::Usage(format!("{} is not a number of spaces", width))
+                    })?;
+                    options.indent = Some(" ".repeat(width));
+                }
+                "--tab" => options.indent = Some("\t".to_string()),
+                "-w" | "--write" => options.write = true,
+                "--check" => options.check = true,
+                "-r" | "--raw" => options.raw = true,
+                "-c" | "--compact" => options.compact = true,
+                "-p" | "--pointer" => options.pointer = Some(value(arg)?),
+                "--schema" => options.schema = Some(value(arg)?),
+                "--name" => options.name = Some(value(arg)?),
+                "--serde" => options.serde = true,
+                "-" => options.arguments.push(arg.clone()),
+                // Everything after "--" is an argument, even if it starts with "-"
+                "--" => options.arguments.extend(args.by_ref().cloned()),
+                _ if arg.starts_with('-') => {
+                    return Err(Failure::Usage(format!("Unknown option {}", arg)))
+                }
+                _ => options.arguments.push(arg.clone()),
+            }
+        }
+        Ok(options)
+    }
+
+    fn indent(&self) -> &str {
+        self.indent.as_deref().unwrap_or("  ")
+    }
+}
+
+// A file (or standard input) and what's in it
+struct Input {
+    name: String,
+    path: Option<String>,
+    text: String,
+}
+
+/// Runs the `json` command with `args` (not including the program name), and gives back the
+/// exit code: 0 on success, 1 when an input is invalid or a command fails, and 2 for bad
+/// arguments.
+pub fn run(
+    args: &[String],
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+    stderr: &mut dyn Write,
+) -> i32 {
+    let result = match args.split_first() {
+        Some((command, rest)) => Options::parse(rest).and_then(|options| match command.as_str() {
+            "validate" => validate(&options, stdin, stdout),
+            "fmt" => format(&options, stdin, stdout),
+            "minify" => minify(&options, stdin, stdout),
+            "get" => get(&options, stdin, stdout),
+            "query" => query(&options, stdin, stdout),
+            "keys" => keys(&options, stdin, stdout),
+            "stats" => stats(&options, stdin, stdout),
+            "codegen" => codegen(&options, stdin, stdout),
+            "help" | "-h" | "--help" => write(stdout, USAGE),
+            _ => Err(Failure::Usage(format!("Unknown command {}", command))),
+        }),
+        None => Err(Failure::Usage("No command given".to_string())),
+    };
+    match result {
+        Ok(code) => code,
+        Err(Failure::Usage(message)) => {
+            let _ = write!(stderr, "{}\n\n{}", message, USAGE);
+            2
+        }
+        Err(Failure::Failed(message)) => {
+            let _ = writeln!(stderr, "{}", message);
+            1
+        }
+    }
+}
+
+fn write(output: &mut dyn Write, text: &str) -> Result<i32, Failure> {
+    output
+        .write_all(text.as_bytes())
+        .map_err(|err| format!("Can't write the output: {}", err))?;
+    Ok(0)
+}
+
+fn read_inputs(paths: &[String], stdin: &mut dyn Read) -> Result<Vec<Input>, Failure> {
+    if paths.is_empty() {
+        return read_inputs(&["-".to_string()], stdin);
+    }
+    paths
+        .iter()
+        .map(|path| {
+            if path == "-" {
+                let mut text = String::new();
+                stdin
+                    .read_to_string(&mut text)
+                    .map_err(|err| format!("Can't read standard input: {}", err))?;
+                Ok(Input {
+                    name: "<stdin>".to_string(),
+                    path: None,
+                    text,
+                })
+            } else {
+                let text =
+                    std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path, err))?;
+                Ok(Input {
+                    name: path.clone(),
+                    path: Some(path.clone()),
+                    text,
+                })
+            }
+        })
+        .collect()
+}
+
+// The commands that read a single document take at most one file
+fn read_input(paths: &[String], stdin: &mut dyn Read) -> Result<Input, Failure> {
+    match paths {
+        [] | [_] => Ok(read_inputs(paths, stdin)?.remove(0)),
+        _ => Err(Failure::Usage(
+            "Only one file can be read at a time".to_string(),
+        )),
+    }
+}
+
+// The syntax tree of a valid input (which isn't needed or available for JSON5), or every error
+// in it, each with where it happened
+fn check<'i>(input: &'i Input, options: &Options) -> Result<Option<CstNode<'i>>, Vec<String>> {
+    if options.json5 {
+        return match loads_json5(&input.text) {
+            Ok(_) => Ok(None),
+            Err(err) => Err(vec![format!("{}: {}", input.name, err)]),
+        };
+    }
+    let (tree, diagnostics) = parse_cst_recovering(&input.text, options.cst);
+    if diagnostics.is_empty() {
+        return Ok(Some(tree));
+    }
+    let lines = LineIndex::new(&input.text);
+    Err(diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let position = lines.position(diagnostic.span.start);
+            format!(
+                "{}:{}:{}: {}",
+                input.name, position.line, position.column, diagnostic.message
+            )
+        })
+        .collect())
+}
+
+fn parse_tree<'i>(input: &'i Input, options: &Options) -> Result<Option<CstNode<'i>>, Failure> {
+    check(input, options).map_err(|errors| Failure::Failed(errors.join("\n")))
+}
+
+fn parse(input: &Input, options: &Options) -> Result<JSONElement, Failure> {
+    match parse_tree(input, options)? {
+        Some(tree) => Ok(tree.to_element()?),
+        None => Ok(loads_json5(&input.text)?),
+    }
+}
+
+fn validate(
+    options: &Options,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+) -> Result<i32, Failure> {
+    let mut code = 0;
+    for input in read_inputs(&options.arguments, stdin)? {
+        if let Err(errors) = check(&input, options) {
+            for error in errors {
+                write(stdout, &format!("{}\n", error))?;
+            }
+            code = 1;
+        }
+    }
+    Ok(code)
+}
+
+fn format(options: &Options, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    if options.write && options.arguments.is_empty() {
+        return Err(Failure::Usage("--write needs files to rewrite".to_string()));
+    }
+    let mut code = 0;
+    for input in read_inputs(&options.arguments, stdin)? {
+        let formatted = match parse_tree(&input, options)? {
+            Some(tree) => tree.format(options.indent()),
+            None => format!(
+                "{}\n",
+                dumps_pretty(&loads_json5(&input.text)?, options.indent())
+            ),
+        };
+        if options.check {
+            if formatted != input.text {
+                write(stdout, &format!("{}\n", input.name))?;
+                code = 1;
+            }
+        } else if let (true, Some(path)) = (options.write, &input.path) {
+            if formatted != input.text {
+                std::fs::write(path, formatted).map_err(|err| format!("{}: {}", path, err))?;
+            }
+        } else {
+            write(stdout, &formatted)?;
+        }
+    }
+    Ok(code)
+}
+
+fn minify(options: &Options, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    for input in read_inputs(&options.arguments, stdin)? {
+        let minified: String = match parse_tree(&input, options)? {
+            // Keeps numbers and strings exactly as they were written
+            Some(tree) => tree
+                .tokens()
+                .iter()
+                .filter(|token| !token.kind.is_trivia())
+                .map(|token| token.text)
+                .collect(),
+            None => dumps(&loads_json5(&input.text)?),
+        };
+        write(stdout, &format!("{}\n", minified))?;
+    }
+    Ok(0)
+}
+
+fn get(options: &Options, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    let Some((pointer, paths)) = options.arguments.split_first() else {
+        return Err(Failure::Usage("get needs a JSON pointer".to_string()));
+    };
+    let input = read_input(paths, stdin)?;
+    let document = parse(&input, options)?;
+    let value = resolve(&document, pointer).map_err(|err| format!("{}: {}", input.name, err))?;
+    match value {
+        JSONElement::String(s) if options.raw => write(stdout, &format!("{}\n", s)),
+        _ => write(
+            stdout,
+            &format!("{}\n", dumps_pretty(value, options.indent())),
+        ),
+    }
+}
+
+fn query(options: &Options, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    let Some((filter, paths)) = options.arguments.split_first() else {
+        return Err(Failure::Usage("query needs a filter".to_string()));
+    };
+    let filter = parse_filter(filter).map_err(|err| format!("Invalid filter: {}", err))?;
+    for input in read_inputs(paths, stdin)? {
+        let document = parse(&input, options)?;
+        let outputs =
+            run_filter(&filter, &document).map_err(|err| format!("{}: {}", input.name, err))?;
+        for output in outputs {
+            let text = match output {
+                JSONElement::String(s) if options.raw => s,
+                _ if options.compact => dumps(&output),
+                _ => dumps_pretty(&output, options.indent()),
+            };
+            write(stdout, &format!("{}\n", text))?;
+        }
+    }
+    Ok(0)
+}
+
+fn keys(options: &Options, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    let input = read_input(&options.arguments, stdin)?;
+    let document = parse(&input, options)?;
+    let pointer = options.pointer.as_deref().unwrap_or("");
+    let value = resolve(&document, pointer).map_err(|err| format!("{}: {}", input.name, err))?;
+    let keys: Vec<String> = match value {
+        JSONElement::Object(members) => {
+            let mut keys: Vec<String> = members.keys().cloned().collect();
+            keys.sort();
+            keys
+        }
+        JSONElement::Array(items) => (0..items.len()).map(|i| i.to_string()).collect(),
+        _ => {
+            return Err(Failure::Failed(format!(
+                "{}: {} is not an object or array",
+                input.name,
+                if pointer.is_empty() {
+                    "the document"
+                } else {
+                    pointer
+                }
+            )))
+        }
+    };
+    write(
+        stdout,
+        &keys
+            .iter()
+            .map(|key| format!("{}\n", key))
+            .collect::<String>(),
+    )
+}
+
+fn stats(options: &Options, stdin: &mut dyn Read, stdout: &mut dyn Write) -> Result<i32, Failure> {
+    let input = read_input(&options.arguments, stdin)?;
+    let document = parse(&input, options)?;
+    let mut counts: HashMap<String, JSONElement> = [
+        "objects", "members", "arrays", "elements", "strings", "numbers", "booleans", "nulls",
+    ]
+    .iter()
+    .map(|name| (name.to_string(), JSONElement::Number(0)))
+    .collect();
+    let depth = count(&document, &mut counts);
+    counts.insert("depth".to_string(), JSONElement::Number(depth));
+    counts.insert(
+        "bytes".to_string(),
+        JSONElement::Number(saturate(input.text.len())),
+    );
+    write(
+        stdout,
+        &format!(
+            "{}\n",
+            dumps_pretty(&JSONElement::Object(counts), options.indent())
+        ),
+    )
+}
+
+// Counts too big for a NumberType stop at its maximum rather than wrapping round
+fn saturate(n: usize) -> NumberType {
+    n.try_into().unwrap_or(NumberType::MAX)
+}
+
+// Adds up how many of each kind of value there are in `element`, and gives back how deeply
+// it's nested
+fn count(element: &JSONElement, counts: &mut HashMap<String, JSONElement>) -> NumberType {
+    let add = |counts: &mut HashMap<String, JSONElement>, name: &str, n: usize| {
+        if let Some(JSONElement::Number(total)) = counts.get_mut(name) {
+            *total = total.saturating_add(saturate(n));
+        }
+    };
+    match element {
+        JSONElement::Object(members) => {
+            add(counts, "objects", 1);
+            add(counts, "members", members.len());
+            1 + members
+                .values()
+                .map(|value| count(value, counts))
+                .max()
+                .unwrap_or(0)
+        }
+        JSONElement::Array(items) => {
+            add(counts, "arrays", 1);
+            add(counts, "elements", items.len());
+            1 + items
+                .iter()
+                .map(|item| count(item, counts))
+                .max()
+                .unwrap_or(0)
+        }
+        JSONElement::String(_) => {
+            add(counts, "strings", 1);
+            0
+        }
+        JSONElement::Number(_) | JSONElement::Float(_) => {
+            add(counts, "numbers", 1);
+            0
+        }
+        JSONElement::Boolean(_) => {
+            add(counts, "booleans", 1);
+            0
+        }
+        JSONElement::Null => {
+            add(counts, "nulls", 1);
+            0
+        }
+    }
+}
+
+fn codegen(
+    options: &Options,
+    stdin: &mut dyn Read,
+    stdout: &mut dyn Write,
+) -> Result<i32, Failure> {
+    let codegen_options = CodegenOptions {
+        root_name: options.name.as_deref().unwrap_or("Root"),
+        serde: options.serde,
+        ..CodegenOptions::default()
+    };
+    let code = match &options.schema {
+        Some(_) if !options.arguments.is_empty() => {
+            return Err(Failure::Usage(
+                "codegen reads either a --schema or samples, not both".to_string(),
+            ))
+        }
+        Some(path) => {
+            let input = read_input(std::slice::from_ref(path), stdin)?;
+            rust_from_schema(&parse(&input, options)?, codegen_options)
+        }
+        None => {
+            let samples = read_inputs(&options.arguments, stdin)?
+                .iter()
+                .map(|input| parse(input, options))
+                .collect::<Result<Vec<_>, _>>()?;
+            rust_from_samples(&samples, codegen_options)
+        }
+    };
+    write(stdout, &code?)
+}
@@ -0,0 +1,577 @@
+use crate::pointer::{parse_pointer, percent_decode, resolve};
+use crate::rustifier::JSONElement;
+use crate::schema::infer::{InferOptions, Inference};
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+mod tests;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CodegenOptions<'a> {
+    // The name of the type for the whole document
+    pub root_name: &'a str,
+    // Derive serde's Serialize and Deserialize (with renames for fields whose Rust names differ
+    // from their keys) instead of implementing FromElement
+    pub serde: bool,
+    // How the generated code refers to this crate
+    pub crate_path: &'a str,
+}
+
+impl Default for CodegenOptions<'_> {
+    fn default() -> Self {
+        CodegenOptions {
+            root_name: "Root",
+            serde: false,
+            crate_path: "json_parser",
+        }
+    }
+}
+
+/// Generates Rust types for the documents a JSON Schema describes. Objects with properties
+/// become structs, with an `Option` for every member that isn't required, and string enums
+/// become enums. Anything that doesn't map onto a Rust type (like an anyOf of different types)
+/// is left as a `JSONElement`, or a `serde_json::Value` with `serde` set.
+pub fn rust_from_schema(schema: &JSONElement, options: CodegenOptions) -> Result<String, String> {
+    let mut generator = Generator {
+        root: schema,
+        options,
+        definitions: vec![],
+        names: HashSet::new(),
+        references: HashMap::new(),
+        in_progress: vec![],
+    };
+    let root = generator.type_of(schema, options.root_name, "")?;
+    // A root that isn't a struct or enum still gets a name
+    if !generator.names.contains(&root) {
+        let name = generator.claim(options.root_name, "");
+        generator
+            .definitions
+            .insert(0, Definition::Alias(name, root));
+    }
+    Ok(generator.render())
+}
+
+/// Generates Rust types for documents like `samples`, going through `infer_schema`. Since the
+/// samples can't show every value a string could have, strings never become enums.
+pub fn rust_from_samples<'a>(
+    samples: impl IntoIterator<Item = &'a JSONElement>,
+    options: CodegenOptions,
+) -> Result<String, String> {
+    let mut inference = Inference::new(InferOptions { enum_limit: 0 });
+    for sample in samples {
+        inference.add(sample);
+    }
+    rust_from_schema(&inference.to_schema(), options)
+}
+
+enum Definition {
+    Struct(String, Vec<Field>),
+    // Each variant's name and the string it stands for
+    Enum(String, Vec<(String, String)>),
+    Alias(String, String),
+}
+
+struct Field {
+    key: String,
+    name: String,
+    rust_type: String,
+}
+
+struct Generator<'s, 'o> {
+    root: &'s JSONElement,
+    options: CodegenOptions<'o>,
+    definitions: Vec<Definition>,
+    names: HashSet<String>,
+    // The type generated for each $ref target
+    references: HashMap<String, String>,
+    // The $ref targets whose types are being generated, which need a Box to refer to themselves
+    in_progress: Vec<String>,
+}
+
+impl<'s> Generator<'s, '_> {
+    // The Rust type for values matching `schema`. `hint` is what to call a new type, and
+    // `parent` the type it's inside of, which helps when the hint is already taken.
+    fn type_of(
+        &mut self,
+        schema: &'s JSONElement,
+        hint: &str,
+        parent: &str,
+    ) -> Result<String, String> {
+        let JSONElement::Object(members) = schema else {
+            return Ok(self.any());
+        };
+        if let Some(JSONElement::String(reference)) = members.get("$ref") {
+            return self.reference(reference);
+        }
+        if let Some(JSONElement::Array(values)) = members.get("enum") {
+            return self.enumeration(values, hint, parent);
+        }
+        if let Some(JSONElement::String(_)) = members.get("const") {
+            return Ok("String".to_string());
+        }
+        let alternatives = match (members.get("anyOf"), members.get("oneOf")) {
+            (Some(JSONElement::Array(alternatives)), _)
+            | (_, Some(JSONElement::Array(alternatives))) => Some(alternatives),
+            _ => None,
+        };
+        if let Some(alternatives) = alternatives {
+            let is_null = |schema: &JSONElement| match schema {
+                JSONElement::Object(members) => {
+                    matches!(members.get("type"), Some(JSONElement::String(name)) if name == "null")
+                }
+                _ => false,
+            };
+            let rest: Vec<&JSONElement> = alternatives
+                .iter()
+                .filter(|schema| !is_null(schema))
+                .collect();
+            return match rest[..] {
+                [schema] if rest.len() < alternatives.len() => {
+                    let inner = self.type_of(schema, hint, parent)?;
+                    Ok(optional(inner))
+                }
+                [schema] => self.type_of(schema, hint, parent),
+                _ => Ok(self.any()),
+            };
+        }
+
+        let mut types: Vec<&str> = match members.get("type") {
+            Some(JSONElement::String(name)) => vec![name],
+            Some(JSONElement::Array(names)) => names
+                .iter()
+                .filter_map(|name| match name {
+                    JSONElement::String(name) => Some(name.as_str()),
+                    _ => None,
+                })
+                .collect(),
+            _ if members.contains_key("properties") => vec!["object"],
+            _ if members.contains_key("items") => vec!["array"],
+            _ => vec![],
+        };
+        let nullable = types.contains(&"null") && types.len() > 1;
+        types.retain(|name| *name != "null" || !nullable);
+        let rust_type = match types[..] {
+            ["string"] => "String".to_string(),
+            ["integer"] => "i64".to_string(),
+            ["number"] => "f64".to_string(),
+            ["boolean"] => "bool".to_string(),
+            ["array"] => match members.get("items") {
+                Some(items) => format!("Vec<{}>", self.type_of(items, &singular(hint), parent)?),
+                None => format!("Vec<{}>", self.any()),
+            },
+            ["object"] => match (
+                members.get("properties"),
+                members.get("additionalProperties"),
+            ) {
+                (Some(JSONElement::Object(properties)), _) => {
+                    let required = match members.get("required") {
+                        Some(JSONElement::Array(keys)) => keys.iter().collect(),
+                        _ => vec![],
+                    };
+                    self.structure(properties, &required, hint, parent)?
+                }
+                (_, Some(values @ JSONElement::Object(_))) => {
+                    format!(
+                        "HashMap<String, {}>",
+                        self.type_of(values, &singular(hint), parent)?
+                    )
+                }
+                _ => format!("HashMap<String, {}>", self.any()),
+            },
+            _ => self.any(),
+        };
+        Ok(if nullable {
+            optional(rust_type)
+        } else {
+            rust_type
+        })
+    }
+
+    fn structure(
+        &mut self,
+        properties: &'s HashMap<String, JSONElement>,
+        required: &[&JSONElement],
+        hint: &str,
+        parent: &str,
+    ) -> Result<String, String> {
+        let name = self.claim(hint, parent);
+        // Added now so nested types come after it
+        let index = self.definitions.len();
+        self.definitions
+            .push(Definition::Struct(name.clone(), vec![]));
+        let mut keys: Vec<&String> = properties.keys().collect();
+        keys.sort();
+        let mut fields: Vec<Field> = vec![];
+        for key in keys {
+            let mut rust_type = self.type_of(&properties[key], &pascal_case(key), &name)?;
+            if !required.contains(&&JSONElement::String(key.clone())) {
+                rust_type = optional(rust_type);
+            }
+            let mut field_name = snake_case(key);
+            while fields.iter().any(|field| field.name == field_name) {
+                field_name.push('_');
+            }
+            fields.push(Field {
+                key: key.clone(),
+                name: field_name,
+                rust_type,
+            });
+        }
+        self.definitions[index] = Definition::Struct(name.clone(), fields);
+        Ok(name)
+    }
+
+    fn enumeration(
+        &mut self,
+        values: &[JSONElement],
+        hint: &str,
+        parent: &str,
+    ) -> Result<String, String> {
+        let strings: Option<Vec<&String>> = values
+            .iter()
+            .map(|value| match value {
+                JSONElement::String(s) => Some(s),
+                _ => None,
+            })
+            .collect();
+        let Some(strings) = strings.filter(|strings| !strings.is_empty()) else {
+            return Ok(self.any());
+        };
+        let name = self.claim(hint, parent);
+        let mut variants: Vec<(String, String)> = vec![];
+        for value in strings {
+            let mut variant = match pascal_case(value) {
+                variant if variant.is_empty() => "Empty".to_string(),
+                variant if variant.starts_with(|c: char| c.is_ascii_digit()) => {
+                    format!("V{}", variant)
+                }
+                variant => variant,
+            };
+            while variants.iter().any(|(existing, _)| *existing == variant) {
+                variant.push('_');
+            }
+            variants.push((variant, value.clone()));
+        }
+        self.definitions
+            .push(Definition::Enum(name.clone(), variants));
+        Ok(name)
+    }
+
+    fn reference(&mut self, reference: &str) -> Result<String, String> {
+        let pointer = percent_decode(reference.strip_prefix('#').ok_or_else(|| {
+            format!(
+                "Only references within the schema are supported, not {}",
+                reference
+            )
+        })?);
+        if self.in_progress.contains(&pointer) {
+            return Ok(format!("Box<{}>", self.references[&pointer]));
+        }
+        if let Some(name) = self.references.get(&pointer) {
+            return Ok(name.clone());
+        }
+        let target = resolve(self.root, &pointer)
+            .map_err(|err| format!("Can't resolve the reference {}: {}", reference, err))?;
+        let hint = match parse_pointer(&pointer)?.pop() {
+            Some(token) => pascal_case(&token),
+            None => self.options.root_name.to_string(),
+        };
+        // A reference back to the root is to the type generated for it
+        if pointer.is_empty() {
+            self.references.insert(pointer.clone(), hint.clone());
+            return Ok(format!("Box<{}>", hint));
+        }
+        // Names the type before generating it, for references that lead back to it
+        let name = next_free(&self.names, &hint);
+        self.references.insert(pointer.clone(), name);
+        self.in_progress.push(pointer.clone());
+        let rust_type = self.type_of(target, &hint, "");
+        self.in_progress.pop();
+        let rust_type = rust_type?;
+        self.references.insert(pointer, rust_type.clone());
+        Ok(rust_type)
+    }
+
+    // A type name based on `hint` that isn't taken yet
+    fn claim(&mut self, hint: &str, parent: &str) -> String {
+        let hint = match pascal_case(hint) {
+            hint if hint.is_empty() || hint.starts_with(|c: char| c.is_ascii_digit()) => {
+                format!("Type{}", hint)
+            }
+            hint => hint,
+        };
+        let name = if self.names.contains(&hint) && !parent.is_empty() {
+            next_free(&self.names, &format!("{}{}", parent, hint))
+        } else {
+            next_free(&self.names, &hint)
+        };
+        self.names.insert(name.clone());
+        name
+    }
+
+    // The type for values that could be anything
+    fn any(&self) -> String {
+        if self.options.serde {
+            "serde_json::Value".to_string()
+        } else {
+            "JSONElement".to_string()
+        }
+    }
+
+    fn render(&self) -> String {
+        let mut output = String::new();
+        let uses_map = self.definitions.iter().any(|definition| match definition {
+            Definition::Struct(_, fields) => fields
+                .iter()
+                .any(|field| field.rust_type.contains("HashMap<")),
+            Definition::Alias(_, rust_type) => rust_type.contains("HashMap<"),
+            Definition::Enum(..) => false,
+        });
+        let uses_any = self.definitions.iter().any(|definition| match definition {
+            Definition::Struct(_, fields) => fields
+                .iter()
+                .any(|field| field.rust_type.contains(&self.any())),
+            Definition::Alias(_, rust_type) => rust_type.contains(&self.any()),
+            Definition::Enum(..) => false,
+        });
+        if self.options.serde {
+            output.push_str("use serde::{Deserialize, Serialize};\n");
+        } else {
+            let implements = self
+                .definitions
+                .iter()
+                .any(|definition| !matches!(definition, Definition::Alias(..)));
+            let has_fields = self.definitions.iter().any(|definition| {
+                matches!(definition, Definition::Struct(_, fields) if !fields.is_empty())
+            });
+            let imports: Vec<&str> = [
+                ("from_member", has_fields),
+                ("FromElement", implements),
+                ("JSONElement", implements || uses_any),
+            ]
+            .into_iter()
+            .filter_map(|(name, used)| used.then_some(name))
+            .collect();
+            match imports[..] {
+                [] => {}
+                [name] => writeln!(
+                    output,
+                    "use {}::rustifier::{};",
+                    self.options.crate_path, name
+                )
+                .unwrap(),
+                _ => writeln!(
+                    output,
+                    "use {}::rustifier::{{{}}};",
+                    self.options.crate_path,
+                    imports.join(", ")
+                )
+                .unwrap(),
+            }
+        }
+        if uses_map {
+            output.push_str("use std::collections::HashMap;\n");
+        }
+        let derives = if self.options.serde {
+            "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]"
+        } else {
+            "#[derive(Debug, Clone, PartialEq)]"
+        };
+        for definition in &self.definitions {
+            if !output.is_empty() {
+                output.push('\n');
+            }
+            match definition {
+                Definition::Alias(name, rust_type) => {
+                    writeln!(output, "pub type {} = {};", name, rust_type).unwrap();
+                }
+                Definition::Struct(name, fields) => {
+                    writeln!(output, "{}\npub struct {} {{", derives, name).unwrap();
+                    for field in fields {
+                        if self.options.serde {
+                            let mut attributes = vec![];
+                            if field.name != field.key {
+                                attributes.push(format!("rename = {:?}", field.key));
+                            }
+                            if field.rust_type.starts_with("Option<") {
+                                attributes.push("default".to_string());
+                                attributes
+                                    .push("skip_serializing_if = \"Option::is_none\"".to_string());
+                            }
+                            if !attributes.is_empty() {
+                                writeln!(output, "    #[serde({})]", attributes.join(", "))
+                                    .unwrap();
+                            }
+                        }
+                        writeln!(output, "    pub {}: {},", field.name, field.rust_type).unwrap();
+                    }
+                    output.push_str("}\n");
+                    if !self.options.serde {
+                        writeln!(
+                            output,
+                            "\nimpl FromElement for {name} {{\n    fn from_element(element: &JSONElement) -> Result<{name}, String> {{\n        match element {{"
+                        )
+                        .unwrap();
+                        if fields.is_empty() {
+                            writeln!(
+                                output,
+                                "            JSONElement::Object(_) => Ok({} {{}}),",
+                                name
+                            )
+                            .unwrap();
+                        } else {
+                            writeln!(
+                                output,
+                                "            JSONElement::Object(members) => Ok({} {{",
+                                name
+                            )
+                            .unwrap();
+                            for field in fields {
+                                writeln!(
+                                    output,
+                                    "                {}: from_member(members, {:?})?,",
+                                    field.name, field.key
+                                )
+                                .unwrap();
+                            }
+                            output.push_str("            }),\n");
+                        }
+                        writeln!(
+                            output,
+                            "            _ => Err(\"{} must be an object\".to_string()),\n        }}\n    }}\n}}",
+                            name
+                        )
+                        .unwrap();
+                    }
+                }
+                Definition::Enum(name, variants) => {
+                    writeln!(output, "{}\npub enum {} {{", derives, name).unwrap();
+                    for (variant, value) in variants {
+                        if self.options.serde && variant != value {
+                            writeln!(output, "    #[serde(rename = {:?})]", value).unwrap();
+                        }
+                        writeln!(output, "    {},", variant).unwrap();
+                    }
+                    output.push_str("}\n");
+                    if !self.options.serde {
+                        writeln!(
+                            output,
+                            "\nimpl FromElement for {name} {{\n    fn from_element(element: &JSONElement) -> Result<{name}, String> {{\n        match element {{"
+                        )
+                        .unwrap();
+                        for (variant, value) in variants {
+                            writeln!(
+                                output,
+                                "            JSONElement::String(s) if s == {:?} => Ok({}::{}),",
+                                value, name, variant
+                            )
+                            .unwrap();
+                        }
+                        writeln!(
+                            output,
+                            "            _ => Err(format!(\"{{:?}} is not a {}\", element)),\n        }}\n    }}\n}}",
+                            name
+                        )
+                        .unwrap();
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+fn optional(rust_type: String) -> String {
+    if rust_type.starts_with("Option<") {
+        rust_type
+    } else {
+        format!("Option<{}>", rust_type)
+    }
+}
+
+fn next_free(names: &HashSet<String>, name: &str) -> String {
+    (1..)
+        .map(|i| match i {
+            1 => name.to_string(),
+            _ => format!("{}{}", name, i),
+        })
+        .find(|candidate| !names.contains(candidate))
+        .expect("there's always a free name")
+}
+
+// Splits a key like "userName", "user_name", "user-name" or "HTTPStatus" into its words
+fn words(s: &str) -> Vec<String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut words: Vec<String> = vec![];
+    let mut current = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            continue;
+        }
+        let previous = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        let boundary = c.is_uppercase()
+            && previous.is_some_and(|previous| {
+                previous.is_lowercase()
+                    || previous.is_ascii_digit()
+                    || (previous.is_uppercase() && next.is_some_and(|next| next.is_lowercase()))
+            });
+        if boundary && !current.is_empty() {
+            words.push(std::mem::take(&mut current));
+        }
+        current.push(*c);
+    }
+    if !current.is_empty() {
+        words.push(current);
+    }
+    words
+}
+
+pub fn pascal_case(s: &str) -> String {
+    words(s)
+        .iter()
+        .map(|word| {
+            let mut chars = word.chars();
+            let first = chars.next().map(|c| c.to_uppercase().collect::<String>());
+            first.unwrap_or_default() + &chars.as_str().to_lowercase()
+        })
+        .collect()
+}
+
+pub fn snake_case(s: &str) -> String {
+    let name = words(s)
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join("_");
+    match name.as_str() {
+        "" => "field".to_string(),
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => format!("_{}", name),
+        _ if KEYWORDS.contains(&name.as_str()) => format!("{}_", name),
+        _ => name,
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub",
+    "ref", "return", "self", "static", "struct", "super", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "abstract", "become", "box", "do", "final", "gen", "macro", "override",
+    "priv", "try", "typeof", "unsized", "virtual", "yield",
+];
+
+// A guess at the singular of a plural name, for naming the items of an array
+fn singular(name: &str) -> String {
+    if let Some(stem) = name.strip_suffix("ies") {
+        format!("{}y", stem)
+    } else if name.ends_with("ss") || name.ends_with("us") {
+        format!("{}Item", name)
+    } else if let Some(stem) = name.strip_suffix('s') {
+        stem.to_string()
+    } else {
+        format!("{}Item", name)
+    }
+}
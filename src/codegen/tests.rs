@@ -0,0 +1,121 @@
+#[cfg(test)]
+use crate::codegen::*;
+#[cfg(test)]
+use crate::rustifier::{loads, loads_as};
+#[cfg(test)]
+use std::path::Path;
+
+// The expected output for testdata/codegen/order.schema.json, included here so the test also
+// checks that it compiles and loads documents
+#[cfg(test)]
+mod order {
+    include!("../../testdata/codegen/order.rs");
+}
+
+#[cfg(test)]
+fn order_options() -> CodegenOptions<'static> {
+    CodegenOptions {
+        root_name: "Order",
+        crate_path: "crate",
+        ..CodegenOptions::default()
+    }
+}
+
+#[test]
+fn generates_types_from_a_schema() {
+    let testdata = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/codegen");
+    let schema =
+        loads(&std::fs::read_to_string(testdata.join("order.schema.json")).unwrap()).unwrap();
+    assert_eq!(
+        rust_from_schema(&schema, order_options()).unwrap(),
+        std::fs::read_to_string(testdata.join("order.rs")).unwrap()
+    );
+}
+
+#[test]
+fn generated_types_load_documents() {
+    let order: order::Order = loads_as(
+        r#"{"id": 7, "status": "in-progress", "type": "retail",
+            "customer": {"name": "Ann", "referredBy": {"name": "Bo"}},
+            "items": [{"sku": "A1", "quantity": 2}], "metadata": {"gift": "yes"}}"#,
+    )
+    .unwrap();
+    assert_eq!(order.status, order::Status::InProgress);
+    assert_eq!(order.type_.as_deref(), Some("retail"));
+    assert_eq!(order.customer.referred_by.unwrap().name, "Bo");
+    assert_eq!(order.items[0].unit_price, None);
+    assert_eq!(
+        loads_as::<order::Order>(
+            r#"{"id": 7, "status": "lost", "customer": {"name": "Ann"}, "items": []}"#
+        )
+        .unwrap_err(),
+        "/status: String(\"lost\") is not a Status"
+    );
+    assert_eq!(
+        loads_as::<order::Order>(r#"{"id": 7, "status": "shipped", "items": []}"#).unwrap_err(),
+        "Missing required member customer"
+    );
+}
+
+#[test]
+fn generates_types_from_samples() {
+    let samples = [
+        loads(r#"{"userName": "a", "tags": ["x"], "address": {"zip": "1"}}"#).unwrap(),
+        loads(r#"{"userName": "b", "tags": [], "age": 3}"#).unwrap(),
+    ];
+    let code = rust_from_samples(&samples, CodegenOptions::default()).unwrap();
+    assert!(code
+        .starts_with("use json_parser::rustifier::{from_member, FromElement, JSONElement};\n\n"));
+    assert!(code.contains("pub struct Root {\n    pub address: Option<Address>,\n    pub age: Option<i64>,\n    pub tags: Vec<String>,\n    pub user_name: String,\n}"));
+    assert!(code.contains("pub struct Address {\n    pub zip: String,\n}"));
+}
+
+#[test]
+fn generates_serde_types() {
+    let schema = loads(
+        r#"{"type": "object", "required": ["kind"], "properties": {
+            "kind": {"enum": ["a-b", "C"]}, "fullName": {"type": "string"}, "data": {}
+        }}"#,
+    )
+    .unwrap();
+    let options = CodegenOptions {
+        serde: true,
+        ..CodegenOptions::default()
+    };
+    assert_eq!(
+        rust_from_schema(&schema, options).unwrap(),
+        r#"use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Root {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<serde_json::Value>,
+    #[serde(rename = "fullName", default, skip_serializing_if = "Option::is_none")]
+    pub full_name: Option<String>,
+    pub kind: Kind,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Kind {
+    #[serde(rename = "a-b")]
+    AB,
+    C,
+}
+"#
+    );
+}
+
+#[test]
+fn names_things_like_rust() {
+    assert_eq!(snake_case("userID"), "user_id");
+    assert_eq!(snake_case("HTTPStatus"), "http_status");
+    assert_eq!(snake_case("2fa"), "_2fa");
+    assert_eq!(snake_case("match"), "match_");
+    assert_eq!(pascal_case("in-progress"), "InProgress");
+    assert_eq!(pascal_case("user_name"), "UserName");
+    let schema = loads(r#"{"type": "array", "items": {"type": "integer"}}"#).unwrap();
+    assert_eq!(
+        rust_from_schema(&schema, CodegenOptions::default()).unwrap(),
+        "pub type Root = Vec<i64>;\n"
+    );
+}
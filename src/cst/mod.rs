@@ -0,0 +1,599 @@
+use crate::ast_parser::{
+    found, ASTNode, BooleanParser, CommentParser, JsonNumberParser, LiteralParser, NullParser,
+    Parser, StringParser, WhitespaceParser,
+};
+use crate::rustifier::{convert, JSONElement};
+use std::fmt;
+
+mod tests;
+
+/// Byte offsets into the original input, `start` inclusive and `end` exclusive
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TokenKind {
+    Whitespace,
+    Comment,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+    Comma,
+    Colon,
+    String,
+    Number,
+    Boolean,
+    Null,
+    // Text a recovering parse had to skip
+    Error,
+}
+
+impl TokenKind {
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Self::Whitespace | Self::Comment)
+    }
+
+    // How error messages name the token
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Whitespace => "whitespace",
+            Self::Comment => "a comment",
+            Self::LeftBracket => "\"[\"",
+            Self::RightBracket => "\"]\"",
+            Self::LeftBrace => "\"{\"",
+            Self::RightBrace => "\"}\"",
+            Self::Comma => "\",\"",
+            Self::Colon => "\":\"",
+            Self::String => "a string",
+            Self::Number => "a number",
+            Self::Boolean => "true or false",
+            Self::Null => "null",
+            Self::Error => "a value",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token<'i> {
+    pub kind: TokenKind,
+    pub text: &'i str,
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NodeKind {
+    Document,
+    Array,
+    Object,
+    // A key, the colon and the value, plus any trivia between them
+    Member,
+    // A string, number, boolean or null token
+    Scalar,
+    // Stands in for a value or member that couldn't be parsed, holding whatever text was skipped
+    Error,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum CstElement<'i> {
+    Node(CstNode<'i>),
+    Token(Token<'i>),
+}
+
+/// A node in the concrete syntax tree. Every byte of the input belongs to exactly one token, so
+/// printing a tree gives back exactly the text it was parsed from.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CstNode<'i> {
+    pub kind: NodeKind,
+    pub span: Span,
+    pub children: Vec<CstElement<'i>>,
+}
+
+impl<'i> CstNode<'i> {
+    pub fn tokens(&self) -> Vec<&Token<'i>> {
+        self.children
+            .iter()
+            .flat_map(|child| match child {
+                CstElement::Node(node) => node.tokens(),
+                CstElement::Token(token) => vec![token],
+            })
+            .collect()
+    }
+
+    pub fn child_nodes(&self) -> impl Iterator<Item = &CstNode<'i>> {
+        self.children.iter().filter_map(|child| match child {
+            CstElement::Node(node) => Some(node),
+            CstElement::Token(_) => None,
+        })
+    }
+
+    fn child_tokens(&self) -> impl Iterator<Item = &Token<'i>> {
+        self.children.iter().filter_map(|child| match child {
+            CstElement::Token(token) => Some(token),
+            CstElement::Node(_) => None,
+        })
+    }
+
+    /// The value of a Document or Member node
+    pub fn value(&self) -> Option<&CstNode<'i>> {
+        match self.kind {
+            NodeKind::Document | NodeKind::Member => self.child_nodes().next(),
+            _ => None,
+        }
+    }
+
+    /// The key token of a Member node, quotes included
+    pub fn key(&self) -> Option<&Token<'i>> {
+        match self.kind {
+            NodeKind::Member => self
+                .child_tokens()
+                .find(|token| token.kind == TokenKind::String),
+            _ => None,
+        }
+    }
+
+    pub fn to_ast(&self) -> Result<ASTNode<'i>, String> {
+        match self.kind {
+            NodeKind::Document | NodeKind::Member => self
+                .value()
+                .ok_or_else(|| format!("{:?} node had no value", self.kind))?
+                .to_ast(),
+            NodeKind::Array => Ok(ASTNode::Sequence(
+                self.child_nodes()
+                    .map(|node| node.to_ast())
+                    .collect::<Result<Vec<_>, _>>()?,
+            )),
+            NodeKind::Object => Ok(ASTNode::Mapping(
+                self.child_nodes()
+                    .filter(|member| member.kind == NodeKind::Member)
+                    .map(|member| {
+                        let key = member
+                            .key()
+                            .ok_or_else(|| "Member node had no key".to_string())?;
+                        Ok((scalar_to_ast(key)?, member.to_ast()?))
+                    })
+                    .collect::<Result<Vec<_>, String>>()?,
+            )),
+            // Members that couldn't be parsed are left out, and values become null
+            NodeKind::Error => Ok(ASTNode::Null),
+            NodeKind::Scalar => scalar_to_ast(
+                self.child_tokens()
+                    .next()
+                    .ok_or_else(|| "Scalar node had no token".to_string())?,
+            ),
+        }
+    }
+
+    pub fn to_element(&self) -> Result<JSONElement, String> {
+        convert(&self.to_ast()?)
+    }
+
+    /// Rewrites the whitespace so every member and element is on its own line, indented by
+    /// `indent` per level. Unlike `dumps_pretty`, everything else stays as written: key order,
+    /// duplicates and comments. Trailing commas are dropped.
+    pub fn format(&self, indent: &str) -> String {
+        // Each token that isn't whitespace, and whether there was a line break before it
+        let mut tokens: Vec<(&Token, bool)> = vec![];
+        let mut line_break = false;
+        for token in self.tokens() {
+            if token.kind == TokenKind::Whitespace {
+                line_break |= token.text.contains(['\n', '\r']);
+            } else {
+                tokens.push((token, line_break));
+                line_break = false;
+            }
+        }
+        let mut output = String::new();
+        let mut depth = 0;
+        let (mut newline, mut space) = (false, false);
+        for (i, &(token, line_break)) in tokens.iter().enumerate() {
+            let next = tokens.get(i + 1).map(|(token, _)| token.kind);
+            let closes = |kind: Option<TokenKind>| {
+                matches!(kind, Some(TokenKind::RightBrace | TokenKind::RightBracket))
+            };
+            match token.kind {
+                TokenKind::Comma if closes(next) => continue,
+                // A comment on the same line as the token before it stays there
+                TokenKind::Comment if i > 0 && !line_break => {
+                    output.push(' ');
+                    output.push_str(token.text);
+                    newline |= token.text.starts_with("//");
+                    continue;
+                }
+                TokenKind::Comment => newline = !output.is_empty(),
+                TokenKind::RightBrace | TokenKind::RightBracket => {
+                    depth -= 1;
+                    // Empty containers stay on one line
+                    newline = !matches!(
+                        tokens[i - 1].0.kind,
+                        TokenKind::LeftBrace | TokenKind::LeftBracket
+                    );
+                }
+                _ => {}
+            }
+            if newline {
+                output.push('\n');
+                output.push_str(&indent.repeat(depth));
+            } else if space {
+                output.push(' ');
+            }
+            output.push_str(token.text);
+            (newline, space) = (false, false);
+            match token.kind {
+                TokenKind::LeftBrace | TokenKind::LeftBracket => {
+                    depth += 1;
+                    newline = !closes(next);
+                }
+                TokenKind::Comma | TokenKind::Comment => newline = true,
+                TokenKind::Colon => space = true,
+                _ => {}
+            }
+        }
+        output.push('\n');
+        output
+    }
+}
+
+// Scalar tokens were recognized by these same parsers, so running them again over just the
+// token's text can't fail in practice
+fn scalar_to_ast<'i>(token: &Token<'i>) -> Result<ASTNode<'i>, String> {
+    let parser: Box<dyn Parser> = match token.kind {
+        TokenKind::String => Box::new(StringParser()),
+        TokenKind::Number => Box::new(JsonNumberParser()),
+        TokenKind::Boolean => Box::new(BooleanParser()),
+        TokenKind::Null => Box::new(NullParser()),
+        kind => return Err(format!("{:?} token is not a value", kind)),
+    };
+    let (_, node) = parser.parse(token.text)?;
+    node.ok_or_else(|| format!("{:?} token had no value", token.kind))
+}
+
+impl fmt::Display for CstNode<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.tokens()
+            .iter()
+            .try_for_each(|token| write!(f, "{}", token.text))
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CstOptions {
+    // Whether "//" and "/* */" comments are allowed wherever whitespace is
+    pub comments: bool,
+}
+
+/// A parse error and the bytes it's about
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub span: Span,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.span.start)
+    }
+}
+
+pub fn parse_cst(input: &str) -> Result<CstNode<'_>, String> {
+    parse_cst_with(input, CstOptions::default())
+}
+
+pub fn parse_cst_with(input: &str, options: CstOptions) -> Result<CstNode<'_>, String> {
+    CstBuilder::new(input, options, false)
+        .document()
+        .map_err(|diagnostic| diagnostic.to_string())
+}
+
+/// Parses as much as it can instead of stopping at the first error. Whenever a value can't be
+/// parsed, everything up to the next comma or closing bracket at the same depth goes into an
+/// Error node standing in for it, and parsing carries on from there. Always gives back a tree
+/// (which still prints back to exactly the input) along with every error found on the way.
+pub fn parse_cst_recovering(input: &str, options: CstOptions) -> (CstNode<'_>, Vec<Diagnostic>) {
+    let mut builder = CstBuilder::new(input, options, true);
+    let document = builder
+        .document()
+        .expect("a recovering parse always produces a document");
+    (document, builder.errors)
+}
+
+struct CstBuilder<'i> {
+    input: &'i str,
+    rest: &'i str,
+    options: CstOptions,
+    // Whether to record errors and carry on rather than return them
+    recover: bool,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'i> CstBuilder<'i> {
+    fn new(input: &'i str, options: CstOptions, recover: bool) -> CstBuilder<'i> {
+        CstBuilder {
+            input,
+            rest: input,
+            options,
+            recover,
+            errors: vec![],
+        }
+    }
+
+    fn offset(&self) -> usize {
+        self.input.len() - self.rest.len()
+    }
+
+    // An error about the character at the current position
+    fn error(&self, message: &str) -> Diagnostic {
+        let start = self.offset();
+        let length = self.rest.chars().next().map_or(0, char::len_utf8);
+        Diagnostic {
+            message: message.to_string(),
+            span: Span {
+                start,
+                end: start + length,
+            },
+        }
+    }
+
+    fn document(&mut self) -> Result<CstNode<'i>, Diagnostic> {
+        let mut children = vec![];
+        self.trivia(&mut children)?;
+        children.push(CstElement::Node(self.attempt(Self::value)?));
+        self.trivia(&mut children)?;
+        if !self.rest.is_empty() {
+            let error = self.error("Trailing data");
+            if !self.recover {
+                return Err(error);
+            }
+            self.errors.push(error);
+            let token = self.advance(TokenKind::Error, "");
+            children.push(CstElement::Node(CstNode {
+                kind: NodeKind::Error,
+                span: token.span,
+                children: vec![CstElement::Token(token)],
+            }));
+        }
+        Ok(CstNode {
+            kind: NodeKind::Document,
+            span: Span {
+                start: 0,
+                end: self.input.len(),
+            },
+            children,
+        })
+    }
+
+    // Turns everything between the current position and `rest` into a token
+    fn advance(&mut self, kind: TokenKind, rest: &'i str) -> Token<'i> {
+        let start = self.offset();
+        let text = &self.rest[..self.rest.len() - rest.len()];
+        self.rest = rest;
+        Token {
+            kind,
+            text,
+            span: Span {
+                start,
+                end: self.offset(),
+            },
+        }
+    }
+
+    fn token(&mut self, kind: TokenKind, parser: &dyn Parser) -> Result<Token<'i>, Diagnostic> {
+        let (rest, _) = parser.parse(self.rest).map_err(|_| {
+            // The diagnostic says where this is, so the character there is enough to show
+            let next = self.rest.chars().next().map_or(0, char::len_utf8);
+            self.error(&format!(
+                "Expected {} but found {}",
+                kind.describe(),
+                found(&self.rest[..next])
+            ))
+        })?;
+        Ok(self.advance(kind, rest))
+    }
+
+    fn trivia(&mut self, children: &mut Vec<CstElement<'i>>) -> Result<(), Diagnostic> {
+        loop {
+            let (rest, _) = WhitespaceParser()
+                .parse(self.rest)
+                .map_err(|err| self.error(&err))?;
+            if rest.len() < self.rest.len() {
+                let token = self.advance(TokenKind::Whitespace, rest);
+                children.push(CstElement::Token(token));
+            } else if self.options.comments && self.rest.starts_with('/') {
+                let token = match self.token(TokenKind::Comment, &CommentParser()) {
+                    Ok(token) => token,
+                    // Only an unclosed block comment gets here, and it runs to the end
+                    Err(error) if self.recover => {
+                        self.errors.push(error);
+                        self.advance(TokenKind::Error, "")
+                    }
+                    Err(error) => return Err(error),
+                };
+                children.push(CstElement::Token(token));
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    // When recovering, a failed parse is undone and replaced with an Error node
+    fn attempt(
+        &mut self,
+        parse: fn(&mut Self) -> Result<CstNode<'i>, Diagnostic>,
+    ) -> Result<CstNode<'i>, Diagnostic> {
+        let (rest, error_count) = (self.rest, self.errors.len());
+        match parse(self) {
+            Err(error) if self.recover => {
+                self.rest = rest;
+                self.errors.truncate(error_count);
+                self.errors.push(error);
+                Ok(self.skip())
+            }
+            result => result,
+        }
+    }
+
+    // Skips ahead to the next comma or closing bracket that isn't nested in something else,
+    // turning everything on the way into an Error node. Strings are skipped whole so brackets
+    // inside them don't count.
+    fn skip(&mut self) -> CstNode<'i> {
+        let (mut depth, mut in_string, mut escaped) = (0, false, false);
+        let end = self
+            .rest
+            .char_indices()
+            .find(|(_, c)| {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' if in_string => escaped = true,
+                    '"' => in_string = !in_string,
+                    // Strings can't span lines, so one that was never closed ends here
+                    '\n' => in_string = false,
+                    _ if in_string => {}
+                    '[' | '{' => depth += 1,
+                    ',' | ']' | '}' if depth == 0 => return true,
+                    ']' | '}' => depth -= 1,
+                    _ => {}
+                }
+                false
+            })
+            .map_or(self.rest.len(), |(i, _)| i);
+        let start = self.offset();
+        let rest = &self.rest[end..];
+        let children = if end > 0 {
+            vec![CstElement::Token(self.advance(TokenKind::Error, rest))]
+        } else {
+            vec![]
+        };
+        CstNode {
+            kind: NodeKind::Error,
+            span: Span {
+                start,
+                end: self.offset(),
+            },
+            children,
+        }
+    }
+
+    fn value(&mut self) -> Result<CstNode<'i>, Diagnostic> {
+        match self.rest.chars().next() {
+            Some('[') => self.delimited(
+                NodeKind::Array,
+                (TokenKind::LeftBracket, "["),
+                (TokenKind::RightBracket, "]"),
+                Self::value,
+            ),
+            Some('{') => self.delimited(
+                NodeKind::Object,
+                (TokenKind::LeftBrace, "{"),
+                (TokenKind::RightBrace, "}"),
+                Self::member,
+            ),
+            _ => self.scalar(),
+        }
+    }
+
+    fn scalar(&mut self) -> Result<CstNode<'i>, Diagnostic> {
+        let scalars: [(TokenKind, Box<dyn Parser>); 4] = [
+            (TokenKind::String, Box::new(StringParser())),
+            (TokenKind::Number, Box::new(JsonNumberParser())),
+            (TokenKind::Boolean, Box::new(BooleanParser())),
+            (TokenKind::Null, Box::new(NullParser())),
+        ];
+        for (kind, parser) in scalars.iter() {
+            if let Ok((rest, _)) = parser.parse(self.rest) {
+                let token = self.advance(*kind, rest);
+                return Ok(CstNode {
+                    kind: NodeKind::Scalar,
+                    span: token.span,
+                    children: vec![CstElement::Token(token)],
+                });
+            }
+        }
+        Err(self.error("Expected a value"))
+    }
+
+    fn member(&mut self) -> Result<CstNode<'i>, Diagnostic> {
+        let start = self.offset();
+        let mut children = vec![CstElement::Token(
+            self.token(TokenKind::String, &StringParser())?,
+        )];
+        self.trivia(&mut children)?;
+        children.push(CstElement::Token(
+            self.token(TokenKind::Colon, &LiteralParser(":"))?,
+        ));
+        self.trivia(&mut children)?;
+        // Once there's a key and a colon, only the value needs replacing if it's broken
+        children.push(CstElement::Node(self.attempt(Self::value)?));
+        Ok(CstNode {
+            kind: NodeKind::Member,
+            span: Span {
+                start,
+                end: self.offset(),
+            },
+            children,
+        })
+    }
+
+    // Like DelimitedSequenceParser, this allows a trailing comma after the last element
+    fn delimited(
+        &mut self,
+        kind: NodeKind,
+        (open_kind, open): (TokenKind, &'static str),
+        (close_kind, close): (TokenKind, &'static str),
+        element: fn(&mut Self) -> Result<CstNode<'i>, Diagnostic>,
+    ) -> Result<CstNode<'i>, Diagnostic> {
+        let start = self.offset();
+        let mut children = vec![CstElement::Token(
+            self.token(open_kind, &LiteralParser(open))?,
+        )];
+        self.trivia(&mut children)?;
+        // When recovering, a closing bracket of the wrong kind (or the end of the input) means
+        // this one is missing, and it's left for an outer container to deal with
+        let at_end = |rest: &str| rest.is_empty() || rest.starts_with([']', '}']);
+        while !(self.rest.starts_with(close) || (self.recover && at_end(self.rest))) {
+            children.push(CstElement::Node(self.attempt(element)?));
+            self.trivia(&mut children)?;
+            if self.rest.starts_with(',') {
+                children.push(CstElement::Token(
+                    self.token(TokenKind::Comma, &LiteralParser(","))?,
+                ));
+                self.trivia(&mut children)?;
+                continue;
+            }
+            if !self.recover || at_end(self.rest) {
+                break;
+            }
+            // A missing comma is the most likely typo, so carry on as if it were there.
+            // Anything that can't start another element is skipped.
+            self.errors
+                .push(self.error(&format!("Expected \",\" or {}", close_kind.describe())));
+            if !self.rest.starts_with(['"', '[', '{', '-', 't', 'f', 'n'])
+                && !self.rest.starts_with(|c: char| c.is_ascii_digit())
+            {
+                children.push(CstElement::Node(self.skip()));
+                self.trivia(&mut children)?;
+                if self.rest.starts_with(',') {
+                    children.push(CstElement::Token(
+                        self.token(TokenKind::Comma, &LiteralParser(","))?,
+                    ));
+                    self.trivia(&mut children)?;
+                }
+            }
+        }
+        match self.token(close_kind, &LiteralParser(close)) {
+            Ok(token) => children.push(CstElement::Token(token)),
+            Err(error) if self.recover => self.errors.push(error),
+            Err(error) => return Err(error),
+        }
+        Ok(CstNode {
+            kind,
+            span: Span {
+                start,
+                end: self.offset(),
+            },
+            children,
+        })
+    }
+}
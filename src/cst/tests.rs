@@ -0,0 +1,137 @@
+#[cfg(test)]
+use crate::cst::*;
+#[cfg(test)]
+use crate::rustifier::loads;
+
+#[cfg(test)]
+const DOCUMENT: &str = "{\r\n\t\"name\": \"frank\",\r\n\t\"sizes\" : [1, -2,3 ,],\r\n\t\"empty\": {},\r\n\t\"on\":true, \"off\": null\r\n}\n";
+
+#[test]
+fn reconstructs_original_text() {
+    let cst = parse_cst(DOCUMENT).unwrap();
+    assert_eq!(cst.to_string(), DOCUMENT);
+}
+
+#[test]
+fn tokens_cover_every_byte() {
+    let cst = parse_cst(DOCUMENT).unwrap();
+    let mut expected_start = 0;
+    for token in cst.tokens() {
+        assert_eq!(token.span.start, expected_start);
+        assert_eq!(&DOCUMENT[token.span.start..token.span.end], token.text);
+        expected_start = token.span.end;
+    }
+    assert_eq!(expected_start, DOCUMENT.len());
+}
+
+#[test]
+fn records_spans_of_nodes() {
+    let input = r#"{"a": [1, 2]}"#;
+    let cst = parse_cst(input).unwrap();
+    let object = cst.value().unwrap();
+    assert_eq!(object.kind, NodeKind::Object);
+    assert_eq!(object.span, Span { start: 0, end: 13 });
+    let member = object.child_nodes().next().unwrap();
+    assert_eq!(member.kind, NodeKind::Member);
+    assert_eq!(member.key().unwrap().text, "\"a\"");
+    let array = member.value().unwrap();
+    assert_eq!(&input[array.span.start..array.span.end], "[1, 2]");
+    let kinds: Vec<TokenKind> = array.tokens().iter().map(|token| token.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            TokenKind::LeftBracket,
+            TokenKind::Number,
+            TokenKind::Comma,
+            TokenKind::Whitespace,
+            TokenKind::Number,
+            TokenKind::RightBracket,
+        ]
+    );
+}
+
+#[test]
+fn converts_down_to_json_element() {
+    let cst = parse_cst(DOCUMENT).unwrap();
+    assert_eq!(cst.to_element().unwrap(), loads(DOCUMENT).unwrap());
+}
+
+#[test]
+fn keeps_comments_as_trivia_when_enabled() {
+    let input = "// leading\n[1, /* inline */ 2] /* trailing */";
+    assert!(parse_cst(input).is_err());
+    let cst = parse_cst_with(input, CstOptions { comments: true }).unwrap();
+    assert_eq!(cst.to_string(), input);
+    let comments: Vec<&str> = cst
+        .tokens()
+        .iter()
+        .filter(|token| token.kind == TokenKind::Comment)
+        .map(|token| token.text)
+        .collect();
+    assert_eq!(
+        comments,
+        vec!["// leading", "/* inline */", "/* trailing */"]
+    );
+}
+
+#[test]
+fn reports_errors_with_offsets() {
+    let Err(err) = parse_cst("[1, 2 3]") else {
+        panic!("Should have failed");
+    };
+    assert!(err.contains("byte 6"), "{}", err);
+    assert!(parse_cst("[1] 2").is_err());
+    assert!(parse_cst_with("[1] /* open", CstOptions { comments: true }).is_err());
+}
+
+#[test]
+fn recovers_from_errors() {
+    let input = "{\"a\": [1, 2 3, @, 4], \"b\": tru, \"c\" 5, \"d\": {\"e\": [}, \"f\": \"]\"}";
+    let (cst, errors) = parse_cst_recovering(input, CstOptions::default());
+    assert_eq!(cst.to_string(), input);
+    let messages: Vec<(&str, usize)> = errors
+        .iter()
+        .map(|error| (error.message.as_str(), error.span.start))
+        .collect();
+    assert_eq!(
+        messages,
+        vec![
+            ("Expected \",\" or \"]\"", 12),
+            ("Expected a value", 15),
+            ("Expected a value", 27),
+            ("Expected \":\" but found \"5\"", 36),
+            ("Expected \"]\" but found \"}\"", 51),
+        ]
+    );
+    // The unclosed array closes "d" early, so "f" ends up in the outer object
+    let expected = loads(r#"{"a": [1, 2, 3, null, 4], "b": null, "d": {"e": []}, "f": "]"}"#);
+    assert_eq!(cst.to_element().unwrap(), expected.unwrap());
+}
+
+#[test]
+fn recovers_at_the_top_level() {
+    let (cst, errors) = parse_cst_recovering("[1] 2", CstOptions::default());
+    assert_eq!(cst.to_string(), "[1] 2");
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].to_string(), "Trailing data at byte 4");
+
+    let (cst, errors) = parse_cst_recovering("[1, [2", CstOptions::default());
+    assert_eq!(cst.to_element().unwrap(), loads("[1, [2]]").unwrap());
+    assert_eq!(errors.len(), 2);
+
+    let (cst, errors) = parse_cst_recovering("", CstOptions::default());
+    assert_eq!(cst.to_element().unwrap(), loads("null").unwrap());
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn formats_keeping_order_and_comments() {
+    let input =
+        "// config\n{\"b\":1, /* one */ \"a\" :[ ], \"c\": [true,\n// last\nnull,],\"d\":{}}";
+    let cst = parse_cst_with(input, CstOptions { comments: true }).unwrap();
+    let expected = "// config\n{\n  \"b\": 1, /* one */\n  \"a\": [],\n  \"c\": [\n    true,\n    // last\n    null\n  ],\n  \"d\": {}\n}\n";
+    assert_eq!(cst.format("  "), expected);
+    let formatted = parse_cst_with(expected, CstOptions { comments: true }).unwrap();
+    assert_eq!(formatted.format("  "), expected);
+    assert_eq!(parse_cst("5").unwrap().format("\t"), "5\n");
+}
@@ -0,0 +1,401 @@
+use crate::ast_parser::infer_scalar;
+use crate::pointer::{format_pointer, parse_pointer};
+use crate::rustifier::{dumps, loads, JSONElement};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+mod tests;
+
+/// How the keys leading to a nested value are joined into its column's name
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColumnNames {
+    // Keys joined by dots, like `address.city`. Keys with dots in them can't be written this way.
+    #[default]
+    Dotted,
+    // JSON pointers, like `/address/city`, which can name any key
+    Pointer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CsvOptions {
+    // `,` for CSV or `\t` for TSV. Fields are quoted the same way with either.
+    pub delimiter: char,
+    pub columns: ColumnNames,
+}
+
+impl Default for CsvOptions {
+    fn default() -> CsvOptions {
+        CsvOptions {
+            delimiter: ',',
+            columns: ColumnNames::Dotted,
+        }
+    }
+}
+
+/// Writes an array of objects as CSV with a header row, one row per object. The columns are
+/// every one that any row has, in the order they first appear. Nested objects are flattened
+/// into a column per value, and arrays are written as JSON. A value a row doesn't have is an
+/// empty field while an empty string is a quoted one, so the two read back differently. Other
+/// strings that `from_csv` would read as another type, like `123` or `true`, are quoted too,
+/// and ones that are themselves JSON arrays, objects or strings are written as JSON strings,
+/// so every value reads back as it was. Lines end in CRLF, as in RFC 4180.
+pub fn to_csv(element: &JSONElement, options: &CsvOptions) -> Result<String, String> {
+    check_delimiter(options.delimiter)?;
+    let JSONElement::Array(rows) = element else {
+        return Err("Only arrays of objects can be written as CSV".to_string());
+    };
+    let mut columns: Vec<String> = vec![];
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut table: Vec<HashMap<usize, Cell>> = vec![];
+    for (i, row) in rows.iter().enumerate() {
+        let JSONElement::Object(members) = row else {
+            return Err(format!("Row {} is not an object", i));
+        };
+        let mut fields = vec![];
+        flatten(members, &mut vec![], &mut fields);
+        let mut cells = HashMap::new();
+        for (keys, cell) in fields {
+            let name = column_name(&keys, options.columns)?;
+            let next = columns.len();
+            let position = *positions.entry(name.clone()).or_insert(next);
+            if position == next {
+                columns.push(name);
+            }
+            cells.insert(position, cell);
+        }
+        table.push(cells);
+    }
+    if columns.is_empty() && !rows.is_empty() {
+        return Err("CSV can't hold rows without any values".to_string());
+    }
+    let mut output = String::new();
+    if !columns.is_empty() {
+        write_record(
+            &mut output,
+            columns.iter().map(|c| Some((c.as_str(), false))),
+            options,
+        );
+    }
+    for cells in &table {
+        let record =
+            (0..columns.len()).map(|i| cells.get(&i).map(|cell| (cell.text.as_str(), cell.quoted)));
+        write_record(&mut output, record, options);
+    }
+    Ok(output)
+}
+
+/// Reads CSV with a header row as an array of objects, splitting the column names back into
+/// keys. Fields that parse as JSON arrays or objects are read as those, whether they're quoted
+/// or not. Otherwise unquoted fields written like JSON's null, booleans and numbers are read as
+/// them, while quoted fields are strings, which are also read from JSON strings. Empty fields
+/// are left out of their row unless they're quoted. Lines can end in CRLF or LF.
+pub fn from_csv(text: &str, options: &CsvOptions) -> Result<JSONElement, String> {
+    check_delimiter(options.delimiter)?;
+    let mut records = Records {
+        rest: text.strip_prefix('\u{feff}').unwrap_or(text),
+        delimiter: options.delimiter,
+        line: 1,
+    };
+    let Some(header) = records.next_record()? else {
+        return Ok(JSONElement::Array(vec![]));
+    };
+    let mut names = HashSet::new();
+    if let Some(field) = header.iter().find(|field| !names.insert(&field.text)) {
+        return Err(format!(
+            "The column {:?} is in the header twice",
+            field.text
+        ));
+    }
+    let columns = header
+        .iter()
+        .map(|field| {
+            Ok((
+                field.text.as_str(),
+                split_column(&field.text, options.columns)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let mut rows = vec![];
+    loop {
+        let line = records.line;
+        let Some(record) = records.next_record()? else {
+            break;
+        };
+        if record.len() != columns.len() {
+            return Err(format!(
+                "Line {} has {} fields but the header has {}",
+                line,
+                record.len(),
+                columns.len()
+            ));
+        }
+        let mut row = HashMap::new();
+        for ((name, keys), field) in columns.iter().zip(record) {
+            if field.text.is_empty() && !field.quoted {
+                continue;
+            }
+            insert(&mut row, name, keys, infer(field)?)
+                .map_err(|err| format!("Line {}: {}", line, err))?;
+        }
+        rows.push(JSONElement::Object(row));
+    }
+    Ok(JSONElement::Array(rows))
+}
+
+fn check_delimiter(delimiter: char) -> Result<(), String> {
+    match delimiter {
+        '"' | '\r' | '\n' => Err(format!("{:?} can't be used as a delimiter", delimiter)),
+        _ => Ok(()),
+    }
+}
+
+// A value as it's written in a field, and whether it has to be quoted to read back the same
+struct Cell {
+    text: String,
+    quoted: bool,
+}
+
+// The values in `members` as a cell each, along with the keys leading to them from the row.
+// Nested objects are flattened in sorted order, except empty ones, which are written as `{}`
+// since they have no values to give columns to.
+fn flatten(
+    members: &HashMap<String, JSONElement>,
+    keys: &mut Vec<String>,
+    fields: &mut Vec<(Vec<String>, Cell)>,
+) {
+    let mut members: Vec<_> = members.iter().collect();
+    members.sort_by_key(|(key, _)| *key);
+    for (key, value) in members {
+        keys.push(key.clone());
+        match value {
+            JSONElement::Object(nested) if !nested.is_empty() => flatten(nested, keys, fields),
+            JSONElement::String(s) => fields.push((keys.clone(), string_cell(s))),
+            _ => fields.push((
+                keys.clone(),
+                Cell {
+                    text: dumps(value),
+                    quoted: false,
+                },
+            )),
+        }
+        keys.pop();
+    }
+}
+
+fn string_cell(s: &str) -> Cell {
+    if parse_json(s).is_some() {
+        return Cell {
+            text: dumps(&JSONElement::String(s.to_string())),
+            quoted: true,
+        };
+    }
+    let unquoted = infer(Field {
+        text: s.to_string(),
+        quoted: false,
+    });
+    Cell {
+        text: s.to_string(),
+        quoted: !matches!(unquoted, Ok(JSONElement::String(_))),
+    }
+}
+
+fn column_name(keys: &[String], columns: ColumnNames) -> Result<String, String> {
+    match columns {
+        ColumnNames::Dotted => match keys.iter().find(|key| key.contains('.')) {
+            Some(key) => Err(format!(
+                "The key {:?} has a dot in it, so it needs pointer-style column names",
+                key
+            )),
+            None => Ok(keys.join(".")),
+        },
+        ColumnNames::Pointer => Ok(format_pointer(keys)),
+    }
+}
+
+fn split_column(name: &str, columns: ColumnNames) -> Result<Vec<String>, String> {
+    match columns {
+        ColumnNames::Dotted => Ok(name.split('.').map(|key| key.to_string()).collect()),
+        ColumnNames::Pointer => match parse_pointer(name)? {
+            keys if keys.is_empty() => Err(format!(
+                "The column {:?} doesn't point to a value in the row",
+                name
+            )),
+            keys => Ok(keys),
+        },
+    }
+}
+
+// Writes a record's fields, where None is a missing value and the bool is whether the field
+// has to be quoted to be read back the same. Fields are also quoted when their text needs it, or
+// when they're empty strings.
+fn write_record<'a>(
+    output: &mut String,
+    fields: impl Iterator<Item = Option<(&'a str, bool)>>,
+    options: &CsvOptions,
+) {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            output.push(options.delimiter);
+        }
+        match field {
+            None => {}
+            Some((text, quoted))
+                if quoted
+                    || text.is_empty()
+                    || text.contains([options.delimiter, '"', '\r', '\n']) =>
+            {
+                output.push('"');
+                output.push_str(&text.replace('"', "\"\""));
+                output.push('"');
+            }
+            Some((text, _)) => output.push_str(text),
+        }
+    }
+    output.push_str("\r\n");
+}
+
+// Puts `value` in `row` at `keys`, making the objects along the way
+fn insert(
+    row: &mut HashMap<String, JSONElement>,
+    name: &str,
+    keys: &[String],
+    value: JSONElement,
+) -> Result<(), String> {
+    let (last, parents) = keys.split_last().expect("columns have at least one key");
+    let mut object = row;
+    for (i, key) in parents.iter().enumerate() {
+        let child = object
+            .entry(key.clone())
+            .or_insert_with(|| JSONElement::Object(HashMap::new()));
+        match child {
+            JSONElement::Object(members) => object = members,
+            _ => {
+                return Err(format!(
+                    "the column {:?} needs {} to be an object, but another column gave it a value",
+                    name,
+                    format_pointer(&keys[..=i])
+                ))
+            }
+        }
+    }
+    match object.entry(last.clone()) {
+        Entry::Occupied(_) => Err(format!(
+            "the column {:?} gives {} a value, but other columns made it an object",
+            name,
+            format_pointer(keys)
+        )),
+        Entry::Vacant(entry) => {
+            entry.insert(value);
+            Ok(())
+        }
+    }
+}
+
+// Reads a field as JSON if it's an array, an object or a string. Otherwise an unquoted field is
+// read the way YAML's JSON schema reads a plain scalar, and a quoted one is a string.
+fn infer(field: Field) -> Result<JSONElement, String> {
+    if let Some(element) = parse_json(&field.text) {
+        return Ok(element);
+    }
+    match field.quoted {
+        true => Ok(JSONElement::String(field.text)),
+        false => Ok(infer_scalar(field.text)),
+    }
+}
+
+// The JSON array, object or string that `text` holds, if any. Unquoted fields can't start with
+// a quote, so only quoted ones can hold strings.
+fn parse_json(text: &str) -> Option<JSONElement> {
+    if !text.trim_start().starts_with(['[', '{', '"']) {
+        return None;
+    }
+    match loads(text) {
+        Ok(element @ (JSONElement::Array(_) | JSONElement::Object(_) | JSONElement::String(_))) => {
+            Some(element)
+        }
+        _ => None,
+    }
+}
+
+struct Field {
+    text: String,
+    quoted: bool,
+}
+
+// Splits CSV text into records of fields as RFC 4180 describes, except that LF line breaks are
+// taken as well as CRLF. `line` is the line the next record starts on.
+struct Records<'t> {
+    rest: &'t str,
+    delimiter: char,
+    line: usize,
+}
+
+impl Records<'_> {
+    fn next_record(&mut self) -> Result<Option<Vec<Field>>, String> {
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+        let mut fields = vec![];
+        loop {
+            fields.push(self.field()?);
+            let Some(c) = self.rest.chars().next() else {
+                return Ok(Some(fields));
+            };
+            if c == self.delimiter {
+                self.rest = &self.rest[c.len_utf8()..];
+                continue;
+            }
+            let after = self
+                .rest
+                .strip_prefix('\n')
+                .or_else(|| self.rest.strip_prefix("\r\n"))
+                .ok_or_else(|| {
+                    format!("Line {} has {:?} where a field should end", self.line, c)
+                })?;
+            self.rest = after;
+            self.line += 1;
+            return Ok(Some(fields));
+        }
+    }
+
+    fn field(&mut self) -> Result<Field, String> {
+        let Some(mut rest) = self.rest.strip_prefix('"') else {
+            let end = self
+                .rest
+                .find([self.delimiter, '"', '\r', '\n'])
+                .unwrap_or(self.rest.len());
+            if self.rest[end..].starts_with('"') {
+                return Err(format!(
+                    "Line {} has a quote inside a field that isn't quoted",
+                    self.line
+                ));
+            }
+            let text = self.rest[..end].to_string();
+            self.rest = &self.rest[end..];
+            return Ok(Field {
+                text,
+                quoted: false,
+            });
+        };
+        let start = self.line;
+        let mut text = String::new();
+        loop {
+            let end = rest
+                .find('"')
+                .ok_or_else(|| format!("The quoted field on line {} is never closed", start))?;
+            text.push_str(&rest[..end]);
+            self.line += rest[..end].matches('\n').count();
+            rest = &rest[end + 1..];
+            // Quotes in a quoted field are doubled
+            match rest.strip_prefix('"') {
+                Some(after) => {
+                    text.push('"');
+                    rest = after;
+                }
+                None => break,
+            }
+        }
+        self.rest = rest;
+        Ok(Field { text, quoted: true })
+    }
+}
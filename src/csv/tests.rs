@@ -0,0 +1,162 @@
+#[cfg(test)]
+use crate::csv::*;
+#[cfg(test)]
+use crate::rustifier::{loads_json5, JSONElement};
+
+#[cfg(test)]
+fn read(text: &str) -> Result<JSONElement, String> {
+    from_csv(text, &CsvOptions::default())
+}
+
+#[test]
+fn writes_rows_with_the_union_of_their_columns() {
+    let element = loads_json5(
+        r#"[
+            {"name": "Ann", "age": 31, "address": {"city": "Oslo", "zip": "0150"}},
+            {"name": "Bo, Jr.", "tags": ["a", "b"], "address": {"city": "Rome"}, "note": null},
+            {"name": "", "age": 2.5, "quote": "say \"hi\"\nbye", "empty": {}, "ok": true}
+        ]"#,
+    )
+    .unwrap();
+    let expected = "address.city,address.zip,age,name,note,tags,empty,ok,quote\r\n\
+                    Oslo,0150,31,Ann,,,,,\r\n\
+                    Rome,,,\"Bo, Jr.\",null,\"[\"\"a\"\",\"\"b\"\"]\",,,\r\n\
+                    ,,2.5,\"\",,,{},true,\"say \"\"hi\"\"\nbye\"\r\n";
+    let csv = to_csv(&element, &CsvOptions::default()).unwrap();
+    assert_eq!(csv, expected);
+    // The zip code stays a string since JSON numbers can't have leading zeros
+    assert_eq!(read(&csv).unwrap(), element);
+}
+
+#[test]
+fn names_columns_with_pointers() {
+    let element = loads_json5(r#"[{"a.b": {"c/d": 1, "~": 2}, "": 3}]"#).unwrap();
+    let err = to_csv(&element, &CsvOptions::default()).unwrap_err();
+    assert!(err.contains("pointer-style"), "{}", err);
+    let options = CsvOptions {
+        columns: ColumnNames::Pointer,
+        ..CsvOptions::default()
+    };
+    let csv = to_csv(&element, &options).unwrap();
+    assert_eq!(csv, "/,/a.b/c~1d,/a.b/~0\r\n3,1,2\r\n");
+    assert_eq!(from_csv(&csv, &options).unwrap(), element);
+    assert!(from_csv("\"\"\r\n1\r\n", &options).is_err());
+    assert!(from_csv("a\r\n1\r\n", &options).is_err());
+}
+
+#[test]
+fn infers_types() {
+    assert_eq!(
+        read("\u{feff}n,f,big,t,f2,z,lead,word,arr,obj,neg,exp,quoted\n\
+              1,1.5,2147483648,true,false,null,007,yes,\"[1, {\"\"x\"\": null}]\",{},-0,1e3,\"12\"\n")
+            .unwrap(),
+        loads_json5(
+            r#"[{"n": 1, "f": 1.5, "big": 2147483648.0, "t": true, "f2": false, "z": null,
+                 "lead": "007", "word": "yes", "arr": [1, {"x": null}], "obj": {}, "neg": 0,
+                 "exp": 1000.0, "quoted": "12"}]"#
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        read("a,b,c\n,\"\",[not json\n").unwrap(),
+        loads_json5(r#"[{"b": "", "c": "[not json"}]"#).unwrap()
+    );
+    assert_eq!(
+        read("big\n1e400\n").unwrap(),
+        loads_json5(r#"[{"big": "1e400"}]"#).unwrap()
+    );
+    assert_eq!(read("").unwrap(), JSONElement::Array(vec![]));
+    assert_eq!(read("a\r\n").unwrap(), JSONElement::Array(vec![]));
+    // A blank line is a row with an empty field
+    assert_eq!(
+        read("a\n\n1").unwrap(),
+        loads_json5(r#"[{}, {"a": 1}]"#).unwrap()
+    );
+}
+
+#[test]
+fn quotes_strings_that_would_read_as_other_types() {
+    let element = loads_json5(
+        r#"[
+            {"n": "123", "t": "true", "z": "null", "f": "-1.5e3", "s": "plain", "arr": "[1, 2]",
+             "obj": "{}", "json": "\"hi\"", "open": "[1", "space": " [1]"},
+            {"n": 123, "t": true, "z": null, "f": -1500.0, "s": "say \"hi\"", "arr": [1, 2],
+             "obj": {}, "json": ["\"hi\""], "open": "", "space": "a,b"}
+        ]"#,
+    )
+    .unwrap();
+    let csv = to_csv(&element, &CsvOptions::default()).unwrap();
+    assert_eq!(
+        csv,
+        concat!(
+            "arr,f,json,n,obj,open,s,space,t,z\r\n",
+            r#""""[1, 2]""","-1.5e3","""\""hi\""""","123","""{}""",[1,plain,""" [1]""","true","null""#,
+            "\r\n",
+            r#""[1,2]",-1500.0,"[""\""hi\""""]",123,{},"","say ""hi""","a,b",true,null"#,
+            "\r\n"
+        )
+    );
+    assert_eq!(read(&csv).unwrap(), element);
+}
+
+#[test]
+fn round_trips_tsv() {
+    let options = CsvOptions {
+        delimiter: '\t',
+        ..CsvOptions::default()
+    };
+    let element = loads_json5(
+        r#"[{"a": {"b": {"c": "tab\there"}, "d": [[]]}, "e": "x,y"}, {"e": "\r\n"}, {}]"#,
+    )
+    .unwrap();
+    let tsv = to_csv(&element, &options).unwrap();
+    assert_eq!(
+        tsv,
+        "a.b.c\ta.d\te\r\n\"tab\there\"\t[[]]\tx,y\r\n\t\t\"\r\n\"\r\n\t\t\r\n"
+    );
+    assert_eq!(from_csv(&tsv, &options).unwrap(), element);
+    assert_eq!(to_csv(&JSONElement::Array(vec![]), &options).unwrap(), "");
+}
+
+#[test]
+fn rejects_what_csv_cannot_express() {
+    for (json, error) in [
+        (r#"{"a": 1}"#, "Only arrays"),
+        ("[1]", "Row 0"),
+        (r#"[{"a": 1}, []]"#, "Row 1"),
+        ("[{}]", "without any values"),
+    ] {
+        let err = to_csv(&loads_json5(json).unwrap(), &CsvOptions::default()).unwrap_err();
+        assert!(err.contains(error), "{}: {}", json, err);
+    }
+    let quote = CsvOptions {
+        delimiter: '"',
+        ..CsvOptions::default()
+    };
+    assert!(to_csv(&JSONElement::Array(vec![]), &quote).is_err());
+    assert!(from_csv("", &quote).is_err());
+}
+
+#[test]
+fn rejects_invalid_csv() {
+    for (csv, error) in [
+        ("a,b\n1\n", "Line 2 has 1 fields"),
+        ("a\n1,2\n", "Line 2 has 2 fields"),
+        ("a\n\"x\n\ny", "line 2 is never closed"),
+        ("a\nx\"y\"\n", "quote inside"),
+        ("a\n\"x\"y\n", "where a field should end"),
+        ("a\n1\r2\n", "where a field should end"),
+        ("a,a\n1,2\n", "twice"),
+        ("a,a.b\n1,2\n", "needs /a to be an object"),
+        ("a.b,a\n1,2\n", "other columns made it an object"),
+        ("a\n\"multi\nline\"\nx,y\n", "Line 4"),
+    ] {
+        let err = read(csv).unwrap_err();
+        assert!(err.contains(error), "{:?}: {}", csv, err);
+    }
+    // Conflicting columns are fine as long as no row has values for both
+    assert_eq!(
+        read("a,a.b\n1,\n,2\n").unwrap(),
+        loads_json5(r#"[{"a": 1}, {"a": {"b": 2}}]"#).unwrap()
+    );
+}
@@ -0,0 +1,301 @@
+use crate::patch::common_subsequence;
+use crate::pointer::{format_pointer, parse_pointer};
+use crate::rustifier::{dumps, dumps_pretty, JSONElement};
+use crate::schema::equal;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+
+mod tests;
+
+/// One difference between two documents. Paths of removed values point into the old document,
+/// and the others into the new one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    Added {
+        path: String,
+        value: JSONElement,
+    },
+    Removed {
+        path: String,
+        value: JSONElement,
+    },
+    Changed {
+        path: String,
+        from: JSONElement,
+        to: JSONElement,
+    },
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Changed { path, .. } => path,
+        }
+    }
+
+    pub fn to_element(&self) -> JSONElement {
+        let string = |s: &str| JSONElement::String(s.to_string());
+        let mut members = HashMap::from([("path".to_string(), string(self.path()))]);
+        let values = match self {
+            Change::Added { value, .. } => vec![("op", string("add")), ("value", value.clone())],
+            Change::Removed { value, .. } => {
+                vec![("op", string("remove")), ("value", value.clone())]
+            }
+            Change::Changed { from, to, .. } => vec![
+                ("op", string("change")),
+                ("from", from.clone()),
+                ("to", to.clone()),
+            ],
+        };
+        members.extend(
+            values
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value)),
+        );
+        JSONElement::Object(members)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DiffOptions {
+    // Compare arrays as if they were sorted, so only elements that were added or removed count
+    pub ignore_array_order: bool,
+    // JSON pointers to values that aren't compared, where a "*" token matches any member or
+    // index. Array indexes are those of the new document.
+    pub ignore: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffFormat {
+    // A line per change, like "~ /port: 80 -> 8080", optionally with terminal colours
+    Text { colour: bool },
+    // Each change as a hunk of "-" and "+" lines holding the pretty-printed values
+    Unified,
+    // An array of change objects with "op" (add, remove or change), "path", and either "value"
+    // or "from" and "to"
+    Json,
+}
+
+/// The differences between two documents. Unlike a text diff, the order of object members
+/// never matters, numbers that are equal in value are the same, and changes inside objects or
+/// arrays are reported where they happened rather than as a whole new value.
+pub fn semantic_diff(
+    from: &JSONElement,
+    to: &JSONElement,
+    options: &DiffOptions,
+) -> Result<Vec<Change>, String> {
+    let differ = Differ {
+        ignore_array_order: options.ignore_array_order,
+        ignore: options
+            .ignore
+            .iter()
+            .map(|pointer| parse_pointer(pointer))
+            .collect::<Result<_, _>>()?,
+    };
+    let mut changes = vec![];
+    differ.diff(from, to, &mut vec![], &mut changes);
+    Ok(changes)
+}
+
+pub fn render(changes: &[Change], format: DiffFormat) -> String {
+    let mut output = String::new();
+    match format {
+        DiffFormat::Text { colour } => {
+            let paint = |code: &str, text: String| match colour {
+                true => format!("\x1b[{}m{}\x1b[0m", code, text),
+                false => text,
+            };
+            for change in changes {
+                let path = match change.path() {
+                    "" => "the root",
+                    path => path,
+                };
+                let line = match change {
+                    Change::Added { value, .. } => {
+                        paint("32", format!("+ {}: {}", path, dumps(value)))
+                    }
+                    Change::Removed { value, .. } => {
+                        paint("31", format!("- {}: {}", path, dumps(value)))
+                    }
+                    Change::Changed { from, to, .. } => paint(
+                        "33",
+                        format!("~ {}: {} -> {}", path, dumps(from), dumps(to)),
+                    ),
+                };
+                writeln!(output, "{}", line).unwrap();
+            }
+        }
+        DiffFormat::Unified => {
+            let lines = |output: &mut String, prefix: char, value: &JSONElement| {
+                for line in dumps_pretty(value, "  ").lines() {
+                    writeln!(output, "{}{}", prefix, line).unwrap();
+                }
+            };
+            for change in changes {
+                writeln!(output, "@@ {} @@", change.path()).unwrap();
+                match change {
+                    Change::Added { value, .. } => lines(&mut output, '+', value),
+                    Change::Removed { value, .. } => lines(&mut output, '-', value),
+                    Change::Changed { from, to, .. } => {
+                        lines(&mut output, '-', from);
+                        lines(&mut output, '+', to);
+                    }
+                }
+            }
+        }
+        DiffFormat::Json => {
+            let changes = JSONElement::Array(changes.iter().map(Change::to_element).collect());
+            output = dumps_pretty(&changes, "  ");
+            output.push('\n');
+        }
+    }
+    output
+}
+
+struct Differ {
+    ignore_array_order: bool,
+    ignore: Vec<Vec<String>>,
+}
+
+impl Differ {
+    fn diff(
+        &self,
+        from: &JSONElement,
+        to: &JSONElement,
+        path: &mut Vec<String>,
+        changes: &mut Vec<Change>,
+    ) {
+        if self.is_ignored(path) {
+            return;
+        }
+        match (from, to) {
+            (JSONElement::Object(from), JSONElement::Object(to)) => {
+                let keys: BTreeSet<&String> = from.keys().chain(to.keys()).collect();
+                for key in keys {
+                    path.push(key.clone());
+                    match (from.get(key), to.get(key)) {
+                        (Some(old), Some(new)) => self.diff(old, new, path, changes),
+                        (Some(old), None) if !self.is_ignored(path) => {
+                            changes.push(Change::Removed {
+                                path: format_pointer(path),
+                                value: old.clone(),
+                            })
+                        }
+                        (None, Some(new)) if !self.is_ignored(path) => {
+                            changes.push(Change::Added {
+                                path: format_pointer(path),
+                                value: new.clone(),
+                            })
+                        }
+                        _ => {}
+                    }
+                    path.pop();
+                }
+            }
+            (JSONElement::Array(from), JSONElement::Array(to)) => {
+                self.diff_arrays(from, to, path, changes)
+            }
+            _ if equal(from, to) => {}
+            _ => changes.push(Change::Changed {
+                path: format_pointer(path),
+                from: from.clone(),
+                to: to.clone(),
+            }),
+        }
+    }
+
+    fn diff_arrays(
+        &self,
+        from: &[JSONElement],
+        to: &[JSONElement],
+        path: &mut Vec<String>,
+        changes: &mut Vec<Change>,
+    ) {
+        // Elements can't be told apart by index until they're paired up, so they're compared
+        // as if they were at any index
+        let mut element_path = path.clone();
+        element_path.push("*".to_string());
+        let same = |a: &JSONElement, b: &JSONElement| self.same(a, b, &element_path);
+
+        let mut matched: Vec<(usize, usize)>;
+        // Pairs of elements that aren't the same, but are compared with each other rather than
+        // one being removed and the other added
+        let mut paired: Vec<(usize, usize)> = vec![];
+        if self.ignore_array_order {
+            let mut used = vec![false; from.len()];
+            matched = vec![];
+            for (j, new) in to.iter().enumerate() {
+                if let Some(i) = (0..from.len()).find(|&i| !used[i] && same(&from[i], new)) {
+                    used[i] = true;
+                    matched.push((i, j));
+                }
+            }
+            let to_used: BTreeSet<usize> = matched.iter().map(|&(_, j)| j).collect();
+            let old = (0..from.len()).filter(|&i| !used[i]);
+            let new = (0..to.len()).filter(|j| !to_used.contains(j));
+            paired.extend(old.zip(new));
+        } else {
+            matched = common_subsequence(from, to, same);
+            // The elements between two matched ones are paired up in order
+            let mut previous = (0, 0);
+            for &(i, j) in matched.iter().chain([&(from.len(), to.len())]) {
+                paired.extend((previous.0..i).zip(previous.1..j));
+                previous = (i + 1, j + 1);
+            }
+        }
+
+        let old: BTreeSet<usize> = matched.iter().chain(&paired).map(|&(i, _)| i).collect();
+        let new: BTreeSet<usize> = matched.iter().chain(&paired).map(|&(_, j)| j).collect();
+        for i in (0..from.len()).filter(|i| !old.contains(i)) {
+            path.push(i.to_string());
+            if !self.is_ignored(path) {
+                changes.push(Change::Removed {
+                    path: format_pointer(path),
+                    value: from[i].clone(),
+                });
+            }
+            path.pop();
+        }
+        // Added elements and changes inside paired ones come out in the new array's order
+        paired.sort_by_key(|&(_, j)| j);
+        let mut paired = paired.into_iter().peekable();
+        for (j, value) in to.iter().enumerate() {
+            path.push(j.to_string());
+            match paired.next_if(|&(_, k)| k == j) {
+                Some((i, _)) => self.diff(&from[i], value, path, changes),
+                None if !new.contains(&j) && !self.is_ignored(path) => {
+                    changes.push(Change::Added {
+                        path: format_pointer(path),
+                        value: value.clone(),
+                    })
+                }
+                None => {}
+            }
+            path.pop();
+        }
+    }
+
+    fn same(&self, from: &JSONElement, to: &JSONElement, path: &[String]) -> bool {
+        if equal(from, to) {
+            return true;
+        }
+        if !self.ignore_array_order && self.ignore.is_empty() {
+            return false;
+        }
+        let mut changes = vec![];
+        self.diff(from, to, &mut path.to_vec(), &mut changes);
+        changes.is_empty()
+    }
+
+    fn is_ignored(&self, path: &[String]) -> bool {
+        self.ignore.iter().any(|pattern| {
+            pattern.len() == path.len()
+                && pattern
+                    .iter()
+                    .zip(path)
+                    .all(|(pattern, token)| pattern == "*" || token == "*" || pattern == token)
+        })
+    }
+}
@@ -0,0 +1,96 @@
+#[cfg(test)]
+use crate::diff::*;
+#[cfg(test)]
+use crate::rustifier::loads;
+
+#[cfg(test)]
+fn text_diff(from: &str, to: &str, options: DiffOptions) -> String {
+    let changes = semantic_diff(&loads(from).unwrap(), &loads(to).unwrap(), &options).unwrap();
+    render(&changes, DiffFormat::Text { colour: false })
+}
+
+#[test]
+fn reports_changes_by_pointer() {
+    assert_eq!(
+        text_diff(
+            r#"{"name": "a", "port": 80, "tags": ["x", "y"], "old": true, "n": 1}"#,
+            r#"{"n": 1.0, "tags": ["x", "z", "y"], "port": 8080, "name": "a", "new": {"k": null}}"#,
+            DiffOptions::default()
+        ),
+        "+ /new: {\"k\":null}\n- /old: true\n~ /port: 80 -> 8080\n+ /tags/1: \"z\"\n"
+    );
+    assert_eq!(
+        text_diff("[1, 2]", r#"{"a": 1}"#, DiffOptions::default()),
+        "~ the root: [1,2] -> {\"a\":1}\n"
+    );
+}
+
+#[test]
+fn diffs_changed_array_elements_in_place() {
+    assert_eq!(
+        text_diff(
+            r#"[{"id": 1, "v": "a"}, {"id": 2, "v": "b"}, {"id": 3}]"#,
+            r#"[{"id": 1, "v": "a"}, {"id": 2, "v": "c"}, 4]"#,
+            DiffOptions::default()
+        ),
+        "~ /1/v: \"b\" -> \"c\"\n~ /2: {\"id\":3} -> 4\n"
+    );
+}
+
+#[test]
+fn ignores_array_order_and_paths() {
+    let options = DiffOptions {
+        ignore_array_order: true,
+        ignore: vec!["/items/*/updated".to_string()],
+    };
+    assert_eq!(
+        text_diff(
+            r#"{"items": [{"id": 1, "updated": 5}, {"id": 2, "updated": 5}, {"id": 3}]}"#,
+            r#"{"items": [{"id": 2, "updated": 6}, {"id": 4}, {"id": 1, "updated": 7}]}"#,
+            options.clone()
+        ),
+        "~ /items/1/id: 3 -> 4\n"
+    );
+    assert_eq!(
+        text_diff("[1, 2, 2]", "[2, 1, 3]", options),
+        "~ /2: 2 -> 3\n"
+    );
+    assert!(semantic_diff(
+        &loads("1").unwrap(),
+        &loads("1").unwrap(),
+        &DiffOptions {
+            ignore: vec!["a".to_string()],
+            ..DiffOptions::default()
+        }
+    )
+    .is_err());
+}
+
+#[test]
+fn renders_each_format() {
+    let changes = semantic_diff(
+        &loads(r#"{"a": {"b": 1}, "c": [1]}"#).unwrap(),
+        &loads(r#"{"a": {"b": 2}, "d": true}"#).unwrap(),
+        &DiffOptions::default(),
+    )
+    .unwrap();
+    assert_eq!(
+        render(&changes, DiffFormat::Text { colour: true }),
+        "\x1b[33m~ /a/b: 1 -> 2\x1b[0m\n\x1b[31m- /c: [1]\x1b[0m\n\x1b[32m+ /d: true\x1b[0m\n"
+    );
+    assert_eq!(
+        render(&changes, DiffFormat::Unified),
+        "@@ /a/b @@\n-1\n+2\n@@ /c @@\n-[\n-  1\n-]\n@@ /d @@\n+true\n"
+    );
+    assert_eq!(
+        loads(&render(&changes, DiffFormat::Json)).unwrap(),
+        loads(
+            r#"[
+                {"op": "change", "path": "/a/b", "from": 1, "to": 2},
+                {"op": "remove", "path": "/c", "value": [1]},
+                {"op": "add", "path": "/d", "value": true}
+            ]"#
+        )
+        .unwrap()
+    );
+}
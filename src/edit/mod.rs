@@ -0,0 +1,370 @@
+use crate::ast_parser::{escape, unescape};
+use crate::cst::{parse_cst_with, CstElement, CstNode, CstOptions, NodeKind, Span, TokenKind};
+use crate::pointer::{parse_index, parse_pointer};
+use crate::rustifier::{dumps, dumps_pretty, JSONElement};
+
+mod tests;
+
+/// A JSON document that can be edited in place. Every edit only rewrites the bytes of the value
+/// it touches (plus a separating comma where one is needed), so key order, indentation, blank
+/// lines and comments everywhere else come out exactly as they went in.
+pub struct Document {
+    text: String,
+    options: CstOptions,
+}
+
+// Replace the bytes in `span` with `replacement`
+struct Splice {
+    span: Span,
+    replacement: String,
+}
+
+impl Document {
+    pub fn parse(text: &str) -> Result<Document, String> {
+        Document::parse_with(text, CstOptions::default())
+    }
+
+    pub fn parse_with(text: &str, options: CstOptions) -> Result<Document, String> {
+        parse_cst_with(text, options)?;
+        Ok(Document {
+            text: text.to_string(),
+            options,
+        })
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Replaces the value at `pointer`. If the pointer names a member that's missing from an
+    /// existing object, the member is added.
+    pub fn set(&mut self, pointer: &str, value: &JSONElement) -> Result<(), String> {
+        let tokens = parse_pointer(pointer)?;
+        let cst = parse_cst_with(&self.text, self.options)?;
+        let splice = match tokens.split_last() {
+            None => {
+                let root = cst.value().ok_or("Document had no value")?;
+                self.replace(root, value, self.text.contains('\n'))
+            }
+            Some((last, path)) => {
+                let container = walk(&cst, path)?;
+                match find_child(container, last)? {
+                    Some(index) => self.replace(
+                        value_at(container, index),
+                        value,
+                        is_multiline(&self.text, container),
+                    ),
+                    None => self.insert_child(container, container_len(container), last, value),
+                }
+            }
+        };
+        self.apply(splice)
+    }
+
+    /// Adds a new member to an object, or a new element to an array before the given index ("-"
+    /// appends). Unlike `set`, this fails if the object already has the member.
+    pub fn insert(&mut self, pointer: &str, value: &JSONElement) -> Result<(), String> {
+        let tokens = parse_pointer(pointer)?;
+        let (last, path) = tokens
+            .split_last()
+            .ok_or("Can't insert at the root of the document, use set instead")?;
+        let cst = parse_cst_with(&self.text, self.options)?;
+        let container = walk(&cst, path)?;
+        let index = match container.kind {
+            NodeKind::Object => {
+                if find_child(container, last)?.is_some() {
+                    return Err(format!("{} already exists, use set instead", pointer));
+                }
+                container_len(container)
+            }
+            _ => parse_index(last, container_len(container), true)?,
+        };
+        let splice = self.insert_child(container, index, last, value);
+        self.apply(splice)
+    }
+
+    /// Removes a member from an object or an element from an array, along with the comma and
+    /// whitespace that separated it from its neighbours
+    pub fn delete(&mut self, pointer: &str) -> Result<(), String> {
+        let tokens = parse_pointer(pointer)?;
+        let (last, path) = tokens
+            .split_last()
+            .ok_or("Can't delete the whole document")?;
+        let cst = parse_cst_with(&self.text, self.options)?;
+        let container = walk(&cst, path)?;
+        let index =
+            find_child(container, last)?.ok_or_else(|| format!("{} does not exist", pointer))?;
+        let children: Vec<&CstNode> = container.child_nodes().collect();
+        let splice = if children.len() == 1 {
+            // Leaves an empty "{}" or "[]" behind
+            Splice {
+                span: Span {
+                    start: container.span.start + 1,
+                    end: container.span.end - 1,
+                },
+                replacement: String::new(),
+            }
+        } else if index + 1 < children.len() {
+            Splice {
+                span: Span {
+                    start: children[index].span.start,
+                    end: children[index + 1].span.start,
+                },
+                replacement: String::new(),
+            }
+        } else {
+            self.delete_last(container, children[index - 1], children[index])
+        };
+        self.apply(splice)
+    }
+
+    // Removes the last child of a container from the comma after the one before it, keeping
+    // any comments on that comma's line, like the `// one` in `[1, // one\n 2]`
+    fn delete_last(&self, container: &CstNode, previous: &CstNode, last: &CstNode) -> Splice {
+        let comma = container
+            .children
+            .iter()
+            .find_map(|element| match element {
+                CstElement::Token(token)
+                    if token.kind == TokenKind::Comma && token.span.start >= previous.span.end =>
+                {
+                    Some(token.span)
+                }
+                _ => None,
+            })
+            .expect("children are separated by commas");
+        let kept_end = container
+            .children
+            .iter()
+            .filter_map(|element| match element {
+                CstElement::Token(token)
+                    if token.kind == TokenKind::Comment
+                        && token.span.start >= comma.end
+                        && token.span.end <= last.span.start
+                        && !self.text[comma.end..token.span.start].contains('\n') =>
+                {
+                    Some(token.span.end)
+                }
+                _ => None,
+            })
+            .max()
+            .unwrap_or(comma.end);
+        Splice {
+            span: Span {
+                start: comma.start,
+                end: last.span.end,
+            },
+            replacement: self.text[comma.end..kept_end].to_string(),
+        }
+    }
+
+    fn apply(&mut self, splice: Splice) -> Result<(), String> {
+        let Splice { span, replacement } = splice;
+        let text = format!(
+            "{}{}{}",
+            &self.text[..span.start],
+            replacement,
+            &self.text[span.end..]
+        );
+        // Should never fail, but it's much better to find out here than after writing the file
+        parse_cst_with(&text, self.options)
+            .map_err(|err| format!("Edit would have produced invalid JSON: {}", err))?;
+        self.text = text;
+        Ok(())
+    }
+
+    fn replace(&self, node: &CstNode, value: &JSONElement, multiline: bool) -> Splice {
+        let indent = line_indent(&self.text, node.span.start);
+        Splice {
+            span: node.span,
+            replacement: self.format_value(value, multiline.then_some(indent)),
+        }
+    }
+
+    // Builds the splice that puts a new child at `index` of `container`. `key` is only used when
+    // the container is an object.
+    fn insert_child(
+        &self,
+        container: &CstNode,
+        index: usize,
+        key: &str,
+        value: &JSONElement,
+    ) -> Splice {
+        let children: Vec<&CstNode> = container.child_nodes().collect();
+        let multiline = is_multiline(&self.text, container);
+        let child_text = |indent: Option<&str>| {
+            let value = self.format_value(value, indent);
+            match container.kind {
+                NodeKind::Object => format!("{}{}{}", escape(key), key_separator(container), value),
+                _ => value,
+            }
+        };
+
+        if children.is_empty() {
+            let (open, close) = (
+                &self.text[container.span.start..container.span.start + 1],
+                &self.text[container.span.end - 1..container.span.end],
+            );
+            let replacement = if self.text.contains('\n') {
+                let outer = line_indent(&self.text, container.span.start);
+                let inner = format!("{}{}", outer, self.indent_unit());
+                let newline = self.newline();
+                format!(
+                    "{}{}{}{}{}{}{}",
+                    open,
+                    newline,
+                    inner,
+                    child_text(Some(&inner)),
+                    newline,
+                    outer,
+                    close
+                )
+            } else {
+                format!("{}{}{}", open, child_text(None), close)
+            };
+            return Splice {
+                span: container.span,
+                replacement,
+            };
+        }
+
+        // Each child is assumed to sit on its own line in a multi-line container, indented
+        // like the first one
+        let indent = line_indent(&self.text, children[0].span.start);
+        let separator = if multiline {
+            format!(",{}{}", self.newline(), indent)
+        } else {
+            ", ".to_string()
+        };
+        let child = child_text(multiline.then_some(indent));
+        if index < children.len() {
+            let start = children[index].span.start;
+            return Splice {
+                span: Span { start, end: start },
+                replacement: format!("{}{}", child, separator),
+            };
+        }
+
+        let last = children[children.len() - 1];
+        let trailing_comma = container.children.iter().find_map(|element| match element {
+            CstElement::Token(token)
+                if token.kind == TokenKind::Comma && token.span.start >= last.span.end =>
+            {
+                Some(token.span.end)
+            }
+            _ => None,
+        });
+        match trailing_comma {
+            // Keep the trailing comma style by putting one after the new child too
+            Some(end) => Splice {
+                span: Span { start: end, end },
+                replacement: format!("{}{},", &separator[1..], child),
+            },
+            None => Splice {
+                span: Span {
+                    start: last.span.end,
+                    end: last.span.end,
+                },
+                replacement: separator + &child,
+            },
+        }
+    }
+
+    // Containers are pretty-printed to fit in at `indent` in multi-line documents, and written
+    // compactly otherwise
+    fn format_value(&self, value: &JSONElement, indent: Option<&str>) -> String {
+        match (value, indent) {
+            (JSONElement::Object(_) | JSONElement::Array(_), Some(indent)) => {
+                dumps_pretty(value, &self.indent_unit())
+                    .replace('\n', &format!("{}{}", self.newline(), indent))
+            }
+            _ => dumps(value),
+        }
+    }
+
+    fn newline(&self) -> &'static str {
+        if self.text.contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    // The indentation of the first indented line, which is usually one level deep
+    fn indent_unit(&self) -> String {
+        self.text
+            .split('\n')
+            .skip(1)
+            .map(|line| {
+                line.chars()
+                    .take_while(|c| *c == ' ' || *c == '\t')
+                    .collect::<String>()
+            })
+            .find(|indent| !indent.is_empty())
+            .unwrap_or_else(|| "  ".to_string())
+    }
+}
+
+// Whatever is between the key and value of the object's first member, usually ": "
+fn key_separator(object: &CstNode) -> String {
+    object
+        .child_nodes()
+        .next()
+        .and_then(|member| {
+            let (key, value) = (member.key()?, member.value()?);
+            let member_text = member.to_string();
+            let offset = member.span.start;
+            Some(member_text[key.span.end - offset..value.span.start - offset].to_string())
+        })
+        .unwrap_or_else(|| ": ".to_string())
+}
+
+fn container_len(container: &CstNode) -> usize {
+    container.child_nodes().count()
+}
+
+fn is_multiline(text: &str, node: &CstNode) -> bool {
+    text[node.span.start..node.span.end].contains('\n')
+}
+
+// The spaces and tabs at the start of the line containing `position`
+fn line_indent(text: &str, position: usize) -> &str {
+    let line_start = text[..position].rfind('\n').map_or(0, |i| i + 1);
+    let line = &text[line_start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+fn value_at<'c, 'i>(container: &'c CstNode<'i>, index: usize) -> &'c CstNode<'i> {
+    let child = container
+        .child_nodes()
+        .nth(index)
+        .expect("index came from find_child");
+    child.value().unwrap_or(child)
+}
+
+// The position of the member or element called `token` in `container`, or None for a member
+// that doesn't exist
+fn find_child(container: &CstNode, token: &str) -> Result<Option<usize>, String> {
+    match container.kind {
+        NodeKind::Object => {
+            let members: Vec<&CstNode> = container.child_nodes().collect();
+            // Later duplicates win, the same as when converting to a JSONElement
+            Ok(members.iter().rposition(|member| {
+                member.key().is_some_and(|key| {
+                    unescape(&key.text[1..key.text.len() - 1]).is_ok_and(|key| key == token)
+                })
+            }))
+        }
+        NodeKind::Array => Ok(Some(parse_index(token, container_len(container), false)?)),
+        _ => Err(format!("Can't look up {} inside a scalar", token)),
+    }
+}
+
+fn walk<'c, 'i>(document: &'c CstNode<'i>, tokens: &[String]) -> Result<&'c CstNode<'i>, String> {
+    let root = document.value().ok_or("Document had no value")?;
+    tokens.iter().try_fold(root, |node, token| {
+        let index =
+            find_child(node, token)?.ok_or_else(|| format!("There is no member {}", token))?;
+        Ok(value_at(node, index))
+    })
+}
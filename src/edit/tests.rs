@@ -0,0 +1,154 @@
+#[cfg(test)]
+use crate::cst::CstOptions;
+#[cfg(test)]
+use crate::edit::*;
+#[cfg(test)]
+use crate::rustifier::loads;
+
+#[cfg(test)]
+const CONFIG: &str = r#"{
+    "name": "deploy",
+
+    "replicas": 2,
+    "ports" : [80, 443],
+    "env": {}
+}
+"#;
+
+#[cfg(test)]
+fn edited(text: &str, edit: impl FnOnce(&mut Document) -> Result<(), String>) -> String {
+    let mut document = Document::parse(text).unwrap();
+    edit(&mut document).unwrap();
+    document.text().to_string()
+}
+
+#[test]
+fn sets_a_value_leaving_everything_else_alone() {
+    let actual = edited(CONFIG, |document| {
+        document.set("/replicas", &loads("5").unwrap())
+    });
+    assert_eq!(actual, CONFIG.replace("\"replicas\": 2", "\"replicas\": 5"));
+}
+
+#[test]
+fn sets_strings_with_escapes() {
+    let actual = edited(CONFIG, |document| {
+        document.set("/name", &loads(r#""say \"hi\"""#).unwrap())
+    });
+    assert!(actual.contains(r#""name": "say \"hi\"","#));
+    assert_eq!(
+        loads(&actual).unwrap(),
+        loads(&CONFIG.replace("\"deploy\"", r#""say \"hi\"""#)).unwrap()
+    );
+}
+
+#[test]
+fn adds_missing_member_with_inferred_style() {
+    let actual = edited(CONFIG, |document| {
+        document.set("/region", &loads(r#""eu-west-1""#).unwrap())
+    });
+    assert_eq!(
+        actual,
+        CONFIG.replace("\"env\": {}", "\"env\": {},\n    \"region\": \"eu-west-1\"")
+    );
+}
+
+#[test]
+fn pretty_prints_containers_at_the_right_depth() {
+    let actual = edited(CONFIG, |document| {
+        document.set("/env", &loads(r#"{"debug": true, "tags": [1]}"#).unwrap())
+    });
+    let expected = CONFIG.replace(
+        "\"env\": {}",
+        "\"env\": {\n        \"debug\": true,\n        \"tags\": [\n            1\n        ]\n    }",
+    );
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn inserts_into_empty_object() {
+    let actual = edited(CONFIG, |document| {
+        document.insert("/env/LEVEL", &loads(r#""debug""#).unwrap())
+    });
+    assert_eq!(
+        actual,
+        CONFIG.replace(
+            "\"env\": {}",
+            "\"env\": {\n        \"LEVEL\": \"debug\"\n    }"
+        )
+    );
+}
+
+#[test]
+fn inserts_into_arrays() {
+    let actual = edited(CONFIG, |document| {
+        document.insert("/ports/1", &loads("8080").unwrap())?;
+        document.insert("/ports/-", &loads("9090").unwrap())
+    });
+    assert_eq!(actual, CONFIG.replace("[80, 443]", "[80, 8080, 443, 9090]"));
+}
+
+#[test]
+fn keeps_tabs_crlf_and_trailing_commas() {
+    let text = "{\r\n\t\"a\": 1,\r\n}";
+    let actual = edited(text, |document| {
+        document.insert("/b", &loads("[2]").unwrap())
+    });
+    assert_eq!(
+        actual,
+        "{\r\n\t\"a\": 1,\r\n\t\"b\": [\r\n\t\t2\r\n\t],\r\n}"
+    );
+}
+
+#[test]
+fn deletes_members_and_elements() {
+    let actual = edited(CONFIG, |document| {
+        document.delete("/name")?;
+        document.delete("/ports/1")?;
+        document.delete("/env")
+    });
+    assert_eq!(actual, "{\n    \"replicas\": 2,\n    \"ports\" : [80]\n}\n");
+    let actual = edited(CONFIG, |document| document.delete("/ports/0"));
+    assert!(actual.contains("\"ports\" : [443],"));
+    let actual = edited(r#"{"only": [1]}"#, |document| document.delete("/only/0"));
+    assert_eq!(actual, r#"{"only": []}"#);
+}
+
+#[test]
+fn keeps_the_previous_comment_when_deleting_the_last_element() {
+    let delete = |text: &str, pointer: &str| {
+        let mut document = Document::parse_with(text, CstOptions { comments: true }).unwrap();
+        document.delete(pointer).unwrap();
+        document.text().to_string()
+    };
+    assert_eq!(delete("[\n  1, // one\n  2\n]", "/1"), "[\n  1 // one\n]");
+    assert_eq!(delete("[1, /* one */ 2]", "/1"), "[1 /* one */]");
+    assert_eq!(
+        delete("{\n  \"a\": 1,\n  // about b\n  \"b\": 2\n}", "/b"),
+        "{\n  \"a\": 1\n}"
+    );
+}
+
+#[test]
+fn keeps_comments_when_enabled() {
+    let text = "{\n  // how many\n  \"replicas\": 2 /* for now */\n}";
+    let mut document = Document::parse_with(text, CstOptions { comments: true }).unwrap();
+    document.set("/replicas", &loads("3").unwrap()).unwrap();
+    assert_eq!(
+        document.text(),
+        "{\n  // how many\n  \"replicas\": 3 /* for now */\n}"
+    );
+}
+
+#[test]
+fn rejects_bad_edits() {
+    let mut document = Document::parse(CONFIG).unwrap();
+    assert!(document
+        .set("/missing/deeper", &loads("1").unwrap())
+        .is_err());
+    assert!(document.insert("/name", &loads("1").unwrap()).is_err());
+    assert!(document.insert("/ports/5", &loads("1").unwrap()).is_err());
+    assert!(document.delete("/ports/2").is_err());
+    assert!(document.delete("/replicas/0").is_err());
+    assert_eq!(document.text(), CONFIG);
+}
@@ -0,0 +1,126 @@
+use crate::ast_parser::unescape;
+use crate::cst::{parse_cst_with, CstElement, CstNode, CstOptions, NodeKind, TokenKind};
+use crate::pointer::format_pointer;
+use crate::rustifier::JSONElement;
+use std::collections::BTreeMap;
+
+mod tests;
+
+/// The comments around one value. Each comment is kept exactly as written, "//" or "/* */"
+/// included.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Comments {
+    // Comments on the lines before the value (or between its key and the value)
+    pub leading: Vec<String>,
+    // A comment after the value on the same line, like `"port": 80, // the default`
+    pub trailing: Option<String>,
+    // For objects and arrays, comments after the last child and before the closing bracket
+    pub end: Vec<String>,
+}
+
+/// Comments attached to the values of a document, keyed by JSON pointer
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct CommentMap {
+    pub values: BTreeMap<String, Comments>,
+    // Comments after the last value in the document that aren't on its line
+    pub footer: Vec<String>,
+}
+
+impl CommentMap {
+    pub fn get(&self, pointer: &str) -> Option<&Comments> {
+        self.values.get(pointer)
+    }
+
+    fn entry(&mut self, pointer: &[String]) -> &mut Comments {
+        self.values.entry(format_pointer(pointer)).or_default()
+    }
+}
+
+/// Parses JSON that may contain comments, keeping them alongside the value so they can be written
+/// back out with `DumpOptions::comments`. Comments go to the value that follows them, unless
+/// they're on the same line as the value before.
+pub fn loads_with_comments(s: &str) -> Result<(JSONElement, CommentMap), String> {
+    let document = parse_cst_with(s, CstOptions { comments: true })?;
+    let mut comments = CommentMap::default();
+    let footer = attach(&document, &mut vec![], &mut comments);
+    comments.footer = footer;
+    Ok((document.to_element()?, comments))
+}
+
+// Walks the children of a Document, Array or Object node at `path`, attaching the comments
+// among them. Returns the comments left over after the last child.
+fn attach(node: &CstNode, path: &mut Vec<String>, comments: &mut CommentMap) -> Vec<String> {
+    let mut previous: Option<Vec<String>> = None;
+    let mut pending = vec![];
+    let mut index = 0;
+    for element in node.children.iter() {
+        match element {
+            CstElement::Token(token) if token.kind == TokenKind::Comment => {
+                match &previous {
+                    Some(previous) if comments.entry(previous).trailing.is_none() => {
+                        comments.entry(previous).trailing = Some(token.text.to_string())
+                    }
+                    _ => pending.push(token.text.to_string()),
+                }
+                if token.text.contains('\n') {
+                    previous = None;
+                }
+            }
+            // Only a comment on the same line as the end of the previous value is trailing
+            CstElement::Token(token) if token.kind == TokenKind::Whitespace => {
+                if token.text.contains(['\n', '\r']) {
+                    previous = None;
+                }
+            }
+            CstElement::Token(_) => {}
+            CstElement::Node(child) => {
+                if node.kind != NodeKind::Document {
+                    path.push(child_token(child, index));
+                    index += 1;
+                }
+                if !pending.is_empty() {
+                    comments.entry(path).leading.append(&mut pending);
+                }
+                attach_value(child, path, comments);
+                previous = Some(path.clone());
+                if node.kind != NodeKind::Document {
+                    path.pop();
+                }
+            }
+        }
+    }
+    pending
+}
+
+// Attaches the comments inside a child of a container, which is a member or a value
+fn attach_value(node: &CstNode, path: &mut Vec<String>, comments: &mut CommentMap) {
+    match node.kind {
+        NodeKind::Member => {
+            for element in node.children.iter() {
+                match element {
+                    CstElement::Token(token) if token.kind == TokenKind::Comment => {
+                        comments.entry(path).leading.push(token.text.to_string())
+                    }
+                    CstElement::Node(value) => attach_value(value, path, comments),
+                    CstElement::Token(_) => {}
+                }
+            }
+        }
+        NodeKind::Array | NodeKind::Object => {
+            let mut end = attach(node, path, comments);
+            if !end.is_empty() {
+                comments.entry(path).end.append(&mut end);
+            }
+        }
+        NodeKind::Document | NodeKind::Scalar | NodeKind::Error => {}
+    }
+}
+
+// The pointer token for the `index`th child of a container: its key for members, otherwise its
+// position
+fn child_token(child: &CstNode, index: usize) -> String {
+    child
+        .key()
+        .and_then(|key| unescape(&key.text[1..key.text.len() - 1]).ok())
+        .unwrap_or_else(|| index.to_string())
+}
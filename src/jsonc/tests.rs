@@ -0,0 +1,119 @@
+#[cfg(test)]
+use crate::cst::CstOptions;
+#[cfg(test)]
+use crate::jsonc::*;
+#[cfg(test)]
+use crate::rustifier::{dumps_with, loads, loads_with, DumpOptions};
+
+#[cfg(test)]
+const SETTINGS: &str = r#"// Editor settings
+{
+    // Font size in points
+    "editor.fontSize": 14, // the default is 12
+    "files.exclude": [
+        "target", /* build output */
+        // dependencies
+        "node_modules"
+    ],
+    "empty": {
+        // nothing yet
+    },
+    "tabs": true /* spaces are fine too */
+    // end of settings
+} // trailing
+// footer
+"#;
+
+#[test]
+fn loads_with_comments_as_trivia() {
+    let actual = loads_with(SETTINGS, CstOptions { comments: true }).unwrap();
+    let expected = loads(
+        r#"{"editor.fontSize": 14, "files.exclude": ["target", "node_modules"], "empty": {}, "tabs": true}"#,
+    )
+    .unwrap();
+    assert_eq!(actual, expected);
+    assert!(loads_with(SETTINGS, CstOptions::default()).is_err());
+}
+
+#[test]
+fn attaches_comments() {
+    let (_, comments) = loads_with_comments(SETTINGS).unwrap();
+    let strings = |comments: &[&str]| comments.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        comments.get(""),
+        Some(&Comments {
+            leading: strings(&["// Editor settings"]),
+            trailing: Some("// trailing".to_string()),
+            end: strings(&["// end of settings"]),
+        })
+    );
+    assert_eq!(
+        comments.get("/editor.fontSize"),
+        Some(&Comments {
+            leading: strings(&["// Font size in points"]),
+            trailing: Some("// the default is 12".to_string()),
+            end: vec![],
+        })
+    );
+    assert_eq!(
+        comments
+            .get("/files.exclude/0")
+            .unwrap()
+            .trailing
+            .as_deref(),
+        Some("/* build output */")
+    );
+    assert_eq!(
+        comments.get("/files.exclude/1").unwrap().leading,
+        strings(&["// dependencies"])
+    );
+    assert_eq!(
+        comments.get("/empty").unwrap().end,
+        strings(&["// nothing yet"])
+    );
+    assert_eq!(
+        comments.get("/tabs").unwrap().trailing.as_deref(),
+        Some("/* spaces are fine too */")
+    );
+    assert_eq!(comments.get("/files.exclude"), None);
+    assert_eq!(comments.footer, strings(&["// footer"]));
+}
+
+#[test]
+fn writes_comments_back_out() {
+    let (element, comments) = loads_with_comments(SETTINGS).unwrap();
+    let options = DumpOptions {
+        indent: Some("    "),
+        comments: Some(&comments),
+        ..DumpOptions::default()
+    };
+    let expected = r#"// Editor settings
+{
+    // Font size in points
+    "editor.fontSize": 14, // the default is 12
+    "empty": {
+        // nothing yet
+    },
+    "files.exclude": [
+        "target", /* build output */
+        // dependencies
+        "node_modules"
+    ],
+    "tabs": true /* spaces are fine too */
+    // end of settings
+} // trailing
+// footer"#;
+    let written = dumps_with(&element, options);
+    assert_eq!(written, expected);
+    assert_eq!(loads_with_comments(&written).unwrap(), (element, comments));
+}
+
+#[test]
+fn leaves_comments_out_of_compact_output() {
+    let (element, comments) = loads_with_comments("// note\n[1, // one\n 2]").unwrap();
+    let options = DumpOptions {
+        comments: Some(&comments),
+        ..DumpOptions::default()
+    };
+    assert_eq!(dumps_with(&element, options), "[1,2]");
+}
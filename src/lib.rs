@@ -0,0 +1,25 @@
+#[macro_use]
+extern crate lazy_static;
+
+pub mod ast_parser;
+pub mod bson;
+pub mod canonical;
+pub mod cbor;
+pub mod cli;
+pub mod codegen;
+pub mod cst;
+pub mod csv;
+pub mod diff;
+pub mod edit;
+pub mod jsonc;
+pub mod lsp;
+pub mod msgpack;
+pub mod patch;
+pub mod pointer;
+pub mod rustifier;
+pub mod schema;
+pub mod spanned;
+
+use crate::ast_parser::*;
+
+pub type NumberType = i32;
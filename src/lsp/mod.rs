@@ -0,0 +1,786 @@
+use crate::ast_parser::{escape, unescape};
+use crate::cst::{
+    parse_cst_recovering, CstElement, CstNode, CstOptions, Diagnostic, NodeKind, Span, TokenKind,
+};
+use crate::pointer::{format_pointer, percent_decode, resolve};
+use crate::rustifier::{dumps, loads, JSONElement};
+use crate::spanned::{LineIndex, Position};
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+mod tests;
+
+// JSON-RPC error codes
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+// The LSP enum values we use
+const FULL_SYNC: i32 = 1;
+const ERROR_SEVERITY: i32 = 1;
+const PROPERTY_COMPLETION: i32 = 10;
+const VALUE_COMPLETION: i32 = 12;
+
+type RpcError = (i32, String);
+
+struct OpenDocument {
+    uri: String,
+    text: String,
+    // .jsonc files and documents the editor calls "jsonc" can have comments
+    comments: bool,
+}
+
+impl OpenDocument {
+    fn options(&self) -> CstOptions {
+        CstOptions {
+            comments: self.comments,
+        }
+    }
+}
+
+// A schema to use for documents whose file name matches one of `patterns`
+struct SchemaAssociation {
+    patterns: Vec<String>,
+    url: String,
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<String, OpenDocument>,
+    schemas: Vec<SchemaAssociation>,
+    shut_down: bool,
+    exited: bool,
+}
+
+/// Speaks the Language Server Protocol over `input` and `output` until the client sends "exit"
+/// or hangs up. Diagnostics, the document outline, folding ranges, hovers (which show the JSON
+/// pointer of the value under the cursor) and formatting work for any document. Completion
+/// needs a schema, either from a top-level "$schema" member or from the "schemas" list in the
+/// client's initializationOptions, which has the same shape as VS Code's `json.schemas` setting.
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> Result<(), String> {
+    let mut server = Server::default();
+    while let Some(body) = read_message(&mut input)? {
+        for message in server.handle(&body) {
+            write_message(&mut output, &message)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Reads one message's body, or None once the input is closed
+pub fn read_message(input: &mut impl BufRead) -> Result<Option<String>, String> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).map_err(|err| err.to_string())? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .map_err(|err| format!("Bad Content-Length {}: {}", value, err))?,
+                );
+            }
+        }
+    }
+    let length = length.ok_or("Message had no Content-Length header")?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).map_err(|err| err.to_string())?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| err.to_string())
+}
+
+pub fn write_message(output: &mut impl Write, message: &JSONElement) -> Result<(), String> {
+    let body = dumps(message);
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)
+        .and_then(|_| output.flush())
+        .map_err(|err| err.to_string())
+}
+
+impl Server {
+    // Handles one message from the client, returning the messages to send back
+    fn handle(&mut self, body: &str) -> Vec<JSONElement> {
+        let message = match loads(body) {
+            Ok(message) => message,
+            Err(err) => return vec![error_response(JSONElement::Null, (PARSE_ERROR, err))],
+        };
+        let method = string_at(&message, "/method");
+        let params = resolve(&message, "/params")
+            .cloned()
+            .unwrap_or(JSONElement::Null);
+        match (resolve(&message, "/id").ok().cloned(), method) {
+            (Some(id), Some(method)) => {
+                let result = if self.shut_down && method != "shutdown" {
+                    Err((INVALID_REQUEST, "The server has been shut down".to_string()))
+                } else {
+                    self.request(method, &params)
+                };
+                match result {
+                    Ok(result) => vec![object([
+                        ("jsonrpc", string("2.0")),
+                        ("id", id),
+                        ("result", result),
+                    ])],
+                    Err(error) => vec![error_response(id, error)],
+                }
+            }
+            (None, Some(method)) => self.notification(method, &params),
+            // Responses to requests we never make
+            (_, None) => vec![],
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &JSONElement) -> Result<JSONElement, RpcError> {
+        match method {
+            "initialize" => {
+                self.schemas = associations(params);
+                Ok(object([
+                    (
+                        "capabilities",
+                        object([
+                            ("textDocumentSync", JSONElement::Number(FULL_SYNC)),
+                            ("hoverProvider", JSONElement::Boolean(true)),
+                            ("documentSymbolProvider", JSONElement::Boolean(true)),
+                            ("foldingRangeProvider", JSONElement::Boolean(true)),
+                            ("documentFormattingProvider", JSONElement::Boolean(true)),
+                            (
+                                "completionProvider",
+                                object([(
+                                    "triggerCharacters",
+                                    JSONElement::Array(vec![string("\""), string(":")]),
+                                )]),
+                            ),
+                        ]),
+                    ),
+                    ("serverInfo", object([("name", string("json_parser"))])),
+                ]))
+            }
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(JSONElement::Null)
+            }
+            "textDocument/hover" => {
+                let (document, offset) = self.position(params)?;
+                Ok(hover(document, offset).unwrap_or(JSONElement::Null))
+            }
+            "textDocument/documentSymbol" => Ok(symbols(self.document(params)?)),
+            "textDocument/foldingRange" => Ok(folding_ranges(self.document(params)?)),
+            "textDocument/formatting" => {
+                let indent = match (
+                    resolve(params, "/options/insertSpaces"),
+                    resolve(params, "/options/tabSize"),
+                ) {
+                    (Ok(JSONElement::Boolean(false)), _) => "\t".to_string(),
+                    (_, Ok(JSONElement::Number(size))) => " ".repeat((*size).max(0) as usize),
+                    _ => "  ".to_string(),
+                };
+                Ok(format(self.document(params)?, &indent))
+            }
+            "textDocument/completion" => {
+                let (document, offset) = self.position(params)?;
+                Ok(JSONElement::Array(self.complete(document, offset)))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("{} is not supported", method))),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &JSONElement) -> Vec<JSONElement> {
+        let uri = string_at(params, "/textDocument/uri").map(str::to_string);
+        match (method, uri) {
+            ("exit", _) => {
+                self.exited = true;
+                vec![]
+            }
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = string_at(params, "/textDocument/text").unwrap_or_default();
+                let comments = uri.ends_with(".jsonc")
+                    || string_at(params, "/textDocument/languageId") == Some("jsonc");
+                let document = OpenDocument {
+                    uri: uri.clone(),
+                    text: text.to_string(),
+                    comments,
+                };
+                let diagnostics = publish_diagnostics(&document);
+                self.documents.insert(uri, document);
+                vec![diagnostics]
+            }
+            // We ask for full sync, so the last change has the whole new text
+            ("textDocument/didChange", Some(uri)) => {
+                let text = match resolve(params, "/contentChanges") {
+                    Ok(JSONElement::Array(changes)) => {
+                        changes.last().and_then(|change| string_at(change, "/text"))
+                    }
+                    _ => None,
+                };
+                match (self.documents.get_mut(&uri), text) {
+                    (Some(document), Some(text)) => {
+                        document.text = text.to_string();
+                        vec![publish_diagnostics(document)]
+                    }
+                    _ => vec![],
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(&uri);
+                // Clear out the diagnostics for the closed file
+                vec![notification(
+                    "textDocument/publishDiagnostics",
+                    object([
+                        ("uri", string(uri)),
+                        ("diagnostics", JSONElement::Array(vec![])),
+                    ]),
+                )]
+            }
+            // Everything else, like "initialized" and "$/cancelRequest", needs no reply
+            _ => vec![],
+        }
+    }
+
+    fn document(&self, params: &JSONElement) -> Result<&OpenDocument, RpcError> {
+        let uri = string_at(params, "/textDocument/uri")
+            .ok_or_else(|| (INVALID_PARAMS, "Missing textDocument.uri".to_string()))?;
+        self.documents
+            .get(uri)
+            .ok_or_else(|| (INVALID_PARAMS, format!("{} is not open", uri)))
+    }
+
+    // The document and byte offset of a TextDocumentPositionParams
+    fn position(&self, params: &JSONElement) -> Result<(&OpenDocument, usize), RpcError> {
+        let document = self.document(params)?;
+        let number = |pointer: &str| match resolve(params, pointer) {
+            Ok(JSONElement::Number(n)) if *n >= 0 => Ok(*n as usize),
+            _ => Err((INVALID_PARAMS, format!("Missing {}", pointer))),
+        };
+        let position = Position {
+            line: number("/position/line")? + 1,
+            column: number("/position/character")? + 1,
+        };
+        Ok((
+            document,
+            LineIndex::new(&document.text).utf16_offset(position),
+        ))
+    }
+
+    fn complete(&self, document: &OpenDocument, offset: usize) -> Vec<JSONElement> {
+        let Some(schema) = self.schema_for(document) else {
+            return vec![];
+        };
+        let (cst, _) = parse_cst_recovering(&document.text, document.options());
+        let Some(value) = cst.value() else {
+            return vec![];
+        };
+        let mut path = vec![];
+        let Some(object) = enclosing_object(value, offset, &mut path) else {
+            return vec![];
+        };
+        let index = LineIndex::new(&document.text);
+        // In a member's value, suggest the values the schema allows
+        if let Some((key, member)) = object
+            .child_nodes()
+            .filter(|member| member.kind == NodeKind::Member)
+            .find_map(|member| value_position(member, offset).map(|key| (key, member)))
+        {
+            path.push(key);
+            let Some(subschema) = subschema(&schema, &schema, &path) else {
+                return vec![];
+            };
+            let replace = member.value().map_or(
+                Span {
+                    start: offset,
+                    end: offset,
+                },
+                |value| Span {
+                    start: value.span.start,
+                    end: value.span.end.max(offset),
+                },
+            );
+            return allowed_values(subschema)
+                .into_iter()
+                .map(|value| {
+                    let text = dumps(&value);
+                    completion(&text, VALUE_COMPLETION, None, &text, range(&index, replace))
+                })
+                .collect();
+        }
+        let Some(subschema) = subschema(&schema, &schema, &path) else {
+            return vec![];
+        };
+        let Ok(JSONElement::Object(properties)) = resolve(subschema, "/properties") else {
+            return vec![];
+        };
+        let existing: Vec<String> = object
+            .child_nodes()
+            .filter_map(|member| member.key())
+            .filter_map(|key| unescape(&key.text[1..key.text.len() - 1]).ok())
+            .collect();
+        // If the cursor is on a key that's partly typed, that's what gets replaced. Until it's
+        // followed by a colon, the recovering parser sees it as an Error node.
+        let replace = object
+            .child_nodes()
+            .find(|child| child.span.start <= offset && offset <= child.span.end)
+            .map_or(
+                Span {
+                    start: offset,
+                    end: offset,
+                },
+                |child| match child.key() {
+                    Some(key) if key.span.start <= offset && offset <= key.span.end => key.span,
+                    _ if child.kind == NodeKind::Error => child.span,
+                    _ => Span {
+                        start: child.span.start,
+                        end: offset,
+                    },
+                },
+            );
+        let mut keys: Vec<&String> = properties
+            .keys()
+            .filter(|key| !existing.contains(key))
+            .collect();
+        keys.sort();
+        keys.into_iter()
+            .map(|key| {
+                let description = string_at(&properties[key], "/description");
+                completion(
+                    key,
+                    PROPERTY_COMPLETION,
+                    description,
+                    &escape(key),
+                    range(&index, replace),
+                )
+            })
+            .collect()
+    }
+
+    fn schema_for(&self, document: &OpenDocument) -> Option<JSONElement> {
+        let (cst, _) = parse_cst_recovering(&document.text, document.options());
+        let declared = cst
+            .to_element()
+            .ok()
+            .and_then(|element| string_at(&element, "/$schema").map(str::to_string));
+        let file_name = document.uri.rsplit('/').next().unwrap_or_default();
+        let url = declared.or_else(|| {
+            self.schemas
+                .iter()
+                .find(|association| {
+                    association
+                        .patterns
+                        .iter()
+                        .any(|pattern| glob_matches(pattern, file_name))
+                })
+                .map(|association| association.url.clone())
+        })?;
+        let path = schema_path(&document.uri, &url)?;
+        loads(&std::fs::read_to_string(path).ok()?).ok()
+    }
+}
+
+fn publish_diagnostics(document: &OpenDocument) -> JSONElement {
+    let (_, errors) = parse_cst_recovering(&document.text, document.options());
+    let index = LineIndex::new(&document.text);
+    let diagnostics = errors
+        .iter()
+        .map(|Diagnostic { message, span }| {
+            object([
+                ("range", range(&index, *span)),
+                ("severity", JSONElement::Number(ERROR_SEVERITY)),
+                ("source", string("json_parser")),
+                ("message", string(message.as_str())),
+            ])
+        })
+        .collect();
+    notification(
+        "textDocument/publishDiagnostics",
+        object([
+            ("uri", string(document.uri.as_str())),
+            ("diagnostics", JSONElement::Array(diagnostics)),
+        ]),
+    )
+}
+
+fn hover(document: &OpenDocument, offset: usize) -> Option<JSONElement> {
+    let (cst, _) = parse_cst_recovering(&document.text, document.options());
+    let mut path = vec![];
+    let span = value_at(cst.value()?, offset, &mut path)?;
+    let pointer = format_pointer(&path);
+    let contents = if pointer.is_empty() {
+        "`\"\"` (the whole document)".to_string()
+    } else {
+        format!("`{}`", pointer)
+    };
+    Some(object([
+        (
+            "contents",
+            object([("kind", string("markdown")), ("value", string(contents))]),
+        ),
+        ("range", range(&LineIndex::new(&document.text), span)),
+    ]))
+}
+
+// The span of the innermost value (or key) at `offset`, pushing the path down to it onto `path`
+fn value_at(node: &CstNode, offset: usize, path: &mut Vec<String>) -> Option<Span> {
+    if !(node.span.start <= offset && offset < node.span.end) || node.kind == NodeKind::Error {
+        return None;
+    }
+    let children = child_entries(node);
+    for (token, child) in children {
+        if child.span.start <= offset && offset < child.span.end {
+            path.push(token);
+            return match child.kind {
+                NodeKind::Member => {
+                    let value = child.value();
+                    match value.and_then(|value| value_at(value, offset, path)) {
+                        Some(span) => Some(span),
+                        // On the key, or between the key and the value
+                        None => Some(child.key().map_or(child.span, |key| key.span)),
+                    }
+                }
+                _ => value_at(child, offset, path).or(Some(child.span)),
+            };
+        }
+    }
+    Some(node.span)
+}
+
+// The children of an array or object along with their pointer tokens
+fn child_entries<'c, 'i>(node: &'c CstNode<'i>) -> Vec<(String, &'c CstNode<'i>)> {
+    match node.kind {
+        NodeKind::Array => node
+            .child_nodes()
+            .enumerate()
+            .map(|(i, child)| (i.to_string(), child))
+            .collect(),
+        NodeKind::Object => node
+            .child_nodes()
+            .filter_map(|member| Some((key_text(member)?, member)))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn key_text(member: &CstNode) -> Option<String> {
+    let key = member.key()?;
+    unescape(&key.text[1..key.text.len() - 1]).ok()
+}
+
+// The innermost object whose braces are around `offset`, pushing the path down to it onto `path`
+fn enclosing_object<'c, 'i>(
+    node: &'c CstNode<'i>,
+    offset: usize,
+    path: &mut Vec<String>,
+) -> Option<&'c CstNode<'i>> {
+    let inside = node.span.start < offset && offset <= node.span.end;
+    if !inside || !matches!(node.kind, NodeKind::Array | NodeKind::Object) {
+        return None;
+    }
+    // A closed container ends with its bracket, and the cursor has to be before that
+    let closed = node.children.last().is_some_and(|last| {
+        matches!(last, CstElement::Token(token) if matches!(token.kind, TokenKind::RightBrace | TokenKind::RightBracket))
+    });
+    if closed && offset == node.span.end {
+        return None;
+    }
+    for (token, child) in child_entries(node) {
+        let value = if child.kind == NodeKind::Member {
+            child.value()
+        } else {
+            Some(child)
+        };
+        path.push(token);
+        if let Some(found) = value.and_then(|value| enclosing_object(value, offset, path)) {
+            return Some(found);
+        }
+        path.pop();
+    }
+    (node.kind == NodeKind::Object).then_some(node)
+}
+
+// If `offset` is after the member's colon, the member's key
+fn value_position(member: &CstNode, offset: usize) -> Option<String> {
+    let colon = member.children.iter().find_map(|child| match child {
+        CstElement::Token(token) if token.kind == TokenKind::Colon => Some(token.span),
+        _ => None,
+    })?;
+    (colon.end <= offset && offset <= member.span.end).then(|| key_text(member))?
+}
+
+fn symbols(document: &OpenDocument) -> JSONElement {
+    let (cst, _) = parse_cst_recovering(&document.text, document.options());
+    let index = LineIndex::new(&document.text);
+    JSONElement::Array(
+        cst.value()
+            .map(|value| child_symbols(value, &index))
+            .unwrap_or_default(),
+    )
+}
+
+fn child_symbols(node: &CstNode, index: &LineIndex) -> Vec<JSONElement> {
+    child_entries(node)
+        .into_iter()
+        .filter_map(|(name, child)| {
+            let (value, selection) = match child.kind {
+                NodeKind::Member => (child.value()?, child.key()?.span),
+                _ => (child, child.span),
+            };
+            // LSP's SymbolKind numbers
+            let kind = match value.kind {
+                NodeKind::Object => 19,
+                NodeKind::Array => 18,
+                NodeKind::Scalar => match value.tokens().first()?.kind {
+                    TokenKind::String => 15,
+                    TokenKind::Number => 16,
+                    TokenKind::Boolean => 17,
+                    _ => 21,
+                },
+                _ => return None,
+            };
+            let mut symbol = vec![
+                ("name", string(name)),
+                ("kind", JSONElement::Number(kind)),
+                ("range", range(index, child.span)),
+                ("selectionRange", range(index, selection)),
+            ];
+            if value.kind == NodeKind::Scalar {
+                symbol.push(("detail", string(value.to_string())));
+            } else {
+                symbol.push(("children", JSONElement::Array(child_symbols(value, index))));
+            }
+            Some(object(symbol))
+        })
+        .collect()
+}
+
+fn folding_ranges(document: &OpenDocument) -> JSONElement {
+    let (cst, _) = parse_cst_recovering(&document.text, document.options());
+    let index = LineIndex::new(&document.text);
+    let mut ranges = vec![];
+    collect_folding_ranges(&cst, &index, &mut ranges);
+    JSONElement::Array(ranges)
+}
+
+fn collect_folding_ranges(node: &CstNode, index: &LineIndex, ranges: &mut Vec<JSONElement>) {
+    // Lines are 0-based in LSP, and the closing bracket's line stays visible
+    let lines = |span: Span| {
+        (
+            index.position(span.start).line as i32 - 1,
+            index.position(span.end).line as i32 - 1,
+        )
+    };
+    for child in node.children.iter() {
+        match child {
+            CstElement::Node(child) => {
+                let (start, end) = lines(child.span);
+                if matches!(child.kind, NodeKind::Array | NodeKind::Object) && end - 1 > start {
+                    ranges.push(object([
+                        ("startLine", JSONElement::Number(start)),
+                        ("endLine", JSONElement::Number(end - 1)),
+                    ]));
+                }
+                collect_folding_ranges(child, index, ranges);
+            }
+            CstElement::Token(token) if token.kind == TokenKind::Comment => {
+                let (start, end) = lines(token.span);
+                if end > start {
+                    ranges.push(object([
+                        ("startLine", JSONElement::Number(start)),
+                        ("endLine", JSONElement::Number(end)),
+                        ("kind", string("comment")),
+                    ]));
+                }
+            }
+            CstElement::Token(_) => {}
+        }
+    }
+}
+
+// A single edit replacing the whole document, or no edits if it doesn't parse
+fn format(document: &OpenDocument, indent: &str) -> JSONElement {
+    let (cst, errors) = parse_cst_recovering(&document.text, document.options());
+    let formatted = cst.format(indent);
+    if !errors.is_empty() || formatted == document.text {
+        return JSONElement::Array(vec![]);
+    }
+    let whole = Span {
+        start: 0,
+        end: document.text.len(),
+    };
+    JSONElement::Array(vec![object([
+        ("range", range(&LineIndex::new(&document.text), whole)),
+        ("newText", string(formatted)),
+    ])])
+}
+
+// Follows `path` down through a schema's properties and items. Only local references are
+// followed, and combinators like allOf are ignored.
+fn subschema<'s>(
+    root: &'s JSONElement,
+    schema: &'s JSONElement,
+    path: &[String],
+) -> Option<&'s JSONElement> {
+    let mut schema = schema;
+    // Bounded so a reference cycle can't hang the server
+    for _ in 0..32 {
+        match string_at(schema, "/$ref").and_then(|reference| reference.strip_prefix('#')) {
+            Some(pointer) => schema = resolve(root, pointer).ok()?,
+            None => break,
+        }
+    }
+    let Some((token, rest)) = path.split_first() else {
+        return Some(schema);
+    };
+    let next = resolve(schema, &format!("/properties{}", format_pointer(&[token])))
+        .or_else(|_| resolve(schema, "/additionalProperties"))
+        .or_else(|_| resolve(schema, &format!("/prefixItems/{}", token)))
+        .or_else(|_| resolve(schema, "/items"))
+        .ok()?;
+    subschema(root, next, rest)
+}
+
+fn allowed_values(schema: &JSONElement) -> Vec<JSONElement> {
+    match (resolve(schema, "/enum"), resolve(schema, "/const")) {
+        (Ok(JSONElement::Array(values)), _) => values.clone(),
+        (_, Ok(value)) => vec![value.clone()],
+        _ if string_at(schema, "/type") == Some("boolean") => {
+            vec![JSONElement::Boolean(true), JSONElement::Boolean(false)]
+        }
+        _ => vec![],
+    }
+}
+
+fn associations(params: &JSONElement) -> Vec<SchemaAssociation> {
+    let Ok(JSONElement::Array(schemas)) = resolve(params, "/initializationOptions/schemas") else {
+        return vec![];
+    };
+    schemas
+        .iter()
+        .filter_map(|schema| {
+            let patterns = match resolve(schema, "/fileMatch") {
+                Ok(JSONElement::Array(patterns)) => patterns
+                    .iter()
+                    .filter_map(|pattern| match pattern {
+                        JSONElement::String(pattern) => Some(pattern.clone()),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => vec![],
+            };
+            Some(SchemaAssociation {
+                patterns,
+                url: string_at(schema, "/url")?.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Matches a file name against a pattern where "*" stands for any run of characters
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            (0..=name.len())
+                .filter(|i| name.is_char_boundary(*i))
+                .any(|i| glob_matches(rest, &name[i..]))
+        }
+    }
+}
+
+// Where to read a schema from. Relative paths are relative to the document.
+fn schema_path(document_uri: &str, url: &str) -> Option<PathBuf> {
+    if let Some(path) = url.strip_prefix("file://") {
+        return Some(PathBuf::from(percent_decode(path)));
+    }
+    if url.contains("://") {
+        return None;
+    }
+    let document = PathBuf::from(percent_decode(document_uri.strip_prefix("file://")?));
+    Some(document.parent().unwrap_or(Path::new("/")).join(url))
+}
+
+fn range(index: &LineIndex, span: Span) -> JSONElement {
+    let position = |offset: usize| {
+        let Position { line, column } = index.utf16_position(offset);
+        object([
+            ("line", JSONElement::Number(line as i32 - 1)),
+            ("character", JSONElement::Number(column as i32 - 1)),
+        ])
+    };
+    object([("start", position(span.start)), ("end", position(span.end))])
+}
+
+fn completion(
+    label: &str,
+    kind: i32,
+    detail: Option<&str>,
+    text: &str,
+    range: JSONElement,
+) -> JSONElement {
+    let mut item = vec![
+        ("label", string(label)),
+        ("kind", JSONElement::Number(kind)),
+        (
+            "textEdit",
+            object([("range", range), ("newText", string(text))]),
+        ),
+    ];
+    if let Some(detail) = detail {
+        item.push(("detail", string(detail)));
+    }
+    object(item)
+}
+
+fn notification(method: &str, params: JSONElement) -> JSONElement {
+    object([
+        ("jsonrpc", string("2.0")),
+        ("method", string(method)),
+        ("params", params),
+    ])
+}
+
+fn error_response(id: JSONElement, (code, message): RpcError) -> JSONElement {
+    object([
+        ("jsonrpc", string("2.0")),
+        ("id", id),
+        (
+            "error",
+            object([
+                ("code", JSONElement::Number(code)),
+                ("message", string(message)),
+            ]),
+        ),
+    ])
+}
+
+fn object<'k>(members: impl IntoIterator<Item = (&'k str, JSONElement)>) -> JSONElement {
+    JSONElement::Object(
+        members
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn string(s: impl Into<String>) -> JSONElement {
+    JSONElement::String(s.into())
+}
+
+fn string_at<'e>(element: &'e JSONElement, pointer: &str) -> Option<&'e str> {
+    match resolve(element, pointer) {
+        Ok(JSONElement::String(s)) => Some(s),
+        _ => None,
+    }
+}
@@ -0,0 +1,339 @@
+#[cfg(test)]
+use crate::lsp::*;
+#[cfg(test)]
+use crate::pointer::resolve;
+#[cfg(test)]
+use crate::rustifier::{dumps, loads, JSONElement};
+#[cfg(test)]
+use std::io::Cursor;
+
+// Plays a whole session against the server and returns everything it sent back
+#[cfg(test)]
+fn session(messages: &[&str]) -> Vec<JSONElement> {
+    let mut input = String::new();
+    for message in messages {
+        input.push_str(&format!(
+            "Content-Length: {}\r\n\r\n{}",
+            message.len(),
+            message
+        ));
+    }
+    let mut output = vec![];
+    run(Cursor::new(input), &mut output).unwrap();
+    let mut output = Cursor::new(output);
+    std::iter::from_fn(|| read_message(&mut output).unwrap())
+        .map(|body| loads(&body).unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+fn open(uri: &str, text: &str) -> String {
+    format!(
+        r#"{{"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {{"textDocument": {{"uri": {}, "languageId": "json", "version": 1, "text": {}}}}}}}"#,
+        dumps(&JSONElement::String(uri.to_string())),
+        dumps(&JSONElement::String(text.to_string()))
+    )
+}
+
+#[cfg(test)]
+fn request(id: i32, method: &str, uri: &str, rest: &str) -> String {
+    format!(
+        r#"{{"jsonrpc": "2.0", "id": {}, "method": "{}", "params": {{"textDocument": {{"uri": "{}"}}{}}}}}"#,
+        id, method, uri, rest
+    )
+}
+
+#[cfg(test)]
+fn at(element: &JSONElement, pointer: &str) -> JSONElement {
+    resolve(element, pointer).unwrap().clone()
+}
+
+#[cfg(test)]
+const INITIALIZE: &str = r#"{"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {}}"#;
+#[cfg(test)]
+const SHUTDOWN: &str = r#"{"jsonrpc": "2.0", "id": 99, "method": "shutdown"}"#;
+#[cfg(test)]
+const EXIT: &str = r#"{"jsonrpc": "2.0", "method": "exit"}"#;
+
+#[test]
+fn initializes_and_shuts_down() {
+    let replies = session(&[
+        INITIALIZE,
+        r#"{"jsonrpc": "2.0", "method": "initialized", "params": {}}"#,
+        SHUTDOWN,
+        EXIT,
+        // Never read
+        INITIALIZE,
+    ]);
+    assert_eq!(replies.len(), 2);
+    assert_eq!(
+        at(&replies[0], "/result/capabilities/hoverProvider"),
+        JSONElement::Boolean(true)
+    );
+    assert_eq!(at(&replies[1], "/id"), JSONElement::Number(99));
+    assert_eq!(at(&replies[1], "/result"), JSONElement::Null);
+}
+
+#[test]
+fn reports_bad_requests() {
+    let replies = session(&[
+        "{not json",
+        r#"{"jsonrpc": "2.0", "id": 1, "method": "workspace/symbol", "params": {}}"#,
+        &request(2, "textDocument/hover", "file:///closed.json", ""),
+    ]);
+    assert_eq!(at(&replies[0], "/error/code"), JSONElement::Number(-32700));
+    assert_eq!(at(&replies[1], "/error/code"), JSONElement::Number(-32601));
+    assert_eq!(at(&replies[2], "/error/code"), JSONElement::Number(-32602));
+}
+
+#[test]
+fn publishes_diagnostics() {
+    let uri = "file:///a.json";
+    let change = r#"{"jsonrpc": "2.0", "method": "textDocument/didChange", "params": {"textDocument": {"uri": "file:///a.json", "version": 2}, "contentChanges": [{"text": "[1, 2]"}]}}"#;
+    let replies = session(&[&open(uri, "{\n  \"é\": [1 2],\n  \"b\": tru\n}"), change]);
+    assert_eq!(
+        at(&replies[0], "/method"),
+        JSONElement::String("textDocument/publishDiagnostics".to_string())
+    );
+    let JSONElement::Array(diagnostics) = at(&replies[0], "/params/diagnostics") else {
+        panic!("diagnostics should be an array");
+    };
+    assert_eq!(diagnostics.len(), 2);
+    // 0-based lines and UTF-16 columns
+    assert_eq!(
+        at(&diagnostics[0], "/range/start"),
+        loads(r#"{"line": 1, "character": 10}"#).unwrap()
+    );
+    assert_eq!(
+        at(&diagnostics[1], "/range/start/line"),
+        JSONElement::Number(2)
+    );
+    assert_eq!(
+        at(&replies[1], "/params/diagnostics"),
+        JSONElement::Array(vec![])
+    );
+}
+
+#[test]
+fn reads_fractional_numbers() {
+    let replies = session(&[
+        r#"{"jsonrpc": "2.0", "id": 3000000000, "method": "initialize", "params": {"ratio": 0.5}}"#,
+        &open("file:///a.json", "{\"price\": 1.5, \"big\": -2e40}"),
+    ]);
+    assert_eq!(at(&replies[0], "/id"), JSONElement::Float(3e9));
+    assert_eq!(
+        at(&replies[1], "/params/diagnostics"),
+        JSONElement::Array(vec![])
+    );
+}
+
+#[test]
+fn allows_comments_in_jsonc() {
+    let text = "// settings\n{\"a\": 1}";
+    let replies = session(&[
+        &open("file:///a.json", text),
+        &open("file:///a.jsonc", text),
+    ]);
+    assert_ne!(
+        at(&replies[0], "/params/diagnostics"),
+        JSONElement::Array(vec![])
+    );
+    assert_eq!(
+        at(&replies[1], "/params/diagnostics"),
+        JSONElement::Array(vec![])
+    );
+}
+
+#[test]
+fn hovers_with_the_pointer() {
+    let uri = "file:///a.json";
+    let text = "{\"server\": {\"a/b\": [true, null]}}";
+    let replies = session(&[
+        &open(uri, text),
+        &request(
+            1,
+            "textDocument/hover",
+            uri,
+            r#", "position": {"line": 0, "character": 27}"#,
+        ),
+        &request(
+            2,
+            "textDocument/hover",
+            uri,
+            r#", "position": {"line": 0, "character": 3}"#,
+        ),
+    ]);
+    assert_eq!(
+        at(&replies[1], "/result/contents/value"),
+        JSONElement::String("`/server/a~1b/1`".to_string())
+    );
+    assert_eq!(
+        at(&replies[1], "/result/range"),
+        loads(r#"{"start": {"line": 0, "character": 26}, "end": {"line": 0, "character": 30}}"#)
+            .unwrap()
+    );
+    // On a key
+    assert_eq!(
+        at(&replies[2], "/result/contents/value"),
+        JSONElement::String("`/server`".to_string())
+    );
+}
+
+#[test]
+fn outlines_documents() {
+    let uri = "file:///a.json";
+    let replies = session(&[
+        &open(uri, "{\n  \"name\": \"x\",\n  \"list\": [1, {}]\n}"),
+        &request(1, "textDocument/documentSymbol", uri, ""),
+    ]);
+    let symbols = at(&replies[1], "/result");
+    assert_eq!(at(&symbols, "/0/name"), JSONElement::String("name".into()));
+    assert_eq!(at(&symbols, "/0/kind"), JSONElement::Number(15));
+    assert_eq!(
+        at(&symbols, "/0/detail"),
+        JSONElement::String("\"x\"".into())
+    );
+    assert_eq!(at(&symbols, "/1/kind"), JSONElement::Number(18));
+    assert_eq!(
+        at(&symbols, "/1/selectionRange"),
+        loads(r#"{"start": {"line": 2, "character": 2}, "end": {"line": 2, "character": 8}}"#)
+            .unwrap()
+    );
+    assert_eq!(
+        at(&symbols, "/1/children/1"),
+        loads(
+            r#"{"name": "1", "kind": 19, "children": [],
+                "range": {"start": {"line": 2, "character": 14}, "end": {"line": 2, "character": 16}},
+                "selectionRange": {"start": {"line": 2, "character": 14}, "end": {"line": 2, "character": 16}}}"#
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn folds_containers_and_comments() {
+    let uri = "file:///a.jsonc";
+    let text = "{\n  /* a\n     b */\n  \"a\": [\n    1\n  ],\n  \"b\": [2]\n}";
+    let replies = session(&[
+        &open(uri, text),
+        &request(1, "textDocument/foldingRange", uri, ""),
+    ]);
+    assert_eq!(
+        at(&replies[1], "/result"),
+        loads(
+            r#"[{"startLine": 0, "endLine": 6},
+                {"startLine": 1, "endLine": 2, "kind": "comment"},
+                {"startLine": 3, "endLine": 4}]"#
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn formats_documents() {
+    let uri = "file:///a.jsonc";
+    let options = r#", "options": {"tabSize": 4, "insertSpaces": true}"#;
+    let replies = session(&[
+        &open(uri, "{\"a\": [1,2], // two\n\"b\": {}}"),
+        &request(1, "textDocument/formatting", uri, options),
+        &open("file:///b.json", "{\"a\": }"),
+        &request(2, "textDocument/formatting", "file:///b.json", options),
+    ]);
+    assert_eq!(
+        at(&replies[1], "/result/0/newText"),
+        JSONElement::String(
+            "{\n    \"a\": [\n        1,\n        2\n    ], // two\n    \"b\": {}\n}\n".to_string()
+        )
+    );
+    assert_eq!(
+        at(&replies[1], "/result/0/range/end"),
+        loads(r#"{"line": 1, "character": 8}"#).unwrap()
+    );
+    // Broken documents are left alone
+    assert_eq!(at(&replies[3], "/result"), JSONElement::Array(vec![]));
+}
+
+#[test]
+fn completes_from_a_schema() {
+    let directory = std::env::temp_dir().join("json_parser_lsp_completion");
+    std::fs::create_dir_all(&directory).unwrap();
+    std::fs::write(
+        directory.join("schema.json"),
+        r##"{
+            "type": "object",
+            "properties": {
+                "name": {"type": "string", "description": "Who it is"},
+                "level": {"$ref": "#/definitions/level"},
+                "debug": {"type": "boolean"},
+                "servers": {"type": "array", "items": {"$ref": "#"}}
+            },
+            "definitions": {"level": {"enum": ["low", "high"]}}
+        }"##,
+    )
+    .unwrap();
+    let uri = format!("file://{}/config.json", directory.display());
+    let initialize = r#"{"jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {"initializationOptions": {"schemas": [{"fileMatch": ["config*.json"], "url": "./schema.json"}]}}}"#;
+    let position = |line: i32, character: i32| {
+        format!(
+            r#", "position": {{"line": {}, "character": {}}}"#,
+            line, character
+        )
+    };
+    let text = "{\n  \"name\": \"x\",\n  \"level\": ,\n  \"servers\": [{\"deb\"}]\n}";
+    let replies = session(&[
+        initialize,
+        &open(&uri, text),
+        // Between members
+        &request(1, "textDocument/completion", &uri, &position(1, 14)),
+        // In a value
+        &request(2, "textDocument/completion", &uri, &position(2, 11)),
+        // In a partly typed key, in an object with the same schema
+        &request(3, "textDocument/completion", &uri, &position(3, 18)),
+        &request(
+            4,
+            "textDocument/completion",
+            "file:///other.json",
+            &position(0, 1),
+        ),
+        &open("file:///other.json", "{}"),
+        &request(
+            5,
+            "textDocument/completion",
+            "file:///other.json",
+            &position(0, 1),
+        ),
+    ]);
+    let labels = |reply: &JSONElement| match at(reply, "/result") {
+        JSONElement::Array(items) => items
+            .iter()
+            .map(|item| dumps(&at(item, "/label")))
+            .collect::<Vec<_>>(),
+        _ => panic!("completions should be an array"),
+    };
+    // Keys that are already there aren't suggested
+    assert_eq!(labels(&replies[2]), ["\"debug\""]);
+    assert_eq!(labels(&replies[3]), ["\"\\\"low\\\"\"", "\"\\\"high\\\"\""]);
+    assert_eq!(
+        at(&replies[3], "/result/1/textEdit/newText"),
+        JSONElement::String("\"high\"".to_string())
+    );
+    assert_eq!(
+        labels(&replies[4]),
+        ["\"debug\"", "\"level\"", "\"name\"", "\"servers\""]
+    );
+    assert_eq!(
+        at(&replies[4], "/result/0/textEdit"),
+        loads(
+            r#"{"newText": "\"debug\"",
+                "range": {"start": {"line": 3, "character": 15}, "end": {"line": 3, "character": 20}}}"#
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        at(&replies[4], "/result/2/detail"),
+        JSONElement::String("Who it is".into())
+    );
+    assert_eq!(at(&replies[5], "/error/code"), JSONElement::Number(-32602));
+    // No schema for this one
+    assert_eq!(at(&replies[7], "/result"), JSONElement::Array(vec![]));
+}
@@ -1,37 +1,14 @@
-#[macro_use]
-extern crate lazy_static;
-
-mod ast_parser;
-mod rustifier;
-
-use crate::ast_parser::*;
-use crate::rustifier::*;
-use std::collections::HashMap;
-
-type NumberType = i32;
+use std::io::{stderr, stdin, stdout, Write};
 
 fn main() {
-    let actual = loads(
-        r#"[
-    "Dog",
-    2, 
-    false, 
-    ["frank"], 
-    {"sing": 55},
-    null,
-    ]"#,
-    )
-    .unwrap();
-    let expected = JSONElement::Array(vec![
-        JSONElement::String(String::from("Dog")),
-        JSONElement::Number(2),
-        JSONElement::Boolean(false),
-        JSONElement::Array(vec![JSONElement::String(String::from("frank"))]),
-        JSONElement::Object(HashMap::from([(
-            String::from("sing"),
-            JSONElement::Number(55),
-        )])),
-        JSONElement::Null,
-    ]);
-    assert_eq!(actual, expected);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let code = json_parser::cli::run(
+        &args,
+        &mut stdin().lock(),
+        &mut stdout().lock(),
+        &mut stderr().lock(),
+    );
+    // Exiting skips the usual flush at the end of main
+    let _ = stdout().flush();
+    std::process::exit(code);
 }
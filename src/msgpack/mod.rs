@@ -0,0 +1,328 @@
+use crate::cbor::{bytes_to_string, float_element, integer_element, BytesPolicy, KeyPolicy};
+use crate::rustifier::{dumps, JSONElement};
+use std::collections::HashMap;
+
+mod tests;
+
+// Nesting deeper than this is rejected rather than risking the stack
+const MAX_DEPTH: usize = 256;
+
+// The extension type MessagePack reserves for timestamps
+const TIMESTAMP: i8 = -1;
+
+/// What happens to str values that aren't valid UTF-8, which some older writers produce
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StringPolicy {
+    #[default]
+    Reject,
+    // Invalid sequences become U+FFFD
+    Replace,
+    // Treat the string as binary and follow the bytes policy
+    Bytes,
+}
+
+/// What happens to extension types other than timestamps, which always become a number of
+/// seconds since the epoch
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ExtensionPolicy {
+    // An object like {"type": 5, "data": "AQI"}, with the data written by the bytes policy
+    #[default]
+    Object,
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MessagePackOptions {
+    // How bin values become strings
+    pub bytes: BytesPolicy,
+    // What happens to map keys that aren't strings
+    pub keys: KeyPolicy,
+    pub strings: StringPolicy,
+    pub extensions: ExtensionPolicy,
+}
+
+/// Encodes each value in the smallest MessagePack type that holds it: fixints, fixstrs,
+/// fixarrays and fixmaps where they fit, and floats as float 32 when that keeps their value.
+/// Object keys are sorted so the output is deterministic.
+pub fn to_msgpack(element: &JSONElement) -> Vec<u8> {
+    let mut output = vec![];
+    encode(&mut output, element);
+    output
+}
+
+/// Decodes a single MessagePack value. Integers that don't fit in a NumberType become floats,
+/// and NaN and the infinities become null.
+pub fn from_msgpack(bytes: &[u8], options: &MessagePackOptions) -> Result<JSONElement, String> {
+    let mut decoder = Decoder {
+        bytes,
+        position: 0,
+        options,
+    };
+    let element = decoder.element(0)?;
+    if decoder.position < bytes.len() {
+        return Err(format!(
+            "{} bytes were left over after the MessagePack value",
+            bytes.len() - decoder.position
+        ));
+    }
+    Ok(element)
+}
+
+fn encode_integer(output: &mut Vec<u8>, n: i32) {
+    match n {
+        -32..=127 => output.push(n as u8),
+        128..=0xff => output.extend([0xcc, n as u8]),
+        0x100..=0xffff => {
+            output.push(0xcd);
+            output.extend((n as u16).to_be_bytes());
+        }
+        0x10000.. => {
+            output.push(0xce);
+            output.extend((n as u32).to_be_bytes());
+        }
+        -0x80..=-33 => output.extend([0xd0, n as u8]),
+        -0x8000..=-0x81 => {
+            output.push(0xd1);
+            output.extend((n as i16).to_be_bytes());
+        }
+        _ => {
+            output.push(0xd2);
+            output.extend(n.to_be_bytes());
+        }
+    }
+}
+
+// The header for a str, array or map: the fix form when `length` fits in it, and otherwise
+// the first of the three markers that is followed by the smallest length that fits
+fn write_length(output: &mut Vec<u8>, fix: u8, fix_limit: usize, markers: [u8; 3], length: usize) {
+    if length < fix_limit {
+        output.push(fix | length as u8);
+    } else if length <= 0xff && markers[0] != 0 {
+        output.extend([markers[0], length as u8]);
+    } else if length <= 0xffff {
+        output.push(markers[1]);
+        output.extend((length as u16).to_be_bytes());
+    } else {
+        output.push(markers[2]);
+        output.extend((length as u32).to_be_bytes());
+    }
+}
+
+fn encode_string(output: &mut Vec<u8>, s: &str) {
+    write_length(output, 0xa0, 32, [0xd9, 0xda, 0xdb], s.len());
+    output.extend(s.as_bytes());
+}
+
+fn encode(output: &mut Vec<u8>, element: &JSONElement) {
+    match element {
+        JSONElement::Null => output.push(0xc0),
+        JSONElement::Boolean(b) => output.push(if *b { 0xc3 } else { 0xc2 }),
+        JSONElement::Number(n) => encode_integer(output, *n),
+        // NaN survives the trip through float 32, even if its payload doesn't
+        JSONElement::Float(f) if (*f as f32) as f64 == *f || f.is_nan() => {
+            output.push(0xca);
+            output.extend((*f as f32).to_be_bytes());
+        }
+        JSONElement::Float(f) => {
+            output.push(0xcb);
+            output.extend(f.to_be_bytes());
+        }
+        JSONElement::String(s) => encode_string(output, s),
+        JSONElement::Array(elements) => {
+            // There's no array 8
+            write_length(output, 0x90, 16, [0, 0xdc, 0xdd], elements.len());
+            for element in elements {
+                encode(output, element);
+            }
+        }
+        JSONElement::Object(members) => {
+            let mut members: Vec<_> = members.iter().collect();
+            members.sort_by_key(|(key, _)| *key);
+            write_length(output, 0x80, 16, [0, 0xde, 0xdf], members.len());
+            for (key, value) in members {
+                encode_string(output, key);
+                encode(output, value);
+            }
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    options: &'a MessagePackOptions,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if count > self.bytes.len() - self.position {
+            return Err(format!(
+                "MessagePack ended at byte {}, in the middle of a value",
+                self.bytes.len()
+            ));
+        }
+        let taken = &self.bytes[self.position..self.position + count];
+        self.position += count;
+        Ok(taken)
+    }
+
+    // A big-endian unsigned integer of `size` bytes
+    fn unsigned(&mut self, size: usize) -> Result<u64, String> {
+        Ok(self
+            .take(size)?
+            .iter()
+            .fold(0, |n, byte| n << 8 | *byte as u64))
+    }
+
+    fn signed(&mut self, size: usize) -> Result<i64, String> {
+        let shift = 64 - 8 * size as u32;
+        Ok((self.unsigned(size)? << shift) as i64 >> shift)
+    }
+
+    fn element(&mut self, depth: usize) -> Result<JSONElement, String> {
+        if depth > MAX_DEPTH {
+            return Err(format!(
+                "MessagePack is nested more than {} deep",
+                MAX_DEPTH
+            ));
+        }
+        let start = self.position;
+        let marker = self.take(1)?[0];
+        Ok(match marker {
+            0x00..=0x7f => JSONElement::Number(marker as i32),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize, depth)?,
+            0x90..=0x9f => self.array((marker & 0x0f) as usize, depth)?,
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize, start)?,
+            0xc0 => JSONElement::Null,
+            0xc2 => JSONElement::Boolean(false),
+            0xc3 => JSONElement::Boolean(true),
+            0xc4..=0xc6 => {
+                let length = self.unsigned(1 << (marker - 0xc4))? as usize;
+                JSONElement::String(bytes_to_string(self.take(length)?, self.options.bytes)?)
+            }
+            0xc7..=0xc9 => {
+                let length = self.unsigned(1 << (marker - 0xc7))? as usize;
+                self.extension(length, start)?
+            }
+            0xca => float_element(f32::from_bits(self.unsigned(4)? as u32) as f64),
+            0xcb => float_element(f64::from_bits(self.unsigned(8)?)),
+            0xcc..=0xcf => integer_element(self.unsigned(1 << (marker - 0xcc))? as i128),
+            0xd0..=0xd3 => integer_element(self.signed(1 << (marker - 0xd0))? as i128),
+            0xd4..=0xd8 => self.extension(1 << (marker - 0xd4), start)?,
+            0xd9..=0xdb => {
+                let length = self.unsigned(1 << (marker - 0xd9))? as usize;
+                self.string(length, start)?
+            }
+            0xdc | 0xdd => {
+                let length = self.unsigned(2 << (marker - 0xdc))? as usize;
+                self.array(length, depth)?
+            }
+            0xde | 0xdf => {
+                let length = self.unsigned(2 << (marker - 0xde))? as usize;
+                self.map(length, depth)?
+            }
+            0xe0..=0xff => JSONElement::Number(marker as i8 as i32),
+            0xc1 => {
+                return Err(format!(
+                    "MessagePack byte {} is the unused marker 0xc1",
+                    start
+                ))
+            }
+        })
+    }
+
+    fn string(&mut self, length: usize, start: usize) -> Result<JSONElement, String> {
+        let bytes = self.take(length)?;
+        let s = match (std::str::from_utf8(bytes), self.options.strings) {
+            (Ok(s), _) => s.to_string(),
+            (Err(_), StringPolicy::Reject) => {
+                return Err(format!("MessagePack string at byte {} isn't UTF-8", start))
+            }
+            (Err(_), StringPolicy::Replace) => String::from_utf8_lossy(bytes).into_owned(),
+            (Err(_), StringPolicy::Bytes) => bytes_to_string(bytes, self.options.bytes)?,
+        };
+        Ok(JSONElement::String(s))
+    }
+
+    fn array(&mut self, length: usize, depth: usize) -> Result<JSONElement, String> {
+        let mut elements = vec![];
+        for _ in 0..length {
+            elements.push(self.element(depth + 1)?);
+        }
+        Ok(JSONElement::Array(elements))
+    }
+
+    fn map(&mut self, length: usize, depth: usize) -> Result<JSONElement, String> {
+        let mut members = HashMap::new();
+        for _ in 0..length {
+            let start = self.position;
+            let is_string = self
+                .bytes
+                .get(start)
+                .is_some_and(|marker| matches!(marker, 0xa0..=0xbf | 0xd9..=0xdb));
+            let key = match self.element(depth + 1)? {
+                JSONElement::String(key) if is_string => key,
+                _ if self.options.keys == KeyPolicy::Reject => {
+                    return Err(format!(
+                        "MessagePack map key at byte {} isn't a string",
+                        start
+                    ))
+                }
+                JSONElement::String(key) => key,
+                key => dumps(&key),
+            };
+            let value = self.element(depth + 1)?;
+            if members.insert(key.clone(), value).is_some() {
+                return Err(format!(
+                    "MessagePack map has the key {:?} more than once",
+                    key
+                ));
+            }
+        }
+        Ok(JSONElement::Object(members))
+    }
+
+    // The type byte and the data of an extension, whose marker started at `start`
+    fn extension(&mut self, length: usize, start: usize) -> Result<JSONElement, String> {
+        let kind = self.take(1)?[0] as i8;
+        let data = self.take(length)?;
+        if kind == TIMESTAMP {
+            return timestamp(data)
+                .ok_or_else(|| format!("MessagePack timestamp at byte {} is invalid", start));
+        }
+        match self.options.extensions {
+            ExtensionPolicy::Object => Ok(JSONElement::Object(HashMap::from([
+                ("type".to_string(), JSONElement::Number(kind as i32)),
+                (
+                    "data".to_string(),
+                    JSONElement::String(bytes_to_string(data, self.options.bytes)?),
+                ),
+            ]))),
+            ExtensionPolicy::Reject => Err(format!(
+                "MessagePack extension type {} at byte {} isn't supported",
+                kind, start
+            )),
+        }
+    }
+}
+
+// Seconds since the epoch, from any of the three timestamp formats
+fn timestamp(data: &[u8]) -> Option<JSONElement> {
+    let number = |bytes: &[u8]| bytes.iter().fold(0u64, |n, byte| n << 8 | *byte as u64);
+    let (seconds, nanoseconds) = match data.len() {
+        4 => (number(data) as i64, 0),
+        8 => {
+            let packed = number(data);
+            ((packed & 0x3_ffff_ffff) as i64, packed >> 34)
+        }
+        12 => (number(&data[4..]) as i64, number(&data[..4])),
+        _ => return None,
+    };
+    if nanoseconds >= 1_000_000_000 {
+        return None;
+    }
+    Some(match nanoseconds {
+        0 => integer_element(seconds as i128),
+        _ => JSONElement::Float(seconds as f64 + nanoseconds as f64 / 1e9),
+    })
+}
@@ -0,0 +1,205 @@
+#[cfg(test)]
+use crate::cbor::{BytesPolicy, KeyPolicy};
+#[cfg(test)]
+use crate::msgpack::*;
+#[cfg(test)]
+use crate::rustifier::loads_json5;
+
+#[cfg(test)]
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+fn unhex(text: &str) -> Vec<u8> {
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+fn decode(text: &str) -> Result<JSONElement, String> {
+    from_msgpack(&unhex(text), &MessagePackOptions::default())
+}
+
+// Encodes and checks the first bytes, then checks it decodes back to the same element
+#[cfg(test)]
+fn assert_round_trip(element: JSONElement, prefix: &str) {
+    let encoded = to_msgpack(&element);
+    assert!(
+        hex(&encoded).starts_with(prefix),
+        "{} doesn't start with {}",
+        hex(&encoded),
+        prefix
+    );
+    assert_eq!(
+        from_msgpack(&encoded, &MessagePackOptions::default()),
+        Ok(element)
+    );
+}
+
+#[test]
+fn round_trips_integers_at_every_boundary() {
+    for (n, expected) in [
+        (0, "00"),
+        (127, "7f"),
+        (128, "cc80"),
+        (255, "ccff"),
+        (256, "cd0100"),
+        (65535, "cdffff"),
+        (65536, "ce00010000"),
+        (i32::MAX, "ce7fffffff"),
+        (-1, "ff"),
+        (-32, "e0"),
+        (-33, "d0df"),
+        (-128, "d080"),
+        (-129, "d1ff7f"),
+        (-32768, "d18000"),
+        (-32769, "d2ffff7fff"),
+        (i32::MIN, "d280000000"),
+    ] {
+        assert_eq!(hex(&to_msgpack(&JSONElement::Number(n))), expected);
+        assert_round_trip(JSONElement::Number(n), expected);
+    }
+}
+
+#[test]
+fn round_trips_every_variant() {
+    assert_round_trip(JSONElement::Null, "c0");
+    assert_round_trip(JSONElement::Boolean(false), "c2");
+    assert_round_trip(JSONElement::Boolean(true), "c3");
+    assert_round_trip(JSONElement::Float(1.5), "ca3fc00000");
+    assert_round_trip(JSONElement::Float(-0.0), "ca80000000");
+    assert_round_trip(JSONElement::Float(1.1), "cb3ff199999999999a");
+    assert_round_trip(JSONElement::Float(1e300), "cb7e37e43c8800759c");
+    for (length, prefix) in [
+        (0, "a0"),
+        (31, "bf"),
+        (32, "d920"),
+        (255, "d9ff"),
+        (256, "da0100"),
+        (65535, "daffff"),
+        (65536, "db00010000"),
+    ] {
+        assert_round_trip(JSONElement::String("x".repeat(length)), prefix);
+    }
+    assert_round_trip(JSONElement::String("caf\u{e9} \u{1f600}".to_string()), "aa");
+    for (length, prefix) in [
+        (0, "90"),
+        (15, "9f"),
+        (16, "dc0010"),
+        (65535, "dcffff"),
+        (65536, "dd00010000"),
+    ] {
+        assert_round_trip(JSONElement::Array(vec![JSONElement::Null; length]), prefix);
+    }
+    for (length, prefix) in [(0, "80"), (15, "8f"), (16, "de0010"), (65536, "df00010000")] {
+        let members = (0..length)
+            .map(|i| (i.to_string(), JSONElement::Number(i)))
+            .collect();
+        assert_round_trip(JSONElement::Object(members), prefix);
+    }
+    let document = loads_json5(
+        r#"{"name": "json", "tags": ["a", "b"], "nested": {"list": [1, -2.5, null, true]}}"#,
+    )
+    .unwrap();
+    assert_round_trip(document, "83");
+    // Keys are written in order
+    assert_eq!(
+        hex(&to_msgpack(&loads_json5(r#"{"b": 1, "a": 2}"#).unwrap())),
+        "82a16102a16201"
+    );
+}
+
+#[test]
+fn decodes_types_json_lacks() {
+    assert_eq!(
+        decode("cfffffffffffffffff"),
+        Ok(JSONElement::Float(18446744073709551615.0))
+    );
+    assert_eq!(
+        decode("d3fffffffeffffffff"),
+        Ok(JSONElement::Float(-4294967297.0))
+    );
+    assert_eq!(decode("d0ff"), Ok(JSONElement::Number(-1)));
+    assert_eq!(decode("ca7fc00000"), Ok(JSONElement::Null));
+    assert_eq!(
+        decode("c403010203"),
+        Ok(JSONElement::String("AQID".to_string()))
+    );
+    assert_eq!(
+        decode("d40501"),
+        Ok(loads_json5(r#"{"type": 5, "data": "AQ"}"#).unwrap())
+    );
+    assert_eq!(
+        decode("c701f0ff"),
+        Ok(loads_json5(r#"{"type": -16, "data": "_w"}"#).unwrap())
+    );
+    // The three timestamp formats
+    assert_eq!(decode("d6ff00000001"), Ok(JSONElement::Number(1)));
+    assert_eq!(decode("d7ff7735940000000001"), Ok(JSONElement::Float(1.5)));
+    assert_eq!(
+        decode("c70cff00000000ffffffffffffffff"),
+        Ok(JSONElement::Number(-1))
+    );
+    assert!(decode("d7ffffffffff00000000").is_err());
+    assert_eq!(
+        decode("82c3a161c0a162"),
+        Ok(loads_json5(r#"{"true": "a", null: "b"}"#).unwrap())
+    );
+}
+
+#[test]
+fn applies_the_conversion_policies() {
+    let invalid = "a2c328";
+    assert!(decode(invalid).is_err());
+    let options = |strings, extensions| MessagePackOptions {
+        bytes: BytesPolicy::Hex,
+        keys: KeyPolicy::Reject,
+        strings,
+        extensions,
+    };
+    let replace = options(StringPolicy::Replace, ExtensionPolicy::Object);
+    assert_eq!(
+        from_msgpack(&unhex(invalid), &replace),
+        Ok(JSONElement::String("\u{fffd}(".to_string()))
+    );
+    let bytes = options(StringPolicy::Bytes, ExtensionPolicy::Reject);
+    assert_eq!(
+        from_msgpack(&unhex(invalid), &bytes),
+        Ok(JSONElement::String("c328".to_string()))
+    );
+    assert_eq!(
+        from_msgpack(&unhex("c403010203"), &bytes),
+        Ok(JSONElement::String("010203".to_string()))
+    );
+    assert!(from_msgpack(&unhex("d40501"), &bytes).is_err());
+    // Timestamps aren't affected by the extension policy
+    assert!(from_msgpack(&unhex("d6ff00000001"), &bytes).is_ok());
+    assert!(from_msgpack(&unhex("810102"), &bytes).is_err());
+}
+
+#[test]
+fn rejects_malformed_input() {
+    for input in [
+        "",
+        "c1",
+        "cc",
+        "cd01",
+        "a2",
+        "a261",
+        "92",
+        "c40401",
+        "d405",
+        "0000",
+        "82a16101a16102",
+    ] {
+        assert!(decode(input).is_err(), "{}", input);
+    }
+    // Claimed lengths far beyond the input don't allocate
+    assert!(decode("ddffffffff").is_err());
+    assert!(decode("dbffffffff").is_err());
+    assert!(decode(&"91".repeat(10000)).is_err());
+    assert!(decode(&format!("{}c0", "91".repeat(200))).is_ok());
+}
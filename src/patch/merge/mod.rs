@@ -0,0 +1,251 @@
+use crate::pointer::format_pointer;
+use crate::rustifier::JSONElement;
+use std::collections::HashMap;
+use std::fmt;
+
+mod tests;
+
+/// Applies an RFC 7396 merge patch. Objects in the patch are merged into the target member by
+/// member, a null member removes that member, and anything else replaces the target whole.
+pub fn apply_merge_patch(target: &JSONElement, patch: &JSONElement) -> JSONElement {
+    let JSONElement::Object(changes) = patch else {
+        return patch.clone();
+    };
+    let mut members = match target {
+        JSONElement::Object(members) => members.clone(),
+        _ => HashMap::new(),
+    };
+    for (key, change) in changes {
+        match change {
+            JSONElement::Null => {
+                members.remove(key);
+            }
+            _ => {
+                let merged =
+                    apply_merge_patch(members.get(key).unwrap_or(&JSONElement::Null), change);
+                members.insert(key.clone(), merged);
+            }
+        }
+    }
+    JSONElement::Object(members)
+}
+
+/// The merge patch that turns `from` into `to`. Merge patches use null to remove members, so
+/// there's no patch when `to` has an object with a null member (unless `from` already has that
+/// member as null, and there's nothing to change).
+pub fn merge_patch(from: &JSONElement, to: &JSONElement) -> Result<JSONElement, String> {
+    generate(from, to, &mut vec![])
+}
+
+fn generate(
+    from: &JSONElement,
+    to: &JSONElement,
+    path: &mut Vec<String>,
+) -> Result<JSONElement, String> {
+    let (JSONElement::Object(from), JSONElement::Object(to)) = (from, to) else {
+        return replacement(to, path);
+    };
+    let mut changes = HashMap::new();
+    for key in from.keys() {
+        if !to.contains_key(key) {
+            changes.insert(key.clone(), JSONElement::Null);
+        }
+    }
+    for (key, value) in to {
+        path.push(key.clone());
+        match from.get(key) {
+            Some(old) if old == value => {}
+            Some(old) => {
+                changes.insert(key.clone(), generate(old, value, path)?);
+            }
+            None => {
+                changes.insert(key.clone(), replacement(value, path)?);
+            }
+        }
+        path.pop();
+    }
+    Ok(JSONElement::Object(changes))
+}
+
+// A patch that sets the value at `path` to `value`, whatever was there before
+fn replacement(value: &JSONElement, path: &mut Vec<String>) -> Result<JSONElement, String> {
+    match value {
+        JSONElement::Null if !path.is_empty() => Err(format!(
+            "A merge patch can't set {} to null",
+            format_pointer(path)
+        )),
+        // Only reached when there isn't an object there already, so there are no old members
+        // to clear out
+        JSONElement::Object(members) => {
+            let mut changes = HashMap::new();
+            for (key, value) in members {
+                path.push(key.clone());
+                changes.insert(key.clone(), replacement(value, path)?);
+                path.pop();
+            }
+            Ok(JSONElement::Object(changes))
+        }
+        _ => Ok(value.clone()),
+    }
+}
+
+/// What `deep_merge` does when both documents have an array in the same place
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ArrayStrategy {
+    // The overlay's array replaces the base's
+    #[default]
+    Replace,
+    // The overlay's elements come after the base's
+    Concatenate,
+    // Objects with the same value for this member are merged, and the rest of the overlay's
+    // elements are added at the end
+    MergeByKey(String),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    pub arrays: ArrayStrategy,
+    // Whether a null in the overlay removes the member from the base, as in a merge patch,
+    // rather than setting it to null
+    pub null_removes: bool,
+}
+
+/// A place where the base and overlay have different kinds of value, so the overlay's value
+/// replaced the base's outright
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    // A JSON pointer into the merged document
+    pub path: String,
+    pub base: &'static str,
+    pub overlay: &'static str,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let path = if self.path.is_empty() {
+            "the root"
+        } else {
+            &self.path
+        };
+        write!(
+            f,
+            "{}: {} was replaced by {}",
+            path, self.base, self.overlay
+        )
+    }
+}
+
+/// Merges `overlay` on top of `base`: objects are merged member by member, arrays according
+/// to `options.arrays`, and anything else in the overlay wins. Also returns every conflict,
+/// where a value was replaced by a different kind of value.
+pub fn deep_merge(
+    base: &JSONElement,
+    overlay: &JSONElement,
+    options: &MergeOptions,
+) -> (JSONElement, Vec<Conflict>) {
+    let mut conflicts = vec![];
+    let merged = merge_into(base, overlay, options, &mut vec![], &mut conflicts);
+    (merged, conflicts)
+}
+
+/// Merges each layer on top of the ones before it, like defaults, then environment settings,
+/// then local overrides
+pub fn merge_layers<'a>(
+    layers: impl IntoIterator<Item = &'a JSONElement>,
+    options: &MergeOptions,
+) -> (JSONElement, Vec<Conflict>) {
+    let mut layers = layers.into_iter();
+    let mut merged = layers.next().cloned().unwrap_or(JSONElement::Null);
+    let mut conflicts = vec![];
+    for layer in layers {
+        merged = merge_into(&merged, layer, options, &mut vec![], &mut conflicts);
+    }
+    (merged, conflicts)
+}
+
+fn merge_into(
+    base: &JSONElement,
+    overlay: &JSONElement,
+    options: &MergeOptions,
+    path: &mut Vec<String>,
+    conflicts: &mut Vec<Conflict>,
+) -> JSONElement {
+    match (base, overlay) {
+        (JSONElement::Object(base), JSONElement::Object(overlay)) => {
+            let mut members = base.clone();
+            let mut keys: Vec<&String> = overlay.keys().collect();
+            keys.sort();
+            for key in keys {
+                let value = &overlay[key];
+                if options.null_removes && *value == JSONElement::Null {
+                    members.remove(key);
+                    continue;
+                }
+                let merged = match base.get(key) {
+                    Some(old) => {
+                        path.push(key.clone());
+                        let merged = merge_into(old, value, options, path, conflicts);
+                        path.pop();
+                        merged
+                    }
+                    None => value.clone(),
+                };
+                members.insert(key.clone(), merged);
+            }
+            JSONElement::Object(members)
+        }
+        (JSONElement::Array(base), JSONElement::Array(overlay)) => match &options.arrays {
+            ArrayStrategy::Replace => JSONElement::Array(overlay.clone()),
+            ArrayStrategy::Concatenate => {
+                JSONElement::Array(base.iter().chain(overlay).cloned().collect())
+            }
+            ArrayStrategy::MergeByKey(key) => {
+                let mut items = base.clone();
+                for item in overlay {
+                    let id = match item {
+                        JSONElement::Object(members) => members.get(key),
+                        _ => None,
+                    };
+                    let matching = id.and_then(|id| {
+                        items.iter().position(|old| match old {
+                            JSONElement::Object(members) => members.get(key) == Some(id),
+                            _ => false,
+                        })
+                    });
+                    match matching {
+                        Some(i) => {
+                            path.push(i.to_string());
+                            items[i] = merge_into(&items[i], item, options, path, conflicts);
+                            path.pop();
+                        }
+                        None => items.push(item.clone()),
+                    }
+                }
+                JSONElement::Array(items)
+            }
+        },
+        // Null stands for "no value" rather than a kind of its own
+        (JSONElement::Null, _) | (_, JSONElement::Null) => overlay.clone(),
+        _ => {
+            if kind(base) != kind(overlay) {
+                conflicts.push(Conflict {
+                    path: format_pointer(path),
+                    base: kind(base),
+                    overlay: kind(overlay),
+                });
+            }
+            overlay.clone()
+        }
+    }
+}
+
+fn kind(element: &JSONElement) -> &'static str {
+    match element {
+        JSONElement::Object(_) => "an object",
+        JSONElement::Array(_) => "an array",
+        JSONElement::String(_) => "a string",
+        JSONElement::Number(_) | JSONElement::Float(_) => "a number",
+        JSONElement::Boolean(_) => "a boolean",
+        JSONElement::Null => "null",
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+use crate::patch::merge::*;
+#[cfg(test)]
+use crate::rustifier::loads;
+
+// The examples from RFC 7396 appendix A
+#[test]
+fn applies_merge_patches() {
+    for (target, patch, result) in [
+        (r#"{"a":"b"}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+        (r#"{"a":"b"}"#, r#"{"b":"c"}"#, r#"{"a":"b","b":"c"}"#),
+        (r#"{"a":"b"}"#, r#"{"a":null}"#, r#"{}"#),
+        (r#"{"a":"b","b":"c"}"#, r#"{"a":null}"#, r#"{"b":"c"}"#),
+        (r#"{"a":["b"]}"#, r#"{"a":"c"}"#, r#"{"a":"c"}"#),
+        (r#"{"a":"c"}"#, r#"{"a":["b"]}"#, r#"{"a":["b"]}"#),
+        (
+            r#"{"a":{"b":"c"}}"#,
+            r#"{"a":{"b":"d","c":null}}"#,
+            r#"{"a":{"b":"d"}}"#,
+        ),
+        (r#"{"a":[{"b":"c"}]}"#, r#"{"a":[1]}"#, r#"{"a":[1]}"#),
+        (r#"["a","b"]"#, r#"["c","d"]"#, r#"["c","d"]"#),
+        (r#"{"a":"b"}"#, r#"["c"]"#, r#"["c"]"#),
+        (r#"{"a":"foo"}"#, "null", "null"),
+        (r#"{"a":"foo"}"#, r#""bar""#, r#""bar""#),
+        (r#"{"e":null}"#, r#"{"a":1}"#, r#"{"e":null,"a":1}"#),
+        ("[1,2]", r#"{"a":"b","c":null}"#, r#"{"a":"b"}"#),
+        ("{}", r#"{"a":{"bb":{"ccc":null}}}"#, r#"{"a":{"bb":{}}}"#),
+    ] {
+        assert_eq!(
+            apply_merge_patch(&loads(target).unwrap(), &loads(patch).unwrap()),
+            loads(result).unwrap(),
+            "{} with {}",
+            target,
+            patch
+        );
+    }
+}
+
+#[test]
+fn generates_merge_patches() {
+    let from = loads(r#"{"a": {"b": 1, "c": 2}, "d": [1], "e": "x", "f": 1}"#).unwrap();
+    let to = loads(r#"{"a": {"b": 1, "c": 3}, "d": [1, 2], "e": {"g": true}, "f": 1}"#).unwrap();
+    let patch = merge_patch(&from, &to).unwrap();
+    assert_eq!(
+        patch,
+        loads(r#"{"a": {"c": 3}, "d": [1, 2], "e": {"g": true}}"#).unwrap()
+    );
+    assert_eq!(apply_merge_patch(&from, &patch), to);
+    assert_eq!(
+        merge_patch(&from, &loads(r#"{"a": {"b": null}}"#).unwrap()).unwrap_err(),
+        "A merge patch can't set /a/b to null"
+    );
+    assert_eq!(
+        merge_patch(&loads("1").unwrap(), &loads(r#"{"a": [null]}"#).unwrap()),
+        Ok(loads(r#"{"a": [null]}"#).unwrap())
+    );
+}
+
+#[test]
+fn merges_layers() {
+    let defaults = loads(
+        r#"{"port": 80, "log": {"level": "info", "file": null}, "hosts": ["a"],
+            "users": [{"name": "root", "shell": "sh"}]}"#,
+    )
+    .unwrap();
+    let environment = loads(
+        r#"{"log": {"level": "debug"}, "hosts": ["b"],
+            "users": [{"name": "root", "shell": "bash"}, {"name": "ci"}]}"#,
+    )
+    .unwrap();
+    let local = loads(r#"{"port": "8080", "log": {"file": "out.log"}}"#).unwrap();
+    let options = MergeOptions {
+        arrays: ArrayStrategy::MergeByKey("name".to_string()),
+        null_removes: false,
+    };
+    let (merged, conflicts) = merge_layers([&defaults, &environment, &local], &options);
+    assert_eq!(
+        merged,
+        loads(
+            r#"{"port": "8080", "log": {"level": "debug", "file": "out.log"}, "hosts": ["a", "b"],
+                "users": [{"name": "root", "shell": "bash"}, {"name": "ci"}]}"#
+        )
+        .unwrap()
+    );
+    assert_eq!(
+        conflicts
+            .iter()
+            .map(|conflict| conflict.to_string())
+            .collect::<Vec<_>>(),
+        ["/port: a number was replaced by a string"]
+    );
+}
+
+#[test]
+fn merges_arrays_by_strategy() {
+    let base = loads(r#"{"a": [1, 2], "b": null}"#).unwrap();
+    let overlay = loads(r#"{"a": [3], "b": {"c": 1}, "d": null}"#).unwrap();
+    let merge = |options: MergeOptions| deep_merge(&base, &overlay, &options).0;
+    assert_eq!(
+        merge(MergeOptions::default()),
+        loads(r#"{"a": [3], "b": {"c": 1}, "d": null}"#).unwrap()
+    );
+    assert_eq!(
+        merge(MergeOptions {
+            arrays: ArrayStrategy::Concatenate,
+            null_removes: true,
+        }),
+        loads(r#"{"a": [1, 2, 3], "b": {"c": 1}}"#).unwrap()
+    );
+    let (_, conflicts) = deep_merge(
+        &loads(r#"{"a": {"b": [1]}}"#).unwrap(),
+        &loads(r#"{"a": {"b": {}}}"#).unwrap(),
+        &MergeOptions::default(),
+    );
+    assert_eq!(
+        conflicts,
+        [Conflict {
+            path: "/a/b".to_string(),
+            base: "an array",
+            overlay: "an object"
+        }]
+    );
+}
@@ -0,0 +1,437 @@
+use crate::pointer::{format_pointer, parse_index, parse_pointer};
+use crate::rustifier::{dumps, from_member, FromElement, JSONElement};
+use crate::schema::equal;
+use std::collections::HashMap;
+use std::fmt;
+
+pub mod merge;
+mod tests;
+
+/// One operation of an RFC 6902 JSON Patch. Paths are JSON pointers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operation {
+    Add { path: String, value: JSONElement },
+    Remove { path: String },
+    Replace { path: String, value: JSONElement },
+    Move { from: String, path: String },
+    Copy { from: String, path: String },
+    Test { path: String, value: JSONElement },
+}
+
+impl FromElement for Operation {
+    fn from_element(element: &JSONElement) -> Result<Operation, String> {
+        let JSONElement::Object(members) = element else {
+            return Err("A patch operation must be an object".to_string());
+        };
+        let path = from_member(members, "path")?;
+        // Unlike other members, a value of null has to be there
+        let value = || match members.get("value") {
+            Some(value) => Ok(value.clone()),
+            None => Err("Missing required member value".to_string()),
+        };
+        match from_member::<String>(members, "op")?.as_str() {
+            "add" => Ok(Operation::Add {
+                path,
+                value: value()?,
+            }),
+            "remove" => Ok(Operation::Remove { path }),
+            "replace" => Ok(Operation::Replace {
+                path,
+                value: value()?,
+            }),
+            "move" => Ok(Operation::Move {
+                from: from_member(members, "from")?,
+                path,
+            }),
+            "copy" => Ok(Operation::Copy {
+                from: from_member(members, "from")?,
+                path,
+            }),
+            "test" => Ok(Operation::Test {
+                path,
+                value: value()?,
+            }),
+            op => Err(format!("{} is not a patch operation", op)),
+        }
+    }
+}
+
+impl Operation {
+    pub fn to_element(&self) -> JSONElement {
+        let string = |s: &str| JSONElement::String(s.to_string());
+        let (op, path, from, value) = match self {
+            Operation::Add { path, value } => ("add", path, None, Some(value)),
+            Operation::Remove { path } => ("remove", path, None, None),
+            Operation::Replace { path, value } => ("replace", path, None, Some(value)),
+            Operation::Move { from, path } => ("move", path, Some(from), None),
+            Operation::Copy { from, path } => ("copy", path, Some(from), None),
+            Operation::Test { path, value } => ("test", path, None, Some(value)),
+        };
+        let mut members = HashMap::from([
+            ("op".to_string(), string(op)),
+            ("path".to_string(), string(path)),
+        ]);
+        if let Some(from) = from {
+            members.insert("from".to_string(), string(from));
+        }
+        if let Some(value) = value {
+            members.insert("value".to_string(), value.clone());
+        }
+        JSONElement::Object(members)
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Add { path, value } => write!(f, "add {} {}", path, dumps(value)),
+            Operation::Remove { path } => write!(f, "remove {}", path),
+            Operation::Replace { path, value } => write!(f, "replace {} {}", path, dumps(value)),
+            Operation::Move { from, path } => write!(f, "move {} to {}", from, path),
+            Operation::Copy { from, path } => write!(f, "copy {} to {}", from, path),
+            Operation::Test { path, value } => write!(f, "test {} {}", path, dumps(value)),
+        }
+    }
+}
+
+/// Reads a patch document: an array of operation objects
+pub fn parse_patch(patch: &JSONElement) -> Result<Vec<Operation>, String> {
+    Vec::<Operation>::from_element(patch)
+}
+
+pub fn patch_to_element(patch: &[Operation]) -> JSONElement {
+    JSONElement::Array(patch.iter().map(Operation::to_element).collect())
+}
+
+/// Applies every operation of `patch` to a copy of `document` in turn. If any of them fails
+/// (including a failed test) the whole patch fails, and `document` is left as it was.
+pub fn apply_patch(document: &JSONElement, patch: &[Operation]) -> Result<JSONElement, String> {
+    let mut document = document.clone();
+    for (i, operation) in patch.iter().enumerate() {
+        apply_operation(&mut document, operation)
+            .map_err(|err| format!("Operation {} ({}) failed: {}", i, operation, err))?;
+    }
+    Ok(document)
+}
+
+fn apply_operation(document: &mut JSONElement, operation: &Operation) -> Result<(), String> {
+    match operation {
+        Operation::Add { path, value } => add(document, path, value.clone()),
+        Operation::Remove { path } => remove(document, path).map(|_| ()),
+        Operation::Replace { path, value } => {
+            *get_mut(document, &parse_pointer(path)?, path)? = value.clone();
+            Ok(())
+        }
+        Operation::Move { from, path } => {
+            let from_tokens = parse_pointer(from)?;
+            let tokens = parse_pointer(path)?;
+            if tokens.len() > from_tokens.len() && tokens.starts_with(&from_tokens) {
+                return Err(format!("Can't move {} into itself", from));
+            }
+            if from == path {
+                return get_mut(document, &from_tokens, from).map(|_| ());
+            }
+            let value = remove(document, from)?;
+            add(document, path, value)
+        }
+        Operation::Copy { from, path } => {
+            let value = get_mut(document, &parse_pointer(from)?, from)?.clone();
+            add(document, path, value)
+        }
+        Operation::Test { path, value } => {
+            let actual = get_mut(document, &parse_pointer(path)?, path)?;
+            if equal(actual, value) {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{} is {}, not {}",
+                    path,
+                    dumps(actual),
+                    dumps(value)
+                ))
+            }
+        }
+    }
+}
+
+fn get_mut<'a>(
+    document: &'a mut JSONElement,
+    tokens: &[String],
+    pointer: &str,
+) -> Result<&'a mut JSONElement, String> {
+    tokens
+        .iter()
+        .try_fold(document, |current, token| match current {
+            JSONElement::Object(members) => members
+                .get_mut(token)
+                .ok_or_else(|| format!("{} has no member {}", pointer, token)),
+            JSONElement::Array(items) => {
+                let index = parse_index(token, items.len(), false)?;
+                Ok(&mut items[index])
+            }
+            _ => Err(format!(
+                "{} goes through a value that isn't a container",
+                pointer
+            )),
+        })
+}
+
+fn add(document: &mut JSONElement, path: &str, value: JSONElement) -> Result<(), String> {
+    let mut tokens = parse_pointer(path)?;
+    let Some(last) = tokens.pop() else {
+        *document = value;
+        return Ok(());
+    };
+    match get_mut(document, &tokens, path)? {
+        JSONElement::Object(members) => {
+            members.insert(last, value);
+        }
+        JSONElement::Array(items) => {
+            let index = parse_index(&last, items.len(), true)?;
+            items.insert(index, value);
+        }
+        _ => return Err(format!("{} is not inside a container", path)),
+    }
+    Ok(())
+}
+
+fn remove(document: &mut JSONElement, path: &str) -> Result<JSONElement, String> {
+    let mut tokens = parse_pointer(path)?;
+    let last = tokens
+        .pop()
+        .ok_or_else(|| "Can't remove the whole document".to_string())?;
+    match get_mut(document, &tokens, path)? {
+        JSONElement::Object(members) => members
+            .remove(&last)
+            .ok_or_else(|| format!("{} has no member {}", path, last)),
+        JSONElement::Array(items) => {
+            let index = parse_index(&last, items.len(), false)?;
+            Ok(items.remove(index))
+        }
+        _ => Err(format!("{} is not inside a container", path)),
+    }
+}
+
+/// A patch that turns `from` into `to`. Changed members and array elements are diffed
+/// recursively rather than replaced whole, and values that only changed position (a renamed
+/// member or a reordered array element) are moved.
+pub fn diff(from: &JSONElement, to: &JSONElement) -> Vec<Operation> {
+    let mut patch = vec![];
+    diff_into(from, to, &mut vec![], &mut patch);
+    patch
+}
+
+fn diff_into(
+    from: &JSONElement,
+    to: &JSONElement,
+    path: &mut Vec<String>,
+    patch: &mut Vec<Operation>,
+) {
+    match (from, to) {
+        _ if from == to => {}
+        (JSONElement::Object(from), JSONElement::Object(to)) => diff_objects(from, to, path, patch),
+        (JSONElement::Array(from), JSONElement::Array(to)) => diff_arrays(from, to, path, patch),
+        _ => patch.push(Operation::Replace {
+            path: format_pointer(path),
+            value: to.clone(),
+        }),
+    }
+}
+
+fn child(path: &[String], token: &str) -> String {
+    let mut path = path.to_vec();
+    path.push(token.to_string());
+    format_pointer(&path)
+}
+
+fn diff_objects(
+    from: &HashMap<String, JSONElement>,
+    to: &HashMap<String, JSONElement>,
+    path: &mut Vec<String>,
+    patch: &mut Vec<Operation>,
+) {
+    let sorted = |members: &HashMap<String, JSONElement>| {
+        let mut keys: Vec<String> = members.keys().cloned().collect();
+        keys.sort();
+        keys
+    };
+    let mut added: Vec<String> = sorted(to)
+        .into_iter()
+        .filter(|key| !from.contains_key(key))
+        .collect();
+    for key in sorted(from) {
+        match to.get(&key) {
+            Some(value) => {
+                path.push(key.clone());
+                diff_into(&from[&key], value, path, patch);
+                path.pop();
+            }
+            // A member that's been renamed
+            None => match added.iter().position(|new| to[new] == from[&key]) {
+                Some(i) => patch.push(Operation::Move {
+                    from: child(path, &key),
+                    path: child(path, &added.remove(i)),
+                }),
+                None => patch.push(Operation::Remove {
+                    path: child(path, &key),
+                }),
+            },
+        }
+    }
+    for key in added {
+        patch.push(Operation::Add {
+            path: child(path, &key),
+            value: to[&key].clone(),
+        });
+    }
+}
+
+// What happens to each element of the new array
+#[derive(Clone, Copy, PartialEq)]
+enum Target {
+    // It's an old element, either where it was or moved
+    Kept,
+    Moved,
+    // It replaces an old element in the same place, so it's diffed against that one
+    Changed(usize),
+    Added,
+}
+
+fn diff_arrays(
+    from: &[JSONElement],
+    to: &[JSONElement],
+    path: &mut Vec<String>,
+    patch: &mut Vec<Operation>,
+) {
+    let mut targets = vec![Target::Added; to.len()];
+    // Where each old element ends up, if it's still there
+    let mut sources: Vec<Option<usize>> = vec![None; from.len()];
+    let common = common_subsequence(from, to, |a, b| a == b);
+    for &(i, j) in &common {
+        sources[i] = Some(j);
+        targets[j] = Target::Kept;
+    }
+    // Elements that aren't in the longest common subsequence but are still in the new array
+    // somewhere
+    for (j, target) in targets.iter_mut().enumerate() {
+        if *target == Target::Added {
+            let moved = (0..from.len()).find(|&i| sources[i].is_none() && from[i] == to[j]);
+            if let Some(i) = moved {
+                sources[i] = Some(j);
+                *target = Target::Moved;
+            }
+        }
+    }
+    // Whatever's left between two common elements is paired up in order
+    let gap = |common: &[(usize, usize)], index: usize, side: fn(&(usize, usize)) -> usize| {
+        common.partition_point(|pair| side(pair) < index)
+    };
+    let mut leftover: Vec<usize> = (0..from.len()).filter(|&i| sources[i].is_none()).collect();
+    for (j, target) in targets.iter_mut().enumerate() {
+        if *target != Target::Added {
+            continue;
+        }
+        let slot = gap(&common, j, |pair| pair.1);
+        let found = leftover
+            .iter()
+            .position(|&i| gap(&common, i, |pair| pair.0) == slot);
+        if let Some(position) = found {
+            let i = leftover.remove(position);
+            sources[i] = Some(j);
+            *target = Target::Changed(i);
+        }
+    }
+
+    // The array as it's being patched, as the new index each element is going to
+    let mut current: Vec<Option<usize>> = sources.clone();
+    for i in (0..from.len()).rev() {
+        if current[i].is_none() {
+            current.remove(i);
+            patch.push(Operation::Remove {
+                path: child(path, &i.to_string()),
+            });
+        }
+    }
+    // Everything is put in place right after the element before it. Kept and changed elements
+    // are already in order relative to each other, so they stay where they are.
+    for (j, target) in targets.into_iter().enumerate() {
+        let position = |current: &[Option<usize>], j| current.iter().position(|&k| k == Some(j));
+        let after = match j {
+            0 => 0,
+            _ => position(&current, j - 1).unwrap() + 1,
+        };
+        match target {
+            Target::Kept => {}
+            Target::Changed(i) => {
+                path.push(position(&current, j).unwrap().to_string());
+                diff_into(&from[i], &to[j], path, patch);
+                path.pop();
+            }
+            Target::Added => {
+                current.insert(after, Some(j));
+                patch.push(Operation::Add {
+                    path: child(path, &after.to_string()),
+                    value: to[j].clone(),
+                });
+            }
+            Target::Moved => {
+                let old = position(&current, j).unwrap();
+                let new = if old < after { after - 1 } else { after };
+                if old != new {
+                    current.remove(old);
+                    current.insert(new, Some(j));
+                    patch.push(Operation::Move {
+                        from: child(path, &old.to_string()),
+                        path: child(path, &new.to_string()),
+                    });
+                }
+            }
+        }
+    }
+}
+
+// The index pairs of a longest common subsequence of `a` and `b`, with elements matching
+// when `same` says so
+pub(crate) fn common_subsequence<T>(
+    a: &[T],
+    b: &[T],
+    same: impl Fn(&T, &T) -> bool,
+) -> Vec<(usize, usize)> {
+    // Equal elements at either end are always part of one, which keeps the table small for
+    // arrays with a few changes in the middle
+    let prefix = a.iter().zip(b).take_while(|(a, b)| same(a, b)).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    let (middle_a, middle_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+    // lengths[i][j] is the length of a longest common subsequence of middle_a[i..] and
+    // middle_b[j..]
+    let mut lengths = vec![vec![0; middle_b.len() + 1]; middle_a.len() + 1];
+    for i in (0..middle_a.len()).rev() {
+        for j in (0..middle_b.len()).rev() {
+            lengths[i][j] = if same(&middle_a[i], &middle_b[j]) {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+    let mut pairs: Vec<(usize, usize)> = (0..prefix).map(|i| (i, i)).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < middle_a.len() && j < middle_b.len() {
+        if same(&middle_a[i], &middle_b[j]) {
+            pairs.push((prefix + i, prefix + j));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs.extend((0..suffix).map(|k| (a.len() - suffix + k, b.len() - suffix + k)));
+    pairs
+}
@@ -0,0 +1,220 @@
+#[cfg(test)]
+use crate::patch::*;
+#[cfg(test)]
+use crate::rustifier::loads;
+#[cfg(test)]
+use std::collections::HashMap;
+
+#[cfg(test)]
+fn patched(document: &str, patch: &str) -> Result<JSONElement, String> {
+    let patch = parse_patch(&loads(patch).unwrap())?;
+    apply_patch(&loads(document).unwrap(), &patch)
+}
+
+#[test]
+fn applies_each_operation() {
+    assert_eq!(
+        patched(
+            r#"{"a": {"b": [1, 2]}, "c": "x"}"#,
+            r#"[
+                {"op": "add", "path": "/a/b/1", "value": 9},
+                {"op": "add", "path": "/a/b/-", "value": null},
+                {"op": "remove", "path": "/a/b/0"},
+                {"op": "replace", "path": "/c", "value": {"d": true}},
+                {"op": "move", "from": "/c/d", "path": "/e"},
+                {"op": "copy", "from": "/a/b", "path": "/f"},
+                {"op": "test", "path": "/f", "value": [9, 2, null]}
+            ]"#
+        ),
+        Ok(loads(r#"{"a": {"b": [9, 2, null]}, "c": {}, "e": true, "f": [9, 2, null]}"#).unwrap())
+    );
+    assert_eq!(
+        patched(
+            r#"{"a": 1}"#,
+            r#"[{"op": "add", "path": "", "value": [1]}]"#
+        ),
+        Ok(loads("[1]").unwrap())
+    );
+}
+
+#[test]
+fn applies_all_or_nothing() {
+    let document = loads(r#"{"a": 1}"#).unwrap();
+    let patch = parse_patch(
+        &loads(
+            r#"[
+                {"op": "add", "path": "/b", "value": 2},
+                {"op": "test", "path": "/a", "value": 2}
+            ]"#,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        apply_patch(&document, &patch).unwrap_err(),
+        "Operation 1 (test /a 2) failed: /a is 1, not 2"
+    );
+    assert_eq!(document, loads(r#"{"a": 1}"#).unwrap());
+}
+
+#[test]
+fn rejects_bad_operations() {
+    for (document, patch) in [
+        (r#"{"a": 1}"#, r#"[{"op": "remove", "path": "/b"}]"#),
+        (
+            r#"{"a": 1}"#,
+            r#"[{"op": "add", "path": "/b/c", "value": 1}]"#,
+        ),
+        ("[1]", r#"[{"op": "add", "path": "/2", "value": 1}]"#),
+        ("[1]", r#"[{"op": "replace", "path": "/01", "value": 1}]"#),
+        (
+            r#"{"a": {}}"#,
+            r#"[{"op": "move", "from": "/a", "path": "/a/b"}]"#,
+        ),
+        (r#"{"a": 1}"#, r#"[{"op": "add", "path": "/b"}]"#),
+        (r#"{"a": 1}"#, r#"[{"op": "copy", "path": "/b"}]"#),
+        (r#"{"a": 1}"#, r#"[{"op": "swap", "path": "/a"}]"#),
+    ] {
+        assert!(patched(document, patch).is_err(), "{}", patch);
+    }
+    // Numbers compare by value, and a null value still has to be given
+    assert!(patched("[1]", r#"[{"op": "test", "path": "/0", "value": 1.0}]"#).is_ok());
+    assert_eq!(
+        patched(
+            r#"{"a": 1}"#,
+            r#"[{"op": "replace", "path": "/a", "value": null}]"#
+        ),
+        Ok(loads(r#"{"a": null}"#).unwrap())
+    );
+}
+
+#[test]
+fn diffs_recursively() {
+    let from = loads(r#"{"a": {"b": 1, "c": [1, 2]}, "old": "x", "gone": 1}"#).unwrap();
+    let to = loads(r#"{"a": {"b": 2, "c": [1, 2]}, "new": "x", "added": []}"#).unwrap();
+    assert_eq!(
+        patch_to_element(&diff(&from, &to)),
+        loads(
+            r#"[
+                {"op": "replace", "path": "/a/b", "value": 2},
+                {"op": "remove", "path": "/gone"},
+                {"op": "move", "from": "/old", "path": "/new"},
+                {"op": "add", "path": "/added", "value": []}
+            ]"#
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn diffs_arrays_with_moves() {
+    let diff_strings = |from: &str, to: &str| -> Vec<String> {
+        diff(&loads(from).unwrap(), &loads(to).unwrap())
+            .iter()
+            .map(|operation| operation.to_string())
+            .collect()
+    };
+    assert_eq!(
+        diff_strings(r#"["x", 1, 2, 3]"#, r#"[1, 2, 3, "x"]"#),
+        ["move /0 to /3"]
+    );
+    assert_eq!(
+        diff_strings(r#"[1, 2, 3, "x"]"#, r#"["x", 1, 2, 3]"#),
+        ["move /3 to /0"]
+    );
+    assert_eq!(
+        diff_strings(
+            r#"[1, {"id": 2, "n": 0}, 3, 4]"#,
+            r#"[0, 1, {"id": 2, "n": 1}, 4]"#
+        ),
+        ["remove /2", "add /0 0", "replace /2/n 1"]
+    );
+}
+
+// A small xorshift generator, so the round trip test sees the same documents every run
+#[cfg(test)]
+struct Random(u64);
+
+#[cfg(test)]
+impl Random {
+    fn below(&mut self, n: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % n
+    }
+
+    // Small documents drawn from a few values, so they have lots in common
+    fn document(&mut self, depth: u32) -> JSONElement {
+        match self.below(if depth == 0 { 4 } else { 6 }) {
+            0 => JSONElement::Null,
+            1 => JSONElement::Number(self.below(3) as i32),
+            2 => JSONElement::String(["a", "b"][self.below(2) as usize].to_string()),
+            3 => JSONElement::Boolean(self.below(2) == 0),
+            4 => JSONElement::Array(
+                (0..self.below(6))
+                    .map(|_| self.document(depth - 1))
+                    .collect(),
+            ),
+            _ => JSONElement::Object(
+                (0..self.below(4))
+                    .map(|_| {
+                        (
+                            ["a", "b", "c", "d/e"][self.below(4) as usize].to_string(),
+                            self.document(depth - 1),
+                        )
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    // A copy of `document` with a few random changes
+    fn mutate(&mut self, document: &JSONElement) -> JSONElement {
+        match document {
+            _ if self.below(8) == 0 => self.document(2),
+            JSONElement::Array(items) => {
+                let mut items: Vec<JSONElement> =
+                    items.iter().map(|item| self.mutate(item)).collect();
+                if !items.is_empty() && self.below(2) == 0 {
+                    let item = items.remove(self.below(items.len() as u64) as usize);
+                    items.insert(self.below(items.len() as u64 + 1) as usize, item);
+                }
+                if self.below(3) == 0 {
+                    items.insert(
+                        self.below(items.len() as u64 + 1) as usize,
+                        self.document(1),
+                    );
+                }
+                JSONElement::Array(items)
+            }
+            JSONElement::Object(members) => {
+                let mut mutated = HashMap::new();
+                for (key, value) in members {
+                    if self.below(6) != 0 {
+                        mutated.insert(key.clone(), self.mutate(value));
+                    }
+                }
+                JSONElement::Object(mutated)
+            }
+            _ => document.clone(),
+        }
+    }
+}
+
+#[test]
+fn applying_a_diff_gives_the_target() {
+    let mut random = Random(0x9e3779b97f4a7c15);
+    for _ in 0..2000 {
+        let from = random.document(4);
+        let to = if random.below(2) == 0 {
+            random.mutate(&from)
+        } else {
+            random.document(4)
+        };
+        let patch = diff(&from, &to);
+        assert_eq!(parse_patch(&patch_to_element(&patch)).as_ref(), Ok(&patch));
+        assert_eq!(apply_patch(&from, &patch), Ok(to.clone()), "{:?}", patch);
+        assert!(from != to || patch.is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::rustifier::JSONElement;
+
+mod tests;
+
+/// Splits an RFC 6901 JSON pointer like "/a/0/b~1c" into its reference tokens, with "~1" and
+/// "~0" turned back into "/" and "~". The empty pointer refers to the whole document.
+pub fn parse_pointer(pointer: &str) -> Result<Vec<String>, String> {
+    if pointer.is_empty() {
+        return Ok(vec![]);
+    }
+    let rest = pointer
+        .strip_prefix('/')
+        .ok_or_else(|| format!("JSON pointer {} does not start with /", pointer))?;
+    rest.split('/')
+        .map(|token| {
+            if token.replace("~0", "").replace("~1", "").contains('~') {
+                Err(format!("JSON pointer {} has a bad ~ escape", pointer))
+            } else {
+                Ok(token.replace("~1", "/").replace("~0", "~"))
+            }
+        })
+        .collect()
+}
+
+pub fn format_pointer<S: AsRef<str>>(tokens: &[S]) -> String {
+    tokens
+        .iter()
+        .map(|token| format!("/{}", token.as_ref().replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Reads a reference token as an index into an array of length `len`. "-" means one past the
+/// end, which only makes sense when `allow_end` is set (e.g. for appending).
+pub fn parse_index(token: &str, len: usize, allow_end: bool) -> Result<usize, String> {
+    let limit = if allow_end {
+        len
+    } else {
+        len.saturating_sub(1)
+    };
+    let index = match token {
+        "-" if allow_end => len,
+        // Leading zeros aren't allowed, so every index has exactly one spelling
+        _ if token.len() > 1 && token.starts_with('0') => {
+            return Err(format!("{} is not a valid array index", token))
+        }
+        _ if !token.is_empty() && token.chars().all(|c| c.is_ascii_digit()) => token
+            .parse::<usize>()
+            .map_err(|err| format!("{} is not a valid array index: {}", token, err))?,
+        _ => return Err(format!("{} is not a valid array index", token)),
+    };
+    if index > limit || (!allow_end && len == 0) {
+        Err(format!(
+            "index {} is out of bounds for an array of length {}",
+            token, len
+        ))
+    } else {
+        Ok(index)
+    }
+}
+
+pub fn resolve<'a>(element: &'a JSONElement, pointer: &str) -> Result<&'a JSONElement, String> {
+    parse_pointer(pointer)?
+        .iter()
+        .try_fold(element, |current, token| match current {
+            JSONElement::Object(map) => map
+                .get(token)
+                .ok_or_else(|| format!("{} has no member {}", pointer, token)),
+            JSONElement::Array(items) => Ok(&items[parse_index(token, items.len(), false)?]),
+            _ => Err(format!(
+                "{} goes through a value that isn't a container",
+                pointer
+            )),
+        })
+}
+
+/// Undoes URI percent-encoding, as used in URI fragment pointers like "#/a%20b" and file URIs.
+/// A "%" that isn't followed by two hex digits is left as it is.
+pub fn percent_decode(s: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = std::str::from_utf8(tail.get(..2).unwrap_or_default())
+            .ok()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
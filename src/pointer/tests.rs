@@ -0,0 +1,48 @@
+#[cfg(test)]
+use crate::pointer::*;
+#[cfg(test)]
+use crate::rustifier::loads;
+
+#[test]
+fn parses_pointers() {
+    assert_eq!(parse_pointer("").unwrap(), Vec::<String>::new());
+    assert_eq!(parse_pointer("/").unwrap(), vec![""]);
+    assert_eq!(
+        parse_pointer("/a/0/b~1c/d~0e").unwrap(),
+        vec!["a", "0", "b/c", "d~e"]
+    );
+    // "~01" is an escaped "~" followed by a "1", not an escaped "/"
+    assert_eq!(parse_pointer("/~01").unwrap(), vec!["~1"]);
+    assert!(parse_pointer("a/b").is_err());
+    assert!(parse_pointer("/a~2").is_err());
+}
+
+#[test]
+fn formats_pointers() {
+    assert_eq!(format_pointer(&["a", "b/c", "d~e"]), "/a/b~1c/d~0e");
+    assert_eq!(format_pointer::<&str>(&[]), "");
+}
+
+#[test]
+fn parses_indexes() {
+    assert_eq!(parse_index("2", 3, false).unwrap(), 2);
+    assert_eq!(parse_index("-", 3, true).unwrap(), 3);
+    assert_eq!(parse_index("3", 3, true).unwrap(), 3);
+    assert!(parse_index("3", 3, false).is_err());
+    assert!(parse_index("-", 3, false).is_err());
+    assert!(parse_index("01", 3, false).is_err());
+    assert!(parse_index("0", 0, false).is_err());
+}
+
+#[test]
+fn resolves_pointers() {
+    let document = loads(r#"{"a": [1, {"b/c": "found"}]}"#).unwrap();
+    assert_eq!(resolve(&document, "").unwrap(), &document);
+    assert_eq!(
+        resolve(&document, "/a/1/b~1c").unwrap(),
+        &loads(r#""found""#).unwrap()
+    );
+    assert!(resolve(&document, "/a/2").is_err());
+    assert!(resolve(&document, "/a/0/x").is_err());
+    assert!(resolve(&document, "/x").is_err());
+}
@@ -1,19 +1,30 @@
 use crate::ast_parser;
+use crate::ast_parser::json5;
+use crate::ast_parser::math::expr::eval;
+use crate::ast_parser::math::number::Number;
+use crate::ast_parser::math::value::Value;
 use crate::ast_parser::ASTNode;
 use crate::ast_parser::Parser;
+use crate::ast_parser::{escape, unescape};
 use crate::boxer;
+use crate::cst::{parse_cst_recovering, parse_cst_with, CstOptions, Diagnostic};
+use crate::jsonc::CommentMap;
+use crate::pointer::format_pointer;
 use crate::NumberType;
 use crate::{choice, sequence};
 use std::collections::HashMap;
+use std::fmt::Write;
 
 mod tests;
+pub mod typed;
+pub use crate::rustifier::typed::{from_member, loads_as, FromElement};
 
 pub fn loads(s: &str) -> Result<JSONElement, String> {
     let (rest_of_string, node) = sequence!(
         ast_parser::WhitespaceParser(),
         choice!(
             ast_parser::ArrayParser(),
-            ast_parser::IntParser(),
+            ast_parser::JsonNumberParser(),
             ast_parser::BooleanParser(),
             ast_parser::StringParser(),
             ast_parser::NullParser(),
@@ -33,41 +44,255 @@ pub fn loads(s: &str) -> Result<JSONElement, String> {
     }
 }
 
-fn convert(node: &ASTNode) -> Result<JSONElement, String> {
+/// Parses JSON5, which is JSON plus comments, trailing commas, unquoted keys, single-quoted
+/// strings, hexadecimal and a few more number formats
+pub fn loads_json5(s: &str) -> Result<JSONElement, String> {
+    let (rest_of_string, node) = json5::DocumentParser().parse(s)?;
+    if !rest_of_string.is_empty() {
+        Err(format!("Trailing data: {}", rest_of_string))
+    } else if let Some(node) = node {
+        convert_with(&node, json5::unescape)
+    } else {
+        Ok(JSONElement::Null)
+    }
+}
+
+/// Parses with the concrete syntax tree parser instead, so `options` can allow things like comments
+pub fn loads_with(s: &str, options: CstOptions) -> Result<JSONElement, String> {
+    parse_cst_with(s, options)?.to_element()
+}
+
+/// Parses as much of `s` as it can, giving back a best-effort element along with every error.
+/// Values that couldn't be parsed become null, and members that couldn't be parsed are left out.
+pub fn loads_recovering(s: &str, options: CstOptions) -> (JSONElement, Vec<Diagnostic>) {
+    let (document, mut errors) = parse_cst_recovering(s, options);
+    let element = document.to_element().unwrap_or_else(|err| {
+        // Error nodes already stand in for anything unparseable, so this only happens for
+        // strings with an unpaired surrogate escape, which the grammar accepts
+        errors.push(Diagnostic {
+            message: err,
+            span: document.span,
+        });
+        JSONElement::Null
+    });
+    (element, errors)
+}
+
+pub(crate) fn convert(node: &ASTNode) -> Result<JSONElement, String> {
+    convert_with(node, unescape)
+}
+
+// Strings and keys are kept raw in the tree, so each dialect passes in how to decode its escapes
+fn convert_with(
+    node: &ASTNode,
+    unescape: fn(&str) -> Result<String, String>,
+) -> Result<JSONElement, String> {
     match node {
         ASTNode::Number(n) => Ok(JSONElement::Number(*n)),
-        ASTNode::String(s) => Ok(JSONElement::String(s.to_string())),
+        ASTNode::Float(f) => Ok(JSONElement::Float(*f)),
+        ASTNode::String(s) => Ok(JSONElement::String(unescape(s)?)),
+        ASTNode::OwnedString(s) => Ok(JSONElement::String(s.clone())),
         ASTNode::Boolean(b) => Ok(JSONElement::Boolean(*b)),
         ASTNode::Pair(_, _) => Err("Can't have top-level pair".to_string()),
         ASTNode::Sequence(items) => Ok(JSONElement::Array(
             items
                 .iter()
-                .map(|item| convert(item))
+                .map(|item| convert_with(item, unescape))
                 .collect::<Result<Vec<_>, String>>()?,
         )),
         ASTNode::Mapping(pairs) => {
             let keyvals: Result<HashMap<String, JSONElement>, String> = pairs
                 .iter()
                 .map(|(key, value)| {
-                    if let ASTNode::String(s) = key {
-                        Ok((s.to_string(), convert(value)?))
-                    } else {
-                        Err(format!("Key {:?} was not a string", key))
-                    }
+                    let key = match key {
+                        ASTNode::String(s) => unescape(s)?,
+                        ASTNode::OwnedString(s) => s.clone(),
+                        _ => return Err(format!("Key {:?} was not a string", key)),
+                    };
+                    Ok((key, convert_with(value, unescape)?))
                 })
                 .collect();
             keyvals.map(JSONElement::Object)
         }
         ASTNode::Null => Ok(JSONElement::Null),
+        ASTNode::Expression(expr) => match eval(expr)? {
+            Value::Number(Number::Integer(n)) => Ok(JSONElement::Number(n)),
+            Value::Number(Number::Float(f)) => Ok(JSONElement::Float(f)),
+            Value::String(s) => Ok(JSONElement::String(s)),
+            Value::Boolean(b) => Ok(JSONElement::Boolean(b)),
+            Value::Null => Ok(JSONElement::Null),
+        },
     }
 }
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub enum JSONElement {
     Object(HashMap<String, JSONElement>),
     Array(Vec<JSONElement>),
     String(String),
     Number(NumberType),
+    Float(f64),
     Boolean(bool),
     Null,
 }
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    Json,
+    // Keys are only quoted when they aren't identifiers, strings prefer single quotes, NaN and
+    // Infinity are written as-is, and indented output has trailing commas
+    Json5,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DumpOptions<'a> {
+    // Put each array element and object member on its own line, indented this much per level
+    pub indent: Option<&'a str>,
+    pub dialect: Dialect,
+    // Comments to write next to the values they belong to. They're only written when there's an
+    // indent, since a "//" comment needs a line to itself.
+    pub comments: Option<&'a CommentMap>,
+}
+
+/// Serializes without any whitespace. Object keys are written in sorted order so the output is
+/// the same every time.
+pub fn dumps(element: &JSONElement) -> String {
+    dumps_with(element, DumpOptions::default())
+}
+
+/// Serializes with one line per array element or object member, indented by `indent` per level
+pub fn dumps_pretty(element: &JSONElement, indent: &str) -> String {
+    dumps_with(
+        element,
+        DumpOptions {
+            indent: Some(indent),
+            ..DumpOptions::default()
+        },
+    )
+}
+
+pub fn dumps_with(element: &JSONElement, options: DumpOptions) -> String {
+    let options = DumpOptions {
+        comments: options.comments.filter(|_| options.indent.is_some()),
+        ..options
+    };
+    let comments = options.comments.and_then(|comments| comments.get(""));
+    let mut output = String::new();
+    for comment in comments.iter().flat_map(|comments| &comments.leading) {
+        output.push_str(comment);
+        output.push('\n');
+    }
+    write_element(&mut output, element, &options, 0, "");
+    if let Some(trailing) = comments.and_then(|comments| comments.trailing.as_ref()) {
+        output.push(' ');
+        output.push_str(trailing);
+    }
+    for comment in options
+        .comments
+        .iter()
+        .flat_map(|comments| &comments.footer)
+    {
+        output.push('\n');
+        output.push_str(comment);
+    }
+    output
+}
+
+fn write_element(
+    output: &mut String,
+    element: &JSONElement,
+    options: &DumpOptions,
+    depth: usize,
+    pointer: &str,
+) {
+    let newline = |output: &mut String, depth: usize| {
+        if let Some(indent) = options.indent {
+            output.push('\n');
+            output.push_str(&indent.repeat(depth));
+        }
+    };
+    let json5 = options.dialect == Dialect::Json5;
+    let key_separator = if options.indent.is_some() { ": " } else { ":" };
+    let write_string = |output: &mut String, s: &str| {
+        output.push_str(&if json5 { json5::escape(s) } else { escape(s) })
+    };
+    let comments_at = |pointer: &str| options.comments.and_then(|comments| comments.get(pointer));
+    // Writes the members or elements of a container, each one with its comments
+    let write_children = |output: &mut String, children: Vec<(Option<&String>, &JSONElement)>| {
+        let count = children.len();
+        for (i, (key, child)) in children.into_iter().enumerate() {
+            let token = key.cloned().unwrap_or_else(|| i.to_string());
+            let child_pointer = format!("{}{}", pointer, format_pointer(&[token]));
+            let comments = comments_at(&child_pointer);
+            for comment in comments.iter().flat_map(|comments| &comments.leading) {
+                newline(output, depth + 1);
+                output.push_str(comment);
+            }
+            newline(output, depth + 1);
+            if let Some(key) = key {
+                if json5 && json5::is_identifier(key) {
+                    output.push_str(key);
+                } else {
+                    write_string(output, key);
+                }
+                output.push_str(key_separator);
+            }
+            write_element(output, child, options, depth + 1, &child_pointer);
+            // JSON5 gets a trailing comma when each child is on its own line
+            if i + 1 < count || (json5 && options.indent.is_some()) {
+                output.push(',');
+            }
+            if let Some(trailing) = comments.and_then(|comments| comments.trailing.as_ref()) {
+                output.push(' ');
+                output.push_str(trailing);
+            }
+        }
+        for comment in comments_at(pointer)
+            .iter()
+            .flat_map(|comments| &comments.end)
+        {
+            newline(output, depth + 1);
+            output.push_str(comment);
+        }
+        newline(output, depth);
+    };
+    // An empty container still needs to be written out in full to hold comments
+    let has_end_comments = comments_at(pointer).is_some_and(|comments| !comments.end.is_empty());
+    match element {
+        JSONElement::Object(map) if !map.is_empty() || has_end_comments => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            output.push('{');
+            write_children(
+                output,
+                keys.into_iter().map(|key| (Some(key), &map[key])).collect(),
+            );
+            output.push('}');
+        }
+        JSONElement::Array(items) if !items.is_empty() || has_end_comments => {
+            output.push('[');
+            write_children(output, items.iter().map(|item| (None, item)).collect());
+            output.push(']');
+        }
+        JSONElement::Object(_) => output.push_str("{}"),
+        JSONElement::Array(_) => output.push_str("[]"),
+        JSONElement::String(s) => write_string(output, s),
+        JSONElement::Number(n) => {
+            let _ = write!(output, "{}", n);
+        }
+        JSONElement::Float(f) if f.is_nan() && json5 => output.push_str("NaN"),
+        JSONElement::Float(f) if f.is_infinite() && json5 => {
+            output.push_str(if *f > 0.0 { "Infinity" } else { "-Infinity" })
+        }
+        // JSON has no way to write NaN or infinity, so they become null like in JavaScript
+        JSONElement::Float(f) if !f.is_finite() => output.push_str("null"),
+        JSONElement::Float(f) => {
+            let _ = write!(output, "{}", Number::Float(*f));
+        }
+        JSONElement::Boolean(b) => {
+            let _ = write!(output, "{}", b);
+        }
+        JSONElement::Null => output.push_str("null"),
+    }
+}
@@ -1,4 +1,6 @@
 #[cfg(test)]
+use crate::cst::CstOptions;
+#[cfg(test)]
 use crate::rustifier::*;
 
 #[test]
@@ -52,6 +54,25 @@ fn parses_object_with_elements() {
     assert_eq!(actual, expected);
 }
 
+#[test]
+fn parses_fractions_exponents_and_large_numbers() {
+    let actual = loads(r#"[1.5, -2E-3, 3000000000, 0, -0.0]"#).unwrap();
+    let expected = JSONElement::Array(vec![
+        JSONElement::Float(1.5),
+        JSONElement::Float(-0.002),
+        JSONElement::Float(3e9),
+        JSONElement::Number(0),
+        JSONElement::Float(-0.0),
+    ]);
+    assert_eq!(actual, expected);
+    assert!(loads("01").is_err());
+    assert!(loads("[1.]").is_err());
+    assert_eq!(
+        loads_with("{\"a\": 2.5e1}", CstOptions::default()).unwrap(),
+        JSONElement::Object(HashMap::from([("a".to_string(), JSONElement::Float(25.0))]))
+    );
+}
+
 #[test]
 fn parses_empty_array() {
     let actual = loads("[]").unwrap();
@@ -113,3 +134,61 @@ fn parses_null() {
     let expected = JSONElement::Null;
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn parses_escaped_string() {
+    let actual = loads(r#"{"say \"hi\"": "tab\there é"}"#).unwrap();
+    let expected = JSONElement::Object(HashMap::from([(
+        "say \"hi\"".to_string(),
+        JSONElement::String(String::from("tab\there \u{e9}")),
+    )]));
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn dumps_compact_with_sorted_keys() {
+    let element = loads(r#"{"b": [1, true, null], "a": "x\ny"}"#).unwrap();
+    assert_eq!(dumps(&element), r#"{"a":"x\ny","b":[1,true,null]}"#);
+    assert_eq!(loads(&dumps(&element)).unwrap(), element);
+}
+
+#[test]
+fn dumps_indented() {
+    let element = loads(r#"{"b": [1, []], "a": {}}"#).unwrap();
+    let expected = "{\n  \"a\": {},\n  \"b\": [\n    1,\n    []\n  ]\n}";
+    assert_eq!(dumps_pretty(&element, "  "), expected);
+}
+
+#[test]
+fn loads_recovering_collects_every_error() {
+    let (actual, errors) = loads_recovering(
+        "[1, tru, {\"a\": nul, \"b\": 2}] /* unclosed",
+        CstOptions { comments: true },
+    );
+    let expected = loads("[1, null, {\"a\": null, \"b\": 2}]").unwrap();
+    assert_eq!(actual, expected);
+    let starts: Vec<usize> = errors.iter().map(|error| error.span.start).collect();
+    assert_eq!(starts, vec![4, 15, 29]);
+}
+
+#[test]
+fn loads_typed_values() {
+    assert_eq!(loads_as::<Vec<i64>>("[1, 2]").unwrap(), vec![1, 2]);
+    assert_eq!(
+        loads_as::<HashMap<String, Option<String>>>(r#"{"a": "x", "b": null}"#).unwrap(),
+        HashMap::from([
+            ("a".to_string(), Some("x".to_string())),
+            ("b".to_string(), None)
+        ])
+    );
+    assert_eq!(
+        loads_as::<Vec<HashMap<String, bool>>>(r#"[{"a/b": 1}]"#).unwrap_err(),
+        "/0/a~1b: Expected a boolean but found a number"
+    );
+    let members = HashMap::from([("a".to_string(), JSONElement::Number(1))]);
+    assert_eq!(from_member::<Option<f64>>(&members, "b"), Ok(None));
+    assert_eq!(
+        from_member::<f64>(&members, "b").unwrap_err(),
+        "Missing required member b"
+    );
+}
@@ -0,0 +1,136 @@
+use crate::rustifier::{loads, JSONElement};
+use std::collections::HashMap;
+
+/// Types that can be read out of a `JSONElement`. Implement it for your own structs (or
+/// generate the implementations with `codegen`) to load documents straight into them.
+pub trait FromElement: Sized {
+    fn from_element(element: &JSONElement) -> Result<Self, String>;
+}
+
+/// Parses `s` and reads it as a `T`
+pub fn loads_as<T: FromElement>(s: &str) -> Result<T, String> {
+    T::from_element(&loads(s)?)
+}
+
+/// Reads the member `key` of an object. A missing member reads like a null, so it's fine for
+/// `Option` fields and an error for anything else.
+pub fn from_member<T: FromElement>(
+    members: &HashMap<String, JSONElement>,
+    key: &str,
+) -> Result<T, String> {
+    match members.get(key) {
+        Some(value) => T::from_element(value).map_err(|err| within(key, err)),
+        None => T::from_element(&JSONElement::Null)
+            .map_err(|_| format!("Missing required member {}", key)),
+    }
+}
+
+// Errors from inside a container say where they happened, like "/users/0/id: expected ..."
+fn within(token: &str, err: String) -> String {
+    let token = token.replace('~', "~0").replace('/', "~1");
+    match err.strip_prefix('/') {
+        Some(rest) => format!("/{}/{}", token, rest),
+        None => format!("/{}: {}", token, err),
+    }
+}
+
+fn mismatch(expected: &str, element: &JSONElement) -> String {
+    let found = match element {
+        JSONElement::Object(_) => "an object",
+        JSONElement::Array(_) => "an array",
+        JSONElement::String(_) => "a string",
+        JSONElement::Number(_) | JSONElement::Float(_) => "a number",
+        JSONElement::Boolean(_) => "a boolean",
+        JSONElement::Null => "null",
+    };
+    format!("Expected {} but found {}", expected, found)
+}
+
+impl FromElement for JSONElement {
+    fn from_element(element: &JSONElement) -> Result<JSONElement, String> {
+        Ok(element.clone())
+    }
+}
+
+impl FromElement for bool {
+    fn from_element(element: &JSONElement) -> Result<bool, String> {
+        match element {
+            JSONElement::Boolean(b) => Ok(*b),
+            _ => Err(mismatch("a boolean", element)),
+        }
+    }
+}
+
+impl FromElement for i64 {
+    fn from_element(element: &JSONElement) -> Result<i64, String> {
+        match element {
+            JSONElement::Number(n) => Ok(*n as i64),
+            // Integers too big for a Number are parsed as floats
+            JSONElement::Float(f) if f.fract() == 0.0 && f.abs() < 9.2e18 => Ok(*f as i64),
+            _ => Err(mismatch("an integer", element)),
+        }
+    }
+}
+
+impl FromElement for f64 {
+    fn from_element(element: &JSONElement) -> Result<f64, String> {
+        match element {
+            JSONElement::Number(n) => Ok(*n as f64),
+            JSONElement::Float(f) => Ok(*f),
+            _ => Err(mismatch("a number", element)),
+        }
+    }
+}
+
+impl FromElement for String {
+    fn from_element(element: &JSONElement) -> Result<String, String> {
+        match element {
+            JSONElement::String(s) => Ok(s.clone()),
+            _ => Err(mismatch("a string", element)),
+        }
+    }
+}
+
+impl<T: FromElement> FromElement for Option<T> {
+    fn from_element(element: &JSONElement) -> Result<Option<T>, String> {
+        match element {
+            JSONElement::Null => Ok(None),
+            _ => T::from_element(element).map(Some),
+        }
+    }
+}
+
+impl<T: FromElement> FromElement for Box<T> {
+    fn from_element(element: &JSONElement) -> Result<Box<T>, String> {
+        T::from_element(element).map(Box::new)
+    }
+}
+
+impl<T: FromElement> FromElement for Vec<T> {
+    fn from_element(element: &JSONElement) -> Result<Vec<T>, String> {
+        match element {
+            JSONElement::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| T::from_element(item).map_err(|err| within(&i.to_string(), err)))
+                .collect(),
+            _ => Err(mismatch("an array", element)),
+        }
+    }
+}
+
+impl<T: FromElement> FromElement for HashMap<String, T> {
+    fn from_element(element: &JSONElement) -> Result<HashMap<String, T>, String> {
+        match element {
+            JSONElement::Object(members) => members
+                .iter()
+                .map(|(key, value)| {
+                    T::from_element(value)
+                        .map(|value| (key.clone(), value))
+                        .map_err(|err| within(key, err))
+                })
+                .collect(),
+            _ => Err(mismatch("an object", element)),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::rustifier::JSONElement;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+mod tests;
+
+#[derive(Debug, Clone, Copy)]
+pub struct InferOptions {
+    // Strings become an enum when there are at most this many distinct values and at least one
+    // of them came up more than once. 0 turns enums off.
+    pub enum_limit: usize,
+}
+
+impl Default for InferOptions {
+    fn default() -> InferOptions {
+        InferOptions { enum_limit: 8 }
+    }
+}
+
+/// Builds up a draft schema from sample documents. Every sample is merged into a summary of
+/// the values seen at each position, so memory use depends on the shape of the samples rather
+/// than how many there are.
+#[derive(Debug, Default)]
+pub struct Inference {
+    options: InferOptions,
+    root: Shape,
+}
+
+// Everything seen at one position in the samples
+#[derive(Debug, Default)]
+struct Shape {
+    null: bool,
+    boolean: bool,
+    integer: bool,
+    // Seen a number with a fractional part
+    number: bool,
+    strings: usize,
+    // The distinct strings, until there are too many for an enum
+    string_values: Option<BTreeSet<String>>,
+    arrays: usize,
+    items: Option<Box<Shape>>,
+    objects: usize,
+    // Each property's shape, along with how many of the objects had it
+    properties: BTreeMap<String, (usize, Shape)>,
+}
+
+impl Inference {
+    pub fn new(options: InferOptions) -> Inference {
+        Inference {
+            options,
+            root: Shape::default(),
+        }
+    }
+
+    pub fn add(&mut self, sample: &JSONElement) {
+        self.root.add(sample, &self.options);
+    }
+
+    /// The schema for every sample added so far, as a draft 2020-12 schema document
+    pub fn to_schema(&self) -> JSONElement {
+        let mut schema = match self.root.to_schema() {
+            JSONElement::Object(schema) => schema,
+            _ => HashMap::new(),
+        };
+        schema.insert(
+            "$schema".to_string(),
+            string("https://json-schema.org/draft/2020-12/schema"),
+        );
+        JSONElement::Object(schema)
+    }
+}
+
+/// Infers a schema that all of `samples` match
+pub fn infer_schema<'a>(samples: impl IntoIterator<Item = &'a JSONElement>) -> JSONElement {
+    let mut inference = Inference::default();
+    for sample in samples {
+        inference.add(sample);
+    }
+    inference.to_schema()
+}
+
+impl Shape {
+    fn add(&mut self, value: &JSONElement, options: &InferOptions) {
+        match value {
+            JSONElement::Null => self.null = true,
+            JSONElement::Boolean(_) => self.boolean = true,
+            JSONElement::Number(_) => self.integer = true,
+            JSONElement::Float(f) if f.fract() == 0.0 => self.integer = true,
+            JSONElement::Float(_) => self.number = true,
+            JSONElement::String(s) => {
+                if self.strings == 0 {
+                    self.string_values = Some(BTreeSet::new());
+                }
+                self.strings += 1;
+                if let Some(values) = &mut self.string_values {
+                    values.insert(s.clone());
+                    if values.len() > options.enum_limit {
+                        self.string_values = None;
+                    }
+                }
+            }
+            JSONElement::Array(items) => {
+                self.arrays += 1;
+                for item in items {
+                    self.items.get_or_insert_default().add(item, options);
+                }
+            }
+            JSONElement::Object(members) => {
+                self.objects += 1;
+                for (key, value) in members {
+                    let (count, shape) = self.properties.entry(key.clone()).or_default();
+                    *count += 1;
+                    shape.add(value, options);
+                }
+            }
+        }
+    }
+
+    // A schema for each kind of value seen, combined into one
+    fn to_schema(&self) -> JSONElement {
+        let mut schemas = vec![];
+        if self.null {
+            schemas.push(typed("null", []));
+        }
+        if self.boolean {
+            schemas.push(typed("boolean", []));
+        }
+        if self.number {
+            schemas.push(typed("number", []));
+        } else if self.integer {
+            schemas.push(typed("integer", []));
+        }
+        if self.strings > 0 {
+            let values = self
+                .string_values
+                .as_ref()
+                .filter(|values| self.strings > values.len());
+            schemas.push(match values {
+                Some(values) => typed(
+                    "string",
+                    [(
+                        "enum",
+                        JSONElement::Array(values.iter().map(|value| string(value)).collect()),
+                    )],
+                ),
+                None => typed("string", []),
+            });
+        }
+        if self.arrays > 0 {
+            let items = self
+                .items
+                .as_ref()
+                .map(|items| ("items", items.to_schema()));
+            schemas.push(typed("array", items));
+        }
+        if self.objects > 0 {
+            let properties = self
+                .properties
+                .iter()
+                .map(|(key, (_, shape))| (key.clone(), shape.to_schema()))
+                .collect();
+            let required: Vec<JSONElement> = self
+                .properties
+                .iter()
+                .filter(|(_, (count, _))| *count == self.objects)
+                .map(|(key, _)| string(key))
+                .collect();
+            let mut keywords = vec![("properties", JSONElement::Object(properties))];
+            if !required.is_empty() {
+                keywords.push(("required", JSONElement::Array(required)));
+            }
+            schemas.push(typed("object", keywords));
+        }
+        combine(schemas)
+    }
+}
+
+// One schema for values matching any of `schemas`. When they're all plain types that's a type
+// list, and otherwise it's an anyOf.
+fn combine(mut schemas: Vec<JSONElement>) -> JSONElement {
+    let plain = schemas.iter().all(|schema| match schema {
+        JSONElement::Object(members) => members.len() == 1,
+        _ => false,
+    });
+    match schemas.len() {
+        // Only reached when no samples were added, and anything matches that
+        0 => JSONElement::Object(HashMap::new()),
+        1 => schemas.remove(0),
+        _ if plain => typed_list(
+            schemas
+                .iter()
+                .filter_map(|schema| match schema {
+                    JSONElement::Object(members) => members.get("type").cloned(),
+                    _ => None,
+                })
+                .collect(),
+        ),
+        _ => JSONElement::Object(HashMap::from([(
+            "anyOf".to_string(),
+            JSONElement::Array(schemas),
+        )])),
+    }
+}
+
+fn typed<'k>(
+    name: &str,
+    keywords: impl IntoIterator<Item = (&'k str, JSONElement)>,
+) -> JSONElement {
+    JSONElement::Object(
+        std::iter::once(("type", string(name)))
+            .chain(keywords)
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+fn typed_list(names: Vec<JSONElement>) -> JSONElement {
+    JSONElement::Object(HashMap::from([(
+        "type".to_string(),
+        JSONElement::Array(names),
+    )]))
+}
+
+fn string(s: &str) -> JSONElement {
+    JSONElement::String(s.to_string())
+}
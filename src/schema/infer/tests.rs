@@ -0,0 +1,106 @@
+#[cfg(test)]
+use crate::rustifier::{loads, JSONElement};
+#[cfg(test)]
+use crate::schema::infer::*;
+#[cfg(test)]
+use crate::schema::Schema;
+
+#[cfg(test)]
+fn infer(samples: &[&str]) -> JSONElement {
+    let samples: Vec<JSONElement> = samples
+        .iter()
+        .map(|sample| loads(sample).unwrap())
+        .collect();
+    let mut schema = infer_schema(&samples);
+    if let JSONElement::Object(members) = &mut schema {
+        members.remove("$schema");
+    }
+    schema
+}
+
+#[test]
+fn finds_required_and_optional_keys() {
+    assert_eq!(
+        infer(&[
+            r#"{"id": 1, "name": "a", "email": null}"#,
+            r#"{"id": 2, "name": "b"}"#,
+        ]),
+        loads(
+            r#"{"type": "object", "required": ["id", "name"], "properties": {
+                "id": {"type": "integer"},
+                "name": {"type": "string"},
+                "email": {"type": "null"}
+            }}"#
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn merges_types() {
+    assert_eq!(
+        infer(&["1", "2.5", "null"]),
+        loads(r#"{"type": ["null", "number"]}"#).unwrap()
+    );
+    assert_eq!(
+        infer(&["1", "2.0"]),
+        loads(r#"{"type": "integer"}"#).unwrap()
+    );
+}
+
+#[test]
+fn finds_enumerations() {
+    // Values that repeat are probably from a fixed set
+    assert_eq!(
+        infer(&[r#"["open", "closed", "open"]"#]),
+        loads(r#"{"type": "array", "items": {"type": "string", "enum": ["closed", "open"]}}"#)
+            .unwrap()
+    );
+    assert_eq!(
+        infer(&[r#"["alice", "bob"]"#]),
+        loads(r#"{"type": "array", "items": {"type": "string"}}"#).unwrap()
+    );
+    let mut inference = Inference::new(InferOptions { enum_limit: 1 });
+    inference.add(&loads(r#"["a", "b", "a"]"#).unwrap());
+    assert_eq!(
+        inference.to_schema(),
+        loads(
+            r#"{"$schema": "https://json-schema.org/draft/2020-12/schema",
+                "type": "array", "items": {"type": "string"}}"#
+        )
+        .unwrap()
+    );
+}
+
+#[test]
+fn unions_array_items() {
+    assert_eq!(
+        infer(&[r#"[1, "x", {"a": true}]"#, "[]"]),
+        loads(
+            r#"{"type": "array", "items": {"anyOf": [
+                {"type": "integer"},
+                {"type": "string"},
+                {"type": "object", "properties": {"a": {"type": "boolean"}}, "required": ["a"]}
+            ]}}"#
+        )
+        .unwrap()
+    );
+    assert_eq!(infer(&["[]"]), loads(r#"{"type": "array"}"#).unwrap());
+}
+
+#[test]
+fn accepts_every_sample() {
+    let samples: Vec<JSONElement> = [
+        r#"{"users": [{"id": 1, "tags": ["a"]}, {"id": 2, "role": "admin"}], "next": null}"#,
+        r#"{"users": [], "next": "abc", "total": 2.5}"#,
+        r#"{"users": [{"id": 3, "role": "admin", "tags": []}]}"#,
+    ]
+    .iter()
+    .map(|sample| loads(sample).unwrap())
+    .collect();
+    let schema = Schema::compile(&infer_schema(&samples)).unwrap();
+    for sample in &samples {
+        assert_eq!(schema.validate(sample), Ok(()));
+    }
+    assert!(!schema.is_valid(&loads(r#"{"users": [{"id": "1"}]}"#).unwrap()));
+}
@@ -0,0 +1,732 @@
+use crate::pointer::{format_pointer, parse_pointer, percent_decode, resolve};
+use crate::rustifier::{dumps, JSONElement};
+use std::collections::HashMap;
+use std::fmt;
+
+pub mod infer;
+pub mod regex;
+mod tests;
+
+use regex::Regex;
+
+// How many $refs deep validation can go without moving into the instance, which is only
+// reached by schemas like {"$ref": "#"} that refer to themselves forever
+const MAX_REF_DEPTH: usize = 256;
+
+/// A JSON Schema (draft 2020-12), compiled so it can validate any number of instances. The
+/// supported keywords are type, enum, const, the numeric, string, array and object bounds,
+/// pattern, properties, patternProperties, additionalProperties, required, prefixItems, items,
+/// uniqueItems, allOf, anyOf, oneOf, not, if/then/else, $defs and $ref. Other keywords are
+/// ignored, as the spec asks, and only references within the schema itself can be followed.
+#[derive(Debug)]
+pub struct Schema {
+    // Every subschema, with the root first. Keywords refer to subschemas by their index.
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug)]
+enum Node {
+    Boolean(bool, String),
+    Keywords(Vec<Keyword>),
+}
+
+#[derive(Debug)]
+struct Keyword {
+    // The keyword's location in the schema, as a JSON pointer
+    location: String,
+    rule: Rule,
+}
+
+#[derive(Debug)]
+enum Rule {
+    Type(Vec<String>),
+    Enum(Vec<JSONElement>),
+    Const(JSONElement),
+    Minimum(f64),
+    Maximum(f64),
+    ExclusiveMinimum(f64),
+    ExclusiveMaximum(f64),
+    MultipleOf(f64),
+    MinLength(usize),
+    MaxLength(usize),
+    Pattern(Regex),
+    MinItems(usize),
+    MaxItems(usize),
+    UniqueItems,
+    MinProperties(usize),
+    MaxProperties(usize),
+    Required(Vec<String>),
+    Properties(Vec<(String, usize)>),
+    PatternProperties(Vec<(Regex, usize)>),
+    // Applies to members that aren't covered by the sibling properties or patternProperties
+    AdditionalProperties(usize, Vec<String>, Vec<Regex>),
+    PrefixItems(Vec<usize>),
+    // Applies to the items after the ones covered by the sibling prefixItems
+    Items(usize, usize),
+    AllOf(Vec<usize>),
+    AnyOf(Vec<usize>),
+    OneOf(Vec<usize>),
+    Not(usize),
+    If(usize, Option<usize>, Option<usize>),
+    // Filled in once every subschema has been compiled
+    Ref(String, Option<usize>),
+}
+
+/// One way an instance fails to match a schema
+#[derive(Debug, PartialEq, Clone)]
+pub struct ValidationError {
+    // JSON pointer to the value in the instance that failed
+    pub instance_path: String,
+    // JSON pointer to the keyword in the schema it failed
+    pub schema_path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let instance_path = match self.instance_path.as_str() {
+            "" => "the root",
+            path => path,
+        };
+        write!(
+            f,
+            "{}: {} (schema {})",
+            instance_path, self.message, self.schema_path
+        )
+    }
+}
+
+struct Compiler<'s> {
+    root: &'s JSONElement,
+    nodes: Vec<Node>,
+    // The subschema compiled for each location, so $refs can find them
+    compiled: HashMap<String, usize>,
+    anchors: HashMap<String, String>,
+    // The root $id, which $refs can start with
+    base: Option<&'s str>,
+}
+
+impl Schema {
+    pub fn compile(schema: &JSONElement) -> Result<Schema, String> {
+        let mut compiler = Compiler {
+            root: schema,
+            nodes: vec![],
+            compiled: HashMap::new(),
+            anchors: HashMap::new(),
+            base: match resolve(schema, "/$id") {
+                Ok(JSONElement::String(id)) => Some(id.trim_end_matches('#')),
+                _ => None,
+            },
+        };
+        compiler.compile(schema, &mut vec![])?;
+        compiler.link()?;
+        Ok(Schema {
+            nodes: compiler.nodes,
+        })
+    }
+
+    /// Checks `instance` against the schema, giving back every error rather than just the first
+    pub fn validate(&self, instance: &JSONElement) -> Result<(), Vec<ValidationError>> {
+        let mut errors = vec![];
+        self.check(0, instance, &mut vec![], 0, &mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn is_valid(&self, instance: &JSONElement) -> bool {
+        self.validate(instance).is_ok()
+    }
+
+    fn passes(&self, node: usize, instance: &JSONElement, path: &[String], depth: usize) -> bool {
+        let mut errors = vec![];
+        self.check(node, instance, &mut path.to_vec(), depth, &mut errors);
+        errors.is_empty()
+    }
+
+    fn check(
+        &self,
+        node: usize,
+        instance: &JSONElement,
+        path: &mut Vec<String>,
+        depth: usize,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        let keywords = match &self.nodes[node] {
+            Node::Boolean(true, _) => return,
+            Node::Boolean(false, location) => {
+                errors.push(ValidationError {
+                    instance_path: format_pointer(path),
+                    schema_path: location.clone(),
+                    message: "No value is allowed here".to_string(),
+                });
+                return;
+            }
+            Node::Keywords(keywords) => keywords,
+        };
+        for keyword in keywords {
+            if let Some(message) = self.check_keyword(&keyword.rule, instance, path, depth, errors)
+            {
+                errors.push(ValidationError {
+                    instance_path: format_pointer(path),
+                    schema_path: keyword.location.clone(),
+                    message,
+                });
+            }
+        }
+    }
+
+    // Checks one keyword, giving back what's wrong if the instance fails it. Keywords with
+    // subschemas add the errors from those to `errors` themselves.
+    fn check_keyword(
+        &self,
+        rule: &Rule,
+        instance: &JSONElement,
+        path: &mut Vec<String>,
+        depth: usize,
+        errors: &mut Vec<ValidationError>,
+    ) -> Option<String> {
+        match (rule, instance) {
+            (Rule::Type(types), _) if !types.iter().any(|name| has_type(instance, name)) => {
+                Some(format!(
+                    "Expected {} but found {}",
+                    types.join(" or "),
+                    type_name(instance)
+                ))
+            }
+            (Rule::Enum(values), _) if !values.iter().any(|value| equal(value, instance)) => Some(
+                format!("{} is not one of the allowed values", dumps(instance)),
+            ),
+            (Rule::Const(value), _) if !equal(value, instance) => {
+                Some(format!("Expected {}", dumps(value)))
+            }
+            (Rule::Minimum(limit), _) if as_number(instance).is_some_and(|n| n < *limit) => {
+                Some(format!("{} is less than {}", dumps(instance), limit))
+            }
+            (Rule::Maximum(limit), _) if as_number(instance).is_some_and(|n| n > *limit) => {
+                Some(format!("{} is greater than {}", dumps(instance), limit))
+            }
+            (Rule::ExclusiveMinimum(limit), _)
+                if as_number(instance).is_some_and(|n| n <= *limit) =>
+            {
+                Some(format!("{} is not greater than {}", dumps(instance), limit))
+            }
+            (Rule::ExclusiveMaximum(limit), _)
+                if as_number(instance).is_some_and(|n| n >= *limit) =>
+            {
+                Some(format!("{} is not less than {}", dumps(instance), limit))
+            }
+            (Rule::MultipleOf(divisor), _)
+                if as_number(instance).is_some_and(|n| !is_multiple(n, *divisor)) =>
+            {
+                Some(format!(
+                    "{} is not a multiple of {}",
+                    dumps(instance),
+                    divisor
+                ))
+            }
+            (Rule::MinLength(limit), JSONElement::String(s)) if s.chars().count() < *limit => {
+                Some(format!("Shorter than {} characters", limit))
+            }
+            (Rule::MaxLength(limit), JSONElement::String(s)) if s.chars().count() > *limit => {
+                Some(format!("Longer than {} characters", limit))
+            }
+            (Rule::Pattern(regex), JSONElement::String(s)) if !regex.is_match(s) => {
+                Some(format!("Does not match {}", regex.as_str()))
+            }
+            (Rule::MinItems(limit), JSONElement::Array(items)) if items.len() < *limit => {
+                Some(format!("Fewer than {} items", limit))
+            }
+            (Rule::MaxItems(limit), JSONElement::Array(items)) if items.len() > *limit => {
+                Some(format!("More than {} items", limit))
+            }
+            (Rule::UniqueItems, JSONElement::Array(items)) => (0..items.len())
+                .find(|i| items[..*i].iter().any(|item| equal(item, &items[*i])))
+                .map(|i| format!("Item {} is a duplicate", i)),
+            (Rule::MinProperties(limit), JSONElement::Object(members))
+                if members.len() < *limit =>
+            {
+                Some(format!("Fewer than {} members", limit))
+            }
+            (Rule::MaxProperties(limit), JSONElement::Object(members))
+                if members.len() > *limit =>
+            {
+                Some(format!("More than {} members", limit))
+            }
+            (Rule::Required(keys), JSONElement::Object(members)) => {
+                let missing: Vec<String> = keys
+                    .iter()
+                    .filter(|key| !members.contains_key(*key))
+                    .map(|key| dumps(&JSONElement::String(key.clone())))
+                    .collect();
+                (!missing.is_empty())
+                    .then(|| format!("Missing required members {}", missing.join(", ")))
+            }
+            (Rule::Properties(properties), JSONElement::Object(members)) => {
+                for (key, subschema) in properties {
+                    if let Some(value) = members.get(key) {
+                        self.check_child(*subschema, value, key, path, errors);
+                    }
+                }
+                None
+            }
+            (Rule::PatternProperties(patterns), JSONElement::Object(members)) => {
+                for (regex, subschema) in patterns {
+                    for (key, value) in sorted(members) {
+                        if regex.is_match(key) {
+                            self.check_child(*subschema, value, key, path, errors);
+                        }
+                    }
+                }
+                None
+            }
+            (
+                Rule::AdditionalProperties(subschema, known, patterns),
+                JSONElement::Object(members),
+            ) => {
+                for (key, value) in sorted(members) {
+                    let covered =
+                        known.contains(key) || patterns.iter().any(|regex| regex.is_match(key));
+                    if !covered {
+                        self.check_child(*subschema, value, key, path, errors);
+                    }
+                }
+                None
+            }
+            (Rule::PrefixItems(subschemas), JSONElement::Array(items)) => {
+                for (i, (subschema, item)) in subschemas.iter().zip(items).enumerate() {
+                    self.check_child(*subschema, item, &i.to_string(), path, errors);
+                }
+                None
+            }
+            (Rule::Items(subschema, skip), JSONElement::Array(items)) => {
+                for (i, item) in items.iter().enumerate().skip(*skip) {
+                    self.check_child(*subschema, item, &i.to_string(), path, errors);
+                }
+                None
+            }
+            (Rule::AllOf(subschemas), _) => {
+                for subschema in subschemas {
+                    self.check(*subschema, instance, path, depth, errors);
+                }
+                None
+            }
+            (Rule::AnyOf(subschemas), _) => (!subschemas
+                .iter()
+                .any(|subschema| self.passes(*subschema, instance, path, depth)))
+            .then(|| "Does not match any of the anyOf schemas".to_string()),
+            (Rule::OneOf(subschemas), _) => {
+                let passed = subschemas
+                    .iter()
+                    .filter(|subschema| self.passes(**subschema, instance, path, depth))
+                    .count();
+                (passed != 1)
+                    .then(|| format!("Matches {} of the oneOf schemas instead of 1", passed))
+            }
+            (Rule::Not(subschema), _) if self.passes(*subschema, instance, path, depth) => {
+                Some("Matches the schema it must not match".to_string())
+            }
+            (Rule::If(condition, then, otherwise), _) => {
+                let branch = if self.passes(*condition, instance, path, depth) {
+                    then
+                } else {
+                    otherwise
+                };
+                if let Some(branch) = branch {
+                    self.check(*branch, instance, path, depth, errors);
+                }
+                None
+            }
+            (Rule::Ref(reference, _), _) if depth >= MAX_REF_DEPTH => {
+                Some(format!("{} refers to itself without end", reference))
+            }
+            (Rule::Ref(_, target), _) => {
+                let target = target.expect("references are linked when compiling");
+                self.check(target, instance, path, depth + 1, errors);
+                None
+            }
+            // The instance passed, or the keyword doesn't apply to its type
+            _ => None,
+        }
+    }
+
+    fn check_child(
+        &self,
+        node: usize,
+        instance: &JSONElement,
+        token: &str,
+        path: &mut Vec<String>,
+        errors: &mut Vec<ValidationError>,
+    ) {
+        path.push(token.to_string());
+        // Moving into the instance can't go on forever, so it resets the $ref limit
+        self.check(node, instance, path, 0, errors);
+        path.pop();
+    }
+}
+
+impl<'s> Compiler<'s> {
+    // Compiles the subschema at `location`, giving back its index
+    fn compile(
+        &mut self,
+        schema: &'s JSONElement,
+        location: &mut Vec<String>,
+    ) -> Result<usize, String> {
+        let pointer = format_pointer(location);
+        if let Some(id) = self.compiled.get(&pointer) {
+            return Ok(*id);
+        }
+        let id = self.nodes.len();
+        self.compiled.insert(pointer.clone(), id);
+        let members = match schema {
+            JSONElement::Boolean(b) => {
+                self.nodes.push(Node::Boolean(*b, pointer));
+                return Ok(id);
+            }
+            JSONElement::Object(members) => members,
+            _ => {
+                return Err(format!(
+                    "The schema at {:?} is not an object or boolean",
+                    pointer
+                ))
+            }
+        };
+        self.nodes.push(Node::Keywords(vec![]));
+        if let Some(JSONElement::String(anchor)) = members.get("$anchor") {
+            self.anchors.insert(anchor.clone(), pointer.clone());
+        }
+        // Subschemas under $defs are compiled so references to them (and into them) resolve
+        if let Some(JSONElement::Object(definitions)) = members.get("$defs") {
+            for (name, definition) in sorted(definitions) {
+                location.extend(["$defs".to_string(), name.clone()]);
+                self.compile(definition, location)?;
+                location.truncate(location.len() - 2);
+            }
+        }
+
+        let mut keywords = vec![];
+        for (name, value) in sorted(members) {
+            location.push(name.clone());
+            let rule = self.rule(name, value, members, location);
+            let keyword_location = format_pointer(location);
+            location.pop();
+            if let Some(rule) = rule? {
+                keywords.push(Keyword {
+                    location: keyword_location,
+                    rule,
+                });
+            }
+        }
+        self.nodes[id] = Node::Keywords(keywords);
+        Ok(id)
+    }
+
+    // The rule for one keyword, or None for keywords that don't need checking
+    fn rule(
+        &mut self,
+        name: &str,
+        value: &'s JSONElement,
+        siblings: &'s HashMap<String, JSONElement>,
+        location: &mut Vec<String>,
+    ) -> Result<Option<Rule>, String> {
+        let pointer = format_pointer(location);
+        let number = || as_number(value).ok_or_else(|| format!("{} must be a number", pointer));
+        let count = || match value {
+            JSONElement::Number(n) if *n >= 0 => Ok(*n as usize),
+            JSONElement::Float(f) if *f >= 0.0 && f.fract() == 0.0 => Ok(*f as usize),
+            _ => Err(format!("{} must be a non-negative integer", pointer)),
+        };
+        let rule = match name {
+            "type" => Rule::Type(match value {
+                JSONElement::String(name) => vec![check_type_name(name, &pointer)?],
+                JSONElement::Array(names) => names
+                    .iter()
+                    .map(|name| match name {
+                        JSONElement::String(name) => check_type_name(name, &pointer),
+                        _ => Err(format!("{} must only list type names", pointer)),
+                    })
+                    .collect::<Result<_, _>>()?,
+                _ => return Err(format!("{} must be a string or an array", pointer)),
+            }),
+            "enum" => match value {
+                JSONElement::Array(values) => Rule::Enum(values.clone()),
+                _ => return Err(format!("{} must be an array", pointer)),
+            },
+            "const" => Rule::Const(value.clone()),
+            "minimum" => Rule::Minimum(number()?),
+            "maximum" => Rule::Maximum(number()?),
+            "exclusiveMinimum" => Rule::ExclusiveMinimum(number()?),
+            "exclusiveMaximum" => Rule::ExclusiveMaximum(number()?),
+            "multipleOf" => match number()? {
+                divisor if divisor > 0.0 => Rule::MultipleOf(divisor),
+                _ => return Err(format!("{} must be greater than 0", pointer)),
+            },
+            "minLength" => Rule::MinLength(count()?),
+            "maxLength" => Rule::MaxLength(count()?),
+            "pattern" => Rule::Pattern(self.regex(value, &pointer)?),
+            "minItems" => Rule::MinItems(count()?),
+            "maxItems" => Rule::MaxItems(count()?),
+            "uniqueItems" => match value {
+                JSONElement::Boolean(true) => Rule::UniqueItems,
+                JSONElement::Boolean(false) => return Ok(None),
+                _ => return Err(format!("{} must be a boolean", pointer)),
+            },
+            "minProperties" => Rule::MinProperties(count()?),
+            "maxProperties" => Rule::MaxProperties(count()?),
+            "required" => Rule::Required(match value {
+                JSONElement::Array(keys) => keys
+                    .iter()
+                    .map(|key| match key {
+                        JSONElement::String(key) => Ok(key.clone()),
+                        _ => Err(format!("{} must only list strings", pointer)),
+                    })
+                    .collect::<Result<_, _>>()?,
+                _ => return Err(format!("{} must be an array", pointer)),
+            }),
+            "properties" => {
+                Rule::Properties(self.schema_map(value, location)?.into_iter().collect())
+            }
+            "patternProperties" => Rule::PatternProperties(
+                self.schema_map(value, location)?
+                    .into_iter()
+                    .map(|(pattern, id)| {
+                        Ok((self.regex(&JSONElement::String(pattern), &pointer)?, id))
+                    })
+                    .collect::<Result<_, String>>()?,
+            ),
+            "additionalProperties" => {
+                let known = match siblings.get("properties") {
+                    Some(JSONElement::Object(properties)) => properties.keys().cloned().collect(),
+                    _ => vec![],
+                };
+                let patterns = match siblings.get("patternProperties") {
+                    Some(JSONElement::Object(patterns)) => sorted(patterns)
+                        .into_iter()
+                        .map(|(pattern, _)| {
+                            self.regex(&JSONElement::String(pattern.clone()), &pointer)
+                        })
+                        .collect::<Result<_, _>>()?,
+                    _ => vec![],
+                };
+                Rule::AdditionalProperties(self.compile(value, location)?, known, patterns)
+            }
+            "prefixItems" => Rule::PrefixItems(self.schema_list(value, location)?),
+            "items" => {
+                let skip = match siblings.get("prefixItems") {
+                    Some(JSONElement::Array(prefix)) => prefix.len(),
+                    _ => 0,
+                };
+                Rule::Items(self.compile(value, location)?, skip)
+            }
+            "allOf" => Rule::AllOf(self.schema_list(value, location)?),
+            "anyOf" => Rule::AnyOf(self.schema_list(value, location)?),
+            "oneOf" => Rule::OneOf(self.schema_list(value, location)?),
+            "not" => Rule::Not(self.compile(value, location)?),
+            "if" => {
+                let condition = self.compile(value, location)?;
+                let mut branch =
+                    |name: &str, compiler: &mut Compiler<'s>| -> Result<Option<usize>, String> {
+                        let Some(branch) = siblings.get(name) else {
+                            return Ok(None);
+                        };
+                        location.pop();
+                        location.push(name.to_string());
+                        let id = compiler.compile(branch, location);
+                        location.pop();
+                        location.push("if".to_string());
+                        id.map(Some)
+                    };
+                let then = branch("then", self)?;
+                let otherwise = branch("else", self)?;
+                Rule::If(condition, then, otherwise)
+            }
+            "$ref" => match value {
+                JSONElement::String(reference) => Rule::Ref(reference.clone(), None),
+                _ => return Err(format!("{} must be a string", pointer)),
+            },
+            // "then" and "else" do nothing without "if", and are compiled along with it
+            _ => return Ok(None),
+        };
+        Ok(Some(rule))
+    }
+
+    fn regex(&self, value: &JSONElement, pointer: &str) -> Result<Regex, String> {
+        match value {
+            JSONElement::String(pattern) => {
+                Regex::new(pattern).map_err(|err| format!("{} at {}", err, pointer))
+            }
+            _ => Err(format!("{} must be a string", pointer)),
+        }
+    }
+
+    // Compiles the subschemas of a keyword like "properties", in key order
+    fn schema_map(
+        &mut self,
+        value: &'s JSONElement,
+        location: &mut Vec<String>,
+    ) -> Result<Vec<(String, usize)>, String> {
+        let JSONElement::Object(members) = value else {
+            return Err(format!("{} must be an object", format_pointer(location)));
+        };
+        sorted(members)
+            .into_iter()
+            .map(|(key, schema)| {
+                location.push(key.clone());
+                let id = self.compile(schema, location);
+                location.pop();
+                Ok((key.clone(), id?))
+            })
+            .collect()
+    }
+
+    // Compiles the subschemas of a keyword like "allOf"
+    fn schema_list(
+        &mut self,
+        value: &'s JSONElement,
+        location: &mut Vec<String>,
+    ) -> Result<Vec<usize>, String> {
+        let JSONElement::Array(schemas) = value else {
+            return Err(format!("{} must be an array", format_pointer(location)));
+        };
+        schemas
+            .iter()
+            .enumerate()
+            .map(|(i, schema)| {
+                location.push(i.to_string());
+                let id = self.compile(schema, location);
+                location.pop();
+                id
+            })
+            .collect()
+    }
+
+    // Points every $ref at the subschema it names, compiling any that haven't been compiled yet
+    // (like ones under "definitions"). Those can have $refs of their own, so this goes until
+    // there are none left.
+    fn link(&mut self) -> Result<(), String> {
+        let mut node = 0;
+        while node < self.nodes.len() {
+            let references: Vec<(usize, String)> = match &self.nodes[node] {
+                Node::Keywords(keywords) => keywords
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, keyword)| match &keyword.rule {
+                        Rule::Ref(reference, None) => Some((i, reference.clone())),
+                        _ => None,
+                    })
+                    .collect(),
+                Node::Boolean(..) => vec![],
+            };
+            for (i, reference) in references {
+                let target = self.target(&reference)?;
+                if let Node::Keywords(keywords) = &mut self.nodes[node] {
+                    keywords[i].rule = Rule::Ref(reference, Some(target));
+                }
+            }
+            node += 1;
+        }
+        Ok(())
+    }
+
+    fn target(&mut self, reference: &str) -> Result<usize, String> {
+        let local = match self.base {
+            Some(base) if !base.is_empty() => reference.strip_prefix(base).unwrap_or(reference),
+            _ => reference,
+        };
+        let fragment = local.strip_prefix('#').ok_or_else(|| {
+            format!(
+                "Only references within the schema are supported, not {}",
+                reference
+            )
+        })?;
+        let pointer = if fragment.is_empty() || fragment.starts_with('/') {
+            percent_decode(fragment)
+        } else {
+            self.anchors
+                .get(fragment)
+                .cloned()
+                .ok_or_else(|| format!("There is no $anchor {}", fragment))?
+        };
+        if let Some(id) = self.compiled.get(&pointer) {
+            return Ok(*id);
+        }
+        let schema = resolve(self.root, &pointer)
+            .map_err(|err| format!("Can't resolve the reference {}: {}", reference, err))?;
+        self.compile(schema, &mut parse_pointer(&pointer)?)
+    }
+}
+
+fn check_type_name(name: &str, pointer: &str) -> Result<String, String> {
+    match name {
+        "null" | "boolean" | "object" | "array" | "number" | "integer" | "string" => {
+            Ok(name.to_string())
+        }
+        _ => Err(format!("{} is not a type, at {}", name, pointer)),
+    }
+}
+
+fn has_type(instance: &JSONElement, name: &str) -> bool {
+    match (name, instance) {
+        ("integer", JSONElement::Float(f)) => f.fract() == 0.0,
+        ("number", JSONElement::Number(_)) => true,
+        _ => type_name(instance) == name,
+    }
+}
+
+fn type_name(instance: &JSONElement) -> &'static str {
+    match instance {
+        JSONElement::Object(_) => "object",
+        JSONElement::Array(_) => "array",
+        JSONElement::String(_) => "string",
+        JSONElement::Number(_) => "integer",
+        JSONElement::Float(_) => "number",
+        JSONElement::Boolean(_) => "boolean",
+        JSONElement::Null => "null",
+    }
+}
+
+fn as_number(instance: &JSONElement) -> Option<f64> {
+    match instance {
+        JSONElement::Number(n) => Some(*n as f64),
+        JSONElement::Float(f) => Some(*f),
+        _ => None,
+    }
+}
+
+// Whole numbers are checked exactly, since the remainder of one float by another is exact.
+// Otherwise this allows for the rounding error in dividing by something like 0.01.
+fn is_multiple(n: f64, divisor: f64) -> bool {
+    if n.fract() == 0.0 && divisor.fract() == 0.0 {
+        return n % divisor == 0.0;
+    }
+    let quotient = n / divisor;
+    quotient.is_finite() && (quotient - quotient.round()).abs() < 1e-9 * quotient.abs().max(1.0)
+}
+
+/// JSON equality, where numbers are equal when they have the same value whichever way they're
+/// written (so 1 and 1.0 are the same)
+pub fn equal(a: &JSONElement, b: &JSONElement) -> bool {
+    match (a, b) {
+        (JSONElement::Object(a), JSONElement::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(key, value)| b.get(key).is_some_and(|other| equal(value, other)))
+        }
+        (JSONElement::Array(a), JSONElement::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| equal(a, b))
+        }
+        _ => match (as_number(a), as_number(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => a == b,
+        },
+    }
+}
+
+// Object members in key order, so errors always come out in the same order
+fn sorted(members: &HashMap<String, JSONElement>) -> Vec<(&String, &JSONElement)> {
+    let mut members: Vec<_> = members.iter().collect();
+    members.sort_by(|a, b| a.0.cmp(b.0));
+    members
+}
@@ -0,0 +1,607 @@
+use std::collections::HashMap;
+
+mod tests;
+
+// Patterns whose repetitions expand to more instructions than this are rejected
+const MAX_INSTRUCTIONS: usize = 100_000;
+
+/// The subset of ECMA-262 regular expressions that JSON Schema recommends for `pattern` and
+/// `patternProperties`: literals, ".", classes, the \d \w \s escapes, anchors, word boundaries,
+/// groups, lookahead, alternation and every kind of quantifier. Backreferences, Unicode property
+/// escapes like \p{L} and flags aren't supported. Patterns are compiled to an automaton that
+/// follows every way of matching at once, so matching takes time proportional to the length of
+/// the text times the size of the pattern, even for patterns like (a+)+$ that make backtracking
+/// engines take exponential time.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    source: String,
+    // The whole pattern, followed by one program per lookahead
+    programs: Vec<Vec<Instruction>>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Literal(char),
+    // "." matches anything but a line terminator
+    Any,
+    Class(Vec<ClassItem>, bool),
+    Start,
+    End,
+    WordBoundary(bool),
+    Lookahead(Box<Node>, bool),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat(Box<Node>, usize, Option<usize>),
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    // \d, \w and \s, or their negations
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            ClassItem::Range(low, high) => *low <= c && c <= *high,
+            ClassItem::Digit(negated) => c.is_ascii_digit() != *negated,
+            ClassItem::Word(negated) => is_word(c) != *negated,
+            ClassItem::Space(negated) => is_space(c) != *negated,
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// ECMA-262's WhiteSpace and LineTerminator
+fn is_space(c: char) -> bool {
+    c.is_whitespace() || c == '\u{feff}'
+}
+
+fn is_line_terminator(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
+// The program for a pattern. Instructions that don't consume a character are followed as soon
+// as a thread reaches them, and the others wait for the next character.
+#[derive(Debug, Clone)]
+enum Instruction {
+    // Consumes a character matching a Literal, Any or Class node
+    Character(Node),
+    Start,
+    End,
+    WordBoundary(bool),
+    // Whether the program with this index matches here has to be the bool
+    Lookahead(usize, bool),
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+impl Regex {
+    pub fn new(source: &str) -> Result<Regex, String> {
+        let mut parser = RegexParser {
+            chars: source.chars().collect(),
+            position: 0,
+        };
+        let node = parser
+            .alternation()
+            .map_err(|err| format!("Bad pattern {}: {}", source, err))?;
+        if parser.position < parser.chars.len() {
+            return Err(format!("Bad pattern {}: unmatched )", source));
+        }
+        let mut compiler = Compiler {
+            programs: vec![vec![]],
+            size: 0,
+        };
+        compiler
+            .program(0, &node)
+            .map_err(|err| format!("Bad pattern {}: {}", source, err))?;
+        Ok(Regex {
+            source: source.to_string(),
+            programs: compiler.programs,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+
+    /// Whether the pattern matches anywhere in `text`. Like JavaScript's `RegExp.test`, the
+    /// pattern isn't implicitly anchored.
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.run(0, &text, 0, &mut HashMap::new())
+    }
+
+    // Runs a program from `start`, with every thread moving forward one character at a time.
+    // The whole pattern can start at any position, while a lookahead only starts at `start`.
+    fn run(
+        &self,
+        program: usize,
+        text: &[char],
+        start: usize,
+        lookaheads: &mut HashMap<(usize, usize), bool>,
+    ) -> bool {
+        let instructions = &self.programs[program];
+        let mut seen = vec![false; instructions.len()];
+        let mut threads = vec![];
+        if self.follow(program, 0, text, start, &mut threads, &mut seen, lookaheads) {
+            return true;
+        }
+        for at in start..text.len() {
+            seen.fill(false);
+            let mut next = vec![];
+            for pc in threads {
+                let Instruction::Character(node) = &instructions[pc] else {
+                    panic!("only threads waiting for a character are kept");
+                };
+                let consumed = match node {
+                    Node::Literal(c) => text[at] == *c,
+                    Node::Any => !is_line_terminator(text[at]),
+                    Node::Class(items, negated) => {
+                        items.iter().any(|item| item.matches(text[at])) != *negated
+                    }
+                    _ => panic!("only characters are consumed"),
+                };
+                if consumed
+                    && self.follow(
+                        program,
+                        pc + 1,
+                        text,
+                        at + 1,
+                        &mut next,
+                        &mut seen,
+                        lookaheads,
+                    )
+                {
+                    return true;
+                }
+            }
+            if program == 0
+                && self.follow(program, 0, text, at + 1, &mut next, &mut seen, lookaheads)
+            {
+                return true;
+            }
+            if next.is_empty() && program != 0 {
+                return false;
+            }
+            threads = next;
+        }
+        false
+    }
+
+    // Adds a thread at `pc`, following every instruction that doesn't consume a character, and
+    // returns whether one of them reaches a match
+    #[allow(clippy::too_many_arguments)]
+    fn follow(
+        &self,
+        program: usize,
+        pc: usize,
+        text: &[char],
+        at: usize,
+        threads: &mut Vec<usize>,
+        seen: &mut [bool],
+        lookaheads: &mut HashMap<(usize, usize), bool>,
+    ) -> bool {
+        let instructions = &self.programs[program];
+        let mut stack = vec![pc];
+        while let Some(pc) = stack.pop() {
+            // A thread that's already here would only do the same again, which also stops
+            // repetitions of something that can match nothing from going on forever
+            if std::mem::replace(&mut seen[pc], true) {
+                continue;
+            }
+            match &instructions[pc] {
+                Instruction::Character(_) => threads.push(pc),
+                Instruction::Start if at == 0 => stack.push(pc + 1),
+                Instruction::End if at == text.len() => stack.push(pc + 1),
+                Instruction::WordBoundary(expected) => {
+                    let before = at > 0 && is_word(text[at - 1]);
+                    let after = text.get(at).is_some_and(|c| is_word(*c));
+                    if (before != after) == *expected {
+                        stack.push(pc + 1);
+                    }
+                }
+                Instruction::Lookahead(lookahead, expected) => {
+                    let matched = match lookaheads.get(&(*lookahead, at)) {
+                        Some(matched) => *matched,
+                        None => {
+                            let matched = self.run(*lookahead, text, at, lookaheads);
+                            lookaheads.insert((*lookahead, at), matched);
+                            matched
+                        }
+                    };
+                    if matched == *expected {
+                        stack.push(pc + 1);
+                    }
+                }
+                Instruction::Split(first, second) => stack.extend([*second, *first]),
+                Instruction::Jump(target) => stack.push(*target),
+                Instruction::Match => return true,
+                Instruction::Start | Instruction::End => {}
+            }
+        }
+        false
+    }
+}
+
+struct Compiler {
+    programs: Vec<Vec<Instruction>>,
+    // The number of instructions in all the programs
+    size: usize,
+}
+
+impl Compiler {
+    fn program(&mut self, program: usize, node: &Node) -> Result<(), String> {
+        self.compile(program, node)?;
+        self.push(program, Instruction::Match)?;
+        Ok(())
+    }
+
+    // Adds an instruction, returning its position
+    fn push(&mut self, program: usize, instruction: Instruction) -> Result<usize, String> {
+        self.size += 1;
+        if self.size > MAX_INSTRUCTIONS {
+            return Err("its repetitions make it too large".to_string());
+        }
+        self.programs[program].push(instruction);
+        Ok(self.programs[program].len() - 1)
+    }
+
+    fn next(&self, program: usize) -> usize {
+        self.programs[program].len()
+    }
+
+    // Points a Split's second branch or a Jump at `target`
+    fn patch(&mut self, program: usize, pc: usize, target: usize) {
+        match &mut self.programs[program][pc] {
+            Instruction::Split(_, second) => *second = target,
+            Instruction::Jump(to) => *to = target,
+            _ => panic!("only splits and jumps are patched"),
+        }
+    }
+
+    fn compile(&mut self, program: usize, node: &Node) -> Result<(), String> {
+        match node {
+            Node::Literal(_) | Node::Any | Node::Class(..) => {
+                self.push(program, Instruction::Character(node.clone()))?;
+            }
+            Node::Start => {
+                self.push(program, Instruction::Start)?;
+            }
+            Node::End => {
+                self.push(program, Instruction::End)?;
+            }
+            Node::WordBoundary(expected) => {
+                self.push(program, Instruction::WordBoundary(*expected))?;
+            }
+            Node::Lookahead(node, expected) => {
+                let lookahead = self.programs.len();
+                self.programs.push(vec![]);
+                self.program(lookahead, node)?;
+                self.push(program, Instruction::Lookahead(lookahead, *expected))?;
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(program, node)?;
+                }
+            }
+            Node::Alternate(nodes) => {
+                let mut jumps = vec![];
+                let (last, rest) = nodes.split_last().expect("alternations have options");
+                for node in rest {
+                    let split = self.push(program, Instruction::Split(0, 0))?;
+                    let first = self.next(program);
+                    self.patch_first(program, split, first);
+                    self.compile(program, node)?;
+                    jumps.push(self.push(program, Instruction::Jump(0))?);
+                    let second = self.next(program);
+                    self.patch(program, split, second);
+                }
+                self.compile(program, last)?;
+                let end = self.next(program);
+                for jump in jumps {
+                    self.patch(program, jump, end);
+                }
+            }
+            Node::Repeat(node, min, max) => {
+                for _ in 0..*min {
+                    self.compile(program, node)?;
+                }
+                match max {
+                    None => {
+                        let split = self.push(program, Instruction::Split(0, 0))?;
+                        self.patch_first(program, split, split + 1);
+                        self.compile(program, node)?;
+                        self.push(program, Instruction::Jump(split))?;
+                        let end = self.next(program);
+                        self.patch(program, split, end);
+                    }
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            let split = self.push(program, Instruction::Split(0, 0))?;
+                            self.patch_first(program, split, split + 1);
+                            splits.push(split);
+                            self.compile(program, node)?;
+                        }
+                        let end = self.next(program);
+                        for split in splits {
+                            self.patch(program, split, end);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn patch_first(&mut self, program: usize, pc: usize, target: usize) {
+        if let Instruction::Split(first, _) = &mut self.programs[program][pc] {
+            *first = target;
+        }
+    }
+}
+
+struct RegexParser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl RegexParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn eat_str(&mut self, s: &str) -> bool {
+        let found = s
+            .chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.position + i) == Some(&c));
+        if found {
+            self.position += s.chars().count();
+        }
+        found
+    }
+
+    fn next(&mut self) -> Result<char, String> {
+        let c = self.peek().ok_or("it ends too soon")?;
+        self.position += 1;
+        Ok(c)
+    }
+
+    fn alternation(&mut self) -> Result<Node, String> {
+        let mut options = vec![self.concat()?];
+        while self.eat('|') {
+            options.push(self.concat()?);
+        }
+        Ok(if options.len() == 1 {
+            options.remove(0)
+        } else {
+            Node::Alternate(options)
+        })
+    }
+
+    fn concat(&mut self) -> Result<Node, String> {
+        let mut nodes = vec![];
+        while !matches!(self.peek(), None | Some('|') | Some(')')) {
+            let atom = self.atom()?;
+            nodes.push(self.quantifier(atom)?);
+        }
+        Ok(Node::Concat(nodes))
+    }
+
+    fn quantifier(&mut self, atom: Node) -> Result<Node, String> {
+        let start = self.position;
+        let (min, max) = match self.peek() {
+            Some('*') => (0, None),
+            Some('+') => (1, None),
+            Some('?') => (0, Some(1)),
+            Some('{') => match self.bounds() {
+                Some(bounds) => bounds,
+                // Not a quantifier after all, so the "{" is just a character
+                None => return Ok(atom),
+            },
+            _ => return Ok(atom),
+        };
+        if self.position == start {
+            self.position += 1;
+        }
+        if let Some(max) = max {
+            if max < min {
+                return Err("a quantifier's maximum is below its minimum".to_string());
+            }
+        }
+        if matches!(
+            atom,
+            Node::Start | Node::End | Node::WordBoundary(_) | Node::Lookahead(..)
+        ) {
+            return Err("nothing to repeat".to_string());
+        }
+        // Whether it's lazy makes no difference to whether there's a match
+        self.eat('?');
+        Ok(Node::Repeat(Box::new(atom), min, max))
+    }
+
+    // Reads "{n}", "{n,}" or "{n,m}", leaving the position alone if it's something else
+    fn bounds(&mut self) -> Option<(usize, Option<usize>)> {
+        let start = self.position;
+        let number = |parser: &mut RegexParser| {
+            let digits_start = parser.position;
+            while parser.peek().is_some_and(|c| c.is_ascii_digit()) {
+                parser.position += 1;
+            }
+            parser.chars[digits_start..parser.position]
+                .iter()
+                .collect::<String>()
+                .parse::<usize>()
+                .ok()
+        };
+        self.position += 1;
+        let bounds = number(self).and_then(|min| {
+            if self.eat('}') {
+                return Some((min, Some(min)));
+            }
+            if !self.eat(',') {
+                return None;
+            }
+            if self.eat('}') {
+                return Some((min, None));
+            }
+            let max = number(self)?;
+            self.eat('}').then_some((min, Some(max)))
+        });
+        if bounds.is_none() {
+            self.position = start;
+        }
+        bounds
+    }
+
+    fn atom(&mut self) -> Result<Node, String> {
+        match self.next()? {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '[' => self.class(),
+            '(' => {
+                let lookahead = if self.eat_str("?:") {
+                    None
+                } else if self.eat_str("?=") {
+                    Some(true)
+                } else if self.eat_str("?!") {
+                    Some(false)
+                } else if self.eat_str("?<") {
+                    // A named group, which matches the same as any other group
+                    while self.next()? != '>' {}
+                    None
+                } else {
+                    None
+                };
+                let node = self.alternation()?;
+                if !self.eat(')') {
+                    return Err("unclosed (".to_string());
+                }
+                Ok(match lookahead {
+                    Some(expected) => Node::Lookahead(Box::new(node), expected),
+                    None => node,
+                })
+            }
+            '\\' => match self.next()? {
+                'b' => Ok(Node::WordBoundary(true)),
+                'B' => Ok(Node::WordBoundary(false)),
+                c if c.is_ascii_digit() && c != '0' => {
+                    Err("backreferences are not supported".to_string())
+                }
+                c => Ok(match self.class_escape(c)? {
+                    ClassItem::Range(c, _) => Node::Literal(c),
+                    item => Node::Class(vec![item], false),
+                }),
+            },
+            c @ ('*' | '+' | '?') => Err(format!("nothing to repeat before {}", c)),
+            c => Ok(Node::Literal(c)),
+        }
+    }
+
+    // Reads what comes after "[", up to and including the "]"
+    fn class(&mut self) -> Result<Node, String> {
+        let negated = self.eat('^');
+        let mut items = vec![];
+        loop {
+            let item = match self.next().map_err(|_| "unclosed [".to_string())? {
+                ']' => return Ok(Node::Class(items, negated)),
+                '\\' => {
+                    let c = self.next()?;
+                    // Inside a class, \b is a backspace
+                    if c == 'b' {
+                        ClassItem::Range('\u{8}', '\u{8}')
+                    } else {
+                        self.class_escape(c)?
+                    }
+                }
+                c => ClassItem::Range(c, c),
+            };
+            let is_range = self.peek() == Some('-')
+                && !matches!(self.chars.get(self.position + 1), None | Some(']'));
+            match item {
+                ClassItem::Range(low, _) if is_range => {
+                    self.position += 1;
+                    let high = match self.next()? {
+                        '\\' => {
+                            let c = self.next()?;
+                            match self.class_escape(c)? {
+                                ClassItem::Range(high, _) => high,
+                                _ => return Err("a class range ends in a class".to_string()),
+                            }
+                        }
+                        c => c,
+                    };
+                    if high < low {
+                        return Err(format!("the class range {}-{} is out of order", low, high));
+                    }
+                    items.push(ClassItem::Range(low, high));
+                }
+                item => items.push(item),
+            }
+        }
+    }
+
+    // An escape that stands for a single character or a class, after its "\"
+    fn class_escape(&mut self, c: char) -> Result<ClassItem, String> {
+        let single = |c| Ok(ClassItem::Range(c, c));
+        match c {
+            'd' | 'D' => Ok(ClassItem::Digit(c == 'D')),
+            'w' | 'W' => Ok(ClassItem::Word(c == 'W')),
+            's' | 'S' => Ok(ClassItem::Space(c == 'S')),
+            'n' => single('\n'),
+            'r' => single('\r'),
+            't' => single('\t'),
+            'f' => single('\u{c}'),
+            'v' => single('\u{b}'),
+            '0' => single('\0'),
+            'p' | 'P' => Err("Unicode property escapes like \\p{L} are not supported".to_string()),
+            'c' => {
+                let letter = self.next()?;
+                if !letter.is_ascii_alphabetic() {
+                    return Err(format!("\\c{} is not a control character", letter));
+                }
+                single(char::from(letter as u8 % 32))
+            }
+            'x' => self.hex(2).and_then(single),
+            'u' if self.eat('{') => {
+                let start = self.position;
+                while self.next()? != '}' {}
+                let hex: String = self.chars[start..self.position - 1].iter().collect();
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("\\u{{{}}} is not a character", hex))
+                    .and_then(single)
+            }
+            'u' => self.hex(4).and_then(single),
+            c if c.is_ascii_alphanumeric() => Err(format!("\\{} is not a known escape", c)),
+            c => single(c),
+        }
+    }
+
+    fn hex(&mut self, digits: usize) -> Result<char, String> {
+        let hex: String = (0..digits).map(|_| self.next()).collect::<Result<_, _>>()?;
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| format!("{} is not a hex character code", hex))
+    }
+}
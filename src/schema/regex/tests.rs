@@ -0,0 +1,71 @@
+#[cfg(test)]
+use crate::schema::regex::*;
+
+#[cfg(test)]
+fn is_match(pattern: &str, text: &str) -> bool {
+    Regex::new(pattern).unwrap().is_match(text)
+}
+
+#[test]
+fn matches_anywhere_unless_anchored() {
+    assert!(is_match("b+", "abbc"));
+    assert!(!is_match("^b+", "abbc"));
+    assert!(is_match("^a.c$", "abc"));
+    assert!(!is_match("^a.c$", "a\nc"));
+    assert!(is_match("", "anything"));
+}
+
+#[test]
+fn matches_classes() {
+    assert!(is_match("^[a-c_]+$", "ab_c"));
+    assert!(!is_match("^[^a-c]$", "b"));
+    assert!(is_match(r"^\d{3}-\w+\s?$", "555-abc "));
+    assert!(!is_match(r"^\D", "5"));
+    assert!(is_match(r"^[\d.-]+$", "1.5-2"));
+    assert!(is_match(r"^é\x41$", "éA"));
+    assert!(is_match(r"\bcat\b", "a cat!"));
+    assert!(!is_match(r"\bcat\b", "concatenate"));
+}
+
+#[test]
+fn backtracks_through_quantifiers() {
+    assert!(is_match("^(a|ab)(c|bcd)d$", "abcd"));
+    assert!(is_match("^a{2,3}b$", "aaab"));
+    assert!(!is_match("^a{2,3}b$", "aaaab"));
+    assert!(is_match("^a{2,}$", "aaaaa"));
+    assert!(is_match("^(?:x*)*y$", "xxy"));
+    assert!(is_match("^a{,2}$", "a{,2}"));
+    assert!(is_match("^(?=.*1)[a-z1]+$", "ab1"));
+    assert!(!is_match("^(?!foo)", "foobar"));
+    assert!(is_match("^.*?x$", "aax"));
+}
+
+#[test]
+fn rejects_bad_patterns() {
+    for pattern in ["(a", "a)", "[a", "*a", "a{3,1}", r"(a)\1", r"\q", "[z-a]"] {
+        assert!(Regex::new(pattern).is_err(), "{}", pattern);
+    }
+}
+
+#[test]
+fn matches_in_linear_time() {
+    let started = std::time::Instant::now();
+    let text = format!("{}b", "a".repeat(35));
+    assert!(!is_match("(a+)+$", &text));
+    assert!(!is_match("^(a|aa)*$", &text));
+    assert!(is_match("(a*)*b", &text));
+    assert!(started.elapsed().as_secs() < 5);
+    assert!(is_match("^(?:a{2,3}){2}$", "aaaaa"));
+    assert!(!is_match("^(?:a{2,3}){2}$", "aaa"));
+    assert!(is_match("^(?=.*\\d)(?!.*x)\\w+$", "ab1"));
+    assert!(!is_match("^(?=.*\\d)(?!.*x)\\w+$", "ax1"));
+    let err = Regex::new("(a{1000}){1000}").unwrap_err();
+    assert!(err.contains("too large"), "{}", err);
+}
+
+#[test]
+fn rejects_property_escapes() {
+    let err = Regex::new(r"^\p{L}+$").unwrap_err();
+    assert!(err.contains("property escapes"), "{}", err);
+    assert!(Regex::new(r"[\P{L}]").is_err());
+}
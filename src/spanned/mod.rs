@@ -0,0 +1,211 @@
+use crate::ast_parser::unescape;
+use crate::cst::{parse_cst_with, CstNode, CstOptions, NodeKind, Span};
+use crate::pointer::{parse_index, parse_pointer};
+use crate::rustifier::JSONElement;
+use std::collections::HashMap;
+use std::fmt;
+
+mod tests;
+
+/// A 1-based line and column. Columns count characters, not bytes.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Where a value sits in the text it was parsed from
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Location {
+    pub span: Span,
+    pub start: Position,
+    pub end: Position,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.start.line, self.start.column)
+    }
+}
+
+/// Turns byte offsets into line/column positions. Building it is a single pass over the text,
+/// after which every lookup is a binary search.
+pub struct LineIndex<'i> {
+    text: &'i str,
+    // Byte offset of the start of every line
+    line_starts: Vec<usize>,
+}
+
+impl<'i> LineIndex<'i> {
+    pub fn new(text: &'i str) -> LineIndex<'i> {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { text, line_starts }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        Position {
+            line: line + 1,
+            column: self.text[line_start..offset].chars().count() + 1,
+        }
+    }
+
+    /// Like `position`, but with the column counted in UTF-16 code units, which is what editors
+    /// speaking the Language Server Protocol expect
+    pub fn utf16_position(&self, offset: usize) -> Position {
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let line_start = self.line_starts[line];
+        Position {
+            line: line + 1,
+            column: self.text[line_start..offset].encode_utf16().count() + 1,
+        }
+    }
+
+    /// The byte offset of a `utf16_position`. Positions past the end of a line or the text are
+    /// moved back to the end.
+    pub fn utf16_offset(&self, position: Position) -> usize {
+        let Some(line_start) = self.line_starts.get(position.line.saturating_sub(1)) else {
+            return self.text.len();
+        };
+        let line = &self.text[*line_start..];
+        let line = &line[..line.find('\n').unwrap_or(line.len())];
+        let mut units = 0;
+        for (i, c) in line.char_indices() {
+            if units + 1 >= position.column {
+                return line_start + i;
+            }
+            units += c.len_utf16();
+        }
+        line_start + line.len()
+    }
+
+    pub fn location(&self, span: Span) -> Location {
+        Location {
+            span,
+            start: self.position(span.start),
+            end: self.position(span.end),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SpannedElement {
+    Object(Vec<SpannedMember>),
+    Array(Vec<Spanned>),
+    // Any string, number, boolean or null
+    Scalar(JSONElement),
+}
+
+/// An object member. Members are kept in document order, duplicates included.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SpannedMember {
+    pub key: String,
+    // The key's location, quotes included
+    pub key_location: Location,
+    pub value: Spanned,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Spanned {
+    pub element: SpannedElement,
+    pub location: Location,
+}
+
+impl Spanned {
+    /// Follows a JSON pointer down to the value it names
+    pub fn pointer(&self, pointer: &str) -> Result<&Spanned, String> {
+        parse_pointer(pointer)?
+            .iter()
+            .try_fold(self, |current, token| match &current.element {
+                // Later duplicates win, the same as when converting to a JSONElement
+                SpannedElement::Object(members) => members
+                    .iter()
+                    .rev()
+                    .find(|member| member.key == *token)
+                    .map(|member| &member.value)
+                    .ok_or_else(|| format!("{} has no member {}", pointer, token)),
+                SpannedElement::Array(items) => Ok(&items[parse_index(token, items.len(), false)?]),
+                SpannedElement::Scalar(_) => Err(format!(
+                    "{} goes through a value that isn't a container",
+                    pointer
+                )),
+            })
+    }
+
+    /// The location of the value at `pointer`, or of its key when `key` is set. The root value
+    /// has no key.
+    pub fn locate(&self, pointer: &str, key: bool) -> Result<Location, String> {
+        if !key {
+            return Ok(self.pointer(pointer)?.location);
+        }
+        let tokens = parse_pointer(pointer)?;
+        let (last, _) = tokens.split_last().ok_or("The root value has no key")?;
+        let parent = &pointer[..pointer.rfind('/').expect("pointer has at least one token")];
+        match &self.pointer(parent)?.element {
+            SpannedElement::Object(members) => members
+                .iter()
+                .rev()
+                .find(|member| member.key == *last)
+                .map(|member| member.key_location)
+                .ok_or_else(|| format!("{} has no member {}", pointer, last)),
+            _ => Err(format!("{} is not an object member", pointer)),
+        }
+    }
+
+    pub fn to_element(&self) -> JSONElement {
+        match &self.element {
+            SpannedElement::Object(members) => JSONElement::Object(
+                members
+                    .iter()
+                    .map(|member| (member.key.clone(), member.value.to_element()))
+                    .collect::<HashMap<_, _>>(),
+            ),
+            SpannedElement::Array(items) => {
+                JSONElement::Array(items.iter().map(Spanned::to_element).collect())
+            }
+            SpannedElement::Scalar(element) => element.clone(),
+        }
+    }
+}
+
+/// Like `loads`, but every value and key remembers where it came from
+pub fn loads_spanned(s: &str) -> Result<Spanned, String> {
+    loads_spanned_with(s, CstOptions::default())
+}
+
+pub fn loads_spanned_with(s: &str, options: CstOptions) -> Result<Spanned, String> {
+    let document = parse_cst_with(s, options)?;
+    let index = LineIndex::new(s);
+    spanned(document.value().ok_or("Document had no value")?, &index)
+}
+
+fn spanned(node: &CstNode, index: &LineIndex) -> Result<Spanned, String> {
+    let element = match node.kind {
+        NodeKind::Object => SpannedElement::Object(
+            node.child_nodes()
+                .map(|member| {
+                    let key = member.key().ok_or("Member node had no key")?;
+                    let value = member.value().ok_or("Member node had no value")?;
+                    Ok(SpannedMember {
+                        key: unescape(&key.text[1..key.text.len() - 1])?,
+                        key_location: index.location(key.span),
+                        value: spanned(value, index)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?,
+        ),
+        NodeKind::Array => SpannedElement::Array(
+            node.child_nodes()
+                .map(|item| spanned(item, index))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        _ => SpannedElement::Scalar(node.to_element()?),
+    };
+    Ok(Spanned {
+        element,
+        location: index.location(node.span),
+    })
+}
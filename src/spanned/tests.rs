@@ -0,0 +1,128 @@
+#[cfg(test)]
+use crate::cst::Span;
+#[cfg(test)]
+use crate::rustifier::loads;
+#[cfg(test)]
+use crate::spanned::*;
+
+#[cfg(test)]
+const CONFIG: &str = "{\n  \"server\": {\n    \"host\": \"é.example\",\n    \"port\": 70000\n  },\n  \"tags\": [\"a\", \"b\"]\n}";
+
+#[test]
+fn finds_lines_and_columns() {
+    let index = LineIndex::new("ab\néc\r\n\nd");
+    assert_eq!(index.position(0), Position { line: 1, column: 1 });
+    assert_eq!(index.position(3), Position { line: 2, column: 1 });
+    // "é" is two bytes but one column
+    assert_eq!(index.position(5), Position { line: 2, column: 2 });
+    assert_eq!(index.position(9), Position { line: 4, column: 1 });
+    assert_eq!(index.position(10), Position { line: 4, column: 2 });
+}
+
+#[test]
+fn locates_values() {
+    let spanned = loads_spanned(CONFIG).unwrap();
+    let port = spanned.pointer("/server/port").unwrap();
+    assert_eq!(
+        port.element,
+        SpannedElement::Scalar(loads("70000").unwrap())
+    );
+    assert_eq!(
+        port.location.start,
+        Position {
+            line: 4,
+            column: 13
+        }
+    );
+    assert_eq!(
+        port.location.end,
+        Position {
+            line: 4,
+            column: 18
+        }
+    );
+    assert_eq!(
+        &CONFIG[port.location.span.start..port.location.span.end],
+        "70000"
+    );
+    assert_eq!(
+        format!("{}: port must be < 65536", port.location),
+        "line 4, column 13: port must be < 65536"
+    );
+
+    let tag = spanned.locate("/tags/1", false).unwrap();
+    assert_eq!(
+        tag.start,
+        Position {
+            line: 6,
+            column: 17
+        }
+    );
+    let root = spanned.locate("", false).unwrap();
+    assert_eq!(
+        root.span,
+        Span {
+            start: 0,
+            end: CONFIG.len()
+        }
+    );
+}
+
+#[test]
+fn locates_keys() {
+    let spanned = loads_spanned(CONFIG).unwrap();
+    let key = spanned.locate("/server/host", true).unwrap();
+    assert_eq!(key.start, Position { line: 3, column: 5 });
+    assert_eq!(
+        key.end,
+        Position {
+            line: 3,
+            column: 11
+        }
+    );
+    assert!(spanned.locate("", true).is_err());
+    assert!(spanned.locate("/tags/0", true).is_err());
+    assert!(spanned.locate("/server/missing", false).is_err());
+    assert!(spanned.locate("/tags/0/deeper", false).is_err());
+}
+
+#[test]
+fn keeps_members_in_order() {
+    let spanned = loads_spanned(r#"{"b": 1, "a": 2, "b": 3}"#).unwrap();
+    match &spanned.element {
+        SpannedElement::Object(members) => {
+            let keys: Vec<&str> = members.iter().map(|member| member.key.as_str()).collect();
+            assert_eq!(keys, vec!["b", "a", "b"]);
+        }
+        other => panic!("expected an object but got {:?}", other),
+    }
+    assert_eq!(spanned.pointer("/b").unwrap().location.span.start, 22);
+}
+
+#[test]
+fn converts_to_element() {
+    let spanned = loads_spanned(CONFIG).unwrap();
+    assert_eq!(spanned.to_element(), loads(CONFIG).unwrap());
+}
+
+#[test]
+fn converts_utf16_positions() {
+    let text = "a😀b\n\"é\"";
+    let index = LineIndex::new(text);
+    // The emoji is one character but two UTF-16 code units
+    assert_eq!(index.utf16_position(5), Position { line: 1, column: 4 });
+    assert_eq!(index.utf16_offset(Position { line: 1, column: 4 }), 5);
+    assert_eq!(index.utf16_position(10), Position { line: 2, column: 3 });
+    assert_eq!(index.utf16_offset(Position { line: 2, column: 3 }), 10);
+    assert_eq!(
+        index.utf16_offset(Position {
+            line: 1,
+            column: 99
+        }),
+        6
+    );
+    assert_eq!(
+        index.utf16_offset(Position { line: 9, column: 1 }),
+        text.len()
+    );
+}
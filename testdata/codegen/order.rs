@@ -0,0 +1,92 @@
+use crate::rustifier::{from_member, FromElement, JSONElement};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub customer: Customer,
+    pub extra: Option<JSONElement>,
+    pub id: i64,
+    pub items: Vec<Item>,
+    pub metadata: Option<HashMap<String, String>>,
+    pub notes: Option<String>,
+    pub parent: Option<Box<Order>>,
+    pub status: Status,
+    pub type_: Option<String>,
+}
+
+impl FromElement for Order {
+    fn from_element(element: &JSONElement) -> Result<Order, String> {
+        match element {
+            JSONElement::Object(members) => Ok(Order {
+                customer: from_member(members, "customer")?,
+                extra: from_member(members, "extra")?,
+                id: from_member(members, "id")?,
+                items: from_member(members, "items")?,
+                metadata: from_member(members, "metadata")?,
+                notes: from_member(members, "notes")?,
+                parent: from_member(members, "parent")?,
+                status: from_member(members, "status")?,
+                type_: from_member(members, "type")?,
+            }),
+            _ => Err("Order must be an object".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Customer {
+    pub e_mail: Option<String>,
+    pub name: String,
+    pub referred_by: Option<Box<Customer>>,
+}
+
+impl FromElement for Customer {
+    fn from_element(element: &JSONElement) -> Result<Customer, String> {
+        match element {
+            JSONElement::Object(members) => Ok(Customer {
+                e_mail: from_member(members, "e-mail")?,
+                name: from_member(members, "name")?,
+                referred_by: from_member(members, "referredBy")?,
+            }),
+            _ => Err("Customer must be an object".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub quantity: i64,
+    pub sku: String,
+    pub unit_price: Option<f64>,
+}
+
+impl FromElement for Item {
+    fn from_element(element: &JSONElement) -> Result<Item, String> {
+        match element {
+            JSONElement::Object(members) => Ok(Item {
+                quantity: from_member(members, "quantity")?,
+                sku: from_member(members, "sku")?,
+                unit_price: from_member(members, "unitPrice")?,
+            }),
+            _ => Err("Item must be an object".to_string()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Pending,
+    InProgress,
+    Shipped,
+}
+
+impl FromElement for Status {
+    fn from_element(element: &JSONElement) -> Result<Status, String> {
+        match element {
+            JSONElement::String(s) if s == "pending" => Ok(Status::Pending),
+            JSONElement::String(s) if s == "in-progress" => Ok(Status::InProgress),
+            JSONElement::String(s) if s == "shipped" => Ok(Status::Shipped),
+            _ => Err(format!("{:?} is not a Status", element)),
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("testdata/cli")
+        .join(name)
+}
+
+// Runs the json binary, giving back its exit code, standard output and standard error
+fn json(args: &[&str], stdin: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_json"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // The binary doesn't read stdin when it's given files or bad arguments, so it can exit before
+    // this is written
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+fn json_file(args: &[&str], name: &str) -> (i32, String, String) {
+    let path = fixture(name);
+    let mut args = args.to_vec();
+    args.push(path.to_str().unwrap());
+    json(&args, "")
+}
+
+#[test]
+fn validates_files() {
+    let invalid = fixture("invalid.json");
+    let (code, stdout, _) = json(
+        &[
+            "validate",
+            fixture("config.json").to_str().unwrap(),
+            invalid.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(code, 1);
+    let invalid = invalid.display();
+    assert_eq!(
+        stdout,
+        format!(
+            "{}:3:22: Expected a value\n{}:4:12: Expected a value\n",
+            invalid, invalid
+        )
+    );
+    assert_eq!(
+        json(&["validate"], "[1, 2]"),
+        (0, String::new(), String::new())
+    );
+    assert_eq!(
+        json(&["validate", "-"], "[1,\n oops]").1,
+        "<stdin>:2:2: Expected a value\n"
+    );
+    assert_eq!(
+        json(&["validate"], "{\"a\" 1}").1,
+        "<stdin>:1:6: Expected \":\" but found \"1\"\n"
+    );
+}
+
+#[test]
+fn formats_and_minifies() {
+    let (code, stdout, _) = json_file(&["fmt", "--comments"], "commented.json");
+    assert_eq!(code, 0);
+    assert_eq!(
+        stdout,
+        "{\n  \"b\": [\n    1,\n    2,\n    {}\n  ],\n  // the a member\n  \"a\": {\n    \"c\": \"d\\u00e9\"\n  }\n}\n"
+    );
+    assert_eq!(
+        json(&["fmt", "--tab"], "[1, [2]]").1,
+        "[\n\t1,\n\t[\n\t\t2\n\t]\n]\n"
+    );
+    assert_eq!(
+        json_file(&["minify", "--comments"], "commented.json").1,
+        "{\"b\":[1,2,{}],\"a\":{\"c\":\"d\\u00e9\"}}\n"
+    );
+    // Without --comments, comments are errors
+    assert_eq!(json_file(&["minify"], "commented.json").0, 1);
+}
+
+#[test]
+fn formats_files_in_place() {
+    let path = std::env::temp_dir().join(format!("json-cli-{}.json", std::process::id()));
+    std::fs::write(&path, "{\"a\":[1,2]}").unwrap();
+    let path_name = path.to_str().unwrap();
+    assert_eq!(json(&["fmt", "--check", path_name], "").0, 1);
+    assert_eq!(
+        json(&["fmt", "-w", "--indent", "4", path_name], ""),
+        (0, String::new(), String::new())
+    );
+    assert_eq!(
+        std::fs::read_to_string(&path).unwrap(),
+        "{\n    \"a\": [\n        1,\n        2\n    ]\n}\n"
+    );
+    assert_eq!(
+        json(&["fmt", "--check", "--indent", "4", path_name], "").0,
+        0
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn queries_values() {
+    assert_eq!(
+        json_file(&["get", "/servers/1"], "config.json").1,
+        "{\n  \"host\": \"b.example\",\n  \"port\": 8080\n}\n"
+    );
+    assert_eq!(
+        json_file(&["get", "-r", "/servers/0/host"], "config.json").1,
+        "a.example\n"
+    );
+    assert_eq!(
+        json_file(&["keys"], "config.json").1,
+        "debug\nname\nowner\nservers\n"
+    );
+    assert_eq!(
+        json_file(&["keys", "-p", "/servers"], "config.json").1,
+        "0\n1\n"
+    );
+    let (code, _, stderr) = json_file(&["get", "/missing"], "config.json");
+    assert_eq!(code, 1);
+    assert!(stderr.ends_with("/missing has no member missing\n"));
+    assert_eq!(json(&["get", "/a", "--json5"], "{a: 1.5}").1, "1.5\n");
+    let prices = r#"{"price": 1.5, "id": 3000000000, "ok": true}"#;
+    assert_eq!(
+        json(&["validate"], prices),
+        (0, String::new(), String::new())
+    );
+    assert_eq!(json(&["get", "/price"], prices).1, "1.5\n");
+    assert_eq!(json(&["get", "/id"], prices).1, "3000000000.0\n");
+    assert_eq!(
+        json(&["fmt"], prices).1,
+        "{\n  \"price\": 1.5,\n  \"id\": 3000000000,\n  \"ok\": true\n}\n"
+    );
+}
+
+#[test]
+fn runs_filters() {
+    assert_eq!(
+        json_file(&["query", "-c", ".servers[] | {port}"], "config.json").1,
+        "{\"port\":80}\n{\"port\":8080}\n"
+    );
+    assert_eq!(
+        json(
+            &[
+                "query",
+                "-r",
+                r#".[] | select(.ok | not) | "\(.id): failed""#
+            ],
+            r#"[{"id": 1, "ok": true}, {"id": 2, "ok": false}]"#
+        )
+        .1,
+        "2: failed\n"
+    );
+    let (code, _, stderr) = json(&["query", ".a |"], "{}");
+    assert_eq!(code, 1);
+    assert!(stderr.starts_with("Invalid filter: "));
+    let (code, _, stderr) = json(&["query", ".[0]"], "{}");
+    assert_eq!(code, 1);
+    assert_eq!(
+        stderr,
+        "<stdin>: Cannot index object ({}) with number (0)\n"
+    );
+    // A filter that starts with "-" has to come after "--"
+    assert_eq!(json(&["query", "-1"], "null").0, 2);
+    assert_eq!(
+        json(&["query", "-c", "--", "-1", "-"], "null"),
+        (0, "-1\n".to_string(), String::new())
+    );
+    assert_eq!(
+        json(&["query", ".[1"], "[]").2,
+        "Invalid filter: Expected \"]\" but found the end of the input\n"
+    );
+}
+
+#[test]
+fn counts_values() {
+    assert_eq!(
+        json_file(&["stats"], "config.json").1,
+        r#"{
+  "arrays": 1,
+  "booleans": 1,
+  "bytes": 133,
+  "depth": 3,
+  "elements": 2,
+  "members": 8,
+  "nulls": 1,
+  "numbers": 2,
+  "objects": 3,
+  "strings": 3
+}
+"#
+    );
+}
+
+// Checks that `code` compiles as a library against this crate, which the test binary was
+// built with and so sits next to it
+fn assert_compiles(code: &str, name: &str) {
+    let deps = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let library = std::fs::read_dir(&deps)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let file = path.file_name().unwrap().to_str().unwrap();
+            file.starts_with("libjson_parser-") && file.ends_with(".rlib")
+        })
+        .max_by_key(|path| std::fs::metadata(path).unwrap().modified().unwrap())
+        .expect("the library should be built before its tests");
+    let directory = std::env::temp_dir().join(format!("json-codegen-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+    let source = directory.join(format!("{}.rs", name));
+    std::fs::write(&source, code).unwrap();
+    let output = Command::new(std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()))
+        .args([
+            "--crate-type",
+            "lib",
+            "--emit=metadata",
+            "--edition",
+            "2021",
+        ])
+        .arg("--crate-name")
+        .arg(name)
+        .arg("--extern")
+        .arg(format!("json_parser={}", library.display()))
+        .arg("-L")
+        .arg(format!("dependency={}", deps.display()))
+        .arg("--out-dir")
+        .arg(&directory)
+        .arg(&source)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}\n{}",
+        code,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn generates_rust_types() {
+    let (code, stdout, _) = json(
+        &["codegen", "--name", "User"],
+        r#"{"userName": "a", "tags": ["x"], "address": {"zip": "1"}, "score": 1.5}"#,
+    );
+    assert_eq!(code, 0);
+    assert!(stdout.contains("pub struct User {"), "{}", stdout);
+    assert_compiles(&stdout, "from_sample");
+
+    let schema = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/codegen/order.schema.json");
+    let (code, stdout, _) = json(
+        &[
+            "codegen",
+            "--name",
+            "Order",
+            "--schema",
+            schema.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert_eq!(code, 0);
+    assert!(stdout.contains("pub struct Order {"), "{}", stdout);
+    assert_compiles(&stdout, "from_schema");
+
+    let (code, _, stderr) = json(&["codegen", "--schema", "-", "sample.json"], "{}");
+    assert_eq!(code, 2);
+    assert!(stderr.contains("not both"), "{}", stderr);
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    for args in [
+        &[][..],
+        &["frobnicate"],
+        &["get"],
+        &["query"],
+        &["keys", "--bogus"],
+        &["fmt", "--indent", "wide"],
+        &["fmt", "--write"],
+    ] {
+        let (code, _, stderr) = json(args, "{}");
+        assert_eq!(code, 2, "{:?}", args);
+        assert!(stderr.contains("Usage: json"));
+    }
+    let (code, stdout, _) = json(&["--help"], "");
+    assert_eq!(code, 0);
+    assert!(stdout.starts_with("Usage: json"));
+}